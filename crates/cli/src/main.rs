@@ -9,15 +9,26 @@
 //! ```bash
 //! sigmos validate spec.sigmos
 //! sigmos run spec.sigmos
+//! sigmos run spec.sigmos --config sigmos.toml --profile prod
 //! sigmos transpile spec.sigmos --to json
 //! ```
 
 use clap::{Parser, Subcommand};
-use miette::{IntoDiagnostic, Result};
+use miette::{IntoDiagnostic, LabeledSpan, NamedSource, Result};
+use sigmos_core::ast::{Modifier, SourceMap, Span, Spec};
+use sigmos_core::lint::{LintConfig, Linter, Severity};
 use sigmos_core::parser::SigmosParser;
-use sigmos_runtime::Runtime;
+use sigmos_core::taint::TaintAnalyzer;
+use sigmos_core::types::TypeChecker;
+use sigmos_core::ParseError;
+use sigmos_plugins::config::{LogLevel, PluginSettings, RuntimeConfig};
+use sigmos_runtime::builtins::BuiltinRegistry;
+use sigmos_runtime::limits::ExecutionLimits;
+use sigmos_runtime::state::{JsonFileStore, SqliteStore, StateStore};
+use sigmos_runtime::{ExecutionReport, Runtime, RuntimeError};
 use sigmos_transpiler::Transpiler;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// SIGMOS: Sigma Modular Operating Spec CLI
 #[derive(Parser)]
@@ -42,9 +53,31 @@ enum Commands {
         /// Path to the SIGMOS specification file
         #[arg(value_name = "FILE")]
         file: PathBuf,
-        /// Runtime configuration options
+        /// Runtime configuration file (defaults to sigmos.toml next to the spec)
         #[arg(long)]
         config: Option<PathBuf>,
+        /// Profile of the runtime configuration to apply, such as dev or prod
+        #[arg(long)]
+        profile: Option<String>,
+        /// Input value, repeatable; overrides values from --inputs-file
+        #[arg(long = "input", value_name = "KEY=VALUE")]
+        inputs: Vec<String>,
+        /// JSON or YAML file of input values, or `-` to read from stdin
+        #[arg(long, value_name = "FILE")]
+        inputs_file: Option<PathBuf>,
+        /// Output format for the execution report
+        #[arg(long, value_enum, default_value = "table")]
+        format: RunFormat,
+        /// Evaluate unresolved names to `${name}` placeholders instead of failing
+        #[arg(long)]
+        lenient: bool,
+        /// Save checkpoints in this directory and resume the run saved there
+        /// if it failed or was interrupted
+        #[arg(long, value_name = "DIR")]
+        state_dir: Option<PathBuf>,
+        /// How checkpoints are stored in --state-dir
+        #[arg(long, value_enum, default_value = "json", requires = "state_dir")]
+        state_format: StateFormat,
     },
     /// Transpile a SIGMOS specification to another format
     Transpile {
@@ -69,6 +102,18 @@ enum Commands {
         #[command(subcommand)]
         command: PluginCommands,
     },
+    /// Lint a SIGMOS specification against house rules
+    Lint {
+        /// Path to the SIGMOS specification file
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Output format
+        #[arg(long, value_enum, default_value = "human")]
+        format: LintFormat,
+        /// Lint configuration file (defaults to sigmos-lint.toml next to the spec)
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
     /// Describe or explain a SIGMOS specification
     Describe {
         /// Path to the SIGMOS specification file
@@ -87,6 +132,41 @@ enum PluginCommands {
     },
 }
 
+#[derive(clap::ValueEnum, Clone)]
+enum LintFormat {
+    Human,
+    Json,
+    Sarif,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+enum RunFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum StateFormat {
+    /// One JSON file per spec
+    Json,
+    /// An SQLite database, `state.db`
+    Sqlite,
+}
+
+/// How `sigmos run` executes a spec and reports on it
+struct RunOptions {
+    format: RunFormat,
+    lenient: bool,
+    /// Where checkpoints are saved, so a failed run can be resumed
+    state_store: Option<Arc<dyn StateStore>>,
+}
+
+/// Exit status of `sigmos run` when constraints are violated
+const EXIT_CONSTRAINT_VIOLATION: i32 = 2;
+/// Exit status of `sigmos run` when inputs are missing or invalid
+const EXIT_INVALID_INPUT: i32 = 3;
+
 #[derive(clap::ValueEnum, Clone)]
 enum OutputFormat {
     Json,
@@ -100,7 +180,35 @@ async fn main() -> Result<()> {
 
     match cli.command {
         Commands::Validate { file } => validate_spec(&file).await?,
-        Commands::Run { file, config } => run_spec(&file, config.as_ref()).await?,
+        Commands::Run {
+            file,
+            config,
+            profile,
+            inputs,
+            inputs_file,
+            format,
+            lenient,
+            state_dir,
+            state_format,
+        } => {
+            let state_store = state_dir
+                .map(|dir| open_state_store(&dir, state_format))
+                .transpose()?;
+            let options = RunOptions {
+                format,
+                lenient,
+                state_store,
+            };
+            run_spec(
+                &file,
+                config.as_ref(),
+                profile.as_deref(),
+                &inputs,
+                inputs_file.as_ref(),
+                options,
+            )
+            .await?
+        }
         Commands::Transpile { file, to, output } => {
             transpile_spec(&file, to, output.as_ref()).await?
         }
@@ -108,6 +216,11 @@ async fn main() -> Result<()> {
         Commands::Plugin { command } => match command {
             PluginCommands::New { name } => create_plugin_scaffold(&name).await?,
         },
+        Commands::Lint {
+            file,
+            format,
+            config,
+        } => lint_spec(&file, format, config.as_ref()).await?,
         Commands::Describe { file } => describe_spec(&file).await?,
     }
 
@@ -119,28 +232,467 @@ async fn validate_spec(file: &PathBuf) -> Result<()> {
         .into_diagnostic()
         .map_err(|e| miette::miette!("Failed to read file {}: {}", file.display(), e))?;
 
-    let spec = SigmosParser::parse_spec(&content).into_diagnostic()?;
+    let (spec, _) = checked_spec(file, &content)?;
+
+    let findings = TaintAnalyzer::new().analyze(&spec);
+    if !findings.is_empty() {
+        for finding in &findings {
+            eprintln!("✗ {finding}");
+        }
+        return Err(miette::miette!(
+            "{} secret leak(s) found in '{}'",
+            findings.len(),
+            spec.name
+        ));
+    }
 
     println!("✓ Specification '{}' v{} is valid", spec.name, spec.version);
     Ok(())
 }
 
-async fn run_spec(file: &PathBuf, _config: Option<&PathBuf>) -> Result<()> {
+/// Parse `content` and type-check it against the builtin functions, reporting
+/// errors at their location in `file`
+fn checked_spec(file: &Path, content: &str) -> Result<(Spec, SourceMap)> {
+    let report = |error: ParseError| match error {
+        ParseError::Spanned { span, source } => {
+            spanned_report(source.to_string(), Some(span), file, content)
+        }
+        other => miette::miette!("{other}"),
+    };
+    let (spec, source_map) = SigmosParser::parse_spec_with_source_map(content).map_err(report)?;
+    TypeChecker::new()
+        .with_functions(BuiltinRegistry::standard().signatures())
+        .with_source_map(source_map.clone())
+        .validate_spec(&spec)
+        .map_err(report)?;
+    Ok((spec, source_map))
+}
+
+async fn run_spec(
+    file: &PathBuf,
+    config: Option<&PathBuf>,
+    profile: Option<&str>,
+    inputs: &[String],
+    inputs_file: Option<&PathBuf>,
+    options: RunOptions,
+) -> Result<()> {
     let content = std::fs::read_to_string(file)
         .into_diagnostic()
         .map_err(|e| miette::miette!("Failed to read file {}: {}", file.display(), e))?;
 
-    let spec = SigmosParser::parse_spec(&content).into_diagnostic()?;
+    // Specs that fail to type-check never reach the runtime
+    let (spec, source_map) = checked_spec(file, &content)?;
+
+    // Values from the file come first so --input can override them
+    let mut values = match inputs_file {
+        Some(path) => read_inputs_file(path)?,
+        None => serde_json::Map::new(),
+    };
+    for input in inputs {
+        let (key, value) = input
+            .split_once('=')
+            .ok_or_else(|| miette::miette!("Invalid --input '{}': expected KEY=VALUE", input))?;
+        values.insert(
+            key.trim().to_string(),
+            serde_json::Value::String(value.to_string()),
+        );
+    }
+
+    let config = load_runtime_config(file, config, profile)?;
+    let log_level = config
+        .as_ref()
+        .map_or(LogLevel::default(), |c| c.runtime.log_level);
+
+    // Secrets from the config fill secret inputs given no other way
+    if let Some(config) = &config {
+        let secret_inputs = spec
+            .inputs
+            .iter()
+            .filter(|field| field.modifiers.contains(&Modifier::Secret));
+        for field in secret_inputs {
+            if let Some(secret) = config.secret(&field.name) {
+                values
+                    .entry(field.name.clone())
+                    .or_insert_with(|| serde_json::Value::String(secret.to_string()));
+            }
+        }
+    }
+
+    let mut runtime = match &config {
+        Some(config) => Runtime::from(
+            config
+                .build_registry()
+                .map_err(|e| miette::miette!("Failed to set up plugins: {}", e))?,
+        ),
+        None => Runtime::new(),
+    }
+    .with_source_map(source_map)
+    .with_strict(!options.lenient);
+    if let Some(max_concurrency) = config.as_ref().and_then(|c| c.runtime.max_concurrency) {
+        let limits = ExecutionLimits {
+            max_concurrency,
+            ..*runtime.limits()
+        };
+        runtime = runtime.with_limits(limits);
+    }
+
+    if let Some(timeout) = config.as_ref().and_then(|c| c.runtime.timeout()) {
+        let token = runtime.cancellation();
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            token.cancel();
+        });
+    }
+    let result = match options.state_store {
+        Some(store) => runtime.resume_with_inputs(&spec, values, store).await,
+        None => runtime.execute_with_inputs(&spec, values).await,
+    };
+
+    // The report is printed whether or not the run succeeded
+    let report = runtime.snapshot().await;
+    if log_level >= LogLevel::Debug {
+        for call in &report.plugin_calls {
+            eprintln!(
+                "debug: {}.{} took {:.1}ms over {} attempt(s){}",
+                call.plugin,
+                call.method,
+                call.duration_ms,
+                call.attempts,
+                call.error
+                    .as_ref()
+                    .map(|e| format!(" and failed: {e}"))
+                    .unwrap_or_default()
+            );
+        }
+    }
+    match options.format {
+        RunFormat::Table => print_report_table(&report),
+        RunFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).into_diagnostic()?
+        ),
+        RunFormat::Yaml => print!("{}", serde_yaml::to_string(&report).into_diagnostic()?),
+    }
 
-    let mut runtime = Runtime::new();
-    runtime
-        .execute(&spec)
-        .await
-        .map_err(|e| miette::miette!("Runtime error: {}", e))?;
+    let Err(error) = result else {
+        return Ok(());
+    };
+    let exit_code = match &error {
+        RuntimeError::ConstraintsViolated(_) => EXIT_CONSTRAINT_VIOLATION,
+        RuntimeError::MissingInputs(_) | RuntimeError::InvalidInput { .. } => EXIT_INVALID_INPUT,
+        _ => 1,
+    };
+    let diagnostic = match error {
+        RuntimeError::ReadonlyAssignment {
+            field,
+            location,
+            span: Some(span),
+        } => spanned_report(
+            format!("Runtime error: Cannot assign to readonly field '{field}' in {location}"),
+            Some(span),
+            file,
+            &content,
+        ),
+        RuntimeError::ConstraintsViolated(violations) => miette::miette!(
+            "{} constraint(s) violated in '{}'",
+            violations.len(),
+            spec.name
+        ),
+        other => miette::miette!("Runtime error: {}", other),
+    };
+    eprintln!("{diagnostic:?}");
+    std::process::exit(exit_code);
+}
+
+/// Print an execution report as aligned sections, skipping empty ones
+fn print_report_table(report: &ExecutionReport) {
+    let stage = match (&report.error, report.stage) {
+        (Some(_), Some(stage)) => format!(" in stage '{}'", stage.name()),
+        _ => String::new(),
+    };
+    println!(
+        "{} v{}: {}{stage} after {:.2} ms",
+        report.spec, report.version, report.state, report.timings.total_ms
+    );
+
+    let section = |title: &str, rows: Vec<(String, String)>| {
+        if rows.is_empty() {
+            return;
+        }
+        let width = rows
+            .iter()
+            .map(|(key, _)| key.chars().count())
+            .max()
+            .unwrap_or(0);
+        println!();
+        println!("{title}");
+        for (key, value) in rows {
+            println!("  {key:<width$}  {value}");
+        }
+    };
+    let values = |values: &std::collections::BTreeMap<String, serde_json::Value>| {
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect()
+    };
+
+    section("Inputs", values(&report.inputs));
+    section("Computed", values(&report.computed));
+    section(
+        "Plugin calls",
+        report
+            .plugin_calls
+            .iter()
+            .map(|call| {
+                let arguments: Vec<String> = call
+                    .arguments
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {value}"))
+                    .collect();
+                let outcome = match &call.error {
+                    Some(error) => format!("failed: {error}"),
+                    None => "ok".to_string(),
+                };
+                let attempts = if call.attempts > 1 {
+                    format!(" after {} attempts", call.attempts)
+                } else {
+                    String::new()
+                };
+                (
+                    format!("{}.{}({})", call.plugin, call.method, arguments.join(", ")),
+                    format!("{outcome} in {:.2} ms{attempts}", call.duration_ms),
+                )
+            })
+            .collect(),
+    );
+    section(
+        "Violations",
+        report
+            .violations
+            .iter()
+            .map(|violation| ("✗".to_string(), violation.to_string()))
+            .collect(),
+    );
+    section(
+        "Log",
+        report
+            .logs
+            .iter()
+            .enumerate()
+            .map(|(i, message)| ((i + 1).to_string(), message.clone()))
+            .collect(),
+    );
+    section(
+        "Phases",
+        report
+            .timings
+            .phases
+            .iter()
+            .map(|timing| {
+                (
+                    timing.phase.clone(),
+                    format!("{:.2} ms", timing.duration_ms),
+                )
+            })
+            .collect(),
+    );
+}
+
+/// Open the checkpoint store in `dir` for `sigmos run --state-dir`
+fn open_state_store(dir: &Path, format: StateFormat) -> Result<Arc<dyn StateStore>> {
+    let store: Arc<dyn StateStore> = match format {
+        StateFormat::Json => Arc::new(JsonFileStore::new(dir).into_diagnostic()?),
+        StateFormat::Sqlite => {
+            std::fs::create_dir_all(dir).into_diagnostic()?;
+            Arc::new(SqliteStore::open(dir.join("state.db")).into_diagnostic()?)
+        }
+    };
+    Ok(store)
+}
+
+/// Load the runtime configuration for `sigmos run`
+///
+/// An explicit config must exist; `sigmos.toml` next to the spec is used when
+/// present. Errors point at the offending line of the config file.
+fn load_runtime_config(
+    file: &std::path::Path,
+    config: Option<&PathBuf>,
+    profile: Option<&str>,
+) -> Result<Option<RuntimeConfig>> {
+    let path = config.cloned().or_else(|| {
+        let default = file
+            .parent()
+            .unwrap_or_else(|| std::path::Path::new("."))
+            .join(RuntimeConfig::FILE_NAME);
+        default.exists().then_some(default)
+    });
+    let Some(path) = path else {
+        return match profile {
+            Some(profile) => Err(miette::miette!(
+                "Profile '{}' was requested, but there is no runtime config; pass --config",
+                profile
+            )),
+            None => Ok(None),
+        };
+    };
+
+    let content = std::fs::read_to_string(&path)
+        .into_diagnostic()
+        .map_err(|e| miette::miette!("Failed to read runtime config {}: {}", path.display(), e))?;
+    let config = RuntimeConfig::from_toml(&content, profile).map_err(|e| {
+        spanned_report(
+            format!("Invalid runtime config: {}", e.message),
+            e.span,
+            &path,
+            &content,
+        )
+    })?;
+
+    if config.runtime.log_level >= LogLevel::Info {
+        let plugins: Vec<String> = config
+            .plugins
+            .iter()
+            .map(|plugin| match &plugin.settings {
+                PluginSettings::Mcp(_) => format!("{} (mcp)", plugin.name),
+                PluginSettings::Rest(_) => format!("{} (rest)", plugin.name),
+            })
+            .collect();
+        eprintln!(
+            "info: using {}{}; plugins: {}",
+            path.display(),
+            profile
+                .map(|profile| format!(" with profile '{profile}'"))
+                .unwrap_or_default(),
+            if plugins.is_empty() {
+                "none".to_string()
+            } else {
+                plugins.join(", ")
+            }
+        );
+    }
+    Ok(Some(config))
+}
+
+/// Read input values from a JSON or YAML file, or from stdin when the path is `-`
+fn read_inputs_file(path: &PathBuf) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let content = if path.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin())
+            .into_diagnostic()
+            .map_err(|e| miette::miette!("Failed to read inputs from stdin: {}", e))?
+    } else {
+        std::fs::read_to_string(path)
+            .into_diagnostic()
+            .map_err(|e| miette::miette!("Failed to read inputs file {}: {}", path.display(), e))?
+    };
+
+    // YAML is a superset of JSON, so only a .json extension forces strict JSON
+    let is_json = path.extension().is_some_and(|ext| ext == "json");
+    let value: serde_json::Value = if is_json {
+        serde_json::from_str(&content).into_diagnostic()?
+    } else {
+        serde_yaml::from_str(&content).into_diagnostic()?
+    };
+
+    match value {
+        serde_json::Value::Object(values) => Ok(values),
+        serde_json::Value::Null => Ok(serde_json::Map::new()),
+        _ => Err(miette::miette!(
+            "Inputs in {} must be a map of field names to values",
+            path.display()
+        )),
+    }
+}
+
+async fn lint_spec(file: &PathBuf, format: LintFormat, config: Option<&PathBuf>) -> Result<()> {
+    let content = std::fs::read_to_string(file)
+        .into_diagnostic()
+        .map_err(|e| miette::miette!("Failed to read file {}: {}", file.display(), e))?;
+
+    // An explicit config must exist; the default one is optional
+    let config_path = config.cloned().or_else(|| {
+        let default = file
+            .parent()
+            .unwrap_or_else(|| std::path::Path::new("."))
+            .join(LintConfig::FILE_NAME);
+        default.exists().then_some(default)
+    });
+    let mut linter = Linter::new();
+    if let Some(path) = config_path {
+        let config_content = std::fs::read_to_string(&path)
+            .into_diagnostic()
+            .map_err(|e| miette::miette!("Failed to read lint config {}: {}", path.display(), e))?;
+        linter = linter
+            .with_config(LintConfig::from_toml(&config_content).into_diagnostic()?)
+            .into_diagnostic()?;
+    }
+
+    let diagnostics = linter.lint_source(&content).into_diagnostic()?;
+    let file_name = file.display().to_string();
+
+    match format {
+        LintFormat::Human => {
+            for d in &diagnostics {
+                let location = d
+                    .span
+                    .map(|span| format!("{file_name}:{}:{}", span.line, span.column))
+                    .unwrap_or_else(|| file_name.clone());
+                println!("{location}: {}[{}]: {}", d.severity, d.rule, d.message);
+            }
+            if diagnostics.is_empty() {
+                println!("✓ No lint findings in {file_name}");
+            } else {
+                println!("{} finding(s)", diagnostics.len());
+            }
+        }
+        LintFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&diagnostics).into_diagnostic()?
+            );
+        }
+        LintFormat::Sarif => {
+            let report = linter.sarif_report(&file_name, &diagnostics);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).into_diagnostic()?
+            );
+        }
+    }
 
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(miette::miette!("{errors} lint error(s) in {file_name}"));
+    }
     Ok(())
 }
 
+/// Build a report labelling `span` in the spec source
+fn spanned_report(
+    message: String,
+    span: Option<Span>,
+    file: &std::path::Path,
+    content: &str,
+) -> miette::Report {
+    match span {
+        Some(span) => miette::miette!(
+            labels = vec![LabeledSpan::at(
+                span.offset..span.offset + span.length,
+                "here"
+            )],
+            "{message}"
+        )
+        .with_source_code(NamedSource::new(
+            file.display().to_string(),
+            content.to_string(),
+        )),
+        None => miette::miette!("{message}"),
+    }
+}
+
 async fn transpile_spec(
     file: &PathBuf,
     format: OutputFormat,
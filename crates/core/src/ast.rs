@@ -19,10 +19,13 @@
 //!     lifecycle: vec![],
 //!     extensions: vec![],
 //!     types: vec![],
+//!     functions: vec![],
 //! };
 //! ```
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
 
 /// Version specification for SIGMOS specs
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -45,6 +48,7 @@ pub struct Spec {
     pub lifecycle: Vec<LifecycleDef>,
     pub extensions: Vec<ExtensionDef>,
     pub types: Vec<TypeDef>,
+    pub functions: Vec<FunctionDef>,
 }
 
 /// Field definition with type and modifiers
@@ -59,8 +63,13 @@ pub struct FieldDef {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TypeExpr {
     Primitive(PrimitiveType),
-    Generic { name: String, args: Vec<TypeExpr> },
+    Generic {
+        name: String,
+        args: Vec<TypeExpr>,
+    },
     Reference(String),
+    /// A type that may also hold `null` (e.g. an `optional` input without a default)
+    Nullable(Box<TypeExpr>),
 }
 
 /// Primitive types
@@ -83,6 +92,19 @@ pub enum Modifier {
     Secret,
     Generate,
     Ref(String),
+    Description(String),
+    /// `min: n`: smallest allowed number
+    Min(f64),
+    /// `max: n`: largest allowed number
+    Max(f64),
+    /// `min_length: n`: fewest characters or array elements
+    MinLength(usize),
+    /// `max_length: n`: most characters or array elements
+    MaxLength(usize),
+    /// `pattern: /regex/`: strings must match the regular expression
+    Pattern(String),
+    /// `one_of: [...]`: the allowed values
+    OneOf(Vec<serde_json::Value>),
 }
 
 /// Computed field with expression
@@ -90,6 +112,74 @@ pub enum Modifier {
 pub struct ComputedField {
     pub name: String,
     pub expression: Expression,
+    /// How the field's plugin calls are retried, bounded and replaced
+    pub policy: Option<CallPolicy>,
+}
+
+impl ComputedField {
+    /// Names of the variables the field reads, in its expression and then in
+    /// its fallback
+    pub fn referenced_identifiers(&self) -> Vec<&str> {
+        let mut names = self.expression.referenced_identifiers();
+        if let Some(fallback) = self.policy.as_ref().and_then(|p| p.fallback.as_ref()) {
+            for name in fallback.referenced_identifiers() {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+}
+
+/// How plugin calls are retried, bounded and replaced when they fail
+///
+/// Written after a computed field's expression or an action as
+/// `{ retry: 3, backoff: exponential(200ms), timeout: 5s, fallback: "n/a" }`.
+/// Retries and timeouts apply to each plugin call the field or action makes;
+/// the fallback replaces the field's value, or the action's expressions, when
+/// one of those calls fails for good.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallPolicy {
+    /// Attempts after the first for a call whose failure may not recur
+    pub retry: u32,
+    /// Wait before each retry
+    pub backoff: Option<Backoff>,
+    /// Longest one attempt may take
+    pub timeout: Option<Duration>,
+    /// Value used when a plugin call fails on its last attempt
+    pub fallback: Option<Expression>,
+}
+
+/// Wait before retrying a failed call
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Backoff {
+    /// `fixed(1s)`: the same wait before every retry
+    Fixed(Duration),
+    /// `exponential(200ms)`: a wait that doubles with each retry
+    Exponential(Duration),
+}
+
+impl Backoff {
+    /// Wait before the retry numbered `retry`, counting from 0
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::ast::Backoff;
+    /// use std::time::Duration;
+    ///
+    /// let backoff = Backoff::Exponential(Duration::from_millis(200));
+    /// assert_eq!(backoff.delay(2), Duration::from_millis(800));
+    /// ```
+    pub fn delay(&self, retry: u32) -> Duration {
+        match self {
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Exponential(base) => {
+                base.saturating_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX))
+            }
+        }
+    }
 }
 
 /// Event definition
@@ -98,6 +188,8 @@ pub struct EventDef {
     pub event_type: EventType,
     pub parameter: String,
     pub action: Action,
+    /// How the action's plugin calls are retried, bounded and replaced
+    pub policy: Option<CallPolicy>,
 }
 
 /// Event types
@@ -118,6 +210,38 @@ pub enum Action {
         arguments: Vec<Argument>,
     },
     Identifier(String),
+    /// `target = value`
+    Assign {
+        target: String,
+        value: Expression,
+    },
+}
+
+impl Action {
+    /// Objects of the `object.method(...)` calls this action makes
+    pub fn called_objects(&self) -> Vec<&str> {
+        let mut objects = Vec::new();
+        let arguments: Vec<&Expression> = match self {
+            Action::FunctionCall {
+                object, arguments, ..
+            } => {
+                if !object.is_empty() {
+                    objects.push(object.as_str());
+                }
+                arguments.iter().map(|argument| &argument.value).collect()
+            }
+            Action::Identifier(_) => Vec::new(),
+            Action::Assign { value, .. } => vec![value],
+        };
+        for expr in arguments {
+            for object in expr.called_objects() {
+                if !objects.contains(&object) {
+                    objects.push(object);
+                }
+            }
+        }
+        objects
+    }
 }
 
 /// Function call arguments
@@ -136,6 +260,7 @@ pub enum Expression {
     },
     Number(f64),
     Boolean(bool),
+    Null,
     Identifier(String),
     FunctionCall {
         object: String,
@@ -162,6 +287,10 @@ pub enum Expression {
     Or(Box<Expression>, Box<Expression>),
     Not(Box<Expression>),
 
+    // Null-safety operators
+    /// `left ?? right`: `right` is used when `left` evaluates to null
+    Coalesce(Box<Expression>, Box<Expression>),
+
     // Conditional expression
     Conditional {
         condition: Box<Expression>,
@@ -172,12 +301,27 @@ pub enum Expression {
     // Array and object access
     ArrayAccess(Box<Expression>, Box<Expression>),
     PropertyAccess(Box<Expression>, String),
+    /// `object?.property`: yields null instead of failing when `object` is null
+    OptionalPropertyAccess(Box<Expression>, String),
+
+    /// `[a, b, ...]`
+    Array(Vec<Expression>),
+    /// `{ key: value, ... }`, with its entries in the order written
+    Object(Vec<(String, Expression)>),
+
+    /// `x => body` or `(total, x) => body`: a function passed to builtins such
+    /// as `filter`, which sees the variables in scope where it is written
+    Lambda {
+        parameters: Vec<String>,
+        body: Box<Expression>,
+    },
 }
 
 /// Parts of string templates
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TemplatePart {
     Text(String),
+    /// A variable, or a path of properties from one such as `error.message`
     Variable(String),
 }
 
@@ -200,6 +344,8 @@ pub enum ConstraintType {
 pub struct LifecycleDef {
     pub phase: LifecyclePhase,
     pub action: Action,
+    /// How the action's plugin calls are retried, bounded and replaced
+    pub policy: Option<CallPolicy>,
 }
 
 /// Lifecycle phases
@@ -217,6 +363,42 @@ pub struct ExtensionDef {
     pub import_spec: String,
 }
 
+impl ExtensionDef {
+    /// Module path of the import, without its version
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::ast::ExtensionDef;
+    ///
+    /// let extension = ExtensionDef {
+    ///     name: "ai".to_string(),
+    ///     import_spec: "sigmos.std.net.mcp@1.0".to_string(),
+    /// };
+    /// assert_eq!(extension.module(), "sigmos.std.net.mcp");
+    /// assert_eq!(extension.plugin_name(), "mcp");
+    /// assert_eq!(extension.version_requirement(), Some("1.0"));
+    /// ```
+    pub fn module(&self) -> &str {
+        self.import_spec
+            .split_once('@')
+            .map_or(self.import_spec.as_str(), |(module, _)| module)
+            .trim()
+    }
+
+    /// Plugin the extension resolves to: the last segment of the module path
+    pub fn plugin_name(&self) -> &str {
+        self.module().rsplit('.').next().unwrap_or_default()
+    }
+
+    /// Version requirement after `@`, if the import has one
+    pub fn version_requirement(&self) -> Option<&str> {
+        self.import_spec
+            .split_once('@')
+            .map(|(_, requirement)| requirement.trim())
+    }
+}
+
 /// Type definitions
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TypeDef {
@@ -224,6 +406,77 @@ pub struct TypeDef {
     pub type_expr: TypeExpr,
 }
 
+/// A pure function declared in the `functions:` section:
+/// `score(x: float, w: float) -> float = x * w`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionDef {
+    pub name: String,
+    pub parameters: Vec<FunctionParameter>,
+    pub return_type: TypeExpr,
+    pub body: Expression,
+}
+
+/// A typed parameter of a [`FunctionDef`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionParameter {
+    pub name: String,
+    pub type_expr: TypeExpr,
+}
+
+/// Location of a construct in the source text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    /// Byte offset of the first character
+    pub offset: usize,
+    /// Length in bytes
+    pub length: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number
+    pub column: usize,
+}
+
+/// Source spans of spec items, keyed by path
+///
+/// Paths name the item they cover: `inputs.api_key`, `inputs.api_key.ref`,
+/// `computed.total`, `events[0]` or `lifecycle[1]`.
+///
+/// # Examples
+///
+/// ```rust
+/// use sigmos_core::parser::SigmosParser;
+///
+/// let (_, source_map) = SigmosParser::parse_spec_with_source_map(r#"
+/// spec "Agent" v1.0 {
+///     inputs:
+///         name: string
+/// }
+/// "#).unwrap();
+/// assert_eq!(source_map.get("inputs.name").unwrap().line, 4);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    spans: HashMap<String, Span>,
+}
+
+impl SourceMap {
+    /// Record the span of the item at `path`
+    pub fn insert(&mut self, path: impl Into<String>, span: Span) {
+        self.spans.insert(path.into(), span);
+    }
+
+    /// Span of the item at `path`, if known
+    pub fn get(&self, path: &str) -> Option<Span> {
+        self.spans.get(path).copied()
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 impl std::fmt::Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.patch {
@@ -233,6 +486,488 @@ impl std::fmt::Display for Version {
     }
 }
 
+impl TypeExpr {
+    /// Whether values of this type may be `null`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::ast::{PrimitiveType, TypeExpr};
+    ///
+    /// let int = TypeExpr::Primitive(PrimitiveType::Int);
+    /// assert!(!int.is_nullable());
+    /// assert!(TypeExpr::Nullable(Box::new(int)).is_nullable());
+    /// ```
+    pub fn is_nullable(&self) -> bool {
+        matches!(
+            self,
+            TypeExpr::Nullable(_) | TypeExpr::Primitive(PrimitiveType::Null)
+        )
+    }
+
+    /// The type with any nullable wrapper removed
+    pub fn non_null(&self) -> &TypeExpr {
+        match self {
+            TypeExpr::Nullable(inner) => inner.non_null(),
+            other => other,
+        }
+    }
+}
+
+impl Expression {
+    /// The JSON value of a literal (string, number, boolean or null), or of
+    /// an array or object literal whose items are all literals
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::ast::Expression;
+    ///
+    /// assert_eq!(Expression::Number(3.0).literal_value(), Some(serde_json::json!(3)));
+    /// assert_eq!(Expression::Identifier("x".to_string()).literal_value(), None);
+    /// ```
+    pub fn literal_value(&self) -> Option<serde_json::Value> {
+        match self {
+            Expression::StringLiteral(s) => Some(serde_json::Value::String(s.clone())),
+            Expression::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                Some(serde_json::Value::from(*n as i64))
+            }
+            Expression::Number(n) => serde_json::Number::from_f64(*n).map(Into::into),
+            Expression::Boolean(b) => Some(serde_json::Value::Bool(*b)),
+            Expression::Null => Some(serde_json::Value::Null),
+            Expression::Array(items) => items.iter().map(Self::literal_value).collect(),
+            Expression::Object(entries) => entries
+                .iter()
+                .map(|(key, value)| Some((key.clone(), value.literal_value()?)))
+                .collect::<Option<serde_json::Map<_, _>>>()
+                .map(serde_json::Value::Object),
+            _ => None,
+        }
+    }
+
+    /// Names of all variables referenced by this expression, including template
+    /// variables, of which `{{error.message}}` references `error`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::ast::Expression;
+    ///
+    /// let expr = Expression::Add(
+    ///     Box::new(Expression::Identifier("a".to_string())),
+    ///     Box::new(Expression::Identifier("b".to_string())),
+    /// );
+    /// assert_eq!(expr.referenced_identifiers(), vec!["a", "b"]);
+    /// ```
+    pub fn referenced_identifiers(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_identifiers(&mut names);
+        names
+    }
+
+    /// Objects of the `object.method(...)` calls in this expression
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::ast::{Argument, Expression};
+    ///
+    /// let expr = Expression::FunctionCall {
+    ///     object: "mcp".to_string(),
+    ///     method: "complete".to_string(),
+    ///     arguments: vec![Argument {
+    ///         name: "prompt".to_string(),
+    ///         value: Expression::FunctionCall {
+    ///             object: String::new(),
+    ///             method: "upper".to_string(),
+    ///             arguments: vec![],
+    ///         },
+    ///     }],
+    /// };
+    /// assert_eq!(expr.called_objects(), vec!["mcp"]);
+    /// ```
+    pub fn called_objects(&self) -> Vec<&str> {
+        let mut objects = Vec::new();
+        self.visit(&mut |expr| {
+            if let Expression::FunctionCall { object, .. } = expr {
+                if !object.is_empty() && !objects.contains(&object.as_str()) {
+                    objects.push(object.as_str());
+                }
+            }
+        });
+        objects
+    }
+
+    /// Call `f` on this expression and every subexpression, outermost first
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Expression)) {
+        f(self);
+        match self {
+            Expression::StringLiteral(_)
+            | Expression::Number(_)
+            | Expression::Boolean(_)
+            | Expression::Null
+            | Expression::Identifier(_)
+            | Expression::StringTemplate { .. } => {}
+            Expression::FunctionCall { arguments, .. } => {
+                for argument in arguments {
+                    argument.value.visit(f);
+                }
+            }
+            Expression::Add(left, right)
+            | Expression::Subtract(left, right)
+            | Expression::Multiply(left, right)
+            | Expression::Divide(left, right)
+            | Expression::Modulo(left, right)
+            | Expression::Equal(left, right)
+            | Expression::NotEqual(left, right)
+            | Expression::LessThan(left, right)
+            | Expression::LessThanOrEqual(left, right)
+            | Expression::GreaterThan(left, right)
+            | Expression::GreaterThanOrEqual(left, right)
+            | Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Coalesce(left, right)
+            | Expression::ArrayAccess(left, right) => {
+                left.visit(f);
+                right.visit(f);
+            }
+            Expression::Not(operand)
+            | Expression::PropertyAccess(operand, _)
+            | Expression::OptionalPropertyAccess(operand, _)
+            | Expression::Lambda { body: operand, .. } => operand.visit(f),
+            Expression::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                condition.visit(f);
+                if_true.visit(f);
+                if_false.visit(f);
+            }
+            Expression::Array(items) => {
+                for item in items {
+                    item.visit(f);
+                }
+            }
+            Expression::Object(entries) => {
+                for (_, value) in entries {
+                    value.visit(f);
+                }
+            }
+        }
+    }
+
+    fn collect_identifiers<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expression::StringLiteral(_)
+            | Expression::Number(_)
+            | Expression::Boolean(_)
+            | Expression::Null => {}
+            Expression::Identifier(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Expression::StringTemplate { parts } => {
+                for part in parts {
+                    if let TemplatePart::Variable(path) = part {
+                        let name = path.split('.').next().unwrap_or(path);
+                        if !names.contains(&name) {
+                            names.push(name);
+                        }
+                    }
+                }
+            }
+            Expression::FunctionCall {
+                object,
+                method,
+                arguments,
+            } => {
+                // `ref("name")` reads the named field
+                if object.is_empty() && method == "ref" {
+                    if let Some(Expression::StringLiteral(name)) =
+                        arguments.first().map(|a| &a.value)
+                    {
+                        if !names.contains(&name.as_str()) {
+                            names.push(name);
+                        }
+                    }
+                }
+                for argument in arguments {
+                    argument.value.collect_identifiers(names);
+                }
+            }
+            Expression::Add(left, right)
+            | Expression::Subtract(left, right)
+            | Expression::Multiply(left, right)
+            | Expression::Divide(left, right)
+            | Expression::Modulo(left, right)
+            | Expression::Equal(left, right)
+            | Expression::NotEqual(left, right)
+            | Expression::LessThan(left, right)
+            | Expression::LessThanOrEqual(left, right)
+            | Expression::GreaterThan(left, right)
+            | Expression::GreaterThanOrEqual(left, right)
+            | Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Coalesce(left, right)
+            | Expression::ArrayAccess(left, right) => {
+                left.collect_identifiers(names);
+                right.collect_identifiers(names);
+            }
+            Expression::Not(operand)
+            | Expression::PropertyAccess(operand, _)
+            | Expression::OptionalPropertyAccess(operand, _) => operand.collect_identifiers(names),
+            Expression::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                condition.collect_identifiers(names);
+                if_true.collect_identifiers(names);
+                if_false.collect_identifiers(names);
+            }
+            Expression::Array(items) => {
+                for item in items {
+                    item.collect_identifiers(names);
+                }
+            }
+            Expression::Object(entries) => {
+                for (_, value) in entries {
+                    value.collect_identifiers(names);
+                }
+            }
+            Expression::Lambda { parameters, body } => {
+                // Parameters are bound by the lambda, not read from the scope
+                for name in body.referenced_identifiers() {
+                    if !parameters.iter().any(|p| p == name) && !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FieldDef {
+    /// Whether the field is `optional` and has no `default`, so it may be unset (null)
+    pub fn is_nullable(&self) -> bool {
+        self.modifiers.contains(&Modifier::Optional)
+            && !self
+                .modifiers
+                .iter()
+                .any(|m| matches!(m, Modifier::Default(expr) if *expr != Expression::Null))
+    }
+}
+
+impl std::fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeExpr::Primitive(primitive) => write!(f, "{primitive}"),
+            TypeExpr::Generic { name, args } => {
+                write!(f, "{name}<")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ">")
+            }
+            TypeExpr::Reference(name) => write!(f, "{name}"),
+            TypeExpr::Nullable(inner) => write!(f, "{inner}?"),
+        }
+    }
+}
+
+impl Expression {
+    /// Binding strength used to parenthesize rendered subexpressions
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::Conditional { .. } | Expression::Lambda { .. } => 0,
+            Expression::Coalesce(..) => 1,
+            Expression::Or(..) => 2,
+            Expression::And(..) => 3,
+            Expression::Equal(..) | Expression::NotEqual(..) => 4,
+            Expression::LessThan(..)
+            | Expression::LessThanOrEqual(..)
+            | Expression::GreaterThan(..)
+            | Expression::GreaterThanOrEqual(..) => 5,
+            Expression::Add(..) | Expression::Subtract(..) => 6,
+            Expression::Multiply(..) | Expression::Divide(..) | Expression::Modulo(..) => 7,
+            Expression::Not(..) => 8,
+            _ => 9,
+        }
+    }
+
+    /// Operator and operands of a binary expression
+    pub fn binary_operands(&self) -> Option<(&'static str, &Expression, &Expression)> {
+        let (op, left, right) = match self {
+            Expression::Add(l, r) => ("+", l, r),
+            Expression::Subtract(l, r) => ("-", l, r),
+            Expression::Multiply(l, r) => ("*", l, r),
+            Expression::Divide(l, r) => ("/", l, r),
+            Expression::Modulo(l, r) => ("%", l, r),
+            Expression::Equal(l, r) => ("==", l, r),
+            Expression::NotEqual(l, r) => ("!=", l, r),
+            Expression::LessThan(l, r) => ("<", l, r),
+            Expression::LessThanOrEqual(l, r) => ("<=", l, r),
+            Expression::GreaterThan(l, r) => (">", l, r),
+            Expression::GreaterThanOrEqual(l, r) => (">=", l, r),
+            Expression::And(l, r) => ("&&", l, r),
+            Expression::Or(l, r) => ("||", l, r),
+            Expression::Coalesce(l, r) => ("??", l, r),
+            _ => return None,
+        };
+        Some((op, left, right))
+    }
+
+    fn fmt_operand(&self, f: &mut std::fmt::Formatter<'_>, parenthesize: bool) -> std::fmt::Result {
+        if parenthesize {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+/// Renders the expression in SIGMOS syntax
+///
+/// # Examples
+///
+/// ```rust
+/// use sigmos_core::ast::Expression;
+///
+/// let expr = Expression::Multiply(
+///     Box::new(Expression::Add(
+///         Box::new(Expression::Identifier("a".to_string())),
+///         Box::new(Expression::Number(1.0)),
+///     )),
+///     Box::new(Expression::Identifier("b".to_string())),
+/// );
+/// assert_eq!(expr.to_string(), "(a + 1) * b");
+/// ```
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((op, left, right)) = self.binary_operands() {
+            let precedence = self.precedence();
+            left.fmt_operand(f, left.precedence() < precedence)?;
+            write!(f, " {op} ")?;
+            return right.fmt_operand(f, right.precedence() <= precedence);
+        }
+
+        match self {
+            Expression::StringLiteral(s) => {
+                write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            Expression::StringTemplate { parts } => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(text) => write!(f, "{}", text.replace('"', "\\\""))?,
+                        TemplatePart::Variable(name) => write!(f, "{{{{{name}}}}}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Expression::Number(n) => match self.literal_value() {
+                Some(value) => write!(f, "{value}"),
+                None => write!(f, "{n}"),
+            },
+            Expression::Boolean(b) => write!(f, "{b}"),
+            Expression::Null => write!(f, "null"),
+            Expression::Identifier(name) => write!(f, "{name}"),
+            Expression::FunctionCall {
+                object,
+                method,
+                arguments,
+            } => {
+                if !object.is_empty() {
+                    write!(f, "{object}.")?;
+                }
+                write!(f, "{method}(")?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    if !argument.name.is_empty() {
+                        write!(f, "{}: ", argument.name)?;
+                    }
+                    write!(f, "{}", argument.value)?;
+                }
+                write!(f, ")")
+            }
+            Expression::Not(operand) => {
+                write!(f, "!")?;
+                operand.fmt_operand(f, operand.precedence() < 8)
+            }
+            Expression::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                condition.fmt_operand(f, condition.precedence() < 1)?;
+                write!(f, " ? {if_true} : {if_false}")
+            }
+            Expression::ArrayAccess(object, index) => {
+                object.fmt_operand(f, object.precedence() < 9)?;
+                write!(f, "[{index}]")
+            }
+            Expression::PropertyAccess(object, property) => {
+                object.fmt_operand(f, object.precedence() < 9)?;
+                write!(f, ".{property}")
+            }
+            Expression::OptionalPropertyAccess(object, property) => {
+                object.fmt_operand(f, object.precedence() < 9)?;
+                write!(f, "?.{property}")
+            }
+            Expression::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Expression::Object(entries) => {
+                if entries.is_empty() {
+                    return write!(f, "{{}}");
+                }
+                write!(f, "{{ ")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    let bare = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                    if bare {
+                        write!(f, "{key}: {value}")?;
+                    } else {
+                        write!(f, "\"{key}\": {value}")?;
+                    }
+                }
+                write!(f, " }}")
+            }
+            Expression::Lambda { parameters, body } => match parameters.as_slice() {
+                [parameter] => write!(f, "{parameter} => {body}"),
+                _ => write!(f, "({}) => {body}", parameters.join(", ")),
+            },
+            _ => unreachable!("binary expressions are rendered above"),
+        }
+    }
+}
+
+impl std::fmt::Display for ConstraintType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintType::Assert => write!(f, "assert"),
+            ConstraintType::Ensure => write!(f, "ensure"),
+        }
+    }
+}
+
 impl std::fmt::Display for PrimitiveType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
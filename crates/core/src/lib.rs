@@ -7,6 +7,8 @@
 //! - Abstract Syntax Tree (AST) types
 //! - Parser implementation with error handling
 //! - Type system definitions
+//! - Secret taint analysis
+//! - Lint rules with configurable severities
 //!
 //! # Examples
 //!
@@ -27,7 +29,9 @@ use pest_derive::Parser;
 use thiserror::Error;
 
 pub mod ast;
+pub mod lint;
 pub mod parser;
+pub mod taint;
 pub mod types;
 
 /// SIGMOS parser using pest grammar
@@ -47,6 +51,32 @@ pub enum ParseError {
     Semantic(String),
     #[error("Type error: {0}")]
     Type(String),
+    #[error("{source} (at {span})")]
+    Spanned {
+        span: ast::Span,
+        source: Box<ParseError>,
+    },
+}
+
+impl ParseError {
+    /// Attach a source span, keeping any span already present
+    pub fn with_span(self, span: Option<ast::Span>) -> Self {
+        match (self, span) {
+            (error @ ParseError::Spanned { .. }, _) | (error, None) => error,
+            (error, Some(span)) => ParseError::Spanned {
+                span,
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The source span of this error, if known
+    pub fn span(&self) -> Option<ast::Span> {
+        match self {
+            ParseError::Spanned { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
 }
 
 /// Result type for parsing operations
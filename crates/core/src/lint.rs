@@ -0,0 +1,864 @@
+//! # Spec linting
+//!
+//! House rules for SIGMOS specifications. Each rule has a stable ID and a
+//! default severity; both can be tuned with a `sigmos-lint.toml` file, and
+//! findings can be silenced with suppression comments in the spec:
+//!
+//! - `// sigmos-lint-disable rule-a, rule-b` silences rules for the whole file
+//! - `// sigmos-lint-disable-line rule-a` silences rules on the same line
+//! - `// sigmos-lint-disable-next-line rule-a` silences rules on the following line
+//!
+//! Omitting the rule list silences every rule.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::lint::{Linter, Severity};
+//!
+//! let diagnostics = Linter::new().lint_source(r#"
+//! spec "Agent" v1.0 {
+//!     inputs:
+//!         apiKey: string
+//! }
+//! "#).unwrap();
+//!
+//! assert!(diagnostics.iter().any(|d| d.rule == "snake-case"));
+//! assert!(diagnostics.iter().any(|d| d.severity == Severity::Warning));
+//! ```
+
+use crate::ast::{Action, Expression, Modifier, SourceMap, Span, Spec};
+use crate::parser::SigmosParser;
+use crate::{ParseError, ParseResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Prefix shared by all suppression comments
+const SUPPRESSION_PREFIX: &str = "sigmos-lint-";
+
+/// Name suffixes that suggest a credential
+const SECRET_NAME_SUFFIXES: &[&str] = &["_key", "_token", "_secret", "_password", "_credentials"];
+
+/// Names that are credentials on their own
+const SECRET_NAMES: &[&str] = &["key", "token", "secret", "password", "credentials"];
+
+/// How serious a lint finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The rule is disabled
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Severity::Off => "off",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A problem reported by a rule, before severity and suppressions are applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    /// Source map path of the offending item, e.g. `inputs.api_key`
+    pub path: String,
+    pub message: String,
+}
+
+impl Finding {
+    /// Create a finding for the item at `path`
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// A lint rule
+///
+/// Implement this to add project-specific rules and register them with
+/// [`Linter::with_rule`].
+///
+/// # Examples
+///
+/// ```rust
+/// use sigmos_core::ast::Spec;
+/// use sigmos_core::lint::{Finding, LintRule, Linter, Severity};
+///
+/// struct NoDescription;
+///
+/// impl LintRule for NoDescription {
+///     fn id(&self) -> &'static str {
+///         "spec-description"
+///     }
+///     fn description(&self) -> &'static str {
+///         "Specs should describe themselves"
+///     }
+///     fn default_severity(&self) -> Severity {
+///         Severity::Warning
+///     }
+///     fn check(&self, spec: &Spec) -> Vec<Finding> {
+///         match spec.description {
+///             Some(_) => vec![],
+///             None => vec![Finding::new("spec", "Spec has no description")],
+///         }
+///     }
+/// }
+///
+/// let linter = Linter::new().with_rule(Box::new(NoDescription));
+/// let diagnostics = linter.lint_source(r#"spec "Agent" v1.0 { }"#).unwrap();
+/// assert_eq!(diagnostics[0].rule, "spec-description");
+/// ```
+pub trait LintRule: Send + Sync {
+    /// Stable kebab-case identifier used in configs and suppression comments
+    fn id(&self) -> &'static str;
+    /// One-line explanation of what the rule checks
+    fn description(&self) -> &'static str;
+    /// Severity used unless the config overrides it
+    fn default_severity(&self) -> Severity;
+    /// Report every violation in `spec`
+    fn check(&self, spec: &Spec) -> Vec<Finding>;
+}
+
+/// A finding with its rule, effective severity and source location
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintDiagnostic {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Source map path of the offending item
+    pub path: String,
+    pub span: Option<Span>,
+}
+
+/// Lint configuration, usually loaded from `sigmos-lint.toml`
+///
+/// ```toml
+/// [rules]
+/// missing-description = "off"
+/// unused-input = "error"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    /// Severity overrides by rule ID
+    #[serde(default)]
+    pub rules: HashMap<String, Severity>,
+}
+
+impl LintConfig {
+    /// Conventional config file name, looked up next to the spec
+    pub const FILE_NAME: &'static str = "sigmos-lint.toml";
+
+    /// Parse a TOML lint configuration
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::lint::{LintConfig, Severity};
+    ///
+    /// let config = LintConfig::from_toml("[rules]\nsnake-case = \"error\"").unwrap();
+    /// assert_eq!(config.rules["snake-case"], Severity::Error);
+    /// ```
+    pub fn from_toml(content: &str) -> ParseResult<Self> {
+        toml_edit::de::from_str(content)
+            .map_err(|e| ParseError::Semantic(format!("Invalid lint configuration: {e}")))
+    }
+}
+
+/// Lint engine running a set of rules over a spec
+pub struct Linter {
+    rules: Vec<Box<dyn LintRule>>,
+    config: LintConfig,
+}
+
+impl Linter {
+    /// Create a linter with the built-in rules
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::lint::Linter;
+    ///
+    /// let linter = Linter::new();
+    /// assert!(linter.rules().any(|rule| rule.id() == "unused-input"));
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            rules: vec![
+                Box::new(UnusedInput),
+                Box::new(UnusedComputed),
+                Box::new(ConstantConstraint),
+                Box::new(MissingDescription),
+                Box::new(SnakeCase),
+                Box::new(SecretName),
+            ],
+            config: LintConfig::default(),
+        }
+    }
+
+    /// Register an additional rule
+    pub fn with_rule(mut self, rule: Box<dyn LintRule>) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Apply a configuration; register custom rules first so their IDs are known
+    pub fn with_config(mut self, config: LintConfig) -> ParseResult<Self> {
+        if let Some(unknown) = config
+            .rules
+            .keys()
+            .find(|id| !self.rules.iter().any(|rule| rule.id() == id.as_str()))
+        {
+            return Err(ParseError::Semantic(format!(
+                "Unknown lint rule '{unknown}' in configuration"
+            )));
+        }
+        self.config = config;
+        Ok(self)
+    }
+
+    /// The registered rules
+    pub fn rules(&self) -> impl Iterator<Item = &dyn LintRule> {
+        self.rules.iter().map(|rule| rule.as_ref())
+    }
+
+    /// Parse `source` and lint the resulting spec
+    pub fn lint_source(&self, source: &str) -> ParseResult<Vec<LintDiagnostic>> {
+        let (spec, source_map) = SigmosParser::parse_spec_with_source_map(source)?;
+        Ok(self.lint(&spec, &source_map, source))
+    }
+
+    /// Lint a parsed spec; `source` is scanned for suppression comments
+    pub fn lint(&self, spec: &Spec, source_map: &SourceMap, source: &str) -> Vec<LintDiagnostic> {
+        let suppressions = Suppressions::parse(source);
+        let mut diagnostics = Vec::new();
+
+        for rule in &self.rules {
+            let severity = self
+                .config
+                .rules
+                .get(rule.id())
+                .copied()
+                .unwrap_or_else(|| rule.default_severity());
+            if severity == Severity::Off {
+                continue;
+            }
+
+            for finding in rule.check(spec) {
+                let span = source_map.get(&finding.path);
+                if suppressions.suppresses(rule.id(), span.map(|span| span.line)) {
+                    continue;
+                }
+                diagnostics.push(LintDiagnostic {
+                    rule: rule.id().to_string(),
+                    severity,
+                    message: finding.message,
+                    path: finding.path,
+                    span,
+                });
+            }
+        }
+
+        diagnostics.sort_by_key(|d| d.span.map(|span| span.offset));
+        diagnostics
+    }
+
+    /// Render diagnostics as a SARIF 2.1.0 log for `uri`
+    pub fn sarif_report(&self, uri: &str, diagnostics: &[LintDiagnostic]) -> serde_json::Value {
+        let level = |severity: Severity| match severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info | Severity::Off => "note",
+        };
+
+        let rules: Vec<_> = self
+            .rules
+            .iter()
+            .map(|rule| {
+                serde_json::json!({
+                    "id": rule.id(),
+                    "shortDescription": { "text": rule.description() },
+                    "defaultConfiguration": { "level": level(rule.default_severity()) },
+                })
+            })
+            .collect();
+
+        let results: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                let mut location = serde_json::json!({
+                    "physicalLocation": { "artifactLocation": { "uri": uri } }
+                });
+                if let Some(span) = d.span {
+                    location["physicalLocation"]["region"] = serde_json::json!({
+                        "startLine": span.line,
+                        "startColumn": span.column,
+                    });
+                }
+                serde_json::json!({
+                    "ruleId": d.rule,
+                    "level": level(d.severity),
+                    "message": { "text": d.message },
+                    "locations": [location],
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "version": "2.1.0",
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "sigmos",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "results": results,
+            }]
+        })
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Suppression comments found in a source file; `*` stands for every rule
+#[derive(Debug, Default)]
+struct Suppressions {
+    file: Option<HashSet<String>>,
+    lines: HashMap<usize, HashSet<String>>,
+}
+
+impl Suppressions {
+    fn parse(source: &str) -> Self {
+        let mut suppressions = Self::default();
+        // Sources that do not lex have no spec to lint, so nothing to suppress
+        let comments = SigmosParser::comments(source).unwrap_or_default();
+
+        for (line, comment) in comments {
+            let Some(directive) = comment.trim().strip_prefix(SUPPRESSION_PREFIX) else {
+                continue;
+            };
+            let (kind, rules) = directive
+                .split_once(char::is_whitespace)
+                .unwrap_or((directive, ""));
+            let rules: HashSet<String> = rules
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|rule| !rule.is_empty())
+                .map(str::to_string)
+                .collect();
+
+            let target = match kind {
+                "disable" => suppressions.file.get_or_insert_with(HashSet::new),
+                "disable-line" => suppressions.lines.entry(line).or_default(),
+                "disable-next-line" => suppressions.lines.entry(line + 1).or_default(),
+                _ => continue,
+            };
+            Self::merge(target, rules);
+        }
+
+        suppressions
+    }
+
+    /// Merge `rules` into `target`; an empty list suppresses every rule
+    fn merge(target: &mut HashSet<String>, rules: HashSet<String>) {
+        if rules.is_empty() {
+            target.clear();
+            target.insert("*".to_string());
+        } else if !target.contains("*") {
+            target.extend(rules);
+        }
+    }
+
+    fn suppresses(&self, rule: &str, line: Option<usize>) -> bool {
+        let covers = |rules: &HashSet<String>| rules.contains("*") || rules.contains(rule);
+        self.file.as_ref().is_some_and(covers)
+            || line
+                .and_then(|line| self.lines.get(&line))
+                .is_some_and(covers)
+    }
+}
+
+/// Names read anywhere in the spec, other than by the field's own definition
+fn references(spec: &Spec) -> HashMap<String, HashSet<String>> {
+    // Maps each referenced name to the items that reference it
+    let mut references: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut record = |name: &str, from: String| {
+        references.entry(name.to_string()).or_default().insert(from);
+    };
+
+    for field in &spec.inputs {
+        for modifier in &field.modifiers {
+            if let Modifier::Ref(target) = modifier {
+                record(target, format!("inputs.{}", field.name));
+            }
+        }
+    }
+    for field in &spec.computed {
+        for name in field.referenced_identifiers() {
+            record(name, format!("computed.{}", field.name));
+        }
+    }
+    for (i, constraint) in spec.constraints.iter().enumerate() {
+        for name in constraint.expression.referenced_identifiers() {
+            record(name, format!("constraints[{i}]"));
+        }
+    }
+    let actions = spec
+        .events
+        .iter()
+        .enumerate()
+        .map(|(i, event)| (format!("events[{i}]"), &event.action))
+        .chain(
+            spec.lifecycle
+                .iter()
+                .enumerate()
+                .map(|(i, lifecycle)| (format!("lifecycle[{i}]"), &lifecycle.action)),
+        );
+    for (location, action) in actions {
+        let expressions: Vec<&Expression> = match action {
+            Action::FunctionCall { arguments, .. } => {
+                arguments.iter().map(|argument| &argument.value).collect()
+            }
+            Action::Assign { value, .. } => vec![value],
+            Action::Identifier(_) => vec![],
+        };
+        for expression in expressions {
+            for name in expression.referenced_identifiers() {
+                record(name, location.clone());
+            }
+        }
+    }
+    for (i, event) in spec.events.iter().enumerate() {
+        record(&event.parameter, format!("events[{i}]"));
+    }
+
+    references
+}
+
+/// Whether `name` is read by anything other than `own_path`
+fn is_referenced(
+    references: &HashMap<String, HashSet<String>>,
+    name: &str,
+    own_path: &str,
+) -> bool {
+    references
+        .get(name)
+        .is_some_and(|from| from.iter().any(|path| path != own_path))
+}
+
+/// `unused-input`: inputs nothing reads
+struct UnusedInput;
+
+impl LintRule for UnusedInput {
+    fn id(&self) -> &'static str {
+        "unused-input"
+    }
+
+    fn description(&self) -> &'static str {
+        "Inputs should be read by a computed field, constraint, event or lifecycle action"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, spec: &Spec) -> Vec<Finding> {
+        let references = references(spec);
+        spec.inputs
+            .iter()
+            .filter(|field| {
+                !is_referenced(&references, &field.name, &format!("inputs.{}", field.name))
+            })
+            .map(|field| {
+                Finding::new(
+                    format!("inputs.{}", field.name),
+                    format!("Input '{}' is never used", field.name),
+                )
+            })
+            .collect()
+    }
+}
+
+/// `unused-computed`: computed fields nothing reads
+struct UnusedComputed;
+
+impl LintRule for UnusedComputed {
+    fn id(&self) -> &'static str {
+        "unused-computed"
+    }
+
+    fn description(&self) -> &'static str {
+        "Computed fields should be referenced elsewhere in the spec"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, spec: &Spec) -> Vec<Finding> {
+        let references = references(spec);
+        spec.computed
+            .iter()
+            .filter(|field| {
+                !is_referenced(
+                    &references,
+                    &field.name,
+                    &format!("computed.{}", field.name),
+                )
+            })
+            .map(|field| {
+                Finding::new(
+                    format!("computed.{}", field.name),
+                    format!("Computed field '{}' is never referenced", field.name),
+                )
+            })
+            .collect()
+    }
+}
+
+/// `constant-constraint`: constraints that can never fail
+struct ConstantConstraint;
+
+impl LintRule for ConstantConstraint {
+    fn id(&self) -> &'static str {
+        "constant-constraint"
+    }
+
+    fn description(&self) -> &'static str {
+        "Constraints should depend on the spec's values rather than always hold"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, spec: &Spec) -> Vec<Finding> {
+        spec.constraints
+            .iter()
+            .enumerate()
+            .filter(|(_, constraint)| {
+                constant_value(&constraint.expression) == Some(Constant::Bool(true))
+            })
+            .map(|(i, _)| Finding::new(format!("constraints[{i}]"), "Constraint is always true"))
+            .collect()
+    }
+}
+
+/// `missing-description`: inputs without a `description:` option
+struct MissingDescription;
+
+impl LintRule for MissingDescription {
+    fn id(&self) -> &'static str {
+        "missing-description"
+    }
+
+    fn description(&self) -> &'static str {
+        "Inputs should carry a `description:` option"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, spec: &Spec) -> Vec<Finding> {
+        spec.inputs
+            .iter()
+            .filter(|field| {
+                !field
+                    .modifiers
+                    .iter()
+                    .any(|modifier| matches!(modifier, Modifier::Description(_)))
+            })
+            .map(|field| {
+                Finding::new(
+                    format!("inputs.{}", field.name),
+                    format!("Input '{}' has no description", field.name),
+                )
+            })
+            .collect()
+    }
+}
+
+/// `snake-case`: field and event parameter naming
+struct SnakeCase;
+
+impl LintRule for SnakeCase {
+    fn id(&self) -> &'static str {
+        "snake-case"
+    }
+
+    fn description(&self) -> &'static str {
+        "Inputs, computed fields and event parameters should be snake_case"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, spec: &Spec) -> Vec<Finding> {
+        let inputs = spec
+            .inputs
+            .iter()
+            .map(|field| (format!("inputs.{}", field.name), "Input", &field.name));
+        let computed = spec.computed.iter().map(|field| {
+            (
+                format!("computed.{}", field.name),
+                "Computed field",
+                &field.name,
+            )
+        });
+        let parameters = spec
+            .events
+            .iter()
+            .enumerate()
+            .map(|(i, event)| (format!("events[{i}]"), "Event parameter", &event.parameter));
+
+        inputs
+            .chain(computed)
+            .chain(parameters)
+            .filter(|(_, _, name)| !is_snake_case(name))
+            .map(|(path, kind, name)| {
+                Finding::new(path, format!("{kind} '{name}' should be snake_case"))
+            })
+            .collect()
+    }
+}
+
+/// `secret-name`: credential-looking inputs missing the `secret` option
+struct SecretName;
+
+impl LintRule for SecretName {
+    fn id(&self) -> &'static str {
+        "secret-name"
+    }
+
+    fn description(&self) -> &'static str {
+        "Inputs named like credentials (e.g. `*_key`, `*_token`) should be marked `secret`"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, spec: &Spec) -> Vec<Finding> {
+        spec.inputs
+            .iter()
+            .filter(|field| looks_like_secret(&field.name))
+            .filter(|field| !field.modifiers.contains(&Modifier::Secret))
+            .map(|field| {
+                Finding::new(
+                    format!("inputs.{}", field.name),
+                    format!(
+                        "Input '{}' looks like a credential; mark it `secret: true`",
+                        field.name
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+fn is_snake_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn looks_like_secret(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SECRET_NAMES.contains(&name.as_str())
+        || SECRET_NAME_SUFFIXES
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+        || name.contains("password")
+}
+
+/// Value of an expression that does not depend on any input
+#[derive(Debug, Clone, PartialEq)]
+enum Constant {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Null,
+}
+
+/// Fold `expr` to a constant when it does not depend on spec values
+fn constant_value(expr: &Expression) -> Option<Constant> {
+    let number = |expr: &Expression| match constant_value(expr) {
+        Some(Constant::Number(n)) => Some(n),
+        _ => None,
+    };
+    let boolean = |expr: &Expression| match constant_value(expr) {
+        Some(Constant::Bool(b)) => Some(b),
+        _ => None,
+    };
+
+    match expr {
+        Expression::Boolean(b) => Some(Constant::Bool(*b)),
+        Expression::Number(n) => Some(Constant::Number(*n)),
+        Expression::StringLiteral(s) => Some(Constant::Text(s.clone())),
+        Expression::Null => Some(Constant::Null),
+        Expression::Not(operand) => boolean(operand).map(|b| Constant::Bool(!b)),
+        Expression::And(left, right) => match (boolean(left), boolean(right)) {
+            (Some(false), _) | (_, Some(false)) => Some(Constant::Bool(false)),
+            (Some(true), Some(true)) => Some(Constant::Bool(true)),
+            _ => None,
+        },
+        Expression::Or(left, right) => match (boolean(left), boolean(right)) {
+            (Some(true), _) | (_, Some(true)) => Some(Constant::Bool(true)),
+            (Some(false), Some(false)) => Some(Constant::Bool(false)),
+            _ => None,
+        },
+        // Comparing an expression with itself needs no values
+        Expression::Equal(left, right) if left == right => Some(Constant::Bool(true)),
+        Expression::NotEqual(left, right) if left == right => Some(Constant::Bool(false)),
+        Expression::Equal(left, right) => Some(Constant::Bool(
+            constant_value(left)? == constant_value(right)?,
+        )),
+        Expression::NotEqual(left, right) => Some(Constant::Bool(
+            constant_value(left)? != constant_value(right)?,
+        )),
+        Expression::LessThan(left, right) => Some(Constant::Bool(number(left)? < number(right)?)),
+        Expression::LessThanOrEqual(left, right) => {
+            Some(Constant::Bool(number(left)? <= number(right)?))
+        }
+        Expression::GreaterThan(left, right) => {
+            Some(Constant::Bool(number(left)? > number(right)?))
+        }
+        Expression::GreaterThanOrEqual(left, right) => {
+            Some(Constant::Bool(number(left)? >= number(right)?))
+        }
+        Expression::Add(left, right) => Some(Constant::Number(number(left)? + number(right)?)),
+        Expression::Subtract(left, right) => Some(Constant::Number(number(left)? - number(right)?)),
+        Expression::Multiply(left, right) => Some(Constant::Number(number(left)? * number(right)?)),
+        Expression::Conditional {
+            condition,
+            if_true,
+            if_false,
+        } => {
+            if boolean(condition)? {
+                constant_value(if_true)
+            } else {
+                constant_value(if_false)
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules_hit(source: &str) -> Vec<String> {
+        Linter::new()
+            .lint_source(source)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.rule)
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let source = r#"
+spec "Agent" v1.0 {
+    inputs:
+        api_key: string { description: "Upstream credential" }
+        userName: string { description: "Display name" }
+        unused: int { description: "Never read" }
+    computed:
+        greeting: -> "Hello {{userName}} {{api_key}}"
+    constraints:
+        assert 1 < 2 || greeting == ""
+}
+"#;
+        let diagnostics = Linter::new().lint_source(source).unwrap();
+        let find = |rule: &str| diagnostics.iter().find(|d| d.rule == rule);
+
+        assert_eq!(find("secret-name").unwrap().severity, Severity::Error);
+        assert_eq!(find("snake-case").unwrap().path, "inputs.userName");
+        assert_eq!(find("unused-input").unwrap().path, "inputs.unused");
+        assert_eq!(find("constant-constraint").unwrap().span.unwrap().line, 10);
+        assert!(find("missing-description").is_none());
+        assert!(find("unused-computed").is_none());
+    }
+
+    #[test]
+    fn test_suppressions_and_config() {
+        let source = r#"
+spec "Agent" v1.0 {
+    // sigmos-lint-disable missing-description
+    inputs:
+        // sigmos-lint-disable-next-line unused-input
+        first: int
+        second: int // sigmos-lint-disable-line
+        third: int
+}
+"#;
+        assert_eq!(rules_hit(source), vec!["unused-input"]);
+
+        let config = LintConfig::from_toml("[rules]\nunused-input = \"off\"").unwrap();
+        let linter = Linter::new().with_config(config).unwrap();
+        assert!(linter.lint_source(source).unwrap().is_empty());
+
+        let unknown = LintConfig::from_toml("[rules]\nno-such-rule = \"error\"").unwrap();
+        assert!(Linter::new().with_config(unknown).is_err());
+    }
+
+    #[test]
+    fn test_suppressions_ignore_slashes_in_strings() {
+        let source = r#"
+spec "Agent" v1.0 {
+    description: "Docs at https://example.com"
+    inputs:
+        first: string { default: "a // sigmos-lint-disable-line" }
+        second: string { default: "https://example.com" } // sigmos-lint-disable-line
+}
+"#;
+        let diagnostics = Linter::new().lint_source(source).unwrap();
+        let unused: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.rule == "unused-input")
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(unused, ["inputs.first"]);
+    }
+
+    #[test]
+    fn test_sarif_report() {
+        let linter = Linter::new();
+        let diagnostics = linter
+            .lint_source("spec \"Agent\" v1.0 {\n    inputs:\n        token: string\n}")
+            .unwrap();
+        let sarif = linter.sarif_report("agent.sigmos", &diagnostics);
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        let secret = results
+            .iter()
+            .find(|r| r["ruleId"] == "secret-name")
+            .unwrap();
+        assert_eq!(secret["level"], "error");
+        assert_eq!(
+            secret["locations"][0]["physicalLocation"]["region"]["startLine"],
+            3
+        );
+    }
+}
@@ -22,10 +22,35 @@ use crate::ast::*;
 use crate::ParseError;
 use crate::ParseResult;
 
+/// Most levels an expression may nest: parentheses, operands of an operator
+/// chain, unary operators, property accesses and calls all count
+///
+/// Every pass over the AST recurses once per level, so deeper expressions
+/// are rejected when parsed rather than overflowing the stack later.
+///
+/// # Examples
+///
+/// ```rust
+/// use sigmos_core::parser::{SigmosParser, MAX_NESTING};
+///
+/// let chain = vec!["1"; MAX_NESTING + 1].join(" + ");
+/// let source = format!("spec \"Deep\" v1.0 {{\n computed:\n total: -> {chain}\n}}");
+/// let error = SigmosParser::parse_spec(&source).unwrap_err();
+/// assert!(error.to_string().contains("nests more than"));
+/// ```
+pub const MAX_NESTING: usize = 64;
+
 /// SIGMOS parser with lexical analysis and recursive descent parsing
 pub struct SigmosParser {
     tokens: Vec<Token>,
+    /// Start and end byte offsets of each token
+    offsets: Vec<(usize, usize)>,
+    /// Byte offset at which each line starts
+    line_starts: Vec<usize>,
+    source_map: SourceMap,
     current: usize,
+    /// Nesting level of the expression being parsed
+    depth: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -40,12 +65,14 @@ enum Token {
     Lifecycle,
     Extensions,
     Types,
+    Functions,
 
     // Literals
     StringLiteral(String),
     IntLiteral(i64),
     FloatLiteral(f64),
     Identifier(String),
+    Regex(String),
 
     // Operators and punctuation
     LeftBrace,
@@ -55,7 +82,30 @@ enum Token {
     Colon,
     Comma,
     Arrow,
+    FatArrow,
     Dot,
+    Equal,
+    LeftBracket,
+    RightBracket,
+
+    // Expression operators
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    EqualEqual,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AndAnd,
+    OrOr,
+    Bang,
+    Question,
+    QuestionQuestion,
+    QuestionDot,
 
     // Version
     Version(u32, u32, Option<u32>),
@@ -64,6 +114,12 @@ enum Token {
     Eof,
 }
 
+/// Value of a field option
+enum OptionValue {
+    Expression(Expression),
+    Regex(String),
+}
+
 impl SigmosParser {
     /// Parse a complete SIGMOS specification
     ///
@@ -82,22 +138,109 @@ impl SigmosParser {
     /// assert_eq!(spec.name, "Test");
     /// ```
     pub fn parse_spec(input: &str) -> ParseResult<Spec> {
+        Self::parse_spec_with_source_map(input).map(|(spec, _)| spec)
+    }
+
+    /// Parse a specification and record where each item appears in `input`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::parser::SigmosParser;
+    ///
+    /// let (spec, source_map) = SigmosParser::parse_spec_with_source_map(r#"
+    /// spec "Test" v1.0 {
+    ///     inputs:
+    ///         name: string
+    /// }
+    /// "#).unwrap();
+    /// let span = source_map.get("inputs.name").unwrap();
+    /// assert_eq!((span.line, span.column), (4, 9));
+    /// ```
+    pub fn parse_spec_with_source_map(input: &str) -> ParseResult<(Spec, SourceMap)> {
         let mut parser = Self::new(input)?;
-        parser.parse_specification()
+        // Errors without a more precise location point at the token parsing stopped on
+        let spec = parser
+            .parse_specification()
+            .map_err(|error| error.with_span(Some(parser.token_span(parser.current))))?;
+        Ok((spec, parser.source_map))
+    }
+
+    /// Line comments in `input`, as the line each is on and its text after `//`
+    ///
+    /// Comments are found by the lexer, so a `//` inside a string or regex
+    /// literal does not start one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::parser::SigmosParser;
+    ///
+    /// let comments = SigmosParser::comments(r#"
+    /// spec "Test" v1.0 { // the spec
+    ///     description: "see https://example.com"
+    /// }
+    /// "#).unwrap();
+    /// assert_eq!(comments, [(2, " the spec".to_string())]);
+    /// ```
+    pub fn comments(input: &str) -> ParseResult<Vec<(usize, String)>> {
+        let mut comments = Vec::new();
+        Self::tokenize(input, Some(&mut comments))?;
+        Ok(comments
+            .into_iter()
+            .map(|(offset, text)| (input[..offset].matches('\n').count() + 1, text))
+            .collect())
+    }
+
+    /// Whether `tokens` end with a value followed by the `matches` operator
+    fn ends_with_matches_operator(tokens: &[Token]) -> bool {
+        match tokens {
+            [.., value, Token::Identifier(operator)] if operator == "matches" => matches!(
+                value,
+                Token::Identifier(_)
+                    | Token::StringLiteral(_)
+                    | Token::RightParen
+                    | Token::RightBracket
+            ),
+            _ => false,
+        }
     }
 
     /// Create a new parser instance
     fn new(input: &str) -> ParseResult<Self> {
-        let tokens = Self::tokenize(input)?;
-        Ok(Self { tokens, current: 0 })
+        let (tokens, offsets) = Self::tokenize(input, None)?;
+        let line_starts = std::iter::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Ok(Self {
+            tokens,
+            offsets,
+            line_starts,
+            source_map: SourceMap::default(),
+            current: 0,
+            depth: 0,
+        })
     }
 
-    /// Tokenize the input string
-    fn tokenize(input: &str) -> ParseResult<Vec<Token>> {
+    /// Tokenize the input string into tokens and their byte offsets, adding
+    /// the byte offset and text of each line comment to `comments`
+    #[allow(clippy::type_complexity)]
+    fn tokenize(
+        input: &str,
+        mut comments: Option<&mut Vec<(usize, String)>>,
+    ) -> ParseResult<(Vec<Token>, Vec<(usize, usize)>)> {
         let mut tokens = Vec::new();
+        let mut offsets = Vec::new();
+        let mut token_start = 0;
         let mut chars = input.char_indices().peekable();
 
         while let Some((i, ch)) = chars.next() {
+            // A token pushed by the previous iteration ends where this one starts
+            if offsets.len() < tokens.len() {
+                offsets.push((token_start, i));
+            }
+            token_start = i;
+
             match ch {
                 // Skip whitespace
                 ' ' | '\t' | '\n' | '\r' => continue,
@@ -110,16 +253,130 @@ impl SigmosParser {
                 ':' => tokens.push(Token::Colon),
                 ',' => tokens.push(Token::Comma),
                 '.' => tokens.push(Token::Dot),
+                '[' => tokens.push(Token::LeftBracket),
+                ']' => tokens.push(Token::RightBracket),
+                '+' => tokens.push(Token::Plus),
+                '*' => tokens.push(Token::Star),
+                '%' => tokens.push(Token::Percent),
 
-                // Arrow ->
+                // Arrow -> or minus
                 '-' => {
                     if let Some((_, '>')) = chars.peek() {
                         chars.next();
                         tokens.push(Token::Arrow);
                     } else {
-                        return Err(ParseError::Grammar(format!("Unexpected character: {ch}")));
+                        tokens.push(Token::Minus);
+                    }
+                }
+
+                // Line comments, regex literals or division
+                '/' => {
+                    if let Some((_, '/')) = chars.peek() {
+                        chars.next();
+                        let mut text = String::new();
+                        for (_, c) in chars.by_ref() {
+                            if c == '\n' {
+                                break;
+                            }
+                            text.push(c);
+                        }
+                        if let Some(comments) = comments.as_mut() {
+                            comments.push((i, text.trim_end_matches('\r').to_string()));
+                        }
+                    } else if matches!(
+                        tokens.last(),
+                        Some(Token::Colon | Token::Comma | Token::LeftParen | Token::LeftBracket)
+                    ) || Self::ends_with_matches_operator(&tokens)
+                    {
+                        // A `/` where no value precedes it starts a regex literal
+                        let mut pattern = String::new();
+                        let mut escaped = false;
+                        let mut terminated = false;
+                        for (_, c) in chars.by_ref() {
+                            match c {
+                                '/' if !escaped => {
+                                    terminated = true;
+                                    break;
+                                }
+                                '\n' => break,
+                                _ => {
+                                    escaped = c == '\\' && !escaped;
+                                    pattern.push(c);
+                                }
+                            }
+                        }
+                        if !terminated {
+                            return Err(ParseError::Grammar(
+                                "Unterminated regex literal".to_string(),
+                            ));
+                        }
+                        tokens.push(Token::Regex(pattern));
+                    } else {
+                        tokens.push(Token::Slash);
+                    }
+                }
+
+                // Two-character operators
+                '=' => match chars.peek() {
+                    Some((_, '=')) => {
+                        chars.next();
+                        tokens.push(Token::EqualEqual);
+                    }
+                    Some((_, '>')) => {
+                        chars.next();
+                        tokens.push(Token::FatArrow);
+                    }
+                    _ => tokens.push(Token::Equal),
+                },
+                '!' => {
+                    if let Some((_, '=')) = chars.peek() {
+                        chars.next();
+                        tokens.push(Token::BangEqual);
+                    } else {
+                        tokens.push(Token::Bang);
+                    }
+                }
+                '<' => {
+                    if let Some((_, '=')) = chars.peek() {
+                        chars.next();
+                        tokens.push(Token::LessEqual);
+                    } else {
+                        tokens.push(Token::Less);
+                    }
+                }
+                '>' => {
+                    if let Some((_, '=')) = chars.peek() {
+                        chars.next();
+                        tokens.push(Token::GreaterEqual);
+                    } else {
+                        tokens.push(Token::Greater);
                     }
                 }
+                '&' => match chars.peek() {
+                    Some((_, '&')) => {
+                        chars.next();
+                        tokens.push(Token::AndAnd);
+                    }
+                    _ => return Err(ParseError::Grammar(format!("Unexpected character: {ch}"))),
+                },
+                '|' => match chars.peek() {
+                    Some((_, '|')) => {
+                        chars.next();
+                        tokens.push(Token::OrOr);
+                    }
+                    _ => return Err(ParseError::Grammar(format!("Unexpected character: {ch}"))),
+                },
+                '?' => match chars.peek() {
+                    Some((_, '?')) => {
+                        chars.next();
+                        tokens.push(Token::QuestionQuestion);
+                    }
+                    Some((_, '.')) => {
+                        chars.next();
+                        tokens.push(Token::QuestionDot);
+                    }
+                    _ => tokens.push(Token::Question),
+                },
 
                 // String literals
                 '"' => {
@@ -205,7 +462,10 @@ impl SigmosParser {
                     let identifier = &input[start..end];
 
                     // Check for version pattern (v1.0, v1.2.3)
-                    if identifier.starts_with('v') && identifier.len() > 1 {
+                    if identifier.starts_with('v')
+                        && identifier.len() > 1
+                        && identifier[1..].chars().all(|c| c.is_ascii_digit())
+                    {
                         // Look ahead to see if this is followed by a version pattern
                         let mut version_str = identifier[1..].to_string();
 
@@ -261,6 +521,7 @@ impl SigmosParser {
                         "lifecycle" => Token::Lifecycle,
                         "extensions" => Token::Extensions,
                         "types" => Token::Types,
+                        "functions" => Token::Functions,
                         _ => Token::Identifier(identifier.to_string()),
                     };
 
@@ -271,8 +532,12 @@ impl SigmosParser {
             }
         }
 
+        if offsets.len() < tokens.len() {
+            offsets.push((token_start, input.len()));
+        }
         tokens.push(Token::Eof);
-        Ok(tokens)
+        offsets.push((input.len(), input.len()));
+        Ok((tokens, offsets))
     }
 
     /// Parse version string like "1.0" or "1.2.3"
@@ -303,7 +568,8 @@ impl SigmosParser {
         let name = match self.advance() {
             Token::StringLiteral(s) => s,
             _ => {
-                return Err(ParseError::Grammar(
+                return Err(self.error_at(
+                    self.current - 1,
                     "Expected spec name as string literal".to_string(),
                 ))
             }
@@ -316,7 +582,8 @@ impl SigmosParser {
                 patch,
             },
             _ => {
-                return Err(ParseError::Grammar(
+                return Err(self.error_at(
+                    self.current - 1,
                     "Expected version (e.g., v1.0)".to_string(),
                 ))
             }
@@ -335,6 +602,7 @@ impl SigmosParser {
             lifecycle: Vec::new(),
             extensions: Vec::new(),
             types: Vec::new(),
+            functions: Vec::new(),
         };
 
         // Parse spec body
@@ -346,7 +614,8 @@ impl SigmosParser {
                     if let Token::StringLiteral(desc) = self.advance() {
                         spec.description = Some(desc);
                     } else {
-                        return Err(ParseError::Grammar(
+                        return Err(self.error_at(
+                            self.current - 1,
                             "Expected string literal for description".to_string(),
                         ));
                     }
@@ -361,9 +630,41 @@ impl SigmosParser {
                     self.expect_token(Token::Colon)?;
                     spec.computed = self.parse_computed_fields()?;
                 }
-                _ => {
-                    // Skip unknown sections for now
+                Token::Events => {
+                    self.advance();
+                    self.expect_token(Token::Colon)?;
+                    spec.events = self.parse_events()?;
+                }
+                Token::Lifecycle => {
+                    self.advance();
+                    self.expect_token(Token::Colon)?;
+                    spec.lifecycle = self.parse_lifecycle()?;
+                }
+                Token::Constraints => {
                     self.advance();
+                    self.expect_token(Token::Colon)?;
+                    spec.constraints = self.parse_constraints()?;
+                }
+                Token::Extensions => {
+                    self.advance();
+                    spec.extensions = self.parse_extensions()?;
+                }
+                Token::Functions => {
+                    self.advance();
+                    self.expect_token(Token::Colon)?;
+                    spec.functions = self.parse_functions()?;
+                }
+                Token::Types => {
+                    return Err(self.error_at(
+                        self.current,
+                        "The types section is not supported yet".to_string(),
+                    ))
+                }
+                other => {
+                    return Err(self.error_at(
+                        self.current,
+                        format!("Unexpected {other:?}; expected a section such as inputs: or the closing brace of the spec"),
+                    ))
                 }
             }
         }
@@ -378,42 +679,229 @@ impl SigmosParser {
 
         while let Token::Identifier(name) = self.peek() {
             let field_name = name.clone();
+            let start = self.current;
             self.advance();
             self.expect_token(Token::Colon)?;
 
-            let type_expr = self.parse_type_expr()?;
-            let modifiers = Vec::new(); // TODO: Parse modifiers
+            // `enum("a", "b")` is a string limited to the values listed
+            let (type_expr, mut modifiers) = match self.enum_values()? {
+                Some(values) => (
+                    TypeExpr::Primitive(PrimitiveType::String),
+                    vec![Modifier::OneOf(values)],
+                ),
+                None => (self.parse_type_expr()?, Vec::new()),
+            };
+            if self.check(&Token::LeftBrace) {
+                modifiers.extend(self.parse_field_options(&field_name)?);
+            }
+            self.source_map
+                .insert(format!("inputs.{field_name}"), self.span_from(start));
 
             fields.push(FieldDef {
-                name: field_name,
+                name: field_name.clone(),
                 type_expr,
                 modifiers,
             });
 
-            // Break if we don't see another identifier
-            if !matches!(self.peek(), Token::Identifier(_)) {
-                break;
+            if !matches!(self.peek(), Token::Identifier(_)) && !self.at_section_end() {
+                return Err(self.error_at(
+                    self.current,
+                    format!("Unexpected {:?} after input '{field_name}'", self.peek()),
+                ));
             }
         }
 
         Ok(fields)
     }
 
+    /// Consume `enum("a", "b", ...)` and return its values, if the next type is one
+    fn enum_values(&mut self) -> ParseResult<Option<Vec<serde_json::Value>>> {
+        let is_enum = matches!(self.peek(), Token::Identifier(name) if name == "enum")
+            && matches!(self.tokens.get(self.current + 1), Some(Token::LeftParen));
+        if !is_enum {
+            return Ok(None);
+        }
+        self.current += 2;
+
+        let mut values = Vec::new();
+        while !self.check(&Token::RightParen) && !self.is_at_end() {
+            match self.advance() {
+                Token::StringLiteral(value) => values.push(serde_json::Value::String(value)),
+                other => {
+                    return Err(self.error_at(
+                        self.current - 1,
+                        format!("Expected enum value as string literal, found {other:?}"),
+                    ))
+                }
+            }
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect_token(Token::RightParen)?;
+        if values.is_empty() {
+            return Err(self.error_at(
+                self.current - 1,
+                "enum(...) requires at least one value".to_string(),
+            ));
+        }
+        Ok(Some(values))
+    }
+
+    /// Parse a `{ modifier: value, ... }` block following a field type
+    fn parse_field_options(&mut self, field_name: &str) -> ParseResult<Vec<Modifier>> {
+        self.expect_token(Token::LeftBrace)?;
+        let mut modifiers = Vec::new();
+
+        while !self.check(&Token::RightBrace) && !self.is_at_end() {
+            let option = match self.advance() {
+                Token::Identifier(name) => name,
+                Token::Computed => "computed".to_string(),
+                Token::Description => "description".to_string(),
+                other => {
+                    return Err(self.error_at(
+                        self.current - 1,
+                        format!("Expected field option, found {other:?}"),
+                    ))
+                }
+            };
+
+            let value_start = self.current + 1;
+            let value = if self.check(&Token::Colon) {
+                self.advance();
+                Some(self.parse_option_value()?)
+            } else {
+                None
+            };
+
+            let enabled = !matches!(
+                value,
+                Some(OptionValue::Expression(Expression::Boolean(false)))
+            );
+            match (option.as_str(), value) {
+                ("optional", _) if enabled => modifiers.push(Modifier::Optional),
+                ("readonly", _) if enabled => modifiers.push(Modifier::Readonly),
+                ("computed", _) if enabled => modifiers.push(Modifier::Computed),
+                ("secret", _) if enabled => modifiers.push(Modifier::Secret),
+                ("generate", _) if enabled => modifiers.push(Modifier::Generate),
+                ("optional" | "readonly" | "computed" | "secret" | "generate", _) => {}
+                ("default", Some(OptionValue::Expression(expr))) => {
+                    modifiers.push(Modifier::Default(expr))
+                }
+                ("description", Some(OptionValue::Expression(Expression::StringLiteral(text)))) => {
+                    modifiers.push(Modifier::Description(text))
+                }
+                (
+                    "ref",
+                    Some(OptionValue::Expression(
+                        Expression::StringLiteral(target) | Expression::Identifier(target),
+                    )),
+                ) => {
+                    self.source_map.insert(
+                        format!("inputs.{field_name}.ref"),
+                        self.span_from(value_start),
+                    );
+                    modifiers.push(Modifier::Ref(target));
+                }
+                ("min", Some(OptionValue::Expression(Expression::Number(n)))) => {
+                    modifiers.push(Modifier::Min(n))
+                }
+                ("max", Some(OptionValue::Expression(Expression::Number(n)))) => {
+                    modifiers.push(Modifier::Max(n))
+                }
+                ("min_length", Some(OptionValue::Expression(Expression::Number(n))))
+                    if n >= 0.0 && n.fract() == 0.0 =>
+                {
+                    modifiers.push(Modifier::MinLength(n as usize))
+                }
+                ("max_length", Some(OptionValue::Expression(Expression::Number(n))))
+                    if n >= 0.0 && n.fract() == 0.0 =>
+                {
+                    modifiers.push(Modifier::MaxLength(n as usize))
+                }
+                (
+                    "pattern" | "validate",
+                    Some(
+                        OptionValue::Regex(pattern)
+                        | OptionValue::Expression(Expression::StringLiteral(pattern)),
+                    ),
+                ) => modifiers.push(Modifier::Pattern(pattern)),
+                ("one_of", Some(OptionValue::Expression(Expression::Array(items)))) => {
+                    let values = items
+                        .iter()
+                        .map(Expression::literal_value)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| {
+                            ParseError::Grammar(
+                                "Field option 'one_of' requires a list of literals".to_string(),
+                            )
+                        })?;
+                    modifiers.push(Modifier::OneOf(values));
+                }
+                (
+                    option @ ("default" | "description" | "ref" | "min" | "max" | "min_length"
+                    | "max_length" | "pattern" | "one_of"),
+                    _,
+                ) => {
+                    let expected = match option {
+                        "default" => "a value",
+                        "description" => "a string",
+                        "ref" => "a field name",
+                        "min" | "max" => "a number",
+                        "min_length" | "max_length" => "a non-negative whole number",
+                        "pattern" => "a regex literal such as /^[a-z]+$/",
+                        _ => "a list of literals",
+                    };
+                    return Err(ParseError::Grammar(format!(
+                        "Field option '{option}' requires {expected}"
+                    )));
+                }
+                _ => {
+                    // Unknown options are accepted and ignored for now
+                }
+            }
+
+            if self.check(&Token::Comma) {
+                self.advance();
+            }
+        }
+
+        self.expect_token(Token::RightBrace)?;
+        Ok(modifiers)
+    }
+
+    /// Parse a field option value: an expression or a regex literal
+    fn parse_option_value(&mut self) -> ParseResult<OptionValue> {
+        match self.peek().clone() {
+            Token::Regex(pattern) => {
+                self.advance();
+                Ok(OptionValue::Regex(pattern))
+            }
+            _ => Ok(OptionValue::Expression(self.parse_expression()?)),
+        }
+    }
+
     /// Parse computed field definitions
     fn parse_computed_fields(&mut self) -> ParseResult<Vec<ComputedField>> {
         let mut fields = Vec::new();
 
         while let Token::Identifier(name) = self.peek() {
             let field_name = name.clone();
+            let start = self.current;
             self.advance();
             self.expect_token(Token::Colon)?;
             self.expect_token(Token::Arrow)?;
 
             let expression = self.parse_expression()?;
+            let policy = self.parse_policy()?;
+            self.source_map
+                .insert(format!("computed.{field_name}"), self.span_from(start));
 
             fields.push(ComputedField {
                 name: field_name,
                 expression,
+                policy,
             });
 
             // Break if we don't see another identifier
@@ -425,28 +913,856 @@ impl SigmosParser {
         Ok(fields)
     }
 
+    /// Parse `event_name(parameter): action` definitions
+    fn parse_events(&mut self) -> ParseResult<Vec<EventDef>> {
+        let mut events = Vec::new();
+
+        // Stop at the next section, which is not followed by `(`
+        while let Token::Identifier(name) = self.peek() {
+            if !matches!(self.tokens.get(self.current + 1), Some(Token::LeftParen)) {
+                break;
+            }
+            let event_type = match name.as_str() {
+                "on_create" => EventType::OnCreate,
+                "on_change" => EventType::OnChange,
+                "on_error" => EventType::OnError,
+                other => EventType::Custom(other.to_string()),
+            };
+            let start = self.current;
+            self.advance();
+            self.expect_token(Token::LeftParen)?;
+            let parameter = self.expect_identifier()?;
+            self.expect_token(Token::RightParen)?;
+            self.expect_token(Token::Colon)?;
+            let action = self.parse_action()?;
+            let policy = self.parse_policy()?;
+
+            self.source_map
+                .insert(format!("events[{}]", events.len()), self.span_from(start));
+            events.push(EventDef {
+                event_type,
+                parameter,
+                action,
+                policy,
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// Parse `name(parameter: type, ...) -> type = expr` definitions
+    fn parse_functions(&mut self) -> ParseResult<Vec<FunctionDef>> {
+        let mut functions = Vec::new();
+
+        while let Token::Identifier(name) = self.peek() {
+            let name = name.clone();
+            let start = self.current;
+            self.advance();
+
+            self.expect_token(Token::LeftParen)?;
+            let mut parameters = Vec::new();
+            while !self.check(&Token::RightParen) && !self.is_at_end() {
+                let parameter = self.expect_identifier()?;
+                self.expect_token(Token::Colon)?;
+                parameters.push(FunctionParameter {
+                    name: parameter,
+                    type_expr: self.parse_type_expr()?,
+                });
+                if !self.check(&Token::RightParen) {
+                    self.expect_token(Token::Comma)?;
+                }
+            }
+            self.expect_token(Token::RightParen)?;
+            self.expect_token(Token::Arrow)?;
+            let return_type = self.parse_type_expr()?;
+            self.expect_token(Token::Equal)?;
+            let body = self.parse_expression()?;
+
+            self.source_map
+                .insert(format!("functions.{name}"), self.span_from(start));
+            functions.push(FunctionDef {
+                name,
+                parameters,
+                return_type,
+                body,
+            });
+        }
+
+        Ok(functions)
+    }
+
+    /// Parse `assert expr` and `ensure expr` definitions
+    fn parse_constraints(&mut self) -> ParseResult<Vec<ConstraintDef>> {
+        let mut constraints = Vec::new();
+
+        while let Token::Identifier(name) = self.peek() {
+            let constraint_type = match name.as_str() {
+                "assert" => ConstraintType::Assert,
+                "ensure" => ConstraintType::Ensure,
+                _ => break,
+            };
+            let start = self.current;
+            self.advance();
+            let expression = self.parse_expression()?;
+
+            let next_constraint = matches!(self.peek(), Token::Identifier(name) if name == "assert" || name == "ensure");
+            if !next_constraint && !self.at_section_end() {
+                return Err(self.error_at(
+                    self.current,
+                    format!("Unexpected {:?} after constraint expression", self.peek()),
+                ));
+            }
+
+            self.source_map.insert(
+                format!("constraints[{}]", constraints.len()),
+                self.span_from(start),
+            );
+            constraints.push(ConstraintDef {
+                constraint_type,
+                expression,
+            });
+        }
+
+        Ok(constraints)
+    }
+
+    /// Parse an `extensions { alias: import("module@version") }` block
+    fn parse_extensions(&mut self) -> ParseResult<Vec<ExtensionDef>> {
+        self.expect_token(Token::LeftBrace)?;
+        let mut extensions = Vec::new();
+
+        while let Token::Identifier(name) = self.peek() {
+            let name = name.clone();
+            let start = self.current;
+            self.advance();
+            self.expect_token(Token::Colon)?;
+
+            match self.advance() {
+                Token::Identifier(keyword) if keyword == "import" => {}
+                other => {
+                    return Err(self.error_at(
+                        self.current - 1,
+                        format!("Expected import(\"...\") for extension '{name}', found {other:?}"),
+                    ))
+                }
+            }
+            self.expect_token(Token::LeftParen)?;
+            let import_spec = match self.advance() {
+                Token::StringLiteral(import_spec) => import_spec,
+                other => {
+                    return Err(self.error_at(
+                        self.current - 1,
+                        format!("Expected module path as string literal for extension '{name}', found {other:?}"),
+                    ))
+                }
+            };
+            self.expect_token(Token::RightParen)?;
+
+            self.source_map
+                .insert(format!("extensions.{name}"), self.span_from(start));
+            extensions.push(ExtensionDef { name, import_spec });
+        }
+
+        self.expect_token(Token::RightBrace)?;
+        Ok(extensions)
+    }
+
+    /// Parse `before|after|finally: action` definitions
+    fn parse_lifecycle(&mut self) -> ParseResult<Vec<LifecycleDef>> {
+        let mut lifecycle = Vec::new();
+
+        while let Token::Identifier(name) = self.peek() {
+            let phase = match name.as_str() {
+                "before" => LifecyclePhase::Before,
+                "after" => LifecyclePhase::After,
+                "finally" => LifecyclePhase::Finally,
+                _ => break,
+            };
+            let start = self.current;
+            self.advance();
+            self.expect_token(Token::Colon)?;
+            let action = self.parse_action()?;
+            let policy = self.parse_policy()?;
+
+            self.source_map.insert(
+                format!("lifecycle[{}]", lifecycle.len()),
+                self.span_from(start),
+            );
+            lifecycle.push(LifecycleDef {
+                phase,
+                action,
+                policy,
+            });
+        }
+
+        Ok(lifecycle)
+    }
+
+    /// Parse an action: `name`, `name = expr`, `name(args)` or `object.method(args)`
+    fn parse_action(&mut self) -> ParseResult<Action> {
+        let name = self.expect_identifier()?;
+
+        match self.peek() {
+            Token::Equal => {
+                self.advance();
+                Ok(Action::Assign {
+                    target: name,
+                    value: self.parse_expression()?,
+                })
+            }
+            Token::Dot | Token::LeftParen => {
+                let mut path = vec![name];
+                while self.check(&Token::Dot) {
+                    self.advance();
+                    path.push(self.expect_identifier()?);
+                }
+                let method = path.pop().unwrap_or_default();
+                Ok(Action::FunctionCall {
+                    object: path.join("."),
+                    method,
+                    arguments: self.parse_call_arguments()?,
+                })
+            }
+            _ => Ok(Action::Identifier(name)),
+        }
+    }
+
+    /// Parse the `{ retry: n, backoff: ..., timeout: ..., fallback: expr }`
+    /// block that may follow a computed expression or an action
+    fn parse_policy(&mut self) -> ParseResult<Option<CallPolicy>> {
+        if !self.check(&Token::LeftBrace) {
+            return Ok(None);
+        }
+        self.advance();
+        let mut policy = CallPolicy::default();
+
+        while !self.check(&Token::RightBrace) && !self.is_at_end() {
+            let option = self.expect_identifier()?;
+            self.expect_token(Token::Colon)?;
+            match option.as_str() {
+                "retry" => match self.advance() {
+                    Token::IntLiteral(n) if (0..=i64::from(u32::MAX)).contains(&n) => {
+                        policy.retry = n as u32
+                    }
+                    _ => {
+                        return Err(self.error_at(
+                            self.current - 1,
+                            "Policy option 'retry' requires a non-negative whole number"
+                                .to_string(),
+                        ))
+                    }
+                },
+                "backoff" => {
+                    let kind = self.expect_identifier()?;
+                    self.expect_token(Token::LeftParen)?;
+                    let delay = self.parse_duration("backoff")?;
+                    self.expect_token(Token::RightParen)?;
+                    policy.backoff = Some(match kind.as_str() {
+                        "fixed" => Backoff::Fixed(delay),
+                        "exponential" => Backoff::Exponential(delay),
+                        _ => {
+                            return Err(ParseError::Grammar(format!(
+                                "Unknown backoff '{kind}'; expected fixed(...) or exponential(...)"
+                            )))
+                        }
+                    });
+                }
+                "timeout" => policy.timeout = Some(self.parse_duration("timeout")?),
+                "fallback" => policy.fallback = Some(self.parse_expression()?),
+                _ => {
+                    return Err(ParseError::Grammar(format!(
+                        "Unknown policy option '{option}'; expected retry, backoff, timeout or fallback"
+                    )))
+                }
+            }
+
+            if self.check(&Token::Comma) {
+                self.advance();
+            }
+        }
+
+        self.expect_token(Token::RightBrace)?;
+        Ok(Some(policy))
+    }
+
+    /// Parse a duration such as `500ms`, `5s`, `1.5s` or `2m` for `option`
+    fn parse_duration(&mut self, option: &str) -> ParseResult<std::time::Duration> {
+        let amount = match self.advance() {
+            Token::IntLiteral(n) => n as f64,
+            Token::FloatLiteral(f) => f,
+            _ => f64::NAN,
+        };
+        let seconds = match self.advance() {
+            Token::Identifier(unit) if unit == "ms" => amount / 1000.0,
+            Token::Identifier(unit) if unit == "s" => amount,
+            Token::Identifier(unit) if unit == "m" => amount * 60.0,
+            _ => f64::NAN,
+        };
+        std::time::Duration::try_from_secs_f64(seconds).map_err(|_| {
+            ParseError::Grammar(format!(
+                "Policy option '{option}' requires a duration such as 500ms, 5s or 1m"
+            ))
+        })
+    }
+
     /// Parse type expressions
+    ///
+    /// `array<T>` and `list<T>` become `Array<T>`, and `map<K, V>` becomes
+    /// `Map<K, V>`, the names the type checker and runtime work with.
     fn parse_type_expr(&mut self) -> ParseResult<TypeExpr> {
-        match self.advance() {
-            Token::Identifier(type_name) => match type_name.as_str() {
-                "string" => Ok(TypeExpr::Primitive(PrimitiveType::String)),
-                "int" => Ok(TypeExpr::Primitive(PrimitiveType::Int)),
-                "float" => Ok(TypeExpr::Primitive(PrimitiveType::Float)),
-                "bool" => Ok(TypeExpr::Primitive(PrimitiveType::Bool)),
-                _ => Ok(TypeExpr::Reference(type_name)),
-            },
-            _ => Err(ParseError::Grammar("Expected type name".to_string())),
+        let type_name = match self.advance() {
+            Token::Identifier(type_name) => type_name,
+            _ => return Err(self.error_at(self.current - 1, "Expected type name".to_string())),
+        };
+        if type_name == "enum" && self.check(&Token::LeftParen) {
+            return Err(self.error_at(
+                self.current - 1,
+                "enum(...) types are only supported for inputs".to_string(),
+            ));
+        }
+
+        if self.check(&Token::Less) {
+            self.advance();
+            let mut args = vec![self.parse_type_expr()?];
+            while self.check(&Token::Comma) {
+                self.advance();
+                args.push(self.parse_type_expr()?);
+            }
+            self.expect_token(Token::Greater)?;
+
+            let (name, arity) = match type_name.as_str() {
+                "array" | "list" | "Array" => ("Array", 1),
+                "map" | "Map" => ("Map", 2),
+                _ => {
+                    return Err(ParseError::Grammar(format!(
+                        "Type '{type_name}' does not take type arguments"
+                    )))
+                }
+            };
+            if args.len() != arity {
+                return Err(ParseError::Grammar(format!(
+                    "Type '{type_name}' takes {arity} type argument(s), found {}",
+                    args.len()
+                )));
+            }
+            return Ok(TypeExpr::Generic {
+                name: name.to_string(),
+                args,
+            });
+        }
+
+        match type_name.as_str() {
+            "string" => Ok(TypeExpr::Primitive(PrimitiveType::String)),
+            "int" => Ok(TypeExpr::Primitive(PrimitiveType::Int)),
+            "float" => Ok(TypeExpr::Primitive(PrimitiveType::Float)),
+            "bool" => Ok(TypeExpr::Primitive(PrimitiveType::Bool)),
+            "null" => Ok(TypeExpr::Primitive(PrimitiveType::Null)),
+            _ => Ok(TypeExpr::Reference(type_name)),
         }
     }
 
     /// Parse expressions
+    ///
+    /// Precedence, lowest first: lambdas (`x => ...`), `?:`, `??`, `||`, `&&`,
+    /// equality, comparison, additive, multiplicative, unary, postfix (`.`,
+    /// `?.`, `[]`, calls).
     fn parse_expression(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        self.nest()?;
+        let expr = self.parse_nested_expression()?;
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    /// Parse an expression one level deeper than the one containing it
+    fn parse_nested_expression(&mut self) -> ParseResult<Expression> {
+        if let Some(parameters) = self.lambda_parameters() {
+            return Ok(Expression::Lambda {
+                parameters,
+                body: Box::new(self.parse_expression()?),
+            });
+        }
+
+        let condition = self.parse_coalesce()?;
+
+        if self.check(&Token::Question) {
+            self.advance();
+            let if_true = self.parse_expression()?;
+            self.expect_token(Token::Colon)?;
+            let if_false = self.parse_expression()?;
+            return Ok(Expression::Conditional {
+                condition: Box::new(condition),
+                if_true: Box::new(if_true),
+                if_false: Box::new(if_false),
+            });
+        }
+
+        Ok(condition)
+    }
+
+    /// Consume the `x =>` or `(a, b) =>` that starts a lambda, if there is one
+    fn lambda_parameters(&mut self) -> Option<Vec<String>> {
+        let mut parameters = Vec::new();
+        let mut next = self.current;
+        match self.tokens.get(next) {
+            Some(Token::Identifier(name)) => {
+                parameters.push(name.clone());
+                next += 1;
+            }
+            Some(Token::LeftParen) => {
+                next += 1;
+                while let Some(Token::Identifier(name)) = self.tokens.get(next) {
+                    parameters.push(name.clone());
+                    next += 1;
+                    match self.tokens.get(next) {
+                        Some(Token::Comma) => next += 1,
+                        _ => break,
+                    }
+                }
+                if !matches!(self.tokens.get(next), Some(Token::RightParen)) {
+                    return None;
+                }
+                next += 1;
+            }
+            _ => return None,
+        }
+        if !matches!(self.tokens.get(next), Some(Token::FatArrow)) {
+            return None;
+        }
+        self.current = next + 1;
+        Some(parameters)
+    }
+
+    /// Enter one more level of nesting, failing past [`MAX_NESTING`]
+    fn nest(&mut self) -> ParseResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(ParseError::Grammar(format!(
+                "Expression nests more than {MAX_NESTING} levels"
+            )));
+        }
+        Ok(())
+    }
+
+    fn parse_coalesce(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_or()?;
+        while self.check(&Token::QuestionQuestion) {
+            self.nest()?;
+            self.advance();
+            let right = self.parse_or()?;
+            expr = Expression::Coalesce(Box::new(expr), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_and()?;
+        while self.check(&Token::OrOr) {
+            self.nest()?;
+            self.advance();
+            let right = self.parse_and()?;
+            expr = Expression::Or(Box::new(expr), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_equality()?;
+        while self.check(&Token::AndAnd) {
+            self.nest()?;
+            self.advance();
+            let right = self.parse_equality()?;
+            expr = Expression::And(Box::new(expr), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_equality(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_comparison()?;
+        loop {
+            let op: fn(Box<Expression>, Box<Expression>) -> Expression = match self.peek() {
+                Token::EqualEqual => Expression::Equal,
+                Token::BangEqual => Expression::NotEqual,
+                _ => break,
+            };
+            self.nest()?;
+            self.advance();
+            let right = self.parse_comparison()?;
+            expr = op(Box::new(expr), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_comparison(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_additive()?;
+        loop {
+            let op: fn(Box<Expression>, Box<Expression>) -> Expression = match self.peek() {
+                Token::Less => Expression::LessThan,
+                Token::LessEqual => Expression::LessThanOrEqual,
+                Token::Greater => Expression::GreaterThan,
+                Token::GreaterEqual => Expression::GreaterThanOrEqual,
+                // `item in collection` is a call to the `contains` builtin
+                Token::Identifier(operator) if operator == "in" => {
+                    self.nest()?;
+                    self.advance();
+                    let collection = self.parse_additive()?;
+                    expr = Expression::FunctionCall {
+                        object: String::new(),
+                        method: "contains".to_string(),
+                        arguments: vec![
+                            Argument {
+                                name: String::new(),
+                                value: collection,
+                            },
+                            Argument {
+                                name: String::new(),
+                                value: expr,
+                            },
+                        ],
+                    };
+                    continue;
+                }
+                // `text matches /pattern/` is a call to the `matches` builtin
+                Token::Identifier(operator) if operator == "matches" => {
+                    let Some(Token::Regex(pattern)) = self.tokens.get(self.current + 1).cloned()
+                    else {
+                        break;
+                    };
+                    self.nest()?;
+                    self.current += 2;
+                    expr = Expression::FunctionCall {
+                        object: String::new(),
+                        method: "matches".to_string(),
+                        arguments: vec![
+                            Argument {
+                                name: String::new(),
+                                value: expr,
+                            },
+                            Argument {
+                                name: String::new(),
+                                value: Expression::StringLiteral(pattern),
+                            },
+                        ],
+                    };
+                    continue;
+                }
+                _ => break,
+            };
+            self.nest()?;
+            self.advance();
+            let right = self.parse_additive()?;
+            expr = op(Box::new(expr), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_additive(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_multiplicative()?;
+        loop {
+            let op: fn(Box<Expression>, Box<Expression>) -> Expression = match self.peek() {
+                Token::Plus => Expression::Add,
+                Token::Minus => Expression::Subtract,
+                _ => break,
+            };
+            self.nest()?;
+            self.advance();
+            let right = self.parse_multiplicative()?;
+            expr = op(Box::new(expr), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_multiplicative(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op: fn(Box<Expression>, Box<Expression>) -> Expression = match self.peek() {
+                Token::Star => Expression::Multiply,
+                Token::Slash => Expression::Divide,
+                Token::Percent => Expression::Modulo,
+                _ => break,
+            };
+            self.nest()?;
+            self.advance();
+            let right = self.parse_unary()?;
+            expr = op(Box::new(expr), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let expr = match self.peek() {
+            Token::Bang => {
+                self.nest()?;
+                self.advance();
+                Expression::Not(Box::new(self.parse_unary()?))
+            }
+            Token::Minus => {
+                self.nest()?;
+                self.advance();
+                match self.parse_unary()? {
+                    Expression::Number(n) => Expression::Number(-n),
+                    operand => {
+                        Expression::Subtract(Box::new(Expression::Number(0.0)), Box::new(operand))
+                    }
+                }
+            }
+            _ => self.parse_postfix()?,
+        };
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_postfix(&mut self) -> ParseResult<Expression> {
+        let depth = self.depth;
+        let mut expr = self.parse_primary()?;
+        loop {
+            if matches!(
+                self.peek(),
+                Token::Dot | Token::QuestionDot | Token::LeftBracket | Token::LeftParen
+            ) {
+                self.nest()?;
+            }
+            match self.peek() {
+                Token::Dot => {
+                    self.advance();
+                    let property = self.expect_identifier()?;
+                    if self.check(&Token::LeftParen) {
+                        let object = Self::call_target(&expr)?;
+                        let arguments = self.parse_call_arguments()?;
+                        expr = Expression::FunctionCall {
+                            object,
+                            method: property,
+                            arguments,
+                        };
+                    } else {
+                        expr = Expression::PropertyAccess(Box::new(expr), property);
+                    }
+                }
+                Token::QuestionDot => {
+                    self.advance();
+                    let property = self.expect_identifier()?;
+                    expr = Expression::OptionalPropertyAccess(Box::new(expr), property);
+                }
+                Token::LeftBracket => {
+                    self.advance();
+                    let index = self.parse_expression()?;
+                    self.expect_token(Token::RightBracket)?;
+                    expr = Expression::ArrayAccess(Box::new(expr), Box::new(index));
+                }
+                Token::LeftParen => {
+                    let method = match &expr {
+                        Expression::Identifier(name) => name.clone(),
+                        _ => break,
+                    };
+                    let arguments = self.parse_call_arguments()?;
+                    expr = Expression::FunctionCall {
+                        object: String::new(),
+                        method,
+                        arguments,
+                    };
+                }
+                _ => break,
+            }
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expression> {
         match self.advance() {
-            Token::StringLiteral(s) => Ok(Expression::StringLiteral(s)),
+            Token::StringLiteral(s) => Ok(Self::string_expression(s)),
             Token::IntLiteral(i) => Ok(Expression::Number(i as f64)),
             Token::FloatLiteral(f) => Ok(Expression::Number(f)),
-            Token::Identifier(id) => Ok(Expression::Identifier(id)),
-            _ => Err(ParseError::Grammar("Expected expression".to_string())),
+            Token::Identifier(id) => Ok(match id.as_str() {
+                "true" => Expression::Boolean(true),
+                "false" => Expression::Boolean(false),
+                "null" => Expression::Null,
+                _ => Expression::Identifier(id),
+            }),
+            Token::LeftParen => {
+                let expr = self.parse_expression()?;
+                self.expect_token(Token::RightParen)?;
+                Ok(expr)
+            }
+            Token::LeftBracket => {
+                let mut items = Vec::new();
+                while !self.check(&Token::RightBracket) && !self.is_at_end() {
+                    items.push(self.parse_expression()?);
+                    if !self.check(&Token::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect_token(Token::RightBracket)?;
+                Ok(Expression::Array(items))
+            }
+            Token::LeftBrace => {
+                let mut entries = Vec::new();
+                while !self.check(&Token::RightBrace) && !self.is_at_end() {
+                    let key = match self.advance() {
+                        Token::Identifier(key) | Token::StringLiteral(key) => key,
+                        other => {
+                            return Err(self.error_at(
+                                self.current - 1,
+                                format!("Expected object key, found {other:?}"),
+                            ))
+                        }
+                    };
+                    self.expect_token(Token::Colon)?;
+                    entries.push((key, self.parse_expression()?));
+                    if !self.check(&Token::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect_token(Token::RightBrace)?;
+                Ok(Expression::Object(entries))
+            }
+            other => Err(self.error_at(
+                self.current - 1,
+                format!("Expected expression, found {other:?}"),
+            )),
+        }
+    }
+
+    /// Turn a string literal into a template when it contains `{{variable}}` parts
+    fn string_expression(literal: String) -> Expression {
+        if !literal.contains("{{") {
+            return Expression::StringLiteral(literal);
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = literal.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_string()));
+            }
+            let variable = rest[start + 2..start + end].trim();
+            parts.push(TemplatePart::Variable(variable.to_string()));
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_string()));
+        }
+
+        Expression::StringTemplate { parts }
+    }
+
+    /// Parse `(arg, name: arg, ...)`; positional arguments get an empty name
+    fn parse_call_arguments(&mut self) -> ParseResult<Vec<Argument>> {
+        self.expect_token(Token::LeftParen)?;
+        let mut arguments = Vec::new();
+
+        while !self.check(&Token::RightParen) && !self.is_at_end() {
+            let named = matches!(self.peek(), Token::Identifier(_))
+                && matches!(self.tokens.get(self.current + 1), Some(Token::Colon));
+            let name = if named {
+                let name = self.expect_identifier()?;
+                self.advance();
+                name
+            } else {
+                String::new()
+            };
+
+            let value = self.parse_expression()?;
+            arguments.push(Argument { name, value });
+
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+
+        self.expect_token(Token::RightParen)?;
+        Ok(arguments)
+    }
+
+    /// Dotted object path for a method call receiver such as `mcp` or `plugins.rest`
+    fn call_target(expr: &Expression) -> ParseResult<String> {
+        match expr {
+            Expression::Identifier(name) => Ok(name.clone()),
+            Expression::PropertyAccess(object, property) => {
+                Ok(format!("{}.{property}", Self::call_target(object)?))
+            }
+            _ => Err(ParseError::Grammar(
+                "Method calls require a named object".to_string(),
+            )),
+        }
+    }
+
+    fn expect_identifier(&mut self) -> ParseResult<String> {
+        match self.advance() {
+            Token::Identifier(name) => Ok(name),
+            other => Err(self.error_at(
+                self.current - 1,
+                format!("Expected identifier, found {other:?}"),
+            )),
+        }
+    }
+
+    /// Grammar error located at the token at index `token`
+    fn error_at(&self, token: usize, message: String) -> ParseError {
+        ParseError::Grammar(message).with_span(Some(self.token_span(token)))
+    }
+
+    /// Whether the next token ends a section: another section or the end of the spec
+    fn at_section_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::Description
+                | Token::Inputs
+                | Token::Computed
+                | Token::Events
+                | Token::Constraints
+                | Token::Lifecycle
+                | Token::Extensions
+                | Token::Types
+                | Token::Functions
+                | Token::RightBrace
+                | Token::Eof
+        )
+    }
+
+    /// Span from the token at index `start` through the last consumed token
+    fn span_from(&self, start: usize) -> Span {
+        let end = self.offsets[self.current.saturating_sub(1).max(start)].1;
+        self.span(self.offsets[start].0, end)
+    }
+
+    /// Span of the token at index `token`
+    fn token_span(&self, token: usize) -> Span {
+        let (offset, end) = self.offsets[token];
+        self.span(offset, end)
+    }
+
+    /// Span of the bytes from `offset` to `end`
+    fn span(&self, offset: usize, end: usize) -> Span {
+        let line = self
+            .line_starts
+            .partition_point(|&line_start| line_start <= offset);
+        Span {
+            offset,
+            length: end.saturating_sub(offset),
+            line,
+            column: offset - self.line_starts[line - 1] + 1,
         }
     }
 
@@ -495,6 +1811,7 @@ impl SigmosParser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn test_parse_simple_spec() {
@@ -553,4 +1870,518 @@ mod tests {
         assert_eq!(spec.computed[0].name, "greeting");
         assert_eq!(spec.computed[1].name, "count");
     }
+
+    #[test]
+    fn test_parse_field_options_and_null_operators() {
+        let input = r#"
+        spec "Pricing" v1.0 {
+            inputs:
+                base: float { default: 10 }
+                discount: float { optional: true }
+                customer: string { optional, readonly: true }
+            computed:
+                total: -> base - (discount ?? 0) * 2
+                name: -> customer?.name
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        assert_eq!(
+            spec.inputs[0].modifiers,
+            vec![Modifier::Default(Expression::Number(10.0))]
+        );
+        assert_eq!(spec.inputs[1].modifiers, vec![Modifier::Optional]);
+        assert_eq!(
+            spec.inputs[2].modifiers,
+            vec![Modifier::Optional, Modifier::Readonly]
+        );
+
+        let expected_total = Expression::Subtract(
+            Box::new(Expression::Identifier("base".to_string())),
+            Box::new(Expression::Multiply(
+                Box::new(Expression::Coalesce(
+                    Box::new(Expression::Identifier("discount".to_string())),
+                    Box::new(Expression::Number(0.0)),
+                )),
+                Box::new(Expression::Number(2.0)),
+            )),
+        );
+        assert_eq!(spec.computed[0].expression, expected_total);
+        assert_eq!(
+            spec.computed[1].expression,
+            Expression::OptionalPropertyAccess(
+                Box::new(Expression::Identifier("customer".to_string())),
+                "name".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_string_templates() {
+        let input = r#"
+        spec "Greeter" v1.0 {
+            computed:
+                greeting: -> "Hello, {{ name }}!"
+                plain: -> "No variables here"
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        assert_eq!(
+            spec.computed[0].expression,
+            Expression::StringTemplate {
+                parts: vec![
+                    TemplatePart::Text("Hello, ".to_string()),
+                    TemplatePart::Variable("name".to_string()),
+                    TemplatePart::Text("!".to_string()),
+                ]
+            }
+        );
+        assert_eq!(
+            spec.computed[1].expression,
+            Expression::StringLiteral("No variables here".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_events_lifecycle_and_spans() {
+        let input = r#"spec "Agent" v1.0 {
+    inputs:
+        owner: string { readonly: true, ref: "team" }
+    events:
+        on_change(owner): notify.send(to: owner)
+        on_error(failure): owner = "ops"
+    lifecycle:
+        before: validate
+        finally: cleanup()
+}"#;
+
+        let (spec, source_map) = SigmosParser::parse_spec_with_source_map(input).unwrap();
+        assert_eq!(spec.events.len(), 2);
+        assert_eq!(spec.events[0].event_type, EventType::OnChange);
+        assert!(matches!(
+            &spec.events[0].action,
+            Action::FunctionCall { object, method, .. } if object == "notify" && method == "send"
+        ));
+        assert_eq!(
+            spec.events[1].action,
+            Action::Assign {
+                target: "owner".to_string(),
+                value: Expression::StringLiteral("ops".to_string()),
+            }
+        );
+        assert_eq!(spec.lifecycle.len(), 2);
+        assert_eq!(
+            spec.lifecycle[0].action,
+            Action::Identifier("validate".to_string())
+        );
+        assert_eq!(spec.lifecycle[1].phase, LifecyclePhase::Finally);
+
+        let field = source_map.get("inputs.owner").unwrap();
+        assert_eq!((field.line, field.column), (3, 9));
+        let reference = source_map.get("inputs.owner.ref").unwrap();
+        assert_eq!(
+            &input[reference.offset..reference.offset + reference.length],
+            "\"team\""
+        );
+        let event = source_map.get("events[1]").unwrap();
+        assert_eq!(
+            &input[event.offset..event.offset + event.length],
+            "on_error(failure): owner = \"ops\""
+        );
+    }
+
+    #[test]
+    fn test_parse_extensions() {
+        let input = r#"spec "Pipeline" v1.0 {
+    extensions {
+        ai: import("sigmos.std.net.mcp@1.0")
+        http: import("sigmos.std.net.rest")
+    }
+    inputs:
+        url: string
+}"#;
+
+        let (spec, source_map) = SigmosParser::parse_spec_with_source_map(input).unwrap();
+        assert_eq!(spec.extensions.len(), 2);
+        assert_eq!(spec.extensions[0].name, "ai");
+        assert_eq!(spec.extensions[0].plugin_name(), "mcp");
+        assert_eq!(spec.extensions[1].version_requirement(), None);
+        assert_eq!(spec.inputs.len(), 1);
+        assert_eq!(
+            source_map.get("extensions.http").map(|span| span.line),
+            Some(4)
+        );
+
+        assert!(SigmosParser::parse_spec(
+            "spec \"Bad\" v1.0 {\n    extensions {\n        ai: load(\"mcp\")\n    }\n}"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_refinement_options() {
+        let input = r#"
+        spec "Trading" v1.0 {
+            inputs:
+                trader_id: string { validate: /^[A-Z]{2}[0-9]{6}$/ }
+                quantity: int { min: 1, max: 10000 }
+                symbol: string { min_length: 1, max_length: 5, pattern: "^[A-Z]+$" }
+                side: string { one_of: ["buy", "sell"] }
+            computed:
+                half: -> quantity / 2
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        assert_eq!(
+            spec.inputs[0].modifiers,
+            vec![Modifier::Pattern("^[A-Z]{2}[0-9]{6}$".to_string())]
+        );
+        assert_eq!(
+            spec.inputs[1].modifiers,
+            vec![Modifier::Min(1.0), Modifier::Max(10000.0)]
+        );
+        assert_eq!(
+            spec.inputs[2].modifiers,
+            vec![
+                Modifier::MinLength(1),
+                Modifier::MaxLength(5),
+                Modifier::Pattern("^[A-Z]+$".to_string()),
+            ]
+        );
+        assert_eq!(
+            spec.inputs[3].modifiers,
+            vec![Modifier::OneOf(vec![
+                serde_json::json!("buy"),
+                serde_json::json!("sell")
+            ])]
+        );
+        // Division after a value is still division
+        assert!(matches!(
+            spec.computed[0].expression,
+            Expression::Divide(_, _)
+        ));
+
+        let bad = r#"spec "T" v1.0 { inputs: n: int { min_length: -1 } }"#;
+        assert!(SigmosParser::parse_spec(bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_matches_operator() {
+        let input = r#"
+        spec "Trading" v1.0 {
+            computed:
+                ratio: -> matches / 2
+            constraints:
+                ensure trader_id matches /^[A-Z]{2}[0-9]{6}$/ && ok
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        // `matches` after no value is a name
+        assert!(matches!(
+            spec.computed[0].expression,
+            Expression::Divide(_, _)
+        ));
+        let Expression::And(matched, _) = &spec.constraints[0].expression else {
+            panic!("expected &&: {:?}", spec.constraints[0].expression);
+        };
+        let Expression::FunctionCall {
+            method, arguments, ..
+        } = &**matched
+        else {
+            panic!("expected a call: {matched:?}");
+        };
+        assert_eq!(method, "matches");
+        assert_eq!(
+            arguments[1].value,
+            Expression::StringLiteral("^[A-Z]{2}[0-9]{6}$".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_agent_example() {
+        let spec =
+            SigmosParser::parse_spec(include_str!("../../../examples/agent.sigmos")).unwrap();
+
+        let inputs: Vec<&str> = spec.inputs.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(inputs, ["name", "tone", "api_key", "max_tokens"]);
+        assert_eq!(spec.constraints.len(), 4);
+        assert_eq!(spec.events.len(), 2);
+        assert_eq!(spec.lifecycle.len(), 3);
+
+        // `enum(...)` is a string limited to its values
+        assert_eq!(
+            spec.inputs[1].type_expr,
+            TypeExpr::Primitive(PrimitiveType::String)
+        );
+        assert_eq!(
+            spec.inputs[1].modifiers,
+            vec![Modifier::OneOf(vec![
+                serde_json::json!("friendly"),
+                serde_json::json!("hostile"),
+                serde_json::json!("professional")
+            ])]
+        );
+        assert_eq!(
+            spec.constraints[1].expression.to_string(),
+            r#"contains(["friendly", "hostile", "professional"], tone)"#
+        );
+    }
+
+    #[test]
+    fn test_parse_collection_literals() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            computed:
+                tags: -> ["a", tone, 3]
+                payload: -> mcp.call("begin", { id: name, "x-tier": { level: 1 }, tags: [] })
+                allowed: -> tone in tags && 1 + 1 in [2]
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        assert_eq!(
+            spec.computed[0].expression,
+            Expression::Array(vec![
+                Expression::StringLiteral("a".to_string()),
+                Expression::Identifier("tone".to_string()),
+                Expression::Number(3.0),
+            ])
+        );
+        assert_eq!(
+            spec.computed[1].expression.to_string(),
+            r#"mcp.call("begin", { id: name, "x-tier": { level: 1 }, tags: [] })"#
+        );
+        // `in` binds tighter than `&&` and looser than `+`
+        assert_eq!(
+            spec.computed[2].expression.to_string(),
+            "contains(tags, tone) && contains([2], 1 + 1)"
+        );
+    }
+
+    #[test]
+    fn test_unexpected_tokens_are_errors() {
+        let error = |input: &str| SigmosParser::parse_spec(input).unwrap_err();
+        let location = |error: &ParseError| {
+            let span = error.span().unwrap();
+            (span.line, span.column)
+        };
+
+        // An unknown section
+        let unknown = error("spec \"T\" v1.0 {\n  actions:\n    go: run()\n}");
+        assert!(unknown
+            .to_string()
+            .contains("Unexpected Identifier(\"actions\")"));
+        assert_eq!(location(&unknown), (2, 3));
+
+        // Tokens left over after a constraint's expression
+        let trailing = error("spec \"T\" v1.0 {\n  constraints:\n    ensure tone is \"x\"\n}");
+        assert!(trailing
+            .to_string()
+            .contains("Unexpected Identifier(\"is\") after constraint expression"));
+        assert_eq!(location(&trailing), (3, 17));
+
+        // Tokens left over after an input's type
+        let field = error("spec \"T\" v1.0 {\n  inputs:\n    tone: string(\"x\")\n}");
+        assert!(field
+            .to_string()
+            .contains("Unexpected LeftParen after input 'tone'"));
+
+        let enum_elsewhere =
+            error("spec \"T\" v1.0 {\n  functions:\n    f(x: enum(\"a\")) -> int = 1\n}");
+        assert!(enum_elsewhere
+            .to_string()
+            .contains("enum(...) types are only supported for inputs"));
+        assert!(error("spec \"T\" v1.0 { inputs: tone: enum(1, 2) }")
+            .to_string()
+            .contains("Expected enum value as string literal"));
+        assert!(error("spec \"T\" v1.0 { types { A = int } }")
+            .to_string()
+            .contains("The types section is not supported yet"));
+    }
+
+    #[test]
+    fn test_nesting_is_limited() {
+        let parse = |expression: String| {
+            SigmosParser::parse_spec(&format!(
+                "spec \"Deep\" v1.0 {{\n computed:\n total: -> {expression}\n}}"
+            ))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+        };
+        let chain = |terms: usize| vec!["1"; terms].join(" + ");
+        let parens = |levels: usize| format!("{}1{}", "(".repeat(levels), ")".repeat(levels));
+        let calls = |levels: usize| format!("{}1{}", "abs(".repeat(levels), ")".repeat(levels));
+
+        assert!(parse(chain(MAX_NESTING)).is_ok());
+        assert!(parse(parens(MAX_NESTING - 1)).is_ok());
+        assert!(parse(calls(MAX_NESTING / 2 - 1)).is_ok());
+
+        // Far past the limit these used to overflow the stack
+        for expression in [
+            chain(20_000),
+            parens(3_000),
+            calls(3_000),
+            "!".repeat(3_000) + "x",
+        ] {
+            let error = parse(expression).unwrap_err();
+            assert!(
+                error.contains(&format!("nests more than {MAX_NESTING} levels")),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_generic_types() {
+        let input = r#"
+        spec "Inventory" v1.0 {
+            inputs:
+                counts: array<int>
+                names: list<string>
+                stock: map<string, array<int>>
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let array = |item| TypeExpr::Generic {
+            name: "Array".to_string(),
+            args: vec![item],
+        };
+        assert_eq!(
+            spec.inputs[0].type_expr,
+            array(TypeExpr::Primitive(PrimitiveType::Int))
+        );
+        assert_eq!(
+            spec.inputs[1].type_expr,
+            array(TypeExpr::Primitive(PrimitiveType::String))
+        );
+        assert_eq!(
+            spec.inputs[2].type_expr,
+            TypeExpr::Generic {
+                name: "Map".to_string(),
+                args: vec![
+                    TypeExpr::Primitive(PrimitiveType::String),
+                    array(TypeExpr::Primitive(PrimitiveType::Int)),
+                ],
+            }
+        );
+
+        let error =
+            SigmosParser::parse_spec("spec \"Bad\" v1.0 {\n inputs:\n pairs: map<string>\n}")
+                .unwrap_err();
+        assert!(
+            error.to_string().contains("takes 2 type argument"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_parse_lambdas() {
+        let input = r#"
+        spec "Orders" v1.0 {
+            computed:
+                large: -> filter(orders, o => o.total > threshold && o.paid)
+                total: -> reduce(orders, (sum, o) => sum + o.total, 0)
+                grouped: -> (a == b)
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let Expression::FunctionCall { arguments, .. } = &spec.computed[0].expression else {
+            panic!("expected a call");
+        };
+        let Expression::Lambda { parameters, body } = &arguments[1].value else {
+            panic!("expected a lambda, got {:?}", arguments[1].value);
+        };
+        assert_eq!(parameters, &["o"]);
+        assert!(matches!(**body, Expression::And(_, _)));
+        assert_eq!(
+            spec.computed[1].expression.to_string(),
+            "reduce(orders, (sum, o) => sum + o.total, 0)"
+        );
+        // Parenthesized expressions are not mistaken for parameter lists
+        assert!(matches!(
+            spec.computed[2].expression,
+            Expression::Equal(_, _)
+        ));
+        // Parameters are bound by the lambda, not read from the spec
+        assert_eq!(
+            spec.computed[0].expression.referenced_identifiers(),
+            vec!["orders", "threshold"]
+        );
+    }
+
+    #[test]
+    fn test_parse_functions() {
+        let input = r#"
+        spec "Risk" v1.0 {
+            functions:
+                score(x: float, w: float) -> float = x * w
+                zero() -> int = 0
+            computed:
+                risk: -> score(1, 2)
+        }
+        "#;
+
+        let (spec, source_map) = SigmosParser::parse_spec_with_source_map(input).unwrap();
+        assert_eq!(
+            spec.functions[0],
+            FunctionDef {
+                name: "score".to_string(),
+                parameters: vec![
+                    FunctionParameter {
+                        name: "x".to_string(),
+                        type_expr: TypeExpr::Primitive(PrimitiveType::Float),
+                    },
+                    FunctionParameter {
+                        name: "w".to_string(),
+                        type_expr: TypeExpr::Primitive(PrimitiveType::Float),
+                    },
+                ],
+                return_type: TypeExpr::Primitive(PrimitiveType::Float),
+                body: Expression::Multiply(
+                    Box::new(Expression::Identifier("x".to_string())),
+                    Box::new(Expression::Identifier("w".to_string())),
+                ),
+            }
+        );
+        assert!(spec.functions[1].parameters.is_empty());
+        assert_eq!(spec.computed.len(), 1);
+        assert_eq!(source_map.get("functions.zero").unwrap().line, 5);
+    }
+
+    #[test]
+    fn test_parse_call_policies() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            computed:
+                reply: -> mcp.complete(prompt: "hi") { retry: 3, backoff: exponential(200ms), timeout: 5s, fallback: "n/a" }
+            lifecycle:
+                after: rest.post(path: "/done") { backoff: fixed(2m) }
+        }
+        "#;
+
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        assert_eq!(
+            spec.computed[0].policy,
+            Some(CallPolicy {
+                retry: 3,
+                backoff: Some(Backoff::Exponential(Duration::from_millis(200))),
+                timeout: Some(Duration::from_secs(5)),
+                fallback: Some(Expression::StringLiteral("n/a".to_string())),
+            })
+        );
+        assert_eq!(
+            spec.lifecycle[0].policy.as_ref().unwrap().backoff,
+            Some(Backoff::Fixed(Duration::from_secs(120)))
+        );
+
+        let error = SigmosParser::parse_spec(&input.replace("retry", "retries")).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Unknown policy option 'retries'"));
+    }
 }
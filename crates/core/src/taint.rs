@@ -0,0 +1,563 @@
+//! # Secret taint analysis
+//!
+//! Tracks values derived from `secret` inputs and reports every place they
+//! could leak: logging calls, string templates, transpiled output and plugin
+//! arguments that are not explicitly allowed to receive credentials.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_core::taint::{TaintAnalyzer, TaintSink};
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Agent" v1.0 {
+//!     inputs:
+//!         api_key: string { secret: true }
+//!     computed:
+//!         header: -> "Bearer {{api_key}}"
+//! }
+//! "#).unwrap();
+//!
+//! let findings = TaintAnalyzer::new().analyze(&spec);
+//! assert_eq!(findings.len(), 1);
+//! assert_eq!(findings[0].sink, TaintSink::Template);
+//! ```
+
+use crate::ast::{Action, Argument, ComputedField, Expression, Modifier, Spec};
+use std::collections::HashSet;
+
+/// Placeholder shown in place of secret values
+pub const SECRET_MASK: &str = "***";
+
+/// Function names treated as logging sinks, including the `log` standard
+/// action
+const LOGGING_FUNCTIONS: &[&str] = &["log", "print", "debug"];
+
+/// Plugin argument names that may receive secrets on any plugin by default
+const DEFAULT_ALLOWED_ARGUMENTS: &[&str] = &["auth", "api_key", "token", "password", "credentials"];
+
+/// Where a secret value ends up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaintSink {
+    /// Argument of a logging call such as `log(...)`
+    Log,
+    /// Interpolated into a string template
+    Template,
+    /// Embedded in transpiled output (e.g. a literal `default:` on a secret input)
+    TranspiledOutput,
+    /// Passed to a plugin argument that is not allowlisted
+    PluginArgument {
+        plugin: String,
+        method: String,
+        argument: String,
+    },
+}
+
+/// A secret value reaching a sink
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaintFinding {
+    /// The tainted field the sink reads: a secret input or a computed field
+    /// derived from one
+    pub secret: String,
+    /// Where the value flows
+    pub sink: TaintSink,
+    /// Spec location of the flow, e.g. `computed.header`
+    pub location: String,
+}
+
+impl std::fmt::Display for TaintFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sink = match &self.sink {
+            TaintSink::Log => "a logging call".to_string(),
+            TaintSink::Template => "a string template".to_string(),
+            TaintSink::TranspiledOutput => "transpiled output".to_string(),
+            TaintSink::PluginArgument {
+                plugin,
+                method,
+                argument,
+            } => format!("argument '{argument}' of {plugin}.{method}()"),
+        };
+        write!(
+            f,
+            "secret '{}' flows into {sink} at {}",
+            self.secret, self.location
+        )
+    }
+}
+
+/// Taint analyzer for secret inputs
+#[derive(Debug, Clone)]
+pub struct TaintAnalyzer {
+    /// `(plugin, argument)` pairs allowed to receive secrets; `*` matches any plugin
+    allowed_arguments: HashSet<(String, String)>,
+}
+
+impl TaintAnalyzer {
+    /// Create an analyzer that allows credential-named plugin arguments
+    /// (`auth`, `api_key`, `token`, `password`, `credentials`)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::taint::TaintAnalyzer;
+    ///
+    /// let analyzer = TaintAnalyzer::new().allow_argument("rest", "headers");
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            allowed_arguments: DEFAULT_ALLOWED_ARGUMENTS
+                .iter()
+                .map(|argument| ("*".to_string(), argument.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Allow a plugin argument to receive secrets; use `*` for any plugin
+    pub fn allow_argument(mut self, plugin: &str, argument: &str) -> Self {
+        self.allowed_arguments
+            .insert((plugin.to_string(), argument.to_string()));
+        self
+    }
+
+    /// Names of secret inputs and every computed field derived from them
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_core::taint::TaintAnalyzer;
+    ///
+    /// let spec = SigmosParser::parse_spec(r#"
+    /// spec "Agent" v1.0 {
+    ///     inputs:
+    ///         api_key: string { secret: true }
+    ///     computed:
+    ///         masked: -> len(api_key)
+    ///         doubled: -> masked * 2
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let tainted = TaintAnalyzer::tainted_fields(&spec);
+    /// assert!(tainted.contains("api_key") && tainted.contains("doubled"));
+    /// ```
+    pub fn tainted_fields(spec: &Spec) -> HashSet<String> {
+        let mut tainted: HashSet<String> = spec
+            .inputs
+            .iter()
+            .filter(|field| field.modifiers.contains(&Modifier::Secret))
+            .map(|field| field.name.clone())
+            .collect();
+
+        // Propagate through computed fields until nothing changes
+        loop {
+            let newly_tainted: Vec<String> = spec
+                .computed
+                .iter()
+                .filter(|field| !tainted.contains(&field.name))
+                .filter(|field| {
+                    Self::values(field).any(|expr| !Self::sources(expr, &tainted).is_empty())
+                })
+                .map(|field| field.name.clone())
+                .collect();
+            if newly_tainted.is_empty() {
+                return tainted;
+            }
+            tainted.extend(newly_tainted);
+        }
+    }
+
+    /// The expressions a computed field's value can come from: its own and
+    /// its fallback's
+    fn values(field: &ComputedField) -> impl Iterator<Item = &Expression> {
+        std::iter::once(&field.expression)
+            .chain(field.policy.as_ref().and_then(|p| p.fallback.as_ref()))
+    }
+
+    /// Analyze a specification and report every secret flow into a sink
+    pub fn analyze(&self, spec: &Spec) -> Vec<TaintFinding> {
+        let tainted = Self::tainted_fields(spec);
+        let mut findings = Vec::new();
+
+        for field in &spec.inputs {
+            if !field.modifiers.contains(&Modifier::Secret) {
+                continue;
+            }
+            for modifier in &field.modifiers {
+                if let Modifier::Default(expr) = modifier {
+                    if !matches!(expr, Expression::Null) {
+                        findings.push(TaintFinding {
+                            secret: field.name.clone(),
+                            sink: TaintSink::TranspiledOutput,
+                            location: format!("inputs.{}.default", field.name),
+                        });
+                    }
+                }
+            }
+        }
+
+        for field in &spec.computed {
+            let location = format!("computed.{}", field.name);
+            for expr in Self::values(field) {
+                self.check_expression(expr, &tainted, &location, &mut findings);
+            }
+        }
+        for (i, constraint) in spec.constraints.iter().enumerate() {
+            let location = format!("constraints[{i}]");
+            self.check_expression(&constraint.expression, &tainted, &location, &mut findings);
+        }
+        for event in &spec.events {
+            let location = format!("events.{:?}({})", event.event_type, event.parameter);
+            self.check_action(&event.action, &tainted, &location, &mut findings);
+        }
+        for lifecycle in &spec.lifecycle {
+            let location = format!("lifecycle.{:?}", lifecycle.phase).to_lowercase();
+            self.check_action(&lifecycle.action, &tainted, &location, &mut findings);
+        }
+
+        findings
+    }
+
+    fn check_action(
+        &self,
+        action: &Action,
+        tainted: &HashSet<String>,
+        location: &str,
+        findings: &mut Vec<TaintFinding>,
+    ) {
+        match action {
+            Action::FunctionCall {
+                object,
+                method,
+                arguments,
+            } => self.check_call(object, method, arguments, tainted, location, findings),
+            Action::Assign { value, .. } => {
+                self.check_expression(value, tainted, location, findings)
+            }
+            Action::Identifier(_) => {}
+        }
+    }
+
+    fn check_call(
+        &self,
+        object: &str,
+        method: &str,
+        arguments: &[Argument],
+        tainted: &HashSet<String>,
+        location: &str,
+        findings: &mut Vec<TaintFinding>,
+    ) {
+        // An object passed positionally to a plugin carries arguments by name
+        let mut named = Vec::with_capacity(arguments.len());
+        for (i, argument) in arguments.iter().enumerate() {
+            match &argument.value {
+                Expression::Object(entries) if !object.is_empty() && argument.name.is_empty() => {
+                    named.extend(entries.iter().map(|(key, value)| (key.clone(), value)));
+                }
+                value if argument.name.is_empty() => named.push((format!("arg_{i}"), value)),
+                value => named.push((argument.name.clone(), value)),
+            }
+        }
+
+        for (name, value) in named {
+            let sink = if object.is_empty() && LOGGING_FUNCTIONS.contains(&method) {
+                Some(TaintSink::Log)
+            } else if !object.is_empty() {
+                (!self.is_allowed(object, &name)).then(|| TaintSink::PluginArgument {
+                    plugin: object.to_string(),
+                    method: method.to_string(),
+                    argument: name,
+                })
+            } else {
+                None
+            };
+
+            let logged = sink == Some(TaintSink::Log);
+            if let Some(sink) = sink {
+                for secret in Self::sources(value, tainted) {
+                    findings.push(TaintFinding {
+                        secret,
+                        sink: sink.clone(),
+                        location: location.to_string(),
+                    });
+                }
+            }
+            // A logged template is reported once, as the log sink
+            if !(logged && matches!(value, Expression::StringTemplate { .. })) {
+                self.check_expression(value, tainted, location, findings);
+            }
+        }
+    }
+
+    fn check_expression(
+        &self,
+        expr: &Expression,
+        tainted: &HashSet<String>,
+        location: &str,
+        findings: &mut Vec<TaintFinding>,
+    ) {
+        match expr {
+            Expression::StringTemplate { .. } => {
+                for secret in Self::sources(expr, tainted) {
+                    findings.push(TaintFinding {
+                        secret,
+                        sink: TaintSink::Template,
+                        location: location.to_string(),
+                    });
+                }
+            }
+            Expression::FunctionCall {
+                object,
+                method,
+                arguments,
+            } => self.check_call(object, method, arguments, tainted, location, findings),
+            Expression::Add(left, right)
+            | Expression::Subtract(left, right)
+            | Expression::Multiply(left, right)
+            | Expression::Divide(left, right)
+            | Expression::Modulo(left, right)
+            | Expression::Equal(left, right)
+            | Expression::NotEqual(left, right)
+            | Expression::LessThan(left, right)
+            | Expression::LessThanOrEqual(left, right)
+            | Expression::GreaterThan(left, right)
+            | Expression::GreaterThanOrEqual(left, right)
+            | Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Coalesce(left, right)
+            | Expression::ArrayAccess(left, right) => {
+                self.check_expression(left, tainted, location, findings);
+                self.check_expression(right, tainted, location, findings);
+            }
+            Expression::Not(operand)
+            | Expression::PropertyAccess(operand, _)
+            | Expression::OptionalPropertyAccess(operand, _) => {
+                self.check_expression(operand, tainted, location, findings)
+            }
+            Expression::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                self.check_expression(condition, tainted, location, findings);
+                self.check_expression(if_true, tainted, location, findings);
+                self.check_expression(if_false, tainted, location, findings);
+            }
+            Expression::Array(items) => {
+                for item in items {
+                    self.check_expression(item, tainted, location, findings);
+                }
+            }
+            Expression::Object(entries) => {
+                for (_, value) in entries {
+                    self.check_expression(value, tainted, location, findings);
+                }
+            }
+            Expression::Lambda { parameters, body } => {
+                // Parameters shadow fields of the same name
+                let mut tainted = tainted.clone();
+                for parameter in parameters {
+                    tainted.remove(parameter);
+                }
+                self.check_expression(body, &tainted, location, findings);
+            }
+            Expression::StringLiteral(_)
+            | Expression::Number(_)
+            | Expression::Boolean(_)
+            | Expression::Null
+            | Expression::Identifier(_) => {}
+        }
+    }
+
+    /// Tainted names an expression reads, including `ref("field")` lookups
+    fn sources(expr: &Expression, tainted: &HashSet<String>) -> Vec<String> {
+        let mut sources: Vec<String> = expr
+            .referenced_identifiers()
+            .into_iter()
+            .filter(|name| tainted.contains(*name))
+            .map(str::to_string)
+            .collect();
+
+        Self::collect_refs(expr, &mut |name| {
+            if tainted.contains(name) && !sources.iter().any(|s| s == name) {
+                sources.push(name.to_string());
+            }
+        });
+        sources
+    }
+
+    fn collect_refs(expr: &Expression, found: &mut impl FnMut(&str)) {
+        if let Expression::FunctionCall {
+            object,
+            method,
+            arguments,
+        } = expr
+        {
+            if object.is_empty() && method == "ref" {
+                if let Some(Expression::StringLiteral(name)) = arguments.first().map(|a| &a.value) {
+                    found(name);
+                }
+            }
+            for argument in arguments {
+                Self::collect_refs(&argument.value, found);
+            }
+        }
+    }
+
+    fn is_allowed(&self, plugin: &str, argument: &str) -> bool {
+        self.allowed_arguments
+            .contains(&(plugin.to_string(), argument.to_string()))
+            || self
+                .allowed_arguments
+                .contains(&("*".to_string(), argument.to_string()))
+    }
+}
+
+impl Default for TaintAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::*;
+    use crate::parser::SigmosParser;
+
+    #[test]
+    fn test_secret_flows_through_computed_fields() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+            spec "Agent" v1.0 {
+                inputs:
+                    api_key: string { secret: true }
+                    name: string
+                computed:
+                    token: -> "sk-" + api_key
+                    greeting: -> "Hello {{name}}"
+                    leaked: -> "Using {{token}}"
+            }
+            "#,
+        )
+        .unwrap();
+
+        let findings = TaintAnalyzer::new().analyze(&spec);
+        assert_eq!(
+            findings,
+            vec![TaintFinding {
+                secret: "token".to_string(),
+                sink: TaintSink::Template,
+                location: "computed.leaked".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plugin_arguments_and_logging() {
+        let mut spec = SigmosParser::parse_spec(
+            r#"
+            spec "Agent" v1.0 {
+                inputs:
+                    api_key: string { secret: true, default: "sk-test" }
+                computed:
+                    allowed: -> mcp.complete(prompt: "hi", auth: api_key)
+                    leaked: -> rest.post(body: api_key)
+            }
+            "#,
+        )
+        .unwrap();
+        spec.lifecycle.push(LifecycleDef {
+            phase: LifecyclePhase::After,
+            action: Action::FunctionCall {
+                object: String::new(),
+                method: "log".to_string(),
+                arguments: vec![Argument {
+                    name: String::new(),
+                    value: Expression::Identifier("api_key".to_string()),
+                }],
+            },
+            policy: None,
+        });
+
+        let sinks: Vec<TaintSink> = TaintAnalyzer::new()
+            .analyze(&spec)
+            .into_iter()
+            .map(|finding| finding.sink)
+            .collect();
+        assert_eq!(
+            sinks,
+            vec![
+                TaintSink::TranspiledOutput,
+                TaintSink::PluginArgument {
+                    plugin: "rest".to_string(),
+                    method: "post".to_string(),
+                    argument: "body".to_string(),
+                },
+                TaintSink::Log,
+            ]
+        );
+
+        let relaxed = TaintAnalyzer::new().allow_argument("rest", "body");
+        assert_eq!(relaxed.analyze(&spec).len(), 2);
+    }
+
+    #[test]
+    fn test_log_action_templates_are_log_sinks() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+            spec "Agent" v1.0 {
+                inputs:
+                    api_key: string { secret: true }
+                lifecycle:
+                    after: log("Calling with {{api_key}}")
+                events:
+                    on_error(failure): log(message: "Failed with {{api_key}}")
+            }
+            "#,
+        )
+        .unwrap();
+
+        let findings: Vec<(TaintSink, String)> = TaintAnalyzer::new()
+            .analyze(&spec)
+            .into_iter()
+            .map(|finding| (finding.sink, finding.location))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                (TaintSink::Log, "events.OnError(failure)".to_string()),
+                (TaintSink::Log, "lifecycle.after".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_arguments_are_checked_by_key() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+            spec "Agent" v1.0 {
+                inputs:
+                    api_key: string { secret: true }
+                computed:
+                    reply: -> mcp.call("begin", { auth: api_key, payload: { key: api_key } })
+            }
+            "#,
+        )
+        .unwrap();
+
+        let sinks: Vec<TaintSink> = TaintAnalyzer::new()
+            .analyze(&spec)
+            .into_iter()
+            .map(|finding| finding.sink)
+            .collect();
+        assert_eq!(
+            sinks,
+            vec![TaintSink::PluginArgument {
+                plugin: "mcp".to_string(),
+                method: "call".to_string(),
+                argument: "payload".to_string(),
+            }]
+        );
+    }
+}
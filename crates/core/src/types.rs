@@ -14,9 +14,15 @@
 //! assert!(checker.is_valid_type(&string_type));
 //! ```
 
-use crate::ast::{FieldDef, PrimitiveType, Spec, TypeExpr};
+use crate::ast::{
+    Argument, ComputedField, Expression, FieldDef, FunctionDef, Modifier, PrimitiveType, SourceMap,
+    Spec, TypeExpr,
+};
+use crate::parser::MAX_NESTING;
 use crate::{ParseError, ParseResult};
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
 
 /// Type checker for SIGMOS specifications
 #[derive(Debug, Default)]
@@ -25,10 +31,63 @@ pub struct TypeChecker {
     user_types: HashMap<String, TypeExpr>,
     /// Built-in type registry
     builtin_types: HashMap<String, TypeExpr>,
+    /// Signatures of the free functions specs may call, keyed by name
+    functions: HashMap<String, FunctionSignature>,
+    /// Functions declared in the `functions:` section of the spec being checked
+    spec_functions: HashMap<String, FunctionDef>,
+    /// Source spans attached to diagnostics
+    source_map: SourceMap,
+    /// Regexes of the `pattern` refinements checked so far
+    patterns: PatternCache,
+    /// Nesting level of the expression being typed
+    depth: Cell<usize>,
 }
 
-/// Type checking context
+/// Regexes of `pattern` refinements, each compiled the first time it is used
+///
+/// # Examples
+///
+/// ```rust
+/// use sigmos_core::types::PatternCache;
+///
+/// let patterns = PatternCache::new();
+/// assert!(patterns.is_match("^[a-z]+$", "rust").unwrap());
+/// assert!(patterns.is_match("(", "rust").is_err());
+/// ```
 #[derive(Debug, Default)]
+pub struct PatternCache {
+    compiled: Mutex<HashMap<String, regex::Regex>>,
+}
+
+impl PatternCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The compiled regex of `pattern`
+    pub fn get(&self, pattern: &str) -> Result<regex::Regex, String> {
+        let mut compiled = self
+            .compiled
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(regex) = compiled.get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| format!("pattern /{pattern}/ is invalid: {e}"))?;
+        compiled.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    }
+
+    /// Whether `text` matches `pattern`
+    pub fn is_match(&self, pattern: &str, text: &str) -> Result<bool, String> {
+        self.get(pattern).map(|regex| regex.is_match(text))
+    }
+}
+
+/// Type checking context
+#[derive(Debug, Clone, Default)]
 pub struct TypeContext {
     /// Available variables in scope
     variables: HashMap<String, TypeExpr>,
@@ -37,10 +96,205 @@ pub struct TypeContext {
 }
 
 /// Function signature for type checking
-#[derive(Debug, Clone)]
+///
+/// Parameters are required, then optional, then at most one variadic
+/// parameter that accepts any number of further arguments.
+///
+/// # Examples
+///
+/// ```rust
+/// use sigmos_core::types::{FunctionSignature, ValueType};
+///
+/// let round = FunctionSignature::new(ValueType::Number)
+///     .required("value", ValueType::Number)
+///     .optional("digits", ValueType::Number);
+/// assert!(round.check_arity("round", 2).is_ok());
+/// assert_eq!(
+///     round.check_arity("round", 3).unwrap_err(),
+///     "round() takes 1 to 2 arguments, got 3"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
 pub struct FunctionSignature {
-    pub parameters: Vec<(String, TypeExpr)>,
-    pub return_type: TypeExpr,
+    pub parameters: Vec<Parameter>,
+    pub return_type: ValueType,
+}
+
+/// A parameter of a [`FunctionSignature`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub value_type: ValueType,
+    pub kind: ParameterKind,
+}
+
+/// How many arguments a parameter binds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ParameterKind {
+    Required,
+    Optional,
+    /// Any number of trailing arguments, including none
+    Variadic,
+}
+
+/// Kind of value a function accepts or returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// Any value; its type is only known at runtime
+    Any,
+    String,
+    /// An int or a float
+    Number,
+    Bool,
+    Array,
+    Object,
+    /// A function, such as the name of a builtin
+    Function,
+}
+
+impl ValueType {
+    /// Type the checker gives a value of this kind
+    pub fn type_expr(self) -> TypeExpr {
+        match self {
+            ValueType::String => TypeExpr::Primitive(PrimitiveType::String),
+            ValueType::Number => TypeExpr::Primitive(PrimitiveType::Float),
+            ValueType::Bool => TypeExpr::Primitive(PrimitiveType::Bool),
+            ValueType::Array => TypeExpr::Generic {
+                name: "Array".to_string(),
+                args: vec![TypeChecker::any_type()],
+            },
+            ValueType::Any | ValueType::Object | ValueType::Function => TypeChecker::any_type(),
+        }
+    }
+
+    /// Whether an argument of type `type_expr` may be passed for this kind
+    ///
+    /// Values whose type is only known at runtime are accepted everywhere.
+    pub fn accepts_type(self, type_expr: &TypeExpr) -> bool {
+        if self == ValueType::Any || TypeChecker::is_dynamic(type_expr) {
+            return true;
+        }
+        match type_expr.non_null() {
+            TypeExpr::Primitive(primitive) => matches!(
+                (self, primitive),
+                (ValueType::String, PrimitiveType::String)
+                    | (ValueType::Number, PrimitiveType::Int | PrimitiveType::Float)
+                    | (ValueType::Bool, PrimitiveType::Bool)
+            ),
+            TypeExpr::Generic { name, .. } if name == "Array" => self == ValueType::Array,
+            TypeExpr::Generic { .. } | TypeExpr::Reference(_) => {
+                matches!(self, ValueType::Array | ValueType::Object)
+            }
+            TypeExpr::Nullable(_) => false,
+        }
+    }
+
+    /// Whether `value` is of this kind; no JSON value is a function
+    pub fn accepts_value(self, value: &serde_json::Value) -> bool {
+        match self {
+            ValueType::Any => true,
+            ValueType::String => value.is_string(),
+            ValueType::Number => value.is_number(),
+            ValueType::Bool => value.is_boolean(),
+            ValueType::Array => value.is_array(),
+            ValueType::Object => value.is_object(),
+            ValueType::Function => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ValueType::Any => "any",
+            ValueType::String => "string",
+            ValueType::Number => "number",
+            ValueType::Bool => "bool",
+            ValueType::Array => "array",
+            ValueType::Object => "object",
+            ValueType::Function => "function",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FunctionSignature {
+    /// A signature without parameters
+    pub fn new(return_type: ValueType) -> Self {
+        Self {
+            parameters: Vec::new(),
+            return_type,
+        }
+    }
+
+    pub fn required(self, name: &str, value_type: ValueType) -> Self {
+        self.with_parameter(name, value_type, ParameterKind::Required)
+    }
+
+    pub fn optional(self, name: &str, value_type: ValueType) -> Self {
+        self.with_parameter(name, value_type, ParameterKind::Optional)
+    }
+
+    pub fn variadic(self, name: &str, value_type: ValueType) -> Self {
+        self.with_parameter(name, value_type, ParameterKind::Variadic)
+    }
+
+    fn with_parameter(mut self, name: &str, value_type: ValueType, kind: ParameterKind) -> Self {
+        debug_assert!(
+            self.parameters
+                .last()
+                .is_none_or(|last| last.kind <= kind && last.kind != ParameterKind::Variadic),
+            "parameters must be required, then optional, then one variadic"
+        );
+        self.parameters.push(Parameter {
+            name: name.to_string(),
+            value_type,
+            kind,
+        });
+        self
+    }
+
+    /// The parameter that binds the argument at `index`
+    pub fn parameter(&self, index: usize) -> Option<&Parameter> {
+        self.parameters.get(index).or_else(|| {
+            self.parameters
+                .last()
+                .filter(|last| last.kind == ParameterKind::Variadic)
+        })
+    }
+
+    /// Fewest and, unless variadic, most arguments accepted
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        let required = self
+            .parameters
+            .iter()
+            .filter(|p| p.kind == ParameterKind::Required)
+            .count();
+        let variadic = self
+            .parameters
+            .iter()
+            .any(|p| p.kind == ParameterKind::Variadic);
+        (required, (!variadic).then_some(self.parameters.len()))
+    }
+
+    /// Check that `count` arguments fit, describing the mismatch otherwise
+    pub fn check_arity(&self, name: &str, count: usize) -> Result<(), String> {
+        let (min, max) = self.arity();
+        if count >= min && max.is_none_or(|max| count <= max) {
+            return Ok(());
+        }
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        Err(match max {
+            Some(max) if max == min => {
+                format!("{name}() takes {min} argument{}, got {count}", plural(min))
+            }
+            Some(max) => format!("{name}() takes {min} to {max} arguments, got {count}"),
+            None => format!(
+                "{name}() takes at least {min} argument{}, got {count}",
+                plural(min)
+            ),
+        })
+    }
 }
 
 impl TypeChecker {
@@ -59,8 +313,78 @@ impl TypeChecker {
         checker
     }
 
+    /// Attach source spans from the parser to the diagnostics this checker reports
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_core::types::TypeChecker;
+    ///
+    /// let (spec, source_map) = SigmosParser::parse_spec_with_source_map(r#"
+    /// spec "Agent" v1.0 {
+    ///     inputs:
+    ///         alias: string { ref: "missing" }
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let error = TypeChecker::new()
+    ///     .with_source_map(source_map)
+    ///     .validate_spec(&spec)
+    ///     .unwrap_err();
+    /// assert_eq!(error.span().unwrap().line, 4);
+    /// ```
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = source_map;
+        self
+    }
+
+    /// Check calls to these free functions against their signatures
+    ///
+    /// Calls to functions without a signature are not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_core::types::{FunctionSignature, TypeChecker, ValueType};
+    ///
+    /// let spec = SigmosParser::parse_spec(r#"
+    /// spec "Shouting" v1.0 {
+    ///     computed:
+    ///         loud: -> shout("hi", "there")
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let shout = FunctionSignature::new(ValueType::String).required("text", ValueType::String);
+    /// let error = TypeChecker::new()
+    ///     .with_functions([("shout".to_string(), shout)])
+    ///     .validate_spec(&spec)
+    ///     .unwrap_err();
+    /// assert!(error.to_string().contains("shout() takes 1 argument, got 2"));
+    /// ```
+    pub fn with_functions(
+        mut self,
+        functions: impl IntoIterator<Item = (String, FunctionSignature)>,
+    ) -> Self {
+        self.functions.extend(functions);
+        self
+    }
+
+    /// Type of values only known at runtime, such as the result of `parse_json`
+    pub fn any_type() -> TypeExpr {
+        TypeExpr::Reference("any".to_string())
+    }
+
+    /// Whether `type_expr` is only known at runtime
+    fn is_dynamic(type_expr: &TypeExpr) -> bool {
+        matches!(type_expr.non_null(), TypeExpr::Reference(name) if name == "any")
+    }
+
     /// Register built-in types
     fn register_builtin_types(&mut self) {
+        self.builtin_types
+            .insert("any".to_string(), Self::any_type());
         self.builtin_types.insert(
             "list".to_string(),
             TypeExpr::Generic {
@@ -68,6 +392,20 @@ impl TypeChecker {
                 args: vec![TypeExpr::Primitive(PrimitiveType::String)], // placeholder
             },
         );
+        self.builtin_types.insert(
+            "Array".to_string(),
+            TypeExpr::Generic {
+                name: "Array".to_string(),
+                args: vec![Self::any_type()],
+            },
+        );
+        self.builtin_types.insert(
+            "Map".to_string(),
+            TypeExpr::Generic {
+                name: "Map".to_string(),
+                args: vec![TypeExpr::Primitive(PrimitiveType::String), Self::any_type()],
+            },
+        );
         self.builtin_types.insert(
             "map".to_string(),
             TypeExpr::Generic {
@@ -108,6 +446,7 @@ impl TypeChecker {
                 }
                 args.iter().all(|arg| self.is_valid_type(arg))
             }
+            TypeExpr::Nullable(inner) => self.is_valid_type(inner),
         }
     }
 
@@ -136,25 +475,321 @@ impl TypeChecker {
 
     /// Validate a complete SIGMOS specification
     ///
+    /// Optional inputs without a default are typed as nullable, so computed
+    /// expressions must guard them with a null check, `??` or `?.` before use.
+    ///
     /// # Arguments
     ///
     /// * `spec` - The specification to validate
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_core::types::TypeChecker;
+    ///
+    /// let spec = SigmosParser::parse_spec(r#"
+    /// spec "Pricing" v1.0 {
+    ///     inputs:
+    ///         discount: float { optional: true }
+    ///     computed:
+    ///         unsafe_total: -> 100 - discount
+    /// }
+    /// "#).unwrap();
+    /// assert!(TypeChecker::new().validate_spec(&spec).is_err());
+    /// ```
     pub fn validate_spec(&mut self, spec: &Spec) -> ParseResult<()> {
         // Register user-defined types first
         for type_def in &spec.types {
             self.register_type(type_def.name.clone(), type_def.type_expr.clone())?;
         }
 
+        // Functions are checked before the fields that call them
+        self.validate_functions(spec)?;
+
         // Validate input fields
         for field in &spec.inputs {
-            self.validate_field(field)?;
+            self.validate_field(field)
+                .map_err(|e| e.with_span(self.span(&format!("inputs.{}", field.name))))?;
+        }
+
+        // Inputs are in scope for computed expressions
+        let mut context = TypeContext::new();
+        for field in &spec.inputs {
+            context.add_variable(field.name.clone(), Self::input_type(field));
+        }
+
+        // Validate computed fields, resolving references between them on demand
+        let computed: HashMap<&str, &ComputedField> = spec
+            .computed
+            .iter()
+            .map(|field| (field.name.as_str(), field))
+            .collect();
+        let mut in_progress = HashSet::new();
+        for field in &spec.computed {
+            self.resolve_computed_type(field, &computed, &mut context, &mut in_progress)?;
+        }
+
+        // References resolve against inputs and computed fields alike
+        for field in &spec.inputs {
+            for modifier in &field.modifiers {
+                if let Modifier::Ref(target) = modifier {
+                    let span = self
+                        .span(&format!("inputs.{}.ref", field.name))
+                        .or_else(|| self.span(&format!("inputs.{}", field.name)));
+                    self.validate_ref(field, target, &context)
+                        .map_err(|e| e.with_span(span))?;
+                }
+            }
+        }
+
+        self.validate_extensions(spec)
+    }
+
+    /// Check the `functions:` section
+    ///
+    /// Functions are pure: their bodies see only their parameters and other
+    /// functions, and cannot call plugins. They may call each other and
+    /// themselves, so every signature is known before any body is checked.
+    fn validate_functions(&mut self, spec: &Spec) -> ParseResult<()> {
+        self.spec_functions.clear();
+        for function in &spec.functions {
+            let span = self.span(&format!("functions.{}", function.name));
+            let error = |message: String| {
+                ParseError::Type(format!("Function '{}': {message}", function.name)).with_span(span)
+            };
+            if function.name == "ref" || self.functions.contains_key(&function.name) {
+                return Err(error("a builtin has the same name".to_string()));
+            }
+            if self.spec_functions.contains_key(&function.name) {
+                return Err(error("declared more than once".to_string()));
+            }
+
+            let mut names = HashSet::new();
+            for parameter in &function.parameters {
+                if !names.insert(parameter.name.as_str()) {
+                    return Err(error(format!(
+                        "parameter '{}' is declared more than once",
+                        parameter.name
+                    )));
+                }
+                if !self.is_valid_type(&parameter.type_expr) {
+                    return Err(error(format!(
+                        "invalid type for parameter '{}': {}",
+                        parameter.name, parameter.type_expr
+                    )));
+                }
+            }
+            if !self.is_valid_type(&function.return_type) {
+                return Err(error(format!(
+                    "invalid return type: {}",
+                    function.return_type
+                )));
+            }
+            if let Some(object) = function.body.called_objects().first() {
+                return Err(error(format!(
+                    "functions are pure and cannot call plugins, but the body calls '{object}'"
+                )));
+            }
+            self.spec_functions
+                .insert(function.name.clone(), function.clone());
+        }
+
+        for function in &spec.functions {
+            let span = self.span(&format!("functions.{}", function.name));
+            let mut context = TypeContext::new();
+            for parameter in &function.parameters {
+                context.add_variable(parameter.name.clone(), parameter.type_expr.clone());
+            }
+            let body_type = self
+                .type_of_expression(&function.body, &context)
+                .map_err(|e| match e {
+                    ParseError::Type(msg) => {
+                        ParseError::Type(format!("Function '{}': {msg}", function.name))
+                    }
+                    other => other,
+                })
+                .map_err(|e| e.with_span(span))?;
+            if !self.fits(&function.body, &body_type, &function.return_type) {
+                return Err(ParseError::Type(format!(
+                    "Function '{}' returns {body_type}, not the declared {}",
+                    function.name, function.return_type
+                ))
+                .with_span(span));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `expr`, of type `expr_type`, can be used where `target` is expected
+    ///
+    /// Whole-number literals fit `int`. Property and element types are not
+    /// tracked, so property accesses fit anything and arrays fit any array;
+    /// both are checked at runtime.
+    fn fits(&self, expr: &Expression, expr_type: &TypeExpr, target: &TypeExpr) -> bool {
+        match expr {
+            Expression::PropertyAccess(..) | Expression::OptionalPropertyAccess(..) => true,
+            Expression::Number(n) if n.fract() == 0.0 => {
+                target.non_null() == &TypeExpr::Primitive(PrimitiveType::Int)
+                    || self.types_compatible(expr_type, target)
+            }
+            // Builtins return untyped arrays, which fit any declared list
+            _ if Self::is_array(expr_type) && Self::is_array(target) => true,
+            _ => self.types_compatible(expr_type, target),
+        }
+    }
+
+    /// Whether `type_expr` is `list`, `list<T>` or `Array<T>`
+    fn is_array(type_expr: &TypeExpr) -> bool {
+        match type_expr.non_null() {
+            TypeExpr::Generic { name, .. } => name == "Array" || name == "list",
+            TypeExpr::Reference(name) => name == "list",
+            _ => false,
+        }
+    }
+
+    /// Check the extensions block and that every `object.method(...)` call
+    /// names a declared extension
+    fn validate_extensions(&self, spec: &Spec) -> ParseResult<()> {
+        let mut aliases = HashSet::new();
+        for extension in &spec.extensions {
+            let span = self.span(&format!("extensions.{}", extension.name));
+            if !aliases.insert(extension.name.as_str()) {
+                return Err(ParseError::Type(format!(
+                    "Extension '{}' is declared more than once",
+                    extension.name
+                ))
+                .with_span(span));
+            }
+            if extension.plugin_name().is_empty() {
+                return Err(ParseError::Type(format!(
+                    "Extension '{}': import(\"{}\") does not name a module",
+                    extension.name, extension.import_spec
+                ))
+                .with_span(span));
+            }
+        }
+
+        let mut calls: Vec<(String, Vec<&str>)> = Vec::new();
+        for field in &spec.inputs {
+            for modifier in &field.modifiers {
+                if let Modifier::Default(expr) = modifier {
+                    calls.push((format!("inputs.{}", field.name), expr.called_objects()));
+                }
+            }
+        }
+        for field in &spec.computed {
+            calls.push((
+                format!("computed.{}", field.name),
+                field.expression.called_objects(),
+            ));
+        }
+        for (index, constraint) in spec.constraints.iter().enumerate() {
+            calls.push((
+                format!("constraints[{index}]"),
+                constraint.expression.called_objects(),
+            ));
+        }
+        for (index, event) in spec.events.iter().enumerate() {
+            calls.push((format!("events[{index}]"), event.action.called_objects()));
+        }
+        for (index, lifecycle) in spec.lifecycle.iter().enumerate() {
+            calls.push((
+                format!("lifecycle[{index}]"),
+                lifecycle.action.called_objects(),
+            ));
+        }
+
+        for (path, objects) in calls {
+            if let Some(object) = objects.iter().find(|object| !aliases.contains(*object)) {
+                return Err(ParseError::Type(format!(
+                    "'{object}' is neither a declared extension nor a builtin; \
+                     import it in the extensions block"
+                ))
+                .with_span(self.span(&path)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Source span recorded for the spec item at `path`
+    fn span(&self, path: &str) -> Option<crate::ast::Span> {
+        self.source_map.get(path)
+    }
+
+    /// Check that `ref` names another field whose type fits this one
+    fn validate_ref(
+        &self,
+        field: &FieldDef,
+        target: &str,
+        context: &TypeContext,
+    ) -> ParseResult<()> {
+        if target == field.name {
+            return Err(ParseError::Type(format!(
+                "Field '{}': ref cannot point to the field itself",
+                field.name
+            )));
+        }
+
+        let Some(target_type) = context.get_variable_type(target) else {
+            return Err(ParseError::Type(format!(
+                "Field '{}': ref \"{target}\" does not name an input or computed field",
+                field.name
+            )));
+        };
+
+        let field_type = Self::input_type(field);
+        if self.types_compatible(target_type, &field_type) {
+            Ok(())
+        } else {
+            Err(ParseError::Type(format!(
+                "Field '{}': ref \"{target}\" has type {target_type}, which is not compatible with {field_type}",
+                field.name
+            )))
+        }
+    }
+
+    /// The type an input has inside expressions
+    ///
+    /// `optional` inputs without a non-null `default` are nullable.
+    pub fn input_type(field: &FieldDef) -> TypeExpr {
+        if field.is_nullable() && !field.type_expr.is_nullable() {
+            TypeExpr::Nullable(Box::new(field.type_expr.clone()))
+        } else {
+            field.type_expr.clone()
+        }
+    }
+
+    /// Type a computed field after the computed fields it references
+    fn resolve_computed_type<'a>(
+        &self,
+        field: &'a ComputedField,
+        computed: &HashMap<&str, &'a ComputedField>,
+        context: &mut TypeContext,
+        in_progress: &mut HashSet<&'a str>,
+    ) -> ParseResult<()> {
+        if context.get_variable_type(&field.name).is_some() {
+            return Ok(());
+        }
+        if !in_progress.insert(field.name.as_str()) {
+            return Err(ParseError::Type(format!(
+                "Computed field '{}' depends on itself",
+                field.name
+            )));
         }
 
-        // Validate computed fields
-        for computed in &spec.computed {
-            self.validate_computed_field(computed)?;
+        for name in field.referenced_identifiers() {
+            if let Some(dependency) = computed.get(name) {
+                self.resolve_computed_type(dependency, computed, context, in_progress)?;
+            }
         }
 
+        let computed_type = self
+            .validate_computed_field(field, context)
+            .map_err(|e| e.with_span(self.span(&format!("computed.{}", field.name))))?;
+        context.add_variable(field.name.clone(), computed_type);
+        in_progress.remove(field.name.as_str());
         Ok(())
     }
 
@@ -167,41 +802,260 @@ impl TypeChecker {
             )));
         }
 
+        // Optional fields accept `default: null`; required ones do not
+        let field_type = if field.modifiers.contains(&Modifier::Optional) {
+            TypeExpr::Nullable(Box::new(field.type_expr.clone()))
+        } else {
+            field.type_expr.clone()
+        };
+
         // Validate field modifiers
         for modifier in &field.modifiers {
-            self.validate_modifier(modifier, &field.type_expr)?;
+            self.validate_modifier(modifier, &field_type)
+                .map_err(|e| match e {
+                    ParseError::Type(msg) => {
+                        ParseError::Type(format!("Field '{}': {msg}", field.name))
+                    }
+                    other => other,
+                })?;
         }
 
-        Ok(())
+        Self::validate_refinement_bounds(field)
+            .and_then(|()| match Self::default_literal(field) {
+                Some(value) => Self::check_refinements(field, &value, &self.patterns)
+                    .map_err(|msg| format!("default {msg}")),
+                None => Ok(()),
+            })
+            .map_err(|msg| ParseError::Type(format!("Field '{}': {msg}", field.name)))
     }
 
-    /// Validate a computed field
-    fn validate_computed_field(&self, computed: &crate::ast::ComputedField) -> ParseResult<()> {
-        // Create a type context for the computed field
-        let context = TypeContext::new();
+    /// Check a value against a field's refinement modifiers
+    /// (`min`, `max`, `min_length`, `max_length`, `pattern`, `one_of`)
+    ///
+    /// Null passes; whether a field may be null is decided by its type.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use sigmos_core::ast::{FieldDef, Modifier, PrimitiveType, TypeExpr};
+    /// use sigmos_core::types::{PatternCache, TypeChecker};
+    ///
+    /// let field = FieldDef {
+    ///     name: "quantity".to_string(),
+    ///     type_expr: TypeExpr::Primitive(PrimitiveType::Int),
+    ///     modifiers: vec![Modifier::Min(1.0), Modifier::Max(100.0)],
+    /// };
+    /// let patterns = PatternCache::new();
+    /// assert!(TypeChecker::check_refinements(&field, &json!(10), &patterns).is_ok());
+    /// assert!(TypeChecker::check_refinements(&field, &json!(0), &patterns).is_err());
+    /// ```
+    pub fn check_refinements(
+        field: &FieldDef,
+        value: &serde_json::Value,
+        patterns: &PatternCache,
+    ) -> Result<(), String> {
+        use serde_json::Value;
+
+        let length = match value {
+            Value::String(s) => Some(s.chars().count()),
+            Value::Array(items) => Some(items.len()),
+            _ => None,
+        };
+
+        for modifier in &field.modifiers {
+            match (modifier, value) {
+                (Modifier::Min(min), Value::Number(n)) if n.as_f64() < Some(*min) => {
+                    return Err(format!("{value} is less than the minimum {min}"));
+                }
+                (Modifier::Max(max), Value::Number(n)) if n.as_f64() > Some(*max) => {
+                    return Err(format!("{value} is greater than the maximum {max}"));
+                }
+                (Modifier::MinLength(min), _) if length.is_some_and(|len| len < *min) => {
+                    return Err(format!("{value} is shorter than the minimum length {min}"));
+                }
+                (Modifier::MaxLength(max), _) if length.is_some_and(|len| len > *max) => {
+                    return Err(format!("{value} is longer than the maximum length {max}"));
+                }
+                (Modifier::Pattern(pattern), Value::String(s)) => {
+                    let regex = patterns.get(pattern)?;
+                    if !regex.is_match(s) {
+                        return Err(format!("{value} does not match pattern /{pattern}/"));
+                    }
+                }
+                (Modifier::OneOf(allowed), _) if !value.is_null() => {
+                    let same = |a: &Value, b: &Value| match (a, b) {
+                        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+                        _ => a == b,
+                    };
+                    if !allowed.iter().any(|candidate| same(candidate, value)) {
+                        let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+                        return Err(format!("{value} is not one of [{}]", allowed.join(", ")));
+                    }
+                }
+                _ => {}
+            }
+        }
 
-        // Add input fields to the context (they can be referenced in computed expressions)
-        // Note: This would need the spec context, for now we'll do basic validation
+        Ok(())
+    }
 
-        // Validate that the expression is well-formed
-        let _expr_type = self.type_of_expression(&computed.expression, &context)?;
+    /// Reject refinements that no value could satisfy
+    fn validate_refinement_bounds(field: &FieldDef) -> Result<(), String> {
+        let mut min = None;
+        let mut max = None;
+        let mut min_length = None;
+        let mut max_length = None;
+        for modifier in &field.modifiers {
+            match modifier {
+                Modifier::Min(n) => min = Some(*n),
+                Modifier::Max(n) => max = Some(*n),
+                Modifier::MinLength(n) => min_length = Some(*n),
+                Modifier::MaxLength(n) => max_length = Some(*n),
+                _ => {}
+            }
+        }
 
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(format!("min {min} is greater than max {max}"));
+            }
+        }
+        if let (Some(min), Some(max)) = (min_length, max_length) {
+            if min > max {
+                return Err(format!("min_length {min} is greater than max_length {max}"));
+            }
+        }
         Ok(())
     }
 
+    /// The field's `default:` when it is a literal
+    fn default_literal(field: &FieldDef) -> Option<serde_json::Value> {
+        field.modifiers.iter().find_map(|modifier| match modifier {
+            Modifier::Default(expr) => expr.literal_value(),
+            _ => None,
+        })
+    }
+
+    /// Validate a computed field and return the type of its expression
+    fn validate_computed_field(
+        &self,
+        computed: &ComputedField,
+        context: &TypeContext,
+    ) -> ParseResult<TypeExpr> {
+        // A field with a fallback has the fallback's value when its calls fail
+        let fallback = computed.policy.as_ref().and_then(|p| p.fallback.as_ref());
+        self.type_of_expression(&computed.expression, context)
+            .and_then(|value_type| match fallback {
+                Some(fallback) => Ok(Self::either(
+                    value_type,
+                    self.type_of_expression(fallback, context)?,
+                )),
+                None => Ok(value_type),
+            })
+            .map_err(|e| match e {
+                ParseError::Type(msg) => {
+                    ParseError::Type(format!("Computed field '{}': {msg}", computed.name))
+                }
+                other => other,
+            })
+    }
+
+    /// Type of a value that is of `then_type` or of `else_type`
+    fn either(then_type: TypeExpr, else_type: TypeExpr) -> TypeExpr {
+        if then_type == else_type {
+            then_type
+        } else if then_type.is_nullable() != else_type.is_nullable() {
+            // One branch may produce null, so the whole expression may
+            let value_type = if then_type.is_nullable() {
+                else_type
+            } else {
+                then_type
+            };
+            TypeExpr::Nullable(Box::new(value_type))
+        } else {
+            // For now, return the then_type (could be improved with type coercion)
+            then_type
+        }
+    }
+
+    /// Reject nullable operands in positions that require a value
+    fn require_non_null(expr: &Expression, expr_type: TypeExpr) -> ParseResult<TypeExpr> {
+        if !expr_type.is_nullable() {
+            return Ok(expr_type);
+        }
+
+        let subject = match expr {
+            Expression::Identifier(name) => format!("'{name}'"),
+            Expression::Null => "null".to_string(),
+            _ => "this expression".to_string(),
+        };
+        Err(ParseError::Type(format!(
+            "Value of {subject} may be null here; guard it with a null check, `??` or `?.`, or give the input a `default:`"
+        )))
+    }
+
+    /// Variables proven non-null when `condition` is true and when it is false
+    fn null_checked(condition: &Expression) -> (Vec<String>, Vec<String>) {
+        let checked_name = |left: &Expression, right: &Expression| match (left, right) {
+            (Expression::Identifier(name), Expression::Null)
+            | (Expression::Null, Expression::Identifier(name)) => Some(name.clone()),
+            _ => None,
+        };
+
+        match condition {
+            Expression::NotEqual(left, right) => {
+                (checked_name(left, right).into_iter().collect(), Vec::new())
+            }
+            Expression::Equal(left, right) => {
+                (Vec::new(), checked_name(left, right).into_iter().collect())
+            }
+            Expression::Not(operand) => {
+                let (when_true, when_false) = Self::null_checked(operand);
+                (when_false, when_true)
+            }
+            Expression::And(left, right) => {
+                let (mut when_true, _) = Self::null_checked(left);
+                when_true.extend(Self::null_checked(right).0);
+                (when_true, Vec::new())
+            }
+            Expression::Or(left, right) => {
+                let (_, mut when_false) = Self::null_checked(left);
+                when_false.extend(Self::null_checked(right).1);
+                (Vec::new(), when_false)
+            }
+            _ => (Vec::new(), Vec::new()),
+        }
+    }
+
     /// Infer the type of an expression
-    #[allow(clippy::only_used_in_recursion)]
+    ///
+    /// Expressions nested more than [`MAX_NESTING`] levels deep are rejected,
+    /// as the parser rejects them in source.
     pub fn type_of_expression(
         &self,
-        expr: &crate::ast::Expression,
+        expr: &Expression,
         context: &TypeContext,
     ) -> ParseResult<TypeExpr> {
-        use crate::ast::Expression;
+        let depth = self.depth.get();
+        if depth >= MAX_NESTING {
+            return Err(ParseError::Type(format!(
+                "Expression nests more than {MAX_NESTING} levels"
+            )));
+        }
+        self.depth.set(depth + 1);
+        let result = self.infer_type(expr, context);
+        self.depth.set(depth);
+        result
+    }
 
+    /// Type of `expr`, one level deeper than the expression containing it
+    fn infer_type(&self, expr: &Expression, context: &TypeContext) -> ParseResult<TypeExpr> {
         match expr {
             Expression::StringLiteral(_) => Ok(TypeExpr::Primitive(PrimitiveType::String)),
             Expression::Number(_) => Ok(TypeExpr::Primitive(PrimitiveType::Float)),
             Expression::Boolean(_) => Ok(TypeExpr::Primitive(PrimitiveType::Bool)),
+            Expression::Null => Ok(TypeExpr::Primitive(PrimitiveType::Null)),
 
             Expression::Identifier(name) => {
                 if let Some(var_type) = context.get_variable_type(name) {
@@ -211,6 +1065,111 @@ impl TypeChecker {
                 }
             }
 
+            Expression::FunctionCall {
+                object,
+                method,
+                arguments,
+            } if object.is_empty() && method == "ref" => {
+                match arguments.first().map(|a| &a.value) {
+                    Some(Expression::StringLiteral(target)) => {
+                        context.get_variable_type(target).cloned().ok_or_else(|| {
+                            ParseError::Type(format!(
+                                "ref(\"{target}\") does not name an input or computed field"
+                            ))
+                        })
+                    }
+                    _ => Err(ParseError::Type(
+                        "ref() requires a field name as a string literal".to_string(),
+                    )),
+                }
+            }
+
+            Expression::FunctionCall {
+                object,
+                method,
+                arguments,
+            } if object.is_empty() && self.spec_functions.contains_key(method) => {
+                let function = &self.spec_functions[method];
+                if arguments.len() != function.parameters.len() {
+                    return Err(ParseError::Type(format!(
+                        "{method}() takes {} argument(s), got {}",
+                        function.parameters.len(),
+                        arguments.len()
+                    )));
+                }
+                for (argument, parameter) in arguments.iter().zip(&function.parameters) {
+                    let mut argument_type = self.type_of_expression(&argument.value, context)?;
+                    if !parameter.type_expr.is_nullable() && !Self::is_dynamic(&parameter.type_expr)
+                    {
+                        argument_type = Self::require_non_null(&argument.value, argument_type)?;
+                    }
+                    if !self.fits(&argument.value, &argument_type, &parameter.type_expr) {
+                        return Err(ParseError::Type(format!(
+                            "Argument '{}' of {method}() must be {}, not {argument_type}",
+                            parameter.name, parameter.type_expr
+                        )));
+                    }
+                }
+                Ok(function.return_type.clone())
+            }
+
+            Expression::FunctionCall {
+                object,
+                method,
+                arguments,
+            } if object.is_empty() && self.functions.contains_key(method) => {
+                let signature = &self.functions[method];
+                signature
+                    .check_arity(method, arguments.len())
+                    .map_err(ParseError::Type)?;
+
+                for (index, argument) in arguments.iter().enumerate() {
+                    let Some(parameter) = signature.parameter(index) else {
+                        continue;
+                    };
+                    if parameter.value_type == ValueType::Function {
+                        match &argument.value {
+                            Expression::Identifier(name)
+                                if (self.functions.contains_key(name)
+                                    || self.spec_functions.contains_key(name))
+                                    && context.get_variable_type(name).is_none() =>
+                            {
+                                continue
+                            }
+                            Expression::Lambda { parameters, body } => {
+                                let items =
+                                    self.collection_argument(signature, arguments, context)?;
+                                self.type_of_lambda(parameters, body, items, context)?;
+                                continue;
+                            }
+                            _ => {
+                                return Err(ParseError::Type(format!(
+                                    "Argument '{}' of {method}() must be a function, such as the name of a builtin",
+                                    parameter.name
+                                )))
+                            }
+                        }
+                    }
+
+                    let argument_type = self.type_of_expression(&argument.value, context)?;
+                    // Property types are not tracked, so they are checked at runtime
+                    let argument_type = match &argument.value {
+                        Expression::PropertyAccess(..) | Expression::OptionalPropertyAccess(..) => {
+                            Self::any_type()
+                        }
+                        _ if parameter.value_type == ValueType::Any => argument_type,
+                        value => Self::require_non_null(value, argument_type)?,
+                    };
+                    if !parameter.value_type.accepts_type(&argument_type) {
+                        return Err(ParseError::Type(format!(
+                            "Argument '{}' of {method}() must be {}, not {argument_type}",
+                            parameter.name, parameter.value_type
+                        )));
+                    }
+                }
+                Ok(signature.return_type.type_expr())
+            }
+
             Expression::FunctionCall {
                 object,
                 method,
@@ -218,7 +1177,7 @@ impl TypeChecker {
             } => {
                 let func_name = format!("{object}.{method}");
                 if let Some(signature) = context.get_function(func_name.as_str()) {
-                    Ok(signature.return_type.clone())
+                    Ok(signature.return_type.type_expr())
                 } else {
                     // For now, assume unknown functions return strings
                     Ok(TypeExpr::Primitive(PrimitiveType::String))
@@ -229,11 +1188,16 @@ impl TypeChecker {
             | Expression::Subtract(left, right)
             | Expression::Multiply(left, right)
             | Expression::Divide(left, right) => {
-                let left_type = self.type_of_expression(left, context)?;
-                let right_type = self.type_of_expression(right, context)?;
+                let left_type =
+                    Self::require_non_null(left, self.type_of_expression(left, context)?)?;
+                let right_type =
+                    Self::require_non_null(right, self.type_of_expression(right, context)?)?;
 
                 // Simple type checking: both operands should be numeric
                 match (&left_type, &right_type) {
+                    _ if Self::is_dynamic(&left_type) || Self::is_dynamic(&right_type) => {
+                        Ok(TypeExpr::Primitive(PrimitiveType::Float))
+                    }
                     (
                         TypeExpr::Primitive(PrimitiveType::Int),
                         TypeExpr::Primitive(PrimitiveType::Int),
@@ -248,25 +1212,46 @@ impl TypeChecker {
                 }
             }
 
-            Expression::Equal(left, right)
-            | Expression::NotEqual(left, right)
-            | Expression::LessThan(left, right)
+            Expression::Equal(left, right) | Expression::NotEqual(left, right) => {
+                // Equality is how null checks are written, so null operands are allowed
+                let _left_type = self.type_of_expression(left, context)?;
+                let _right_type = self.type_of_expression(right, context)?;
+                Ok(TypeExpr::Primitive(PrimitiveType::Bool))
+            }
+
+            Expression::LessThan(left, right)
             | Expression::LessThanOrEqual(left, right)
             | Expression::GreaterThan(left, right)
             | Expression::GreaterThanOrEqual(left, right) => {
                 // Comparison operations return boolean
-                let _left_type = self.type_of_expression(left, context)?;
-                let _right_type = self.type_of_expression(right, context)?;
+                Self::require_non_null(left, self.type_of_expression(left, context)?)?;
+                Self::require_non_null(right, self.type_of_expression(right, context)?)?;
                 // TODO: Check that types are comparable
                 Ok(TypeExpr::Primitive(PrimitiveType::Bool))
             }
 
             Expression::And(left, right) | Expression::Or(left, right) => {
-                let left_type = self.type_of_expression(left, context)?;
-                let right_type = self.type_of_expression(right, context)?;
+                let left_type =
+                    Self::require_non_null(left, self.type_of_expression(left, context)?)?;
+
+                // `x != null && x > 0` and `x == null || x > 0` narrow `x` on the right
+                let (when_true, when_false) = Self::null_checked(left);
+                let narrowed = match expr {
+                    Expression::And(..) => context.narrowed(&when_true),
+                    _ => context.narrowed(&when_false),
+                };
+                let right_type =
+                    Self::require_non_null(right, self.type_of_expression(right, &narrowed)?)?;
 
                 // Both operands should be boolean
                 match (&left_type, &right_type) {
+                    (left, right)
+                        if [left, right].iter().all(|t| {
+                            Self::is_dynamic(t) || *t == &TypeExpr::Primitive(PrimitiveType::Bool)
+                        }) =>
+                    {
+                        Ok(TypeExpr::Primitive(PrimitiveType::Bool))
+                    }
                     (
                         TypeExpr::Primitive(PrimitiveType::Bool),
                         TypeExpr::Primitive(PrimitiveType::Bool),
@@ -278,11 +1263,15 @@ impl TypeChecker {
             }
 
             Expression::Not(operand) => {
-                let operand_type = self.type_of_expression(operand, context)?;
+                let operand_type =
+                    Self::require_non_null(operand, self.type_of_expression(operand, context)?)?;
                 match operand_type {
                     TypeExpr::Primitive(PrimitiveType::Bool) => {
                         Ok(TypeExpr::Primitive(PrimitiveType::Bool))
                     }
+                    ref dynamic if Self::is_dynamic(dynamic) => {
+                        Ok(TypeExpr::Primitive(PrimitiveType::Bool))
+                    }
                     _ => Err(ParseError::Type(format!(
                         "Invalid operand type for logical NOT: {operand_type:?}"
                     ))),
@@ -295,11 +1284,16 @@ impl TypeChecker {
             }
 
             Expression::Modulo(left, right) => {
-                let left_type = self.type_of_expression(left, context)?;
-                let right_type = self.type_of_expression(right, context)?;
+                let left_type =
+                    Self::require_non_null(left, self.type_of_expression(left, context)?)?;
+                let right_type =
+                    Self::require_non_null(right, self.type_of_expression(right, context)?)?;
 
                 // Modulo operation on numeric types
                 match (&left_type, &right_type) {
+                    _ if Self::is_dynamic(&left_type) || Self::is_dynamic(&right_type) => {
+                        Ok(TypeExpr::Primitive(PrimitiveType::Float))
+                    }
                     (
                         TypeExpr::Primitive(PrimitiveType::Int),
                         TypeExpr::Primitive(PrimitiveType::Int),
@@ -320,31 +1314,40 @@ impl TypeChecker {
                 if_false,
             } => {
                 let condition_type = self.type_of_expression(condition, context)?;
-                let then_type = self.type_of_expression(if_true, context)?;
-                let else_type = self.type_of_expression(if_false, context)?;
+
+                // Branches see the variables the condition proves non-null
+                let (when_true, when_false) = Self::null_checked(condition);
+                let then_type = self.type_of_expression(if_true, &context.narrowed(&when_true))?;
+                let else_type =
+                    self.type_of_expression(if_false, &context.narrowed(&when_false))?;
 
                 // Condition must be boolean
-                if !matches!(condition_type, TypeExpr::Primitive(PrimitiveType::Bool)) {
+                if !matches!(condition_type, TypeExpr::Primitive(PrimitiveType::Bool))
+                    && !Self::is_dynamic(&condition_type)
+                {
                     return Err(ParseError::Type(format!(
                         "Conditional condition must be boolean, got: {condition_type:?}"
                     )));
                 }
 
                 // Both branches should have compatible types
-                if then_type == else_type {
-                    Ok(then_type)
-                } else {
-                    // For now, return the then_type (could be improved with type coercion)
-                    Ok(then_type)
-                }
+                Ok(Self::either(then_type, else_type))
             }
 
             Expression::ArrayAccess(array_expr, index_expr) => {
-                let array_type = self.type_of_expression(array_expr, context)?;
+                let array_type = Self::require_non_null(
+                    array_expr,
+                    self.type_of_expression(array_expr, context)?,
+                )?;
                 let index_type = self.type_of_expression(index_expr, context)?;
 
-                // Index should be integer
-                if !matches!(index_type, TypeExpr::Primitive(PrimitiveType::Int)) {
+                // Index should be integer; whole-number literals count
+                let whole_literal =
+                    matches!(**index_expr, Expression::Number(n) if n.fract() == 0.0);
+                if !matches!(index_type, TypeExpr::Primitive(PrimitiveType::Int))
+                    && !whole_literal
+                    && !Self::is_dynamic(&index_type)
+                {
                     return Err(ParseError::Type(format!(
                         "Array index must be integer, got: {index_type:?}"
                     )));
@@ -355,6 +1358,7 @@ impl TypeChecker {
                     TypeExpr::Generic { name, args } if name == "Array" && args.len() == 1 => {
                         Ok(args[0].clone())
                     }
+                    ref dynamic if Self::is_dynamic(dynamic) => Ok(Self::any_type()),
                     _ => Err(ParseError::Type(format!(
                         "Cannot index non-array type: {array_type:?}"
                     ))),
@@ -362,21 +1366,117 @@ impl TypeChecker {
             }
 
             Expression::PropertyAccess(object_expr, _property) => {
-                let _object_type = self.type_of_expression(object_expr, context)?;
+                let object_type = self.type_of_expression(object_expr, context)?;
+                let object_type = Self::require_non_null(object_expr, object_type)?;
+                if Self::is_dynamic(&object_type) {
+                    return Ok(Self::any_type());
+                }
                 // For now, assume property access returns string (would need struct/object type info)
                 Ok(TypeExpr::Primitive(PrimitiveType::String))
             }
+
+            Expression::OptionalPropertyAccess(object_expr, _property) => {
+                let _object_type = self.type_of_expression(object_expr, context)?;
+                // Short-circuits to null when the object is null
+                Ok(TypeExpr::Nullable(Box::new(TypeExpr::Primitive(
+                    PrimitiveType::String,
+                ))))
+            }
+
+            Expression::Array(items) => {
+                let mut item_types = Vec::with_capacity(items.len());
+                for item in items {
+                    item_types.push(self.type_of_expression(item, context)?);
+                }
+                // Items of mixed types are only known at runtime
+                let item_type = match item_types.split_first() {
+                    Some((first, rest)) if rest.iter().all(|other| other == first) => first.clone(),
+                    _ => Self::any_type(),
+                };
+                Ok(TypeExpr::Generic {
+                    name: "Array".to_string(),
+                    args: vec![item_type],
+                })
+            }
+
+            Expression::Object(entries) => {
+                for (_, value) in entries {
+                    self.type_of_expression(value, context)?;
+                }
+                Ok(ValueType::Object.type_expr())
+            }
+
+            Expression::Lambda { .. } => Err(ParseError::Type(
+                "Lambdas can only be passed to functions that take one, such as map or filter"
+                    .to_string(),
+            )),
+
+            Expression::Coalesce(left, right) => {
+                let left_type = self.type_of_expression(left, context)?;
+                let right_type = self.type_of_expression(right, context)?;
+
+                if left_type == TypeExpr::Primitive(PrimitiveType::Null) {
+                    Ok(right_type)
+                } else if right_type.is_nullable() {
+                    Ok(TypeExpr::Nullable(Box::new(left_type.non_null().clone())))
+                } else {
+                    Ok(left_type.non_null().clone())
+                }
+            }
         }
     }
 
-    /// Validate a field modifier
-    fn validate_modifier(
+    /// Type of the collection a higher-order call iterates over: its first
+    /// array argument
+    fn collection_argument(
         &self,
-        modifier: &crate::ast::Modifier,
-        field_type: &TypeExpr,
-    ) -> ParseResult<()> {
-        use crate::ast::Modifier;
+        signature: &FunctionSignature,
+        arguments: &[Argument],
+        context: &TypeContext,
+    ) -> ParseResult<Option<TypeExpr>> {
+        for (index, argument) in arguments.iter().enumerate() {
+            if signature
+                .parameter(index)
+                .is_some_and(|parameter| parameter.value_type == ValueType::Array)
+            {
+                return self.type_of_expression(&argument.value, context).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Type of a lambda's body, with its last parameter bound to the items of
+    /// `collection` and any others (such as the total of `reduce`) dynamic
+    fn type_of_lambda(
+        &self,
+        parameters: &[String],
+        body: &Expression,
+        collection: Option<TypeExpr>,
+        context: &TypeContext,
+    ) -> ParseResult<TypeExpr> {
+        let item_type = match collection.as_ref().map(TypeExpr::non_null) {
+            Some(TypeExpr::Generic { name, args })
+                if (name == "Array" || name == "list") && args.len() == 1 =>
+            {
+                args[0].clone()
+            }
+            _ => Self::any_type(),
+        };
 
+        let mut scope = context.clone();
+        for (index, parameter) in parameters.iter().enumerate() {
+            let parameter_type = if index + 1 == parameters.len() {
+                item_type.clone()
+            } else {
+                Self::any_type()
+            };
+            scope.add_variable(parameter.clone(), parameter_type);
+        }
+        self.type_of_expression(body, &scope)
+    }
+
+    /// Validate a field modifier
+    fn validate_modifier(&self, modifier: &Modifier, field_type: &TypeExpr) -> ParseResult<()> {
         match modifier {
             Modifier::Optional => {
                 // Optional modifier is always valid
@@ -391,7 +1491,20 @@ impl TypeChecker {
             Modifier::Default(expr) => {
                 // Validate that the default expression type matches the field type
                 let context = TypeContext::new();
-                let expr_type = self.type_of_expression(expr, &context)?;
+                let expr_type = match expr {
+                    // Whole-number literals are valid defaults for int fields
+                    Expression::Number(n) if n.fract() == 0.0 => {
+                        if matches!(
+                            field_type.non_null(),
+                            TypeExpr::Primitive(PrimitiveType::Int)
+                        ) {
+                            TypeExpr::Primitive(PrimitiveType::Int)
+                        } else {
+                            TypeExpr::Primitive(PrimitiveType::Float)
+                        }
+                    }
+                    _ => self.type_of_expression(expr, &context)?,
+                };
 
                 if self.types_compatible(&expr_type, field_type) {
                     Ok(())
@@ -417,9 +1530,75 @@ impl TypeChecker {
                 Ok(())
             }
 
-            Modifier::Ref(_ref_name) => {
-                // Reference modifier - would need to validate the reference exists
-                // For now, assume it's valid
+            Modifier::Description(_) => {
+                // Descriptions are documentation only
+                Ok(())
+            }
+
+            Modifier::Min(_) | Modifier::Max(_) => match field_type.non_null() {
+                TypeExpr::Primitive(PrimitiveType::Int | PrimitiveType::Float) => Ok(()),
+                other => Err(ParseError::Type(format!(
+                    "min and max apply only to int and float fields, not {other}"
+                ))),
+            },
+
+            Modifier::MinLength(_) | Modifier::MaxLength(_) => match field_type.non_null() {
+                TypeExpr::Primitive(PrimitiveType::String) => Ok(()),
+                TypeExpr::Generic { name, .. } if name == "Array" => Ok(()),
+                other => Err(ParseError::Type(format!(
+                    "min_length and max_length apply only to string and Array fields, not {other}"
+                ))),
+            },
+
+            Modifier::Pattern(pattern) => {
+                if !matches!(
+                    field_type.non_null(),
+                    TypeExpr::Primitive(PrimitiveType::String)
+                ) {
+                    return Err(ParseError::Type(format!(
+                        "pattern applies only to string fields, not {}",
+                        field_type.non_null()
+                    )));
+                }
+                self.patterns
+                    .get(pattern)
+                    .map(|_| ())
+                    .map_err(ParseError::Type)
+            }
+
+            Modifier::OneOf(values) => {
+                if values.is_empty() {
+                    return Err(ParseError::Type(
+                        "one_of requires at least one value".to_string(),
+                    ));
+                }
+                for value in values {
+                    let value_type = match value {
+                        serde_json::Value::String(_) => TypeExpr::Primitive(PrimitiveType::String),
+                        serde_json::Value::Bool(_) => TypeExpr::Primitive(PrimitiveType::Bool),
+                        serde_json::Value::Null => TypeExpr::Primitive(PrimitiveType::Null),
+                        serde_json::Value::Number(n)
+                            if n.is_i64()
+                                && matches!(
+                                    field_type.non_null(),
+                                    TypeExpr::Primitive(PrimitiveType::Int)
+                                ) =>
+                        {
+                            TypeExpr::Primitive(PrimitiveType::Int)
+                        }
+                        _ => TypeExpr::Primitive(PrimitiveType::Float),
+                    };
+                    if !self.types_compatible(&value_type, field_type) {
+                        return Err(ParseError::Type(format!(
+                            "one_of value {value} does not match field type {field_type}"
+                        )));
+                    }
+                }
+                Ok(())
+            }
+
+            Modifier::Ref(_) => {
+                // Resolved in `validate_spec` once every field's type is known
                 Ok(())
             }
         }
@@ -427,11 +1606,20 @@ impl TypeChecker {
 
     /// Check if two types are compatible (for assignment, default values, etc.)
     fn types_compatible(&self, source_type: &TypeExpr, target_type: &TypeExpr) -> bool {
-        // Exact match
-        if source_type == target_type {
+        // Exact match, or a value whose type is only known at runtime
+        if source_type == target_type
+            || Self::is_dynamic(source_type)
+            || Self::is_dynamic(target_type)
+        {
             return true;
         }
 
+        // Null and non-null values are assignable to nullable types
+        if let TypeExpr::Nullable(inner) = target_type {
+            return source_type == &TypeExpr::Primitive(PrimitiveType::Null)
+                || self.types_compatible(source_type.non_null(), inner);
+        }
+
         // Numeric type compatibility
         match (source_type, target_type) {
             // Int can be assigned to Float
@@ -470,6 +1658,17 @@ impl TypeContext {
     pub fn get_function(&self, name: &str) -> Option<&FunctionSignature> {
         self.functions.get(name)
     }
+
+    /// A copy of the context where the named variables are known to be non-null
+    fn narrowed(&self, names: &[String]) -> TypeContext {
+        let mut context = self.clone();
+        for name in names {
+            if let Some(var_type) = context.variables.get_mut(name) {
+                *var_type = var_type.non_null().clone();
+            }
+        }
+        context
+    }
 }
 
 #[cfg(test)]
@@ -511,4 +1710,346 @@ mod tests {
         let reference = TypeExpr::Reference("UserId".to_string());
         assert!(checker.is_valid_type(&reference));
     }
+
+    fn optional_field(name: &str, type_expr: TypeExpr) -> FieldDef {
+        FieldDef {
+            name: name.to_string(),
+            type_expr,
+            modifiers: vec![Modifier::Optional],
+        }
+    }
+
+    fn spec_with(inputs: Vec<FieldDef>, expression: Expression) -> Spec {
+        Spec {
+            name: "NullSafety".to_string(),
+            version: Version {
+                major: 1,
+                minor: 0,
+                patch: None,
+            },
+            description: None,
+            inputs,
+            computed: vec![ComputedField {
+                name: "result".to_string(),
+                expression,
+                policy: None,
+            }],
+            events: vec![],
+            constraints: vec![],
+            lifecycle: vec![],
+            extensions: vec![],
+            types: vec![],
+            functions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_optional_input_requires_guard() {
+        let float = TypeExpr::Primitive(PrimitiveType::Float);
+        let discount = || Box::new(Expression::Identifier("discount".to_string()));
+
+        // Unguarded use in arithmetic is rejected
+        let unguarded = spec_with(
+            vec![optional_field("discount", float.clone())],
+            Expression::Add(discount(), Box::new(Expression::Number(1.0))),
+        );
+        let err = TypeChecker::new().validate_spec(&unguarded).unwrap_err();
+        assert!(err.to_string().contains("'discount' may be null"));
+
+        // `??` supplies a non-null fallback
+        let coalesced = spec_with(
+            vec![optional_field("discount", float.clone())],
+            Expression::Add(
+                Box::new(Expression::Coalesce(
+                    discount(),
+                    Box::new(Expression::Number(0.0)),
+                )),
+                Box::new(Expression::Number(1.0)),
+            ),
+        );
+        assert!(TypeChecker::new().validate_spec(&coalesced).is_ok());
+
+        // A null check narrows the guarded branch
+        let checked = spec_with(
+            vec![optional_field("discount", float.clone())],
+            Expression::Conditional {
+                condition: Box::new(Expression::NotEqual(discount(), Box::new(Expression::Null))),
+                if_true: Box::new(Expression::Add(
+                    discount(),
+                    Box::new(Expression::Number(1.0)),
+                )),
+                if_false: Box::new(Expression::Number(0.0)),
+            },
+        );
+        assert!(TypeChecker::new().validate_spec(&checked).is_ok());
+
+        // A default makes the input non-nullable
+        let mut defaulted = optional_field("discount", float);
+        defaulted
+            .modifiers
+            .push(Modifier::Default(Expression::Number(5.0)));
+        let with_default = spec_with(
+            vec![defaulted],
+            Expression::Add(discount(), Box::new(Expression::Number(1.0))),
+        );
+        assert!(TypeChecker::new().validate_spec(&with_default).is_ok());
+    }
+
+    #[test]
+    fn test_null_default_requires_optional() {
+        let required = FieldDef {
+            name: "limit".to_string(),
+            type_expr: TypeExpr::Primitive(PrimitiveType::Int),
+            modifiers: vec![Modifier::Default(Expression::Null)],
+        };
+        let spec = spec_with(vec![required], Expression::Number(1.0));
+        assert!(TypeChecker::new().validate_spec(&spec).is_err());
+
+        let mut optional = optional_field("limit", TypeExpr::Primitive(PrimitiveType::Int));
+        optional.modifiers.push(Modifier::Default(Expression::Null));
+        let spec = spec_with(vec![optional], Expression::Number(1.0));
+        assert!(TypeChecker::new().validate_spec(&spec).is_ok());
+    }
+
+    #[test]
+    fn test_ref_must_resolve_to_compatible_field() {
+        let parse = |inputs: &str| {
+            crate::parser::SigmosParser::parse_spec_with_source_map(&format!(
+                "spec \"Refs\" v1.0 {{\n    inputs:\n{inputs}\n    computed:\n        label: -> \"x\"\n}}"
+            ))
+            .unwrap()
+        };
+        let check = |inputs: &str| {
+            let (spec, source_map) = parse(inputs);
+            TypeChecker::new()
+                .with_source_map(source_map)
+                .validate_spec(&spec)
+        };
+
+        assert!(check("        name: string\n        alias: string { ref: \"name\" }").is_ok());
+        assert!(check("        alias: string { ref: \"label\" }").is_ok());
+
+        let missing = check("        alias: string { ref: \"nope\" }").unwrap_err();
+        assert!(missing
+            .to_string()
+            .contains("does not name an input or computed field"));
+        assert_eq!(missing.span().map(|span| span.line), Some(3));
+
+        let mismatch =
+            check("        count: int\n        alias: string { ref: \"count\" }").unwrap_err();
+        assert!(mismatch.to_string().contains("not compatible"));
+        assert_eq!(mismatch.span().map(|span| span.line), Some(4));
+    }
+
+    #[test]
+    fn test_refinements_are_checked() {
+        let check = |inputs: &str| {
+            let spec = crate::parser::SigmosParser::parse_spec(&format!(
+                "spec \"Refined\" v1.0 {{\n    inputs:\n        {inputs}\n}}"
+            ))
+            .unwrap();
+            TypeChecker::new().validate_spec(&spec)
+        };
+
+        assert!(check("qty: int { min: 1, max: 10, default: 5 }").is_ok());
+        assert!(check("code: string { pattern: /^[A-Z]+$/, default: \"ABC\" }").is_ok());
+        assert!(
+            check("tier: string { one_of: [\"gold\", \"silver\"], default: \"gold\" }").is_ok()
+        );
+
+        let error = check("qty: int { min: 1, default: 0 }")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("default 0 is less than the minimum 1"),
+            "{error}"
+        );
+        assert!(check("code: string { pattern: /^[A-Z]+$/, default: \"abc\" }").is_err());
+        assert!(check("tier: string { one_of: [\"gold\"], default: \"bronze\" }").is_err());
+        assert!(check("name: string { max_length: 2, default: \"abc\" }").is_err());
+
+        // Refinements must fit the field type and admit some value
+        assert!(check("qty: string { min: 1 }").is_err());
+        assert!(check("code: int { pattern: /x/ }").is_err());
+        assert!(check("code: string { pattern: /(/ }").is_err());
+        assert!(check("qty: int { min: 5, max: 1 }").is_err());
+        assert!(check("tier: int { one_of: [\"gold\"] }").is_err());
+    }
+
+    #[test]
+    fn test_calls_must_name_declared_extensions() {
+        let check = |extensions: &str, call: &str| {
+            let (spec, source_map) = crate::parser::SigmosParser::parse_spec_with_source_map(
+                &format!(
+                    "spec \"Agent\" v1.0 {{\n    extensions {{\n{extensions}\n    }}\n    computed:\n        reply: -> {call}\n}}"
+                ),
+            )
+            .unwrap();
+            TypeChecker::new()
+                .with_source_map(source_map)
+                .validate_spec(&spec)
+        };
+
+        let ai = "        ai: import(\"sigmos.std.net.mcp@1.0\")";
+        assert!(check(ai, "ai.complete(prompt: upper(\"hi\"))").is_ok());
+
+        let error = check(ai, "mcp.complete(prompt: \"hi\")").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("'mcp' is neither a declared extension"));
+        assert_eq!(error.span().map(|span| span.line), Some(6));
+
+        let duplicate = check(&format!("{ai}\n{ai}"), "\"hi\"").unwrap_err();
+        assert!(duplicate.to_string().contains("declared more than once"));
+    }
+
+    #[test]
+    fn test_calls_are_checked_against_function_signatures() {
+        let functions = [
+            (
+                "len".to_string(),
+                FunctionSignature::new(ValueType::Number).required("value", ValueType::Any),
+            ),
+            (
+                "upper".to_string(),
+                FunctionSignature::new(ValueType::String).required("text", ValueType::String),
+            ),
+            (
+                "map".to_string(),
+                FunctionSignature::new(ValueType::Array)
+                    .required("items", ValueType::Array)
+                    .required("function", ValueType::Function),
+            ),
+            (
+                "parse_json".to_string(),
+                FunctionSignature::new(ValueType::Any).required("text", ValueType::String),
+            ),
+        ];
+        let check = |expression: &str| {
+            let spec = crate::parser::SigmosParser::parse_spec(&format!(
+                "spec \"Calls\" v1.0 {{\n    inputs:\n        name: string\n        tags: list\n    computed:\n        value: -> {expression}\n}}"
+            ))
+            .unwrap();
+            TypeChecker::new()
+                .with_functions(functions.clone())
+                .validate_spec(&spec)
+                .map_err(|e| e.to_string())
+        };
+
+        // Results are typed by the signature, so arithmetic on len() works
+        assert!(check("len(name) + 1").is_ok());
+        assert!(check("map(tags, upper)[0]").is_ok());
+        assert!(check("parse_json(name).total * 2").is_ok());
+
+        let error = check("upper(name, name)").unwrap_err();
+        assert!(error.contains("upper() takes 1 argument, got 2"), "{error}");
+        let error = check("upper(len(name))").unwrap_err();
+        assert!(
+            error.contains("Argument 'text' of upper() must be string, not float"),
+            "{error}"
+        );
+        let error = check("map(tags, name)").unwrap_err();
+        assert!(error.contains("must be a function"), "{error}");
+    }
+
+    #[test]
+    fn test_lambdas_are_typed_by_their_collection() {
+        let map = FunctionSignature::new(ValueType::Array)
+            .required("items", ValueType::Array)
+            .required("function", ValueType::Function);
+        let upper = FunctionSignature::new(ValueType::String).required("text", ValueType::String);
+        let check = |source: &str| {
+            let spec = crate::parser::SigmosParser::parse_spec(&format!(
+                "spec \"Lambdas\" v1.0 {{\n    inputs:\n        counts: array<int>\n        limit: int\n    computed:\n        value: -> {source}\n}}"
+            ))
+            .unwrap();
+            TypeChecker::new()
+                .with_functions([
+                    ("map".to_string(), map.clone()),
+                    ("upper".to_string(), upper.clone()),
+                ])
+                .validate_spec(&spec)
+                .map_err(|e| e.to_string())
+        };
+
+        assert!(check("map(counts, c => c * 2)").is_ok());
+        // Closures see the surrounding scope
+        assert!(check("map(counts, c => c > limit)").is_ok());
+
+        let error = check("map(counts, c => upper(c))").unwrap_err();
+        assert!(error.contains("must be string, not int"), "{error}");
+        let error = check("map(counts, c => c + total)").unwrap_err();
+        assert!(error.contains("Undefined variable: total"), "{error}");
+        let error = check("c => c").unwrap_err();
+        assert!(error.contains("Lambdas can only be passed"), "{error}");
+    }
+
+    #[test]
+    fn test_nesting_is_limited() {
+        // Built by hand, since the parser rejects expressions this deep
+        let nested = |levels: usize| {
+            (1..levels).fold(Expression::Number(1.0), |expr, _| {
+                Expression::Add(Box::new(expr), Box::new(Expression::Number(1.0)))
+            })
+        };
+        let checker = TypeChecker::new();
+        let context = TypeContext::new();
+
+        assert!(checker
+            .type_of_expression(&nested(MAX_NESTING), &context)
+            .is_ok());
+        let error = checker
+            .type_of_expression(&nested(MAX_NESTING + 1), &context)
+            .unwrap_err();
+        assert!(error.to_string().contains("nests more than"), "{error}");
+        // The depth is restored after an error
+        assert!(checker.type_of_expression(&nested(2), &context).is_ok());
+    }
+
+    #[test]
+    fn test_spec_functions_are_type_checked() {
+        let upper = FunctionSignature::new(ValueType::String).required("text", ValueType::String);
+        let check = |functions: &str, expression: &str| {
+            let spec = crate::parser::SigmosParser::parse_spec(&format!(
+                "spec \"Scores\" v1.0 {{\n    inputs:\n        name: string\n        exposure: float\n    functions:\n        {functions}\n    computed:\n        value: -> {expression}\n}}"
+            ))
+            .unwrap();
+            TypeChecker::new()
+                .with_functions([("upper".to_string(), upper.clone())])
+                .validate_spec(&spec)
+                .map_err(|e| e.to_string())
+        };
+        let score = "score(x: float, w: float) -> float = x * w";
+
+        assert!(check(score, "score(exposure, 2) + 1").is_ok());
+        assert!(check(
+            "fib(n: float) -> float = n < 2 ? n : fib(n - 1) + fib(n - 2)",
+            "fib(10)"
+        )
+        .is_ok());
+
+        let error = check(score, "score(exposure)").unwrap_err();
+        assert!(
+            error.contains("score() takes 2 argument(s), got 1"),
+            "{error}"
+        );
+        let error = check(score, "score(name, 2)").unwrap_err();
+        assert!(
+            error.contains("Argument 'x' of score() must be float, not string"),
+            "{error}"
+        );
+        let error = check("shout(x: float) -> string = upper(x)", "shout(1)").unwrap_err();
+        assert!(error.contains("Function 'shout'"), "{error}");
+        let error = check("greet(x: string) -> string = x + name", "greet(name)").unwrap_err();
+        assert!(error.contains("Undefined variable: name"), "{error}");
+        let error = check("half(x: float) -> bool = x / 2", "half(1)").unwrap_err();
+        assert!(
+            error.contains("returns float, not the declared bool"),
+            "{error}"
+        );
+        let error = check("upper(x: string) -> string = x", "upper(name)").unwrap_err();
+        assert!(error.contains("a builtin has the same name"), "{error}");
+        let error = check("ask(x: string) -> string = llm.complete(x)", "ask(name)").unwrap_err();
+        assert!(error.contains("cannot call plugins"), "{error}");
+    }
 }
@@ -0,0 +1,754 @@
+//! Runtime configuration files
+//!
+//! `sigmos run` builds its plugins and runtime settings from a `sigmos.toml`
+//! file:
+//!
+//! ```toml
+//! [runtime]
+//! log_level = "info"
+//! timeout_seconds = 60
+//! max_concurrency = 4
+//!
+//! [secrets]
+//! openai = { env = "OPENAI_API_KEY" }
+//!
+//! [plugins.mcp]
+//! type = "mcp"
+//! aliases = ["llm"]
+//! model = "gpt-4"
+//! api_key = { secret = "openai" }
+//!
+//! [plugins.api]
+//! type = "rest"
+//! base_url = "https://staging.example.com"
+//!
+//! [profiles.prod.runtime]
+//! log_level = "warn"
+//!
+//! [profiles.prod.plugins.api]
+//! base_url = "https://api.example.com"
+//! ```
+//!
+//! Each `[plugins.<name>]` table registers one plugin under `<name>`. `type`
+//! picks the implementation, `aliases` adds more names, `enabled = false`
+//! registers it disabled, and the remaining keys set fields of its config
+//! struct ([`McpConfig`] or [`RestConfig`]); fields left out keep their
+//! defaults. A setting written as `{ secret = "<name>" }` takes the value of a
+//! secret, read from an environment variable (`env`) or a file (`file`,
+//! relative to the working directory) when the config is loaded.
+//!
+//! Selecting a profile overlays its `runtime`, `secrets` and `plugins` tables
+//! key by key onto the rest of the file. Errors point at the key or value at
+//! fault, in whichever of the two it was written.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_plugins::config::RuntimeConfig;
+//! use sigmos_plugins::rest::RestConfig;
+//! use sigmos_plugins::config::PluginSettings;
+//!
+//! let source = r#"
+//! [plugins.api]
+//! type = "rest"
+//! base_url = "https://staging.example.com"
+//!
+//! [profiles.prod.plugins.api]
+//! base_url = "https://api.example.com"
+//! "#;
+//!
+//! let config = RuntimeConfig::from_toml(source, Some("prod")).unwrap();
+//! let PluginSettings::Rest(RestConfig { base_url, .. }) = &config.plugins[0].settings else {
+//!     unreachable!()
+//! };
+//! assert_eq!(base_url, "https://api.example.com");
+//! assert!(config.build_registry().unwrap().has_plugin("api"));
+//!
+//! let error = RuntimeConfig::from_toml(source, Some("qa")).unwrap_err();
+//! assert_eq!(error.message, "profile 'qa' is not defined; available profiles: prod");
+//! ```
+
+use crate::mcp::{McpConfig, McpPlugin};
+use crate::registry::PluginRegistry;
+use crate::rest::{RestConfig, RestPlugin};
+use crate::{ConfigurablePlugin, PluginConfig, PluginError, PluginMetadata};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Serialize};
+use sigmos_core::ast::Span;
+use sigmos_core::taint::SECRET_MASK;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use toml_edit::{ImDocument, InlineTable, Item, Key, TableLike, Value};
+
+/// Error in a runtime configuration file
+#[derive(Error, Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub message: String,
+    /// Location of the key or value at fault, when it is known
+    pub span: Option<Span>,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} (at {span})", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// How much `sigmos run` reports on stderr besides errors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// The `[runtime]` table
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RuntimeSettings {
+    pub log_level: LogLevel,
+    /// Deadline for a run; plugin calls still in flight when it passes are
+    /// cancelled
+    pub timeout_seconds: Option<u64>,
+    /// Most computed fields evaluated at once, overriding the runtime's
+    /// default
+    pub max_concurrency: Option<usize>,
+}
+
+impl RuntimeSettings {
+    const KEYS: [&'static str; 3] = ["log_level", "timeout_seconds", "max_concurrency"];
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_seconds.map(Duration::from_secs)
+    }
+}
+
+/// Where a secret's value comes from
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretSource {
+    /// An environment variable
+    Env(String),
+    /// A file, read without its trailing newline
+    File(PathBuf),
+}
+
+impl SecretSource {
+    fn read(&self) -> Result<Secret, String> {
+        match self {
+            SecretSource::Env(variable) => std::env::var(variable)
+                .map(Secret)
+                .map_err(|_| format!("environment variable {variable} is not set")),
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map(|content| Secret(content.trim_end_matches(['\r', '\n']).to_string()))
+                .map_err(|e| format!("cannot read {}: {e}", path.display())),
+        }
+    }
+}
+
+/// A secret's value, masked in `Debug` output
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret({SECRET_MASK})")
+    }
+}
+
+/// Implementation and settings of a configured plugin
+#[derive(Debug, Clone)]
+pub enum PluginSettings {
+    Mcp(McpConfig),
+    Rest(RestConfig),
+}
+
+/// A `[plugins.<name>]` table
+#[derive(Debug, Clone)]
+pub struct PluginInstance {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub enabled: bool,
+    pub settings: PluginSettings,
+}
+
+/// Runtime configuration, usually loaded from `sigmos.toml`
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeConfig {
+    pub runtime: RuntimeSettings,
+    pub secrets: BTreeMap<String, Secret>,
+    /// Plugins in the order they appear in the file
+    pub plugins: Vec<PluginInstance>,
+    /// Profile the configuration was loaded with
+    pub profile: Option<String>,
+}
+
+impl RuntimeConfig {
+    /// File name looked for next to a spec when no config is given
+    pub const FILE_NAME: &'static str = "sigmos.toml";
+
+    /// Parse a configuration, overlaying `profile` if one is given
+    ///
+    /// Secrets are read here, so a missing environment variable or file is
+    /// reported against the secret that names it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_plugins::config::RuntimeConfig;
+    ///
+    /// let error = RuntimeConfig::from_toml("[plugins.ai]\ntype = \"mcp\"\ntemperature = 3.0\n", None)
+    ///     .unwrap_err();
+    /// assert_eq!(error.span.unwrap().line, 3);
+    /// ```
+    pub fn from_toml(source: &str, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let document = ImDocument::parse(source).map_err(|e| ConfigError {
+            message: e.message().trim().to_string(),
+            span: locate(source, e.span()),
+        })?;
+        let root: &dyn TableLike = document.as_table();
+
+        let mut layers = Layers {
+            source,
+            tables: vec![root],
+        };
+        layers.check_keys(
+            &table_entries(root),
+            &["runtime", "secrets", "plugins", "profiles"],
+            "the config",
+        )?;
+        if let Some(profile) = profile {
+            let table = layers.profile(root, profile)?;
+            layers.check_keys(
+                &table_entries(table),
+                &["runtime", "secrets", "plugins"],
+                &format!("profile '{profile}'"),
+            )?;
+            layers.tables.push(table);
+        }
+
+        let secrets = layers.secrets()?;
+        Ok(Self {
+            runtime: layers.runtime()?,
+            plugins: layers.plugins(&secrets)?,
+            secrets,
+            profile: profile.map(str::to_string),
+        })
+    }
+
+    /// Value of the secret `name`
+    pub fn secret(&self, name: &str) -> Option<&str> {
+        self.secrets.get(name).map(Secret::expose)
+    }
+
+    /// Registry holding every configured plugin under its name and aliases
+    pub fn build_registry(&self) -> Result<PluginRegistry, PluginError> {
+        let mut registry = PluginRegistry::new();
+        for instance in &self.plugins {
+            match &instance.settings {
+                PluginSettings::Mcp(config) => registry.register_plugin(
+                    Box::new(McpPlugin::new(config.clone())?),
+                    PluginMetadata {
+                        name: instance.name.clone(),
+                        ..McpPlugin::metadata()
+                    },
+                    McpPlugin::capabilities(),
+                )?,
+                PluginSettings::Rest(config) => registry.register_async_plugin(
+                    Box::new(RestPlugin::new(config.clone())?),
+                    PluginMetadata {
+                        name: instance.name.clone(),
+                        ..RestPlugin::metadata()
+                    },
+                    RestPlugin::capabilities(),
+                )?,
+            }
+            for alias in &instance.aliases {
+                registry.add_alias(alias, &instance.name)?;
+            }
+            if !instance.enabled {
+                registry.disable_plugin(&instance.name)?;
+            }
+        }
+        Ok(registry)
+    }
+}
+
+type Entry<'a> = (&'a Key, &'a Item);
+
+/// The parsed file, with the selected profile's tables layered over it
+struct Layers<'a> {
+    source: &'a str,
+    tables: Vec<&'a dyn TableLike>,
+}
+
+impl<'a> Layers<'a> {
+    fn error(&self, message: impl Into<String>, range: Option<Range<usize>>) -> ConfigError {
+        ConfigError {
+            message: message.into(),
+            span: locate(self.source, range),
+        }
+    }
+
+    /// Entries of the table at `path` in file order, later layers replacing
+    /// the values of earlier ones
+    fn entries(&self, path: &[&str]) -> Result<Vec<Entry<'a>>, ConfigError> {
+        let mut entries: Vec<Entry<'a>> = Vec::new();
+        for &layer in &self.tables {
+            let Some(table) = self.table_at(layer, path)? else {
+                continue;
+            };
+            for (key, item) in table_entries(table) {
+                match entries.iter_mut().find(|(seen, _)| seen.get() == key.get()) {
+                    Some(entry) => *entry = (key, item),
+                    None => entries.push((key, item)),
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn table_at(
+        &self,
+        mut table: &'a dyn TableLike,
+        path: &[&str],
+    ) -> Result<Option<&'a dyn TableLike>, ConfigError> {
+        for segment in path {
+            let Some((key, item)) = table.get_key_value(segment) else {
+                return Ok(None);
+            };
+            table = item.as_table_like().ok_or_else(|| {
+                self.error(format!("`{}` must be a table", path.join(".")), key.span())
+            })?;
+        }
+        Ok(Some(table))
+    }
+
+    fn profile(
+        &self,
+        root: &'a dyn TableLike,
+        name: &str,
+    ) -> Result<&'a dyn TableLike, ConfigError> {
+        let profiles = self.table_at(root, &["profiles"])?;
+        match profiles.and_then(|profiles| profiles.get_key_value(name)) {
+            Some((key, item)) => item
+                .as_table_like()
+                .ok_or_else(|| self.error(format!("profile '{name}' must be a table"), key.span())),
+            None => {
+                let available: Vec<&str> = profiles
+                    .map(|profiles| profiles.iter().map(|(name, _)| name).collect())
+                    .unwrap_or_default();
+                let message = if available.is_empty() {
+                    format!("profile '{name}' is not defined; the config has no profiles")
+                } else {
+                    format!(
+                        "profile '{name}' is not defined; available profiles: {}",
+                        available.join(", ")
+                    )
+                };
+                Err(self.error(message, None))
+            }
+        }
+    }
+
+    fn check_keys(
+        &self,
+        entries: &[Entry<'a>],
+        allowed: &[&str],
+        table: &str,
+    ) -> Result<(), ConfigError> {
+        match entries
+            .iter()
+            .find(|(key, _)| !allowed.contains(&key.get()))
+        {
+            Some((key, _)) => Err(self.error(
+                format!(
+                    "unknown key `{}` in {table}; expected one of: {}",
+                    key.get(),
+                    allowed.join(", ")
+                ),
+                key.span(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn runtime(&self) -> Result<RuntimeSettings, ConfigError> {
+        let entries = self.entries(&["runtime"])?;
+        self.check_keys(&entries, &RuntimeSettings::KEYS, "[runtime]")?;
+        let settings: RuntimeSettings = self.table(owned(&entries), None)?;
+        let zero = [
+            ("timeout_seconds", settings.timeout_seconds == Some(0)),
+            ("max_concurrency", settings.max_concurrency == Some(0)),
+        ];
+        if let Some((name, _)) = zero.into_iter().find(|(_, zero)| *zero) {
+            let span = entries
+                .iter()
+                .find(|(key, _)| key.get() == name)
+                .and_then(|(_, item)| item.span());
+            return Err(self.error(format!("{name} must be greater than 0"), span));
+        }
+        Ok(settings)
+    }
+
+    fn secrets(&self) -> Result<BTreeMap<String, Secret>, ConfigError> {
+        self.entries(&["secrets"])?
+            .into_iter()
+            .map(|(key, item)| {
+                let source: SecretSource = self.value(key, item)?;
+                let secret = source.read().map_err(|e| {
+                    self.error(
+                        format!("secret '{}': {e}", key.get()),
+                        item.span().or(key.span()),
+                    )
+                })?;
+                Ok((key.get().to_string(), secret))
+            })
+            .collect()
+    }
+
+    fn plugins(
+        &self,
+        secrets: &BTreeMap<String, Secret>,
+    ) -> Result<Vec<PluginInstance>, ConfigError> {
+        let mut plugins = Vec::new();
+        let mut alias_spans = Vec::new();
+        for (key, _) in self.entries(&["plugins"])? {
+            let name = key.get();
+            let mut kind = None;
+            let mut instance = PluginInstance {
+                name: name.to_string(),
+                aliases: Vec::new(),
+                enabled: true,
+                settings: PluginSettings::Mcp(McpConfig::default()),
+            };
+            let mut aliases_span = None;
+            let mut settings = Vec::new();
+
+            for (field, item) in self.entries(&["plugins", name])? {
+                match field.get() {
+                    "type" => kind = Some((self.value::<String>(field, item)?, item.span())),
+                    "aliases" => {
+                        instance.aliases = self.value(field, item)?;
+                        aliases_span = item.span();
+                    }
+                    "enabled" => instance.enabled = self.value(field, item)?,
+                    "name" => {
+                        return Err(self.error(
+                            format!("plugin '{name}' is named by its table; remove `name`"),
+                            field.span(),
+                        ))
+                    }
+                    _ => settings.push((field.clone(), self.resolve_secret(item, secrets)?)),
+                }
+            }
+
+            let Some((kind, kind_span)) = kind else {
+                return Err(self.error(
+                    format!("plugin '{name}' needs a `type`: mcp or rest"),
+                    key.span(),
+                ));
+            };
+            instance.settings = match kind.as_str() {
+                "mcp" => PluginSettings::Mcp(self.plugin_config(key, "mcp", settings)?),
+                "rest" => PluginSettings::Rest(self.plugin_config(key, "rest", settings)?),
+                other => {
+                    return Err(self.error(
+                        format!("unknown plugin type '{other}'; expected mcp or rest"),
+                        kind_span,
+                    ))
+                }
+            };
+            plugins.push(instance);
+            alias_spans.push(aliases_span);
+        }
+
+        let mut taken: HashSet<&str> = plugins.iter().map(|p| p.name.as_str()).collect();
+        for (plugin, span) in plugins.iter().zip(alias_spans) {
+            for alias in &plugin.aliases {
+                if !taken.insert(alias) {
+                    return Err(self.error(
+                        format!(
+                            "alias '{alias}' of plugin '{}' is already in use",
+                            plugin.name
+                        ),
+                        span,
+                    ));
+                }
+            }
+        }
+        Ok(plugins)
+    }
+
+    /// Settings of the plugin at `key`, checked against its config struct
+    fn plugin_config<C>(
+        &self,
+        key: &Key,
+        kind: &str,
+        settings: Vec<(Key, Item)>,
+    ) -> Result<C, ConfigError>
+    where
+        C: PluginConfig + Default + Serialize + DeserializeOwned,
+    {
+        let name = key.get();
+        let known: Vec<String> = match serde_json::to_value(C::default()) {
+            Ok(serde_json::Value::Object(fields)) => fields
+                .into_iter()
+                .map(|(field, _)| field)
+                .filter(|f| f != "name")
+                .collect(),
+            _ => Vec::new(),
+        };
+        if let Some((field, _)) = settings
+            .iter()
+            .find(|(field, _)| !known.iter().any(|k| k == field.get()))
+        {
+            return Err(self.error(
+                format!(
+                    "unknown setting `{}` for {kind} plugin '{name}'; expected one of: {}",
+                    field.get(),
+                    known.join(", ")
+                ),
+                field.span(),
+            ));
+        }
+
+        // Validation messages start with the field they are about
+        let spans: Vec<(String, Option<Range<usize>>)> = settings
+            .iter()
+            .map(|(field, item)| (field.get().to_string(), item.span().or(field.span())))
+            .collect();
+        let mut entries = settings;
+        entries.push((Key::new("name"), Item::Value(Value::from(name))));
+        let config: C = self.table(entries, key.span())?;
+
+        config.validate().map_err(|e| {
+            let message = match e {
+                PluginError::InvalidConfiguration(message) => message,
+                other => other.to_string(),
+            };
+            let span = spans
+                .iter()
+                .find(|(field, _)| message.split_whitespace().next() == Some(field.as_str()))
+                .map_or(key.span(), |(_, span)| span.clone());
+            self.error(format!("plugin '{name}': {message}"), span)
+        })?;
+        Ok(config)
+    }
+
+    /// `item`, or the secret's value if it is written `{ secret = "<name>" }`
+    fn resolve_secret(
+        &self,
+        item: &Item,
+        secrets: &BTreeMap<String, Secret>,
+    ) -> Result<Item, ConfigError> {
+        let reference = item
+            .as_inline_table()
+            .filter(|table| table.len() == 1)
+            .and_then(|table| table.get("secret"))
+            .and_then(Value::as_str);
+        let Some(reference) = reference else {
+            return Ok(item.clone());
+        };
+        match secrets.get(reference) {
+            Some(secret) => Ok(Item::Value(Value::from(secret.expose()))),
+            None => Err(self.error(
+                format!("unknown secret '{reference}'; declare it in [secrets]"),
+                item.span(),
+            )),
+        }
+    }
+
+    fn value<T: DeserializeOwned>(&self, key: &Key, item: &Item) -> Result<T, ConfigError> {
+        let value = self.as_value(key, item)?;
+        T::deserialize(value.into_deserializer()).map_err(|e| {
+            self.error(
+                format!("`{}`: {}", key.get(), e.message()),
+                e.span().or(item.span()).or(key.span()),
+            )
+        })
+    }
+
+    /// Deserialize `entries` as one table, pointing errors at the value at
+    /// fault or else at `fallback`
+    fn table<T: DeserializeOwned>(
+        &self,
+        entries: Vec<(Key, Item)>,
+        fallback: Option<Range<usize>>,
+    ) -> Result<T, ConfigError> {
+        let mut table = InlineTable::new();
+        for (key, item) in &entries {
+            table.insert(key.get(), self.as_value(key, item)?);
+        }
+        T::deserialize(Value::InlineTable(table).into_deserializer()).map_err(|e| {
+            self.error(
+                e.to_string().trim().replace('\n', " "),
+                e.span().or(fallback),
+            )
+        })
+    }
+
+    fn as_value(&self, key: &Key, item: &Item) -> Result<Value, ConfigError> {
+        item.clone().into_value().map_err(|_| {
+            self.error(
+                format!("`{}` must be a value or inline table", key.get()),
+                key.span(),
+            )
+        })
+    }
+}
+
+fn table_entries(table: &dyn TableLike) -> Vec<Entry<'_>> {
+    table
+        .iter()
+        .filter_map(|(name, _)| table.get_key_value(name))
+        .collect()
+}
+
+fn owned(entries: &[Entry<'_>]) -> Vec<(Key, Item)> {
+    entries
+        .iter()
+        .map(|(key, item)| ((*key).clone(), (*item).clone()))
+        .collect()
+}
+
+/// Span with line and column of the byte range `range` in `source`
+fn locate(source: &str, range: Option<Range<usize>>) -> Option<Span> {
+    let range = range?;
+    let before = source.get(..range.start)?;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Some(Span {
+        offset: range.start,
+        length: range.len(),
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[runtime]
+log_level = "info"
+
+[plugins.assistant]
+type = "mcp"
+aliases = ["mcp"]
+model = "gpt-4"
+temperature = 0.2
+
+[plugins.api]
+type = "rest"
+base_url = "https://staging.example.com"
+
+[profiles.prod.runtime]
+log_level = "warn"
+timeout_seconds = 30
+
+[profiles.prod.plugins.assistant]
+temperature = 3.5
+"#;
+
+    #[test]
+    fn test_profiles_overlay_base_settings() {
+        let config = RuntimeConfig::from_toml(CONFIG, None).unwrap();
+        assert_eq!(config.runtime.log_level, LogLevel::Info);
+        assert_eq!(config.runtime.timeout(), None);
+        let names: Vec<&str> = config.plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["assistant", "api"]);
+        let PluginSettings::Mcp(mcp) = &config.plugins[0].settings else {
+            panic!("expected an mcp plugin");
+        };
+        assert_eq!(
+            (mcp.name.as_str(), mcp.model.as_str()),
+            ("assistant", "gpt-4")
+        );
+        assert_eq!(mcp.endpoint, McpConfig::default().endpoint);
+
+        let registry = config.build_registry().unwrap();
+        assert!(registry.has_plugin("assistant") && registry.has_plugin("mcp"));
+
+        // The profile's temperature is out of range; the error points at it
+        let error = RuntimeConfig::from_toml(CONFIG, Some("prod")).unwrap_err();
+        assert!(
+            error.message.contains("temperature must be between"),
+            "{error}"
+        );
+        assert_eq!(error.span.unwrap().line, 20);
+    }
+
+    #[test]
+    fn test_errors_point_at_the_offending_key() {
+        let line = |source: &str| {
+            RuntimeConfig::from_toml(source, None)
+                .unwrap_err()
+                .span
+                .unwrap()
+                .line
+        };
+
+        assert_eq!(line("[plugins.ai]\ntype = \"mcp\"\nmodel = 4\n"), 3);
+        assert_eq!(
+            line("[plugins.ai]\ntype = \"mcp\"\n\nmodle = \"gpt-4\"\n"),
+            4
+        );
+        assert_eq!(line("[plugins.ai]\ntype = \"grpc\"\n"), 2);
+        assert_eq!(line("[runtime]\nlog_level = \"loud\"\n"), 2);
+        assert_eq!(
+            line("[runtime]\nlog_level = \"warn\"\nmax_concurrency = 0\n"),
+            3
+        );
+        assert_eq!(line("[plugins]\nai = { type = \"mcp\" }\n[telemetry]\n"), 3);
+        assert_eq!(line("[plugins.ai]\ntype = \"mcp\"\nmodel = \n"), 3);
+        assert_eq!(
+            line("[plugins.a]\ntype = \"mcp\"\n[plugins.b]\ntype = \"rest\"\naliases = [\"a\"]\n"),
+            5
+        );
+    }
+
+    #[test]
+    fn test_secrets_fill_plugin_settings() {
+        std::env::set_var("SIGMOS_CONFIG_TEST_TOKEN", "s3cret");
+        let config = RuntimeConfig::from_toml(
+            "[secrets]\ntoken = { env = \"SIGMOS_CONFIG_TEST_TOKEN\" }\n\
+             [plugins.api]\ntype = \"rest\"\nauth_token = { secret = \"token\" }\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.secret("token"), Some("s3cret"));
+        assert_eq!(format!("{:?}", config.secrets["token"]), "Secret(***)");
+        assert!(matches!(
+            &config.plugins[0].settings,
+            PluginSettings::Rest(rest) if rest.auth_token.as_deref() == Some("s3cret")
+        ));
+
+        let error = RuntimeConfig::from_toml(
+            "[plugins.api]\ntype = \"rest\"\nauth_token = { secret = \"token\" }\n",
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.message,
+            "unknown secret 'token'; declare it in [secrets]"
+        );
+        assert_eq!(error.span.unwrap().line, 3);
+    }
+}
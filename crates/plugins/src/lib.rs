@@ -5,6 +5,7 @@
 //! - MCP (Model Context Protocol) plugin for AI integration
 //! - REST plugin for HTTP/API interactions
 //! - Plugin registry and management
+//! - Runtime configuration files (`sigmos.toml`)
 //!
 //! # Usage Example
 //!
@@ -18,8 +19,9 @@
 //! use std::collections::HashMap;
 //! use serde_json::Value as JsonValue;
 //!
-//! // Create plugin registry
-//! let mut registry = PluginRegistry::new();
+//! // Create plugin registry; synchronous calls run on the given Tokio runtime
+//! let runtime = tokio::runtime::Runtime::new().unwrap();
+//! let mut registry = PluginRegistry::new().with_runtime(runtime.handle().clone());
 //!
 //! // Configure and register MCP plugin
 //! let mcp_config = McpConfig {
@@ -62,9 +64,12 @@
 //! let api_response = registry.execute_plugin_method("rest", "get", &args).unwrap();
 //! ```
 
-use sigmos_runtime::Plugin;
+use sigmos_runtime::{Plugin, RuntimeError, RuntimeResult};
+use std::future::Future;
 use thiserror::Error;
+use tokio::runtime::Handle;
 
+pub mod config;
 pub mod mcp;
 pub mod registry;
 pub mod rest;
@@ -86,6 +91,26 @@ pub enum PluginError {
     SerializationError(#[from] serde_json::Error),
 }
 
+impl PluginError {
+    /// Whether the operation that failed with this error may succeed if it is
+    /// tried again
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, PluginError::NetworkError(_))
+    }
+}
+
+/// Retryable plugin errors become [`RuntimeError::Transient`], so policies
+/// retry the calls that failed with them
+impl From<PluginError> for RuntimeError {
+    fn from(error: PluginError) -> Self {
+        if error.is_retryable() {
+            RuntimeError::Transient(error.to_string())
+        } else {
+            RuntimeError::Plugin(error.to_string())
+        }
+    }
+}
+
 /// Plugin configuration trait
 pub trait PluginConfig: std::fmt::Debug + Clone {
     /// Validate the configuration
@@ -130,6 +155,50 @@ pub struct PluginCapabilities {
     pub requires_auth: bool,
 }
 
+/// Handle of the Tokio runtime a synchronous plugin call runs on:
+/// `configured` if given, or else the runtime of the calling thread
+///
+/// # Examples
+///
+/// ```rust
+/// assert!(sigmos_plugins::runtime_handle(None).is_err());
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// assert!(sigmos_plugins::runtime_handle(Some(runtime.handle())).is_ok());
+/// ```
+pub fn runtime_handle(configured: Option<&Handle>) -> RuntimeResult<Handle> {
+    configured
+        .cloned()
+        .or_else(|| Handle::try_current().ok())
+        .ok_or_else(|| {
+            RuntimeError::Plugin(
+                "No Tokio runtime to run the plugin call on; call it from within one or configure a runtime handle".to_string(),
+            )
+        })
+}
+
+/// Run `future` on the Tokio runtime behind `handle` and wait for its result
+///
+/// The calling thread blocks until the future completes, so it must not be
+/// the only thread driving that runtime, as a current-thread runtime's is.
+///
+/// # Examples
+///
+/// ```rust
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let answer = sigmos_plugins::block_on(runtime.handle(), async { Ok(42) }).unwrap();
+/// assert_eq!(answer, 42);
+/// ```
+pub fn block_on<T, F>(handle: &Handle, future: F) -> RuntimeResult<T>
+where
+    F: Future<Output = RuntimeResult<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    let task = handle.spawn(future);
+    futures::executor::block_on(task)
+        .map_err(|e| RuntimeError::Plugin(format!("Plugin task failed: {e}")))?
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,4 +208,15 @@ mod tests {
         let error = PluginError::MethodNotFound("test_method".to_string());
         assert!(error.to_string().contains("test_method"));
     }
+
+    #[test]
+    fn test_network_errors_are_retryable() {
+        let error = PluginError::NetworkError("connection reset".to_string());
+        assert!(error.is_retryable());
+        assert!(RuntimeError::from(error).is_retryable());
+
+        let error = PluginError::ExecutionFailed("bad request".to_string());
+        assert!(!error.is_retryable());
+        assert!(matches!(RuntimeError::from(error), RuntimeError::Plugin(_)));
+    }
 }
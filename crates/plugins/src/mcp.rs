@@ -10,7 +10,10 @@ use sigmos_runtime::{Plugin, RuntimeError, RuntimeResult};
 use std::collections::HashMap;
 
 /// MCP plugin configuration
+///
+/// Fields missing when deserializing take their [`Default`] values.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct McpConfig {
     pub name: String,
     pub endpoint: String,
@@ -101,6 +104,10 @@ impl Plugin for McpPlugin {
         &self.config.name
     }
 
+    fn version(&self) -> Option<&str> {
+        Some(Self::VERSION)
+    }
+
     fn initialize(&mut self) -> RuntimeResult<()> {
         // Initialize MCP connection
         // For now, this is a placeholder - would normally establish connection to MCP server
@@ -236,11 +243,14 @@ impl McpPlugin {
         }))
     }
 
+    /// Plugin version reported to `import("...@x.y")` requirements
+    pub const VERSION: &'static str = "1.0.0";
+
     /// Get plugin metadata
     pub fn metadata() -> PluginMetadata {
         PluginMetadata {
             name: "mcp".to_string(),
-            version: "1.0.0".to_string(),
+            version: Self::VERSION.to_string(),
             description: "Model Context Protocol integration for AI services".to_string(),
             author: "SIGMOS Team".to_string(),
             methods: vec![
@@ -2,28 +2,87 @@
 //!
 //! This module provides plugin registration, discovery, and management capabilities
 //! for the SIGMOS plugin system.
+//!
+//! A registry can back a [`Runtime`], so calls made by specs resolve aliases,
+//! fail for disabled plugins, respect the capability policy and reach plugins
+//! initialized in registration order.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_plugins::mcp::{McpConfig, McpPlugin};
+//! use sigmos_plugins::registry::PluginRegistry;
+//! use sigmos_plugins::ConfigurablePlugin;
+//! use sigmos_runtime::Runtime;
+//!
+//! let mut registry = PluginRegistry::new();
+//! registry
+//!     .register_plugin_with_aliases(
+//!         Box::new(McpPlugin::new(McpConfig::default()).unwrap()),
+//!         McpPlugin::metadata(),
+//!         McpPlugin::capabilities(),
+//!         vec!["ai".to_string()],
+//!     )
+//!     .unwrap();
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Assistant" v1.0 {
+//!     computed:
+//!         answer: -> ai.complete(prompt: "hello")
+//! }
+//! "#).unwrap();
+//!
+//! # tokio_test::block_on(async {
+//! let mut runtime = Runtime::from(registry);
+//! runtime.execute(&spec).await.unwrap();
+//! # });
+//! ```
 
 use crate::{PluginCapabilities, PluginError, PluginMetadata};
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use sigmos_runtime::{Plugin, RuntimeResult};
+use sigmos_runtime::plugins::{AsyncPlugin, Blocking, CancellationToken, PluginHost};
+use sigmos_runtime::{Plugin, Runtime, RuntimeError, RuntimeResult};
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::RwLock;
+
+/// Decides from its capabilities whether a plugin may be called
+pub type CapabilityPolicy = Arc<dyn Fn(&PluginCapabilities) -> bool + Send + Sync>;
 
 /// Plugin registry entry
 #[derive(Debug)]
 pub struct PluginEntry {
     pub metadata: PluginMetadata,
     pub capabilities: PluginCapabilities,
-    pub plugin: Arc<RwLock<Box<dyn Plugin>>>,
+    pub plugin: Arc<RwLock<Box<dyn AsyncPlugin>>>,
     pub enabled: bool,
+    pub initialized: bool,
 }
 
 /// Plugin registry for managing loaded plugins
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct PluginRegistry {
     plugins: HashMap<String, PluginEntry>,
     aliases: HashMap<String, String>, // alias -> plugin_name mapping
+    order: Vec<String>,               // registration order, used for initialization
+    policy: Option<CapabilityPolicy>,
+    runtime: Option<Handle>, // runs `execute_plugin_method` calls
+}
+
+impl std::fmt::Debug for PluginRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PluginRegistry")
+            .field("plugins", &self.plugins)
+            .field("aliases", &self.aliases)
+            .field("order", &self.order)
+            .field("policy", &self.policy.as_ref().map(|_| "<policy>"))
+            .field("runtime", &self.runtime)
+            .finish()
+    }
 }
 
 /// Plugin registration info
@@ -43,16 +102,35 @@ impl PluginRegistry {
         Self::default()
     }
 
+    /// Run [`PluginRegistry::execute_plugin_method`] calls on the Tokio
+    /// runtime behind `handle`, rather than on the caller's
+    pub fn with_runtime(mut self, handle: Handle) -> Self {
+        self.runtime = Some(handle);
+        self
+    }
+
     /// Register a plugin in the registry
+    ///
+    /// Synchronous plugins are awaited through the [`Blocking`] adapter.
     pub fn register_plugin(
         &mut self,
-        plugin: Box<dyn Plugin>,
+        plugin: Box<dyn Plugin + Send + Sync>,
+        metadata: PluginMetadata,
+        capabilities: PluginCapabilities,
+    ) -> Result<(), PluginError> {
+        self.register_async_plugin(Box::new(Blocking::from(plugin)), metadata, capabilities)
+    }
+
+    /// Register a plugin whose calls are awaited
+    pub fn register_async_plugin(
+        &mut self,
+        plugin: Box<dyn AsyncPlugin>,
         metadata: PluginMetadata,
         capabilities: PluginCapabilities,
     ) -> Result<(), PluginError> {
         let name = metadata.name.clone();
 
-        if self.plugins.contains_key(&name) {
+        if self.plugins.contains_key(&name) || self.aliases.contains_key(&name) {
             return Err(PluginError::InitializationFailed(format!(
                 "Plugin '{name}' is already registered"
             )));
@@ -61,19 +139,20 @@ impl PluginRegistry {
         let entry = PluginEntry {
             metadata,
             capabilities,
-            #[allow(clippy::arc_with_non_send_sync)]
             plugin: Arc::new(RwLock::new(plugin)),
             enabled: true,
+            initialized: false,
         };
 
-        self.plugins.insert(name, entry);
+        self.plugins.insert(name.clone(), entry);
+        self.order.push(name);
         Ok(())
     }
 
     /// Register a plugin with aliases
     pub fn register_plugin_with_aliases(
         &mut self,
-        plugin: Box<dyn Plugin>,
+        plugin: Box<dyn Plugin + Send + Sync>,
         metadata: PluginMetadata,
         capabilities: PluginCapabilities,
         aliases: Vec<String>,
@@ -85,17 +164,45 @@ impl PluginRegistry {
 
         // Register aliases
         for alias in aliases {
-            if self.aliases.contains_key(&alias) || self.plugins.contains_key(&alias) {
-                return Err(PluginError::InitializationFailed(format!(
-                    "Alias '{alias}' is already in use"
-                )));
-            }
-            self.aliases.insert(alias, name.clone());
+            self.add_alias(&alias, &name)?;
         }
 
         Ok(())
     }
 
+    /// Make `alias` another name for the registered plugin `name`
+    pub fn add_alias(&mut self, alias: &str, name: &str) -> Result<(), PluginError> {
+        if !self.plugins.contains_key(name) {
+            return Err(PluginError::InitializationFailed(format!(
+                "Plugin '{name}' not found"
+            )));
+        }
+        if self.aliases.contains_key(alias) || self.plugins.contains_key(alias) {
+            return Err(PluginError::InitializationFailed(format!(
+                "Alias '{alias}' is already in use"
+            )));
+        }
+        self.aliases.insert(alias.to_string(), name.to_string());
+        Ok(())
+    }
+
+    /// Only allow calls to plugins whose capabilities pass `policy`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_plugins::registry::PluginRegistry;
+    ///
+    /// let mut registry = PluginRegistry::new();
+    /// registry.set_capability_policy(|capabilities| !capabilities.requires_network);
+    /// ```
+    pub fn set_capability_policy(
+        &mut self,
+        policy: impl Fn(&PluginCapabilities) -> bool + Send + Sync + 'static,
+    ) {
+        self.policy = Some(Arc::new(policy));
+    }
+
     /// Get a plugin by name or alias
     pub fn get_plugin(&self, name: &str) -> Option<&PluginEntry> {
         // Try direct lookup first
@@ -111,30 +218,63 @@ impl PluginRegistry {
         None
     }
 
-    /// Execute a method on a plugin
-    pub fn execute_plugin_method(
+    /// The plugin `plugin_name` resolves to, if it may be called
+    fn callable_plugin(
         &self,
         plugin_name: &str,
-        method: &str,
-        args: &HashMap<String, JsonValue>,
-    ) -> RuntimeResult<JsonValue> {
-        let entry = self.get_plugin(plugin_name).ok_or_else(|| {
-            sigmos_runtime::RuntimeError::Plugin(format!("Plugin '{plugin_name}' not found"))
-        })?;
+    ) -> RuntimeResult<Arc<RwLock<Box<dyn AsyncPlugin>>>> {
+        let entry = self
+            .get_plugin(plugin_name)
+            .ok_or_else(|| RuntimeError::Plugin(format!("Plugin '{plugin_name}' not found")))?;
 
         if !entry.enabled {
-            return Err(sigmos_runtime::RuntimeError::Plugin(format!(
+            return Err(RuntimeError::Plugin(format!(
                 "Plugin '{plugin_name}' is disabled"
             )));
         }
 
-        let plugin = entry.plugin.read().map_err(|_| {
-            sigmos_runtime::RuntimeError::Plugin("Failed to acquire plugin lock".to_string())
-        })?;
+        if let Some(policy) = &self.policy {
+            if !policy(&entry.capabilities) {
+                return Err(RuntimeError::Plugin(format!(
+                    "Plugin '{plugin_name}' is not allowed by the capability policy"
+                )));
+            }
+        }
 
-        plugin.execute(method, args)
+        Ok(Arc::clone(&entry.plugin))
     }
 
+    /// Execute a method on a plugin, waiting for the result
+    ///
+    /// The call runs on the runtime given to [`PluginRegistry::with_runtime`],
+    /// or else on the caller's, and fails without either.
+    pub fn execute_plugin_method(
+        &self,
+        plugin_name: &str,
+        method: &str,
+        args: &HashMap<String, JsonValue>,
+    ) -> RuntimeResult<JsonValue> {
+        let handle = crate::runtime_handle(self.runtime.as_ref())?;
+        let plugin = self.callable_plugin(plugin_name)?;
+        let (method, args) = (method.to_string(), args.clone());
+        crate::block_on(&handle, async move {
+            let plugin = plugin.read().await;
+            plugin.call(&method, &args, &CancellationToken::new()).await
+        })
+    }
+
+    /// Call a method on a plugin
+    pub async fn call_plugin_method(
+        &self,
+        plugin_name: &str,
+        method: &str,
+        args: &HashMap<String, JsonValue>,
+        cancel: &CancellationToken,
+    ) -> RuntimeResult<JsonValue> {
+        let plugin = self.callable_plugin(plugin_name)?;
+        let plugin = plugin.read().await;
+        plugin.call(method, args, cancel).await
+    }
     /// Enable a plugin
     pub fn enable_plugin(&mut self, name: &str) -> Result<(), PluginError> {
         let real_name = self.resolve_name(name);
@@ -168,6 +308,7 @@ impl PluginRegistry {
         let real_name = self.resolve_name(name);
 
         if self.plugins.remove(&real_name).is_some() {
+            self.order.retain(|plugin_name| plugin_name != &real_name);
             // Remove any aliases pointing to this plugin
             self.aliases
                 .retain(|_, plugin_name| plugin_name != &real_name);
@@ -243,24 +384,35 @@ impl PluginRegistry {
             .unwrap_or_else(|| name.to_string())
     }
 
-    /// Initialize all registered plugins
-    pub fn initialize_all(&self) -> Result<Vec<String>, PluginError> {
+    /// Initialize enabled plugins in registration order
+    ///
+    /// Plugins already initialized are skipped. Returns the names of the
+    /// plugins initialized by this call.
+    pub fn initialize_all(&mut self) -> Result<Vec<String>, PluginError> {
+        let mut initialized = Vec::new();
         let mut failed_plugins = Vec::new();
 
-        for (name, entry) in &self.plugins {
-            if entry.enabled {
-                if let Ok(mut plugin) = entry.plugin.write() {
-                    if let Err(e) = plugin.initialize() {
-                        failed_plugins.push(format!("{name}: {e}"));
+        for name in &self.order {
+            let Some(entry) = self.plugins.get_mut(name) else {
+                continue;
+            };
+            if !entry.enabled || entry.initialized {
+                continue;
+            }
+            match entry.plugin.try_write() {
+                Ok(mut plugin) => match plugin.initialize() {
+                    Ok(()) => {
+                        entry.initialized = true;
+                        initialized.push(name.clone());
                     }
-                } else {
-                    failed_plugins.push(format!("{name}: Failed to acquire write lock"));
-                }
+                    Err(e) => failed_plugins.push(format!("{name}: {e}")),
+                },
+                Err(_) => failed_plugins.push(format!("{name}: Failed to acquire write lock")),
             }
         }
 
         if failed_plugins.is_empty() {
-            Ok(Vec::new())
+            Ok(initialized)
         } else {
             Err(PluginError::InitializationFailed(format!(
                 "Failed to initialize plugins: {}",
@@ -287,6 +439,63 @@ impl PluginRegistry {
     }
 }
 
+impl PluginHost for PluginRegistry {
+    fn register(&mut self, plugin: Box<dyn AsyncPlugin>) -> RuntimeResult<()> {
+        let metadata = PluginMetadata {
+            name: plugin.name().to_string(),
+            version: plugin.version().unwrap_or_default().to_string(),
+            description: String::new(),
+            author: String::new(),
+            methods: Vec::new(),
+        };
+        let capabilities = PluginCapabilities {
+            supports_async: true,
+            supports_streaming: false,
+            requires_network: false,
+            requires_auth: false,
+        };
+        self.register_async_plugin(plugin, metadata, capabilities)
+            .map_err(RuntimeError::from)
+    }
+
+    fn initialize(&mut self) -> RuntimeResult<()> {
+        self.initialize_all()
+            .map(|_| ())
+            .map_err(RuntimeError::from)
+    }
+
+    fn has_plugin(&self, name: &str) -> bool {
+        PluginRegistry::has_plugin(self, name)
+    }
+
+    fn plugin_names(&self) -> Vec<String> {
+        self.order.clone()
+    }
+
+    fn plugin_version(&self, name: &str) -> Option<String> {
+        self.get_plugin_metadata(name)
+            .map(|metadata| metadata.version.clone())
+            .filter(|version| !version.is_empty())
+    }
+
+    fn call<'a>(
+        &'a self,
+        name: &'a str,
+        method: &'a str,
+        args: &'a HashMap<String, JsonValue>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        Box::pin(self.call_plugin_method(name, method, args, cancel))
+    }
+}
+
+impl From<PluginRegistry> for Runtime {
+    /// A runtime whose specs call the registry's plugins
+    fn from(registry: PluginRegistry) -> Self {
+        Runtime::new().with_plugin_host(registry)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,6 +505,7 @@ mod tests {
     struct MockPlugin {
         name: String,
         initialized: bool,
+        init_log: Option<Arc<std::sync::Mutex<Vec<String>>>>,
     }
 
     impl MockPlugin {
@@ -303,8 +513,35 @@ mod tests {
             Self {
                 name,
                 initialized: false,
+                init_log: None,
             }
         }
+
+        fn logged(name: &str, init_log: &Arc<std::sync::Mutex<Vec<String>>>) -> Box<Self> {
+            Box::new(Self {
+                init_log: Some(Arc::clone(init_log)),
+                ..Self::new(name.to_string())
+            })
+        }
+    }
+
+    fn metadata(name: &str) -> PluginMetadata {
+        PluginMetadata {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: "Test plugin".to_string(),
+            author: "Test Author".to_string(),
+            methods: vec!["test_method".to_string()],
+        }
+    }
+
+    fn capabilities(requires_network: bool) -> PluginCapabilities {
+        PluginCapabilities {
+            supports_async: false,
+            supports_streaming: false,
+            requires_network,
+            requires_auth: false,
+        }
     }
 
     impl Plugin for MockPlugin {
@@ -314,6 +551,9 @@ mod tests {
 
         fn initialize(&mut self) -> RuntimeResult<()> {
             self.initialized = true;
+            if let Some(init_log) = &self.init_log {
+                init_log.lock().unwrap().push(self.name.clone());
+            }
             Ok(())
         }
 
@@ -385,6 +625,13 @@ mod tests {
     #[test]
     fn test_plugin_execution() {
         let mut registry = PluginRegistry::new();
+        let args = HashMap::new();
+        assert!(registry
+            .execute_plugin_method("test", "test_method", &args)
+            .is_err());
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        registry = registry.with_runtime(runtime.handle().clone());
 
         let plugin = Box::new(MockPlugin::new("test".to_string()));
         let metadata = PluginMetadata {
@@ -405,11 +652,102 @@ mod tests {
             .register_plugin(plugin, metadata, capabilities)
             .unwrap();
 
-        let args = HashMap::new();
         let result = registry.execute_plugin_method("test", "test_method", &args);
         assert!(result.is_ok());
 
         let value = result.unwrap();
         assert_eq!(value, JsonValue::String("test:test_method".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_runtime_delegates_to_registry() {
+        use sigmos_core::parser::SigmosParser;
+
+        let init_log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut registry = PluginRegistry::new();
+        for name in ["zeta", "alpha", "remote", "off"] {
+            registry
+                .register_plugin_with_aliases(
+                    MockPlugin::logged(name, &init_log),
+                    metadata(name),
+                    capabilities(name == "remote"),
+                    vec![format!("{name}_alias")],
+                )
+                .unwrap();
+        }
+        registry.disable_plugin("off").unwrap();
+        registry.set_capability_policy(|capabilities| !capabilities.requires_network);
+
+        let mut runtime = Runtime::from(registry);
+        let run = |call: &str| {
+            SigmosParser::parse_spec(&format!(
+                "spec \"Calls\" v1.0 {{\n computed:\n result: -> {call}\n}}"
+            ))
+            .unwrap()
+        };
+
+        runtime.execute(&run("zeta_alias.ping()")).await.unwrap();
+        assert_eq!(runtime.snapshot().await.computed["result"], "zeta:ping");
+        assert_eq!(*init_log.lock().unwrap(), vec!["zeta", "alpha", "remote"]);
+
+        let error = runtime.execute(&run("off.ping()")).await.unwrap_err();
+        assert!(error.to_string().contains("Plugin 'off' is disabled"));
+
+        let error = runtime.execute(&run("remote.ping()")).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("not allowed by the capability policy"));
+    }
+
+    #[tokio::test]
+    async fn test_extensions_bind_registry_plugins() {
+        use sigmos_core::parser::SigmosParser;
+        use sigmos_runtime::RuntimeError;
+
+        let mut registry = PluginRegistry::new();
+        registry
+            .register_plugin(
+                Box::new(MockPlugin::new("mcp".to_string())),
+                metadata("mcp"),
+                capabilities(false),
+            )
+            .unwrap();
+        let mut runtime = Runtime::from(registry);
+
+        let spec = |version: &str| {
+            SigmosParser::parse_spec(&format!(
+                "spec \"Agent\" v1.0 {{\n extensions {{\n ai: import(\"sigmos.std.net.mcp@{version}\")\n }}\n computed:\n reply: -> ai.complete()\n}}"
+            ))
+            .unwrap()
+        };
+
+        runtime.execute(&spec("1.0")).await.unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.computed["reply"], "mcp:complete");
+        assert_eq!(report.plugin_calls[0].plugin, "mcp");
+
+        let error = runtime.execute(&spec("2")).await.unwrap_err();
+        assert!(matches!(error, RuntimeError::Extension(_)));
+    }
+
+    #[tokio::test]
+    async fn test_host_registration_keeps_plugin_versions() {
+        use crate::mcp::{McpConfig, McpPlugin};
+        use crate::ConfigurablePlugin;
+
+        let mut registry = PluginRegistry::new();
+        let mcp = McpPlugin::new(McpConfig::default()).unwrap();
+        PluginHost::register(&mut registry, Box::new(Blocking::new(mcp))).unwrap();
+        PluginHost::register(
+            &mut registry,
+            Box::new(Blocking::new(MockPlugin::new("echo".to_string()))),
+        )
+        .unwrap();
+
+        assert_eq!(
+            registry.plugin_version("mcp").as_deref(),
+            Some(McpPlugin::VERSION)
+        );
+        assert_eq!(registry.plugin_version("echo"), None);
+    }
 }
@@ -4,10 +4,14 @@
 //! enabling interaction with web services, APIs, and HTTP endpoints.
 
 use crate::{ConfigurablePlugin, PluginCapabilities, PluginConfig, PluginError, PluginMetadata};
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use sigmos_runtime::plugins::{AsyncPlugin, CancellationToken};
 use sigmos_runtime::{Plugin, RuntimeError, RuntimeResult};
 use std::collections::HashMap;
+use std::future::Future;
+use tokio::runtime::Handle;
 
 /// HTTP method enumeration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,7 +40,10 @@ impl std::fmt::Display for HttpMethod {
 }
 
 /// REST plugin configuration
+///
+/// Fields missing when deserializing take their [`Default`] values.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RestConfig {
     pub name: String,
     pub base_url: String,
@@ -103,6 +110,8 @@ pub struct RestPlugin {
     config: RestConfig,
     initialized: bool,
     client: Option<reqwest::Client>,
+    /// Runs synchronous `execute` calls; the caller's runtime when unset
+    runtime: Option<Handle>,
 }
 
 impl ConfigurablePlugin for RestPlugin {
@@ -110,11 +119,12 @@ impl ConfigurablePlugin for RestPlugin {
 
     fn new(config: Self::Config) -> Result<Self, PluginError> {
         config.validate()?;
-        let client = Some(reqwest::Client::new());
+        let client = Some(Self::client(&config)?);
         Ok(RestPlugin {
             config,
             initialized: false,
             client,
+            runtime: None,
         })
     }
 
@@ -124,6 +134,7 @@ impl ConfigurablePlugin for RestPlugin {
 
     fn update_config(&mut self, config: Self::Config) -> Result<(), PluginError> {
         config.validate()?;
+        self.client = Some(Self::client(&config)?);
         self.config = config;
         Ok(())
     }
@@ -134,6 +145,10 @@ impl Plugin for RestPlugin {
         &self.config.name
     }
 
+    fn version(&self) -> Option<&str> {
+        Some(Self::VERSION)
+    }
+
     fn initialize(&mut self) -> RuntimeResult<()> {
         // Initialize HTTP client
         // For now, this is a placeholder - would normally set up HTTP client with config
@@ -141,47 +156,98 @@ impl Plugin for RestPlugin {
         Ok(())
     }
 
+    /// Run a request to completion on the runtime given to
+    /// [`RestPlugin::with_runtime`], or else on the caller's
+    ///
+    /// Prefer registering the plugin as an [`AsyncPlugin`] so requests are
+    /// awaited instead of blocking the calling thread.
     fn execute(&self, method: &str, args: &HashMap<String, JsonValue>) -> RuntimeResult<JsonValue> {
-        if !self.initialized {
-            return Err(RuntimeError::Plugin(
-                "REST plugin not initialized".to_string(),
-            ));
-        }
+        let handle = crate::runtime_handle(self.runtime.as_ref())?;
+        let request = self.request(method, args)?;
+        crate::block_on(&handle, request)
+    }
+}
 
-        // Use tokio runtime to handle async HTTP requests
-        let rt = tokio::runtime::Runtime::new()
-            .map_err(|e| RuntimeError::Plugin(format!("Failed to create async runtime: {e}")))?;
-
-        match method {
-            "get" => rt.block_on(self.http_request(HttpMethod::GET, args)),
-            "post" => rt.block_on(self.http_request(HttpMethod::POST, args)),
-            "put" => rt.block_on(self.http_request(HttpMethod::PUT, args)),
-            "delete" => rt.block_on(self.http_request(HttpMethod::DELETE, args)),
-            "patch" => rt.block_on(self.http_request(HttpMethod::PATCH, args)),
-            "head" => rt.block_on(self.http_request(HttpMethod::HEAD, args)),
-            "options" => rt.block_on(self.http_request(HttpMethod::OPTIONS, args)),
-            "request" => self.generic_request(args),
-            _ => Err(RuntimeError::Plugin(format!(
-                "Unknown REST method: {method}"
-            ))),
-        }
+impl AsyncPlugin for RestPlugin {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn version(&self) -> Option<&str> {
+        Some(Self::VERSION)
+    }
+
+    fn initialize(&mut self) -> RuntimeResult<()> {
+        Plugin::initialize(self)
+    }
+
+    fn call<'a>(
+        &'a self,
+        method: &'a str,
+        args: &'a HashMap<String, JsonValue>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        Box::pin(async move {
+            let request = self.request(method, args)?;
+            let operation = format!("{}.{method}", self.config.name);
+            cancel.run(&operation, request).await
+        })
     }
 }
 
 impl RestPlugin {
-    /// Generic HTTP request method
-    async fn http_request(
+    /// Run synchronous [`Plugin::execute`] calls on the Tokio runtime behind
+    /// `handle`, rather than on the caller's
+    pub fn with_runtime(mut self, handle: Handle) -> Self {
+        self.runtime = Some(handle);
+        self
+    }
+
+    /// HTTP client that gives up on requests after the configured timeout
+    fn client(config: &RestConfig) -> Result<reqwest::Client, PluginError> {
+        reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.timeout_seconds))
+            .build()
+            .map_err(|e| PluginError::InitializationFailed(format!("HTTP client: {e}")))
+    }
+
+    /// Prepare the request for a plugin method call
+    ///
+    /// The returned future owns everything it needs, so it can be awaited
+    /// directly or spawned onto another runtime.
+    fn request(
         &self,
-        method: HttpMethod,
+        method: &str,
         args: &HashMap<String, JsonValue>,
-    ) -> RuntimeResult<JsonValue> {
+    ) -> RuntimeResult<impl Future<Output = RuntimeResult<JsonValue>> + Send + 'static> {
+        if !self.initialized {
+            return Err(RuntimeError::Plugin(
+                "REST plugin not initialized".to_string(),
+            ));
+        }
+
+        let http_method = match method {
+            "get" => HttpMethod::GET,
+            "post" => HttpMethod::POST,
+            "put" => HttpMethod::PUT,
+            "delete" => HttpMethod::DELETE,
+            "patch" => HttpMethod::PATCH,
+            "head" => HttpMethod::HEAD,
+            "options" => HttpMethod::OPTIONS,
+            "request" => Self::requested_method(args)?,
+            _ => {
+                return Err(RuntimeError::Plugin(format!(
+                    "Unknown REST method: {method}"
+                )))
+            }
+        };
+
         let client = self
             .client
-            .as_ref()
+            .clone()
             .ok_or_else(|| RuntimeError::Plugin("HTTP client not initialized".to_string()))?;
 
         let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
-
         let url = if path.is_empty() {
             self.config.base_url.clone()
         } else {
@@ -192,6 +258,16 @@ impl RestPlugin {
             )
         };
 
+        Ok(Self::http_request(client, url, http_method, args.clone()))
+    }
+
+    /// Generic HTTP request method
+    async fn http_request(
+        client: reqwest::Client,
+        url: String,
+        method: HttpMethod,
+        args: HashMap<String, JsonValue>,
+    ) -> RuntimeResult<JsonValue> {
         // Build the request
         let mut request_builder = match method {
             HttpMethod::GET => client.get(&url),
@@ -239,7 +315,7 @@ impl RestPlugin {
         let response = request_builder
             .send()
             .await
-            .map_err(|e| RuntimeError::Plugin(format!("HTTP request failed: {e}")))?;
+            .map_err(|e| PluginError::NetworkError(format!("HTTP request failed: {e}")))?;
 
         let status = response.status().as_u16();
         let headers_map: serde_json::Map<String, JsonValue> = response
@@ -256,7 +332,7 @@ impl RestPlugin {
         let body_text = response
             .text()
             .await
-            .map_err(|e| RuntimeError::Plugin(format!("Failed to read response body: {e}")))?;
+            .map_err(|e| PluginError::NetworkError(format!("Failed to read response body: {e}")))?;
 
         // Try to parse as JSON, fallback to string
         let body_json =
@@ -276,39 +352,35 @@ impl RestPlugin {
         }))
     }
 
-    /// Generic request with custom method
-    fn generic_request(&self, args: &HashMap<String, JsonValue>) -> RuntimeResult<JsonValue> {
+    /// HTTP method named by the `method` argument of a generic `request` call
+    fn requested_method(args: &HashMap<String, JsonValue>) -> RuntimeResult<HttpMethod> {
         let method_str = args
             .get("method")
             .and_then(|v| v.as_str())
             .ok_or_else(|| RuntimeError::Plugin("Missing 'method' argument".to_string()))?;
 
-        let method = match method_str.to_uppercase().as_str() {
-            "GET" => HttpMethod::GET,
-            "POST" => HttpMethod::POST,
-            "PUT" => HttpMethod::PUT,
-            "DELETE" => HttpMethod::DELETE,
-            "PATCH" => HttpMethod::PATCH,
-            "HEAD" => HttpMethod::HEAD,
-            "OPTIONS" => HttpMethod::OPTIONS,
-            _ => {
-                return Err(RuntimeError::Plugin(format!(
-                    "Unsupported HTTP method: {method_str}"
-                )))
-            }
-        };
-
-        let rt = tokio::runtime::Runtime::new()
-            .map_err(|e| RuntimeError::Plugin(format!("Failed to create async runtime: {e}")))?;
-
-        rt.block_on(self.http_request(method, args))
+        match method_str.to_uppercase().as_str() {
+            "GET" => Ok(HttpMethod::GET),
+            "POST" => Ok(HttpMethod::POST),
+            "PUT" => Ok(HttpMethod::PUT),
+            "DELETE" => Ok(HttpMethod::DELETE),
+            "PATCH" => Ok(HttpMethod::PATCH),
+            "HEAD" => Ok(HttpMethod::HEAD),
+            "OPTIONS" => Ok(HttpMethod::OPTIONS),
+            _ => Err(RuntimeError::Plugin(format!(
+                "Unsupported HTTP method: {method_str}"
+            ))),
+        }
     }
 
+    /// Plugin version reported to `import("...@x.y")` requirements
+    pub const VERSION: &'static str = "1.0.0";
+
     /// Get plugin metadata
     pub fn metadata() -> PluginMetadata {
         PluginMetadata {
             name: "rest".to_string(),
-            version: "1.0.0".to_string(),
+            version: Self::VERSION.to_string(),
             description: "HTTP/REST API integration plugin".to_string(),
             author: "SIGMOS Team".to_string(),
             methods: vec![
@@ -376,8 +448,11 @@ mod tests {
             user_agent: "SIGMOS-REST-Plugin/1.0".to_string(),
         };
 
-        let mut plugin = RestPlugin::new(config).unwrap();
-        assert!(plugin.initialize().is_ok());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut plugin = RestPlugin::new(config)
+            .unwrap()
+            .with_runtime(runtime.handle().clone());
+        assert!(Plugin::initialize(&mut plugin).is_ok());
 
         let mut args = HashMap::new();
         args.insert("path".to_string(), JsonValue::String("/get".to_string()));
@@ -395,9 +470,9 @@ mod tests {
                 assert!(response.get("body").is_some());
             }
             Err(e) => {
-                // If it fails (e.g., no internet), ensure it's a plugin error
+                // If it fails (e.g., no internet), ensure it's a retryable network error
                 match e {
-                    RuntimeError::Plugin(_) => {
+                    RuntimeError::Transient(_) => {
                         // This is expected if there's no network access
                         println!("Network request failed (expected in some environments): {e}");
                     }
@@ -407,6 +482,26 @@ mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_calls_from_async_context() {
+        let config = RestConfig {
+            base_url: "http://127.0.0.1:9".to_string(),
+            ..RestConfig::default()
+        };
+        let mut plugin = RestPlugin::new(config).unwrap();
+        Plugin::initialize(&mut plugin).unwrap();
+        let args = HashMap::new();
+
+        // Blocking calls run on the caller's runtime instead of panicking here
+        let error = plugin.execute("get", &args).unwrap_err();
+        assert!(matches!(error, RuntimeError::Transient(_)));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let error = plugin.call("get", &args, &token).await.unwrap_err();
+        assert!(matches!(error, RuntimeError::Cancelled(ref op) if op == "rest.get"));
+    }
+
     #[test]
     fn test_http_method_display() {
         assert_eq!(HttpMethod::GET.to_string(), "GET");
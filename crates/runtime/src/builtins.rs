@@ -0,0 +1,1153 @@
+//! Builtin functions
+//!
+//! Free function calls such as `upper(name)` resolve to a [`BuiltinRegistry`].
+//! Each builtin has a [`FunctionSignature`] that the runtime checks arguments
+//! against and that [`TypeChecker::with_functions`] uses to type calls before
+//! anything runs, so both agree on what a builtin accepts.
+//!
+//! The standard library covers:
+//!
+//! - strings: `len`, `upper`, `lower`, `trim`, `split`, `join`, `replace`,
+//!   `contains`, `starts_with`, `ends_with`, `matches`, `format`
+//! - math: `abs`, `min`, `max`, `round`, `floor`, `ceil`, `pow`, `sqrt`
+//! - collections: `map`, `filter`, `reduce`, `sort`, `sum`, `avg`, `keys`,
+//!   `values`
+//! - JSON: `parse_json`, `to_json`, `json_path`
+//! - hashing and identifiers: `sha256`, `sha512`, `uuid`, `uuid_v5`
+//!
+//! Higher-order builtins take a function argument, written as the name of
+//! another builtin, `map(tags, upper)`, or as a lambda,
+//! `filter(orders, o => o.total > threshold)`. Lambdas see the variables in
+//! scope where they are written.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use serde_json::{json, Value as JsonValue};
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_core::types::{FunctionSignature, TypeChecker, ValueType};
+//! use sigmos_runtime::builtins::BuiltinRegistry;
+//! use sigmos_runtime::Runtime;
+//!
+//! let mut builtins = BuiltinRegistry::standard();
+//! builtins.register(
+//!     "cents",
+//!     FunctionSignature::new(ValueType::Number).required("amount", ValueType::Number),
+//!     |args: &[JsonValue]| Ok(json!((args[0].as_f64().unwrap_or(0.0) * 100.0).round())),
+//! ).unwrap();
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Checkout" v1.0 {
+//!     inputs:
+//!         prices: list
+//!     computed:
+//!         total: -> cents(sum(prices))
+//!         labels: -> join(map(split("a,b", ","), upper), "+")
+//! }
+//! "#).unwrap();
+//! TypeChecker::new()
+//!     .with_functions(builtins.signatures())
+//!     .validate_spec(&spec)
+//!     .unwrap();
+//!
+//! # tokio_test::block_on(async {
+//! let mut runtime = Runtime::new().with_builtins(builtins);
+//! let inputs = serde_json::from_value(json!({ "prices": [1.25, 2.5] })).unwrap();
+//! runtime.execute_with_inputs(&spec, inputs).await.unwrap();
+//!
+//! let report = runtime.snapshot().await;
+//! assert_eq!(report.computed["total"], json!(375.0));
+//! assert_eq!(report.computed["labels"], "A+B");
+//! # });
+//! ```
+
+use crate::engine::Closure;
+use crate::{RuntimeError, RuntimeResult};
+use futures::future::BoxFuture;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256, Sha512};
+use sigmos_core::ast::Expression;
+use sigmos_core::types::{FunctionSignature, PatternCache, ValueType};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Implementation of a builtin that only takes values
+pub type BuiltinFn = Arc<dyn Fn(&[JsonValue]) -> RuntimeResult<JsonValue> + Send + Sync>;
+
+/// Implementation of a builtin that takes functions and calls them through
+/// an [`Invoker`]
+pub type HigherOrderFn =
+    for<'a> fn(Vec<Arg>, &'a dyn Invoker) -> BoxFuture<'a, RuntimeResult<JsonValue>>;
+
+/// An argument passed to a builtin
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Value(JsonValue),
+    Function(Callable),
+}
+
+/// A function passed as an argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callable {
+    /// A builtin, named by an identifier such as the `upper` in `map(tags, upper)`
+    Builtin(String),
+    /// A lambda such as `o => o.total > threshold`, with the values of the
+    /// variables its body reads from where it was written
+    Lambda {
+        parameters: Vec<String>,
+        body: Expression,
+        captured: HashMap<String, JsonValue>,
+    },
+    /// A lambda or spec function compiled into an
+    /// [`ExecutionPlan`](crate::engine::ExecutionPlan), callable while the
+    /// runtime executes that plan
+    Compiled(Closure),
+}
+
+/// Calls the functions passed to higher-order builtins
+pub trait Invoker: Send + Sync {
+    fn invoke<'a>(
+        &'a self,
+        function: &'a Callable,
+        args: Vec<JsonValue>,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>>;
+}
+
+/// A builtin function and its signature
+#[derive(Clone)]
+pub struct Builtin {
+    signature: FunctionSignature,
+    implementation: Implementation,
+}
+
+#[derive(Clone)]
+enum Implementation {
+    Values(BuiltinFn),
+    HigherOrder(HigherOrderFn),
+}
+
+impl std::fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Builtin")
+            .field("signature", &self.signature)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Builtin {
+    pub fn signature(&self) -> &FunctionSignature {
+        &self.signature
+    }
+
+    /// Whether the builtin takes functions, which it calls asynchronously
+    pub fn is_higher_order(&self) -> bool {
+        matches!(self.implementation, Implementation::HigherOrder(_))
+    }
+
+    /// Check `args` against the signature and call the builtin `name`
+    pub async fn call(
+        &self,
+        name: &str,
+        args: Vec<Arg>,
+        invoker: &dyn Invoker,
+    ) -> RuntimeResult<JsonValue> {
+        match &self.implementation {
+            Implementation::Values(_) => self.call_now(name, args),
+            Implementation::HigherOrder(function) => {
+                self.check(name, &args)?;
+                function(args, invoker).await
+            }
+        }
+    }
+
+    /// Check `args` against the signature and call the builtin `name`, which
+    /// must only take values, without awaiting it
+    pub(crate) fn call_now(&self, name: &str, args: Vec<Arg>) -> RuntimeResult<JsonValue> {
+        self.check(name, &args)?;
+        match &self.implementation {
+            Implementation::Values(function) => {
+                let values: Vec<JsonValue> = args
+                    .into_iter()
+                    .filter_map(|arg| match arg {
+                        Arg::Value(value) => Some(value),
+                        Arg::Function(_) => None,
+                    })
+                    .collect();
+                function(&values)
+            }
+            Implementation::HigherOrder(_) => Err(RuntimeError::Evaluation(format!(
+                "{name}() takes functions, so calls to it must be awaited"
+            ))),
+        }
+    }
+
+    fn check(&self, name: &str, args: &[Arg]) -> RuntimeResult<()> {
+        self.signature
+            .check_arity(name, args.len())
+            .map_err(RuntimeError::Evaluation)?;
+        for (index, arg) in args.iter().enumerate() {
+            let Some(parameter) = self.signature.parameter(index) else {
+                continue;
+            };
+            let fits = match arg {
+                Arg::Function(_) => parameter.value_type == ValueType::Function,
+                Arg::Value(value) => parameter.value_type.accepts_value(value),
+            };
+            if !fits {
+                return Err(RuntimeError::Evaluation(format!(
+                    "{name}(): argument '{}' must be {}, got {}",
+                    parameter.name,
+                    parameter.value_type,
+                    kind(arg)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builtin functions by name
+#[derive(Debug, Clone, Default)]
+pub struct BuiltinRegistry {
+    builtins: BTreeMap<String, Builtin>,
+}
+
+impl BuiltinRegistry {
+    /// A registry without any builtins
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The standard library
+    pub fn standard() -> Self {
+        use ValueType::{Any, Array, Bool, Function, Number, Object, String};
+        let signature = FunctionSignature::new;
+        let mut registry = Self::new();
+        let mut add =
+            |name: &str,
+             signature: FunctionSignature,
+             function: fn(&[JsonValue]) -> RuntimeResult<JsonValue>| {
+                registry.insert(name, signature, Implementation::Values(Arc::new(function)));
+            };
+
+        // Strings
+        add("len", signature(Number).required("value", Any), len);
+        add(
+            "upper",
+            signature(String).required("text", String),
+            |args| Ok(JsonValue::from(text(args, 0).to_uppercase())),
+        );
+        add(
+            "lower",
+            signature(String).required("text", String),
+            |args| Ok(JsonValue::from(text(args, 0).to_lowercase())),
+        );
+        add("trim", signature(String).required("text", String), |args| {
+            Ok(JsonValue::from(text(args, 0).trim()))
+        });
+        add(
+            "split",
+            signature(Array)
+                .required("text", String)
+                .required("separator", String),
+            split,
+        );
+        add(
+            "join",
+            signature(String)
+                .required("items", Array)
+                .optional("separator", String),
+            join,
+        );
+        add(
+            "replace",
+            signature(String)
+                .required("text", String)
+                .required("from", String)
+                .required("to", String),
+            |args| {
+                Ok(JsonValue::from(
+                    text(args, 0).replace(text(args, 1), text(args, 2)),
+                ))
+            },
+        );
+        add(
+            "contains",
+            signature(Bool)
+                .required("collection", Any)
+                .required("item", Any),
+            contains,
+        );
+        add(
+            "starts_with",
+            signature(Bool)
+                .required("text", String)
+                .required("prefix", String),
+            |args| Ok(JsonValue::from(text(args, 0).starts_with(text(args, 1)))),
+        );
+        add(
+            "ends_with",
+            signature(Bool)
+                .required("text", String)
+                .required("suffix", String),
+            |args| Ok(JsonValue::from(text(args, 0).ends_with(text(args, 1)))),
+        );
+        add(
+            "format",
+            signature(String)
+                .required("template", String)
+                .variadic("values", Any),
+            format,
+        );
+
+        // Math
+        add("abs", signature(Number).required("value", Number), |args| {
+            number("abs", float(args, 0).abs())
+        });
+        add(
+            "min",
+            signature(Number)
+                .required("first", Any)
+                .variadic("rest", Any),
+            |args| extreme("min", args, Ordering::Less),
+        );
+        add(
+            "max",
+            signature(Number)
+                .required("first", Any)
+                .variadic("rest", Any),
+            |args| extreme("max", args, Ordering::Greater),
+        );
+        add(
+            "round",
+            signature(Number)
+                .required("value", Number)
+                .optional("digits", Number),
+            round,
+        );
+        add(
+            "floor",
+            signature(Number).required("value", Number),
+            |args| number("floor", float(args, 0).floor()),
+        );
+        add(
+            "ceil",
+            signature(Number).required("value", Number),
+            |args| number("ceil", float(args, 0).ceil()),
+        );
+        add(
+            "pow",
+            signature(Number)
+                .required("base", Number)
+                .required("exponent", Number),
+            |args| number("pow", float(args, 0).powf(float(args, 1))),
+        );
+        add(
+            "sqrt",
+            signature(Number).required("value", Number),
+            |args| match float(args, 0) {
+                value if value < 0.0 => Err(RuntimeError::Evaluation(format!(
+                    "sqrt(): {value} is negative"
+                ))),
+                value => number("sqrt", value.sqrt()),
+            },
+        );
+
+        // Collections
+        add("sum", signature(Number).required("items", Array), |args| {
+            number("sum", numbers("sum", items(args, 0))?.iter().sum())
+        });
+        add("avg", signature(Number).required("items", Array), |args| {
+            let values = numbers("avg", items(args, 0))?;
+            if values.is_empty() {
+                return Err(RuntimeError::Evaluation(
+                    "avg(): the array is empty".to_string(),
+                ));
+            }
+            number("avg", values.iter().sum::<f64>() / values.len() as f64)
+        });
+        add(
+            "keys",
+            signature(Array).required("object", Object),
+            |args| {
+                Ok(JsonValue::Array(
+                    fields(args, 0)
+                        .keys()
+                        .cloned()
+                        .map(JsonValue::from)
+                        .collect(),
+                ))
+            },
+        );
+        add(
+            "values",
+            signature(Array).required("object", Object),
+            |args| {
+                Ok(JsonValue::Array(
+                    fields(args, 0).values().cloned().collect(),
+                ))
+            },
+        );
+
+        // JSON
+        add(
+            "parse_json",
+            signature(Any).required("text", String),
+            |args| {
+                serde_json::from_str(text(args, 0))
+                    .map_err(|e| RuntimeError::Evaluation(format!("parse_json(): {e}")))
+            },
+        );
+        add(
+            "to_json",
+            signature(String)
+                .required("value", Any)
+                .optional("pretty", Bool),
+            |args| {
+                let pretty = args.get(1).and_then(JsonValue::as_bool).unwrap_or(false);
+                let json = if pretty {
+                    serde_json::to_string_pretty(&args[0])
+                } else {
+                    serde_json::to_string(&args[0])
+                };
+                json.map(JsonValue::from)
+                    .map_err(|e| RuntimeError::Evaluation(format!("to_json(): {e}")))
+            },
+        );
+        add(
+            "json_path",
+            signature(Any)
+                .required("value", Any)
+                .required("path", String),
+            |args| json_path(&args[0], text(args, 1)),
+        );
+
+        // Hashing and identifiers
+        add(
+            "sha256",
+            signature(String).required("text", String),
+            |args| Ok(JsonValue::from(hex(&Sha256::digest(text(args, 0))))),
+        );
+        add(
+            "sha512",
+            signature(String).required("text", String),
+            |args| Ok(JsonValue::from(hex(&Sha512::digest(text(args, 0))))),
+        );
+        add("uuid", signature(String), |_| {
+            Ok(JsonValue::from(uuid::Uuid::new_v4().to_string()))
+        });
+        add(
+            "uuid_v5",
+            signature(String)
+                .required("namespace", String)
+                .required("name", String),
+            |args| {
+                let namespace = uuid::Uuid::parse_str(text(args, 0)).map_err(|e| {
+                    RuntimeError::Evaluation(format!("uuid_v5(): invalid namespace: {e}"))
+                })?;
+                Ok(JsonValue::from(
+                    uuid::Uuid::new_v5(&namespace, text(args, 1).as_bytes()).to_string(),
+                ))
+            },
+        );
+
+        // Regexes, each compiled once; `text matches /pattern/` calls `matches`
+        let patterns = Arc::new(PatternCache::new());
+        registry.insert(
+            "matches",
+            signature(Bool)
+                .required("text", String)
+                .required("pattern", String),
+            Implementation::Values(Arc::new(move |args: &[JsonValue]| {
+                patterns
+                    .is_match(text(args, 1), text(args, 0))
+                    .map(JsonValue::from)
+                    .map_err(|e| RuntimeError::Evaluation(format!("matches(): {e}")))
+            })),
+        );
+
+        // Higher-order
+        registry.register_higher_order(
+            "map",
+            signature(Array)
+                .required("items", Array)
+                .required("function", Function),
+            map,
+        );
+        registry.register_higher_order(
+            "filter",
+            signature(Array)
+                .required("items", Array)
+                .required("predicate", Function),
+            filter,
+        );
+        registry.register_higher_order(
+            "reduce",
+            signature(Any)
+                .required("items", Array)
+                .required("function", Function)
+                .required("initial", Any),
+            reduce,
+        );
+        registry.register_higher_order(
+            "sort",
+            signature(Array)
+                .required("items", Array)
+                .optional("key", Function),
+            sort,
+        );
+        registry
+    }
+
+    /// Add or replace the builtin `name`
+    ///
+    /// The implementation receives the evaluated arguments after they have
+    /// been checked against `signature`, so it cannot take function
+    /// arguments; see [`register_higher_order`](Self::register_higher_order).
+    pub fn register(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        function: impl Fn(&[JsonValue]) -> RuntimeResult<JsonValue> + Send + Sync + 'static,
+    ) -> RuntimeResult<()> {
+        if let Some(parameter) = signature
+            .parameters
+            .iter()
+            .find(|parameter| parameter.value_type == ValueType::Function)
+        {
+            return Err(RuntimeError::Evaluation(format!(
+                "{name}(): parameter '{}' takes a function; register it with register_higher_order",
+                parameter.name
+            )));
+        }
+        self.insert(name, signature, Implementation::Values(Arc::new(function)));
+        Ok(())
+    }
+
+    /// Add or replace the builtin `name`, which may take function arguments
+    pub fn register_higher_order(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        function: HigherOrderFn,
+    ) {
+        self.insert(name, signature, Implementation::HigherOrder(function));
+    }
+
+    fn insert(&mut self, name: &str, signature: FunctionSignature, implementation: Implementation) {
+        self.builtins.insert(
+            name.to_string(),
+            Builtin {
+                signature,
+                implementation,
+            },
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.builtins.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    /// Signatures by name, for [`TypeChecker::with_functions`]
+    ///
+    /// [`TypeChecker::with_functions`]: sigmos_core::types::TypeChecker::with_functions
+    pub fn signatures(&self) -> impl Iterator<Item = (String, FunctionSignature)> + '_ {
+        self.builtins
+            .iter()
+            .map(|(name, builtin)| (name.clone(), builtin.signature.clone()))
+    }
+}
+
+impl Invoker for BuiltinRegistry {
+    fn invoke<'a>(
+        &'a self,
+        function: &'a Callable,
+        args: Vec<JsonValue>,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        Box::pin(async move {
+            let Callable::Builtin(name) = function else {
+                return Err(RuntimeError::Evaluation(
+                    "Lambdas can only be called by a Runtime".to_string(),
+                ));
+            };
+            let builtin = self
+                .get(name)
+                .ok_or_else(|| RuntimeError::Evaluation(format!("Unknown function: {name}")))?;
+            builtin
+                .call(name, args.into_iter().map(Arg::Value).collect(), self)
+                .await
+        })
+    }
+}
+
+/// Name of the kind of `arg`, for error messages
+fn kind(arg: &Arg) -> &'static str {
+    match arg {
+        Arg::Function(_) => "a function",
+        Arg::Value(JsonValue::Null) => "null",
+        Arg::Value(JsonValue::Bool(_)) => "a bool",
+        Arg::Value(JsonValue::Number(_)) => "a number",
+        Arg::Value(JsonValue::String(_)) => "a string",
+        Arg::Value(JsonValue::Array(_)) => "an array",
+        Arg::Value(JsonValue::Object(_)) => "an object",
+    }
+}
+
+// Arguments have been checked against the signature before these are used
+
+fn text(args: &[JsonValue], index: usize) -> &str {
+    args.get(index)
+        .and_then(JsonValue::as_str)
+        .unwrap_or_default()
+}
+
+fn float(args: &[JsonValue], index: usize) -> f64 {
+    args.get(index)
+        .and_then(JsonValue::as_f64)
+        .unwrap_or_default()
+}
+
+fn items(args: &[JsonValue], index: usize) -> &[JsonValue] {
+    args.get(index)
+        .and_then(JsonValue::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+fn fields(args: &[JsonValue], index: usize) -> serde_json::Map<String, JsonValue> {
+    args.get(index)
+        .and_then(JsonValue::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn number(name: &str, value: f64) -> RuntimeResult<JsonValue> {
+    serde_json::Number::from_f64(value)
+        .map(JsonValue::Number)
+        .ok_or_else(|| {
+            RuntimeError::Evaluation(format!("{name}(): result {value} is not a valid number"))
+        })
+}
+
+fn numbers(name: &str, values: &[JsonValue]) -> RuntimeResult<Vec<f64>> {
+    values
+        .iter()
+        .map(|value| {
+            value.as_f64().ok_or_else(|| {
+                RuntimeError::Evaluation(format!("{name}(): {value} is not a number"))
+            })
+        })
+        .collect()
+}
+
+/// Text of a value as string templates render it
+fn display(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Equality that treats `1` and `1.0` as the same number
+fn same(left: &JsonValue, right: &JsonValue) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(left), Some(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+fn len(args: &[JsonValue]) -> RuntimeResult<JsonValue> {
+    match &args[0] {
+        JsonValue::String(s) => Ok(JsonValue::from(s.chars().count())),
+        JsonValue::Array(items) => Ok(JsonValue::from(items.len())),
+        JsonValue::Object(fields) => Ok(JsonValue::from(fields.len())),
+        _ => Err(RuntimeError::Evaluation(
+            "len() can only be applied to strings, arrays, or objects".to_string(),
+        )),
+    }
+}
+
+fn split(args: &[JsonValue]) -> RuntimeResult<JsonValue> {
+    let (source, separator) = (text(args, 0), text(args, 1));
+    let parts: Vec<JsonValue> = if separator.is_empty() {
+        source
+            .chars()
+            .map(|c| JsonValue::from(c.to_string()))
+            .collect()
+    } else {
+        source.split(separator).map(JsonValue::from).collect()
+    };
+    Ok(JsonValue::Array(parts))
+}
+
+fn join(args: &[JsonValue]) -> RuntimeResult<JsonValue> {
+    let parts: Vec<String> = items(args, 0).iter().map(display).collect();
+    Ok(JsonValue::from(parts.join(text(args, 1))))
+}
+
+fn contains(args: &[JsonValue]) -> RuntimeResult<JsonValue> {
+    match (&args[0], &args[1]) {
+        (JsonValue::String(haystack), JsonValue::String(needle)) => {
+            Ok(JsonValue::from(haystack.contains(needle.as_str())))
+        }
+        (JsonValue::Array(items), item) => Ok(JsonValue::from(
+            items.iter().any(|candidate| same(candidate, item)),
+        )),
+        (JsonValue::Object(fields), JsonValue::String(key)) => {
+            Ok(JsonValue::from(fields.contains_key(key)))
+        }
+        (collection, item) => Err(RuntimeError::Evaluation(format!(
+            "contains(): cannot look for {item} in {collection}"
+        ))),
+    }
+}
+
+/// Fill `{}` and `{0}`-style placeholders; `{{` and `}}` are literal braces
+fn format(args: &[JsonValue]) -> RuntimeResult<JsonValue> {
+    let values = &args[1..];
+    let mut output = String::new();
+    let mut next = 0;
+    let mut chars = text(args, 0).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let index = if placeholder.is_empty() {
+                    next += 1;
+                    next - 1
+                } else {
+                    placeholder.parse().map_err(|_| {
+                        RuntimeError::Evaluation(format!(
+                            "format(): invalid placeholder {{{placeholder}}}"
+                        ))
+                    })?
+                };
+                let value = values.get(index).ok_or_else(|| {
+                    RuntimeError::Evaluation(format!(
+                        "format(): placeholder {index} has no value; {} given",
+                        values.len()
+                    ))
+                })?;
+                output.push_str(&display(value));
+            }
+            c => output.push(c),
+        }
+    }
+    Ok(JsonValue::from(output))
+}
+
+/// Smallest or largest of the arguments, or of a single array argument
+fn extreme(name: &str, args: &[JsonValue], wanted: Ordering) -> RuntimeResult<JsonValue> {
+    let candidates = match args {
+        [JsonValue::Array(items)] => items.as_slice(),
+        _ => args,
+    };
+    let values = numbers(name, candidates)?;
+    let best = values
+        .into_iter()
+        .reduce(|best, value| {
+            if value.partial_cmp(&best) == Some(wanted) {
+                value
+            } else {
+                best
+            }
+        })
+        .ok_or_else(|| RuntimeError::Evaluation(format!("{name}(): the array is empty")))?;
+    number(name, best)
+}
+
+fn round(args: &[JsonValue]) -> RuntimeResult<JsonValue> {
+    let value = float(args, 0);
+    let digits = args.get(1).and_then(JsonValue::as_f64).unwrap_or(0.0);
+    if digits.fract() != 0.0 {
+        return Err(RuntimeError::Evaluation(format!(
+            "round(): digits must be a whole number, got {digits}"
+        )));
+    }
+    let scale = 10f64.powi(digits as i32);
+    number("round", (value * scale).round() / scale)
+}
+
+fn json_path(value: &JsonValue, path: &str) -> RuntimeResult<JsonValue> {
+    let invalid = |reason: &str| {
+        RuntimeError::Evaluation(format!("json_path(): invalid path '{path}': {reason}"))
+    };
+    let mut nodes = vec![value];
+    let mut wildcard = false;
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+
+    while !rest.is_empty() {
+        let (segment, remaining) = if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| invalid("unclosed '['"))?;
+            (after[..end].trim(), &after[end + 1..])
+        } else {
+            let after = rest.strip_prefix('.').unwrap_or(rest);
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid("empty segment"));
+            }
+            (&after[..end], &after[end..])
+        };
+        rest = remaining;
+
+        let quoted = segment
+            .strip_prefix('\'')
+            .and_then(|s| s.strip_suffix('\''))
+            .or_else(|| segment.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+        nodes = if segment == "*" {
+            wildcard = true;
+            nodes
+                .into_iter()
+                .flat_map(|node| -> Vec<&JsonValue> {
+                    match node {
+                        JsonValue::Array(items) => items.iter().collect(),
+                        JsonValue::Object(fields) => fields.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect()
+        } else if let (None, Ok(index)) = (quoted, segment.parse::<i64>()) {
+            nodes
+                .into_iter()
+                .filter_map(|node| {
+                    let items = node.as_array()?;
+                    let index = if index < 0 {
+                        items.len().checked_sub(index.unsigned_abs() as usize)?
+                    } else {
+                        index as usize
+                    };
+                    items.get(index)
+                })
+                .collect()
+        } else {
+            let key = quoted.unwrap_or(segment);
+            nodes.into_iter().filter_map(|node| node.get(key)).collect()
+        };
+    }
+
+    Ok(if wildcard {
+        JsonValue::Array(nodes.into_iter().cloned().collect())
+    } else {
+        nodes
+            .first()
+            .map_or(JsonValue::Null, |node| (*node).clone())
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn value_arg(args: &[Arg], index: usize) -> &JsonValue {
+    match args.get(index) {
+        Some(Arg::Value(value)) => value,
+        _ => &JsonValue::Null,
+    }
+}
+
+fn function_arg(args: &[Arg], index: usize) -> Option<&Callable> {
+    match args.get(index) {
+        Some(Arg::Function(function)) => Some(function),
+        _ => None,
+    }
+}
+
+fn array_arg(args: &[Arg], index: usize) -> &[JsonValue] {
+    value_arg(args, index).as_array().map_or(&[], Vec::as_slice)
+}
+
+fn map(args: Vec<Arg>, invoker: &dyn Invoker) -> BoxFuture<'_, RuntimeResult<JsonValue>> {
+    Box::pin(async move {
+        let Some(function) = function_arg(&args, 1) else {
+            return Ok(JsonValue::Null);
+        };
+        let mut mapped = Vec::new();
+        for item in array_arg(&args, 0) {
+            mapped.push(invoker.invoke(function, vec![item.clone()]).await?);
+        }
+        Ok(JsonValue::Array(mapped))
+    })
+}
+
+fn filter(args: Vec<Arg>, invoker: &dyn Invoker) -> BoxFuture<'_, RuntimeResult<JsonValue>> {
+    Box::pin(async move {
+        let Some(predicate) = function_arg(&args, 1) else {
+            return Ok(JsonValue::Null);
+        };
+        let mut kept = Vec::new();
+        for item in array_arg(&args, 0) {
+            match invoker.invoke(predicate, vec![item.clone()]).await? {
+                JsonValue::Bool(true) => kept.push(item.clone()),
+                JsonValue::Bool(false) => {}
+                other => {
+                    return Err(RuntimeError::Evaluation(format!(
+                        "filter(): the predicate must return a bool, got {other}"
+                    )))
+                }
+            }
+        }
+        Ok(JsonValue::Array(kept))
+    })
+}
+
+fn reduce(args: Vec<Arg>, invoker: &dyn Invoker) -> BoxFuture<'_, RuntimeResult<JsonValue>> {
+    Box::pin(async move {
+        let Some(function) = function_arg(&args, 1) else {
+            return Ok(JsonValue::Null);
+        };
+        let mut accumulator = value_arg(&args, 2).clone();
+        for item in array_arg(&args, 0) {
+            accumulator = invoker
+                .invoke(function, vec![accumulator, item.clone()])
+                .await?;
+        }
+        Ok(accumulator)
+    })
+}
+
+/// Sort numbers or strings, by the values of `key` when one is given
+fn sort(args: Vec<Arg>, invoker: &dyn Invoker) -> BoxFuture<'_, RuntimeResult<JsonValue>> {
+    Box::pin(async move {
+        let items = array_arg(&args, 0);
+        let mut keyed = Vec::with_capacity(items.len());
+        for item in items {
+            let key = match function_arg(&args, 1) {
+                Some(key) => invoker.invoke(key, vec![item.clone()]).await?,
+                None => item.clone(),
+            };
+            keyed.push((key, item.clone()));
+        }
+
+        let comparable = keyed.iter().all(|(key, _)| key.is_number())
+            || keyed.iter().all(|(key, _)| key.is_string());
+        if !comparable {
+            return Err(RuntimeError::Evaluation(
+                "sort(): can only order numbers or strings, not a mix".to_string(),
+            ));
+        }
+        keyed.sort_by(|(left, _), (right, _)| match (left, right) {
+            (JsonValue::String(left), JsonValue::String(right)) => left.cmp(right),
+            _ => left
+                .as_f64()
+                .partial_cmp(&right.as_f64())
+                .unwrap_or(Ordering::Equal),
+        });
+        Ok(JsonValue::Array(
+            keyed.into_iter().map(|(_, item)| item).collect(),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn call(name: &str, args: Vec<JsonValue>) -> RuntimeResult<JsonValue> {
+        let registry = BuiltinRegistry::standard();
+        registry
+            .invoke(&Callable::Builtin(name.to_string()), args)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_standard_library() {
+        let cases = [
+            ("split", vec![json!("a,b"), json!(",")], json!(["a", "b"])),
+            ("join", vec![json!(["a", 1]), json!("-")], json!("a-1")),
+            ("contains", vec![json!([1, 2.0]), json!(2)], json!(true)),
+            ("contains", vec![json!({"id": 1}), json!("id")], json!(true)),
+            (
+                "matches",
+                vec![json!("AB123"), json!("^[A-Z]+[0-9]+$")],
+                json!(true),
+            ),
+            (
+                "matches",
+                vec![json!("ab"), json!("^[A-Z]+$")],
+                json!(false),
+            ),
+            (
+                "format",
+                vec![json!("{} has {1} {{items}}"), json!("cart"), json!(3)],
+                json!("cart has 3 {items}"),
+            ),
+            ("min", vec![json!(3), json!(-1.5), json!(2)], json!(-1.5)),
+            ("max", vec![json!([3, 7, 5])], json!(7.0)),
+            ("round", vec![json!(2.345), json!(2)], json!(2.35)),
+            ("avg", vec![json!([1, 2, 3, 4])], json!(2.5)),
+            ("keys", vec![json!({"b": 1, "a": 2})], json!(["a", "b"])),
+            (
+                "json_path",
+                vec![
+                    json!({"orders": [{"id": 7}, {"id": 9}]}),
+                    json!("$.orders[-1].id"),
+                ],
+                json!(9),
+            ),
+            (
+                "json_path",
+                vec![
+                    json!({"orders": [{"id": 7}, {"id": 9}]}),
+                    json!("orders[*]['id']"),
+                ],
+                json!([7, 9]),
+            ),
+            (
+                "parse_json",
+                vec![json!("{\"ok\": true}")],
+                json!({"ok": true}),
+            ),
+            (
+                "sha256",
+                vec![json!("abc")],
+                json!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            ),
+            (
+                "uuid_v5",
+                vec![
+                    json!("6ba7b810-9dad-11d1-80b4-00c04fd430c8"),
+                    json!("sigmos"),
+                ],
+                json!(uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_DNS, b"sigmos").to_string()),
+            ),
+        ];
+        for (name, args, expected) in cases {
+            assert_eq!(call(name, args).await.unwrap(), expected, "{name}");
+        }
+        assert_eq!(
+            call("uuid", vec![]).await.unwrap().as_str().unwrap().len(),
+            36
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signatures_are_enforced() {
+        let error = call("upper", vec![json!(1)]).await.unwrap_err().to_string();
+        assert!(
+            error.contains("upper(): argument 'text' must be string, got a number"),
+            "{error}"
+        );
+        let error = call("pow", vec![json!(2)]).await.unwrap_err().to_string();
+        assert!(error.contains("pow() takes 2 arguments, got 1"), "{error}");
+        assert!(call("format", vec![json!("{} {}"), json!(1)])
+            .await
+            .is_err());
+
+        let mut registry = BuiltinRegistry::new();
+        let higher_order =
+            FunctionSignature::new(ValueType::Array).required("function", ValueType::Function);
+        assert!(registry
+            .register("apply", higher_order, |_| Ok(JsonValue::Null))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_higher_order_builtins() {
+        let registry = BuiltinRegistry::standard();
+        let builtin = |name: &str| Arg::Function(Callable::Builtin(name.to_string()));
+        let call = |name: &'static str, args: Vec<Arg>| {
+            let registry = &registry;
+            async move { registry.get(name).unwrap().call(name, args, registry).await }
+        };
+
+        let words = Arg::Value(json!(["b", "Ccc", "aa"]));
+        assert_eq!(
+            call("map", vec![words.clone(), builtin("upper")])
+                .await
+                .unwrap(),
+            json!(["B", "CCC", "AA"])
+        );
+        assert_eq!(
+            call("sort", vec![words.clone()]).await.unwrap(),
+            json!(["Ccc", "aa", "b"])
+        );
+        assert_eq!(
+            call("sort", vec![words.clone(), builtin("len")])
+                .await
+                .unwrap(),
+            json!(["b", "aa", "Ccc"])
+        );
+        assert_eq!(
+            call(
+                "reduce",
+                vec![
+                    Arg::Value(json!([3, 9, 4])),
+                    builtin("max"),
+                    Arg::Value(json!(0))
+                ]
+            )
+            .await
+            .unwrap(),
+            json!(9.0)
+        );
+        assert!(call("filter", vec![words, builtin("upper")]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lambdas_capture_their_scope() {
+        let spec = sigmos_core::parser::SigmosParser::parse_spec(
+            r#"
+spec "Orders" v1.0 {
+    inputs:
+        orders: list
+        threshold: float
+    computed:
+        large: -> filter(orders, o => o.total > threshold)
+        total: -> reduce(orders, (sum, o) => sum + o.total, 0)
+        ranked: -> map(sort(orders, o => 0 - o.total), o => o.id)
+        nested: -> map(orders, o => map(o.tags, t => format("{}:{}", o.id, t)))
+}
+"#,
+        )
+        .unwrap();
+        let inputs = json!({
+            "orders": [
+                { "id": "a", "total": 5, "tags": ["x"] },
+                { "id": "b", "total": 20, "tags": ["y", "z"] },
+            ],
+            "threshold": 10,
+        });
+
+        let mut runtime = crate::Runtime::new();
+        runtime
+            .execute_with_inputs(&spec, serde_json::from_value(inputs).unwrap())
+            .await
+            .unwrap();
+        let computed = runtime.snapshot().await.computed;
+        assert_eq!(
+            computed["large"],
+            json!([{ "id": "b", "total": 20, "tags": ["y", "z"] }])
+        );
+        assert_eq!(computed["total"], json!(25.0));
+        assert_eq!(computed["ranked"], json!(["b", "a"]));
+        assert_eq!(computed["nested"], json!([["a:x"], ["b:y", "b:z"]]));
+
+        let error = runtime
+            .invoke(
+                &Callable::Lambda {
+                    parameters: vec!["a".to_string(), "b".to_string()],
+                    body: sigmos_core::ast::Expression::Null,
+                    captured: HashMap::new(),
+                },
+                vec![json!(1)],
+            )
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Lambda (a, b) takes 2 argument(s), got 1"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Constraint enforcement
+//!
+//! `assert` constraints are checked once inputs are bound and `ensure`
+//! constraints once computed fields are available. Every failing constraint
+//! is collected into a [`ConstraintViolation`] rather than stopping at the first.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::{Runtime, RuntimeError};
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Agent" v1.0 {
+//!     inputs:
+//!         max_tokens: int { default: 5000 }
+//!     constraints:
+//!         assert max_tokens <= 4000
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new();
+//! match runtime.execute(&spec).await.unwrap_err() {
+//!     RuntimeError::ConstraintsViolated(violations) => {
+//!         assert_eq!(violations[0].source, "assert max_tokens <= 4000");
+//!         assert_eq!(violations[0].operands["max_tokens"], 5000);
+//!     }
+//!     other => panic!("unexpected error: {other}"),
+//! }
+//! # });
+//! ```
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sigmos_core::ast::{ConstraintType, Expression, Span};
+use std::collections::BTreeMap;
+
+/// A constraint that did not hold during execution
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConstraintViolation {
+    /// Position of the constraint in the spec's `constraints:` section
+    pub index: usize,
+    pub constraint_type: ConstraintType,
+    /// The constraint as written, e.g. `assert max_tokens <= 4000`
+    pub source: String,
+    /// Location in the spec, when the runtime has a source map
+    pub span: Option<Span>,
+    /// Evaluated operand values keyed by their source text; secrets are masked
+    pub operands: BTreeMap<String, JsonValue>,
+    /// Why the constraint failed when it did not simply evaluate to `false`
+    pub error: Option<String>,
+}
+
+impl std::fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(span) = self.span {
+            write!(f, " (at {span})")?;
+        }
+        if !self.operands.is_empty() {
+            let operands: Vec<String> = self
+                .operands
+                .iter()
+                .map(|(operand, value)| format!("{operand} = {value}"))
+                .collect();
+            write!(f, " where {}", operands.join(", "))?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, ": {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Subexpressions whose values explain why `expression` failed
+///
+/// These are the sides of a binary operator or the operand of `!`; literal
+/// sides are left out since their value is already in the source.
+pub(crate) fn operands(expression: &Expression) -> Vec<&Expression> {
+    let candidates = match expression {
+        Expression::Not(operand) => vec![operand.as_ref()],
+        _ => match expression.binary_operands() {
+            Some((_, left, right)) => vec![left, right],
+            None => vec![expression],
+        },
+    };
+    candidates
+        .into_iter()
+        .filter(|operand| operand.literal_value().is_none())
+        .collect()
+}
+
+/// Summary of a list of violations for error messages
+pub(crate) fn summarize(violations: &[ConstraintViolation]) -> String {
+    violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operands_skip_literals() {
+        let expression = Expression::LessThanOrEqual(
+            Box::new(Expression::Identifier("max_tokens".to_string())),
+            Box::new(Expression::Number(4000.0)),
+        );
+        let found: Vec<String> = operands(&expression)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(found, vec!["max_tokens"]);
+
+        let violation = ConstraintViolation {
+            index: 0,
+            constraint_type: ConstraintType::Assert,
+            source: format!("assert {expression}"),
+            span: None,
+            operands: BTreeMap::from([("max_tokens".to_string(), JsonValue::from(5000))]),
+            error: None,
+        };
+        assert_eq!(
+            violation.to_string(),
+            "assert max_tokens <= 4000 where max_tokens = 5000"
+        );
+    }
+}
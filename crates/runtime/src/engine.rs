@@ -1,19 +1,1796 @@
 //! # SIGMOS Execution Engine
 //!
-//! Core execution logic for SIGMOS specifications.
+//! Compiles a [`Spec`] into an [`ExecutionPlan`], which the runtime executes
+//! instead of walking the AST. Compiling:
+//!
+//! - gives every input and computed field a slot, so expressions read
+//!   variables by index from one frame rather than by name from a cloned map;
+//! - resolves each call to the builtin, spec function or plugin it names;
+//! - folds operations on literals, such as `60 * 60`, into constants;
+//! - orders computed fields so each comes after the fields it reads, and
+//!   rejects fields that depend on each other.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::builtins::BuiltinRegistry;
+//! use sigmos_runtime::engine::Engine;
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Quota" v1.0 {
+//!     inputs:
+//!         hours: float
+//!     computed:
+//!         limit: -> seconds * 2
+//!         seconds: -> hours * (60 * 60)
+//! }
+//! "#).unwrap();
+//!
+//! let builtins = BuiltinRegistry::standard();
+//! let plan = Engine::new(&builtins).compile(&spec).unwrap();
+//! assert_eq!(plan.slot("hours"), Some(0));
+//! assert_eq!(plan.computed_order().collect::<Vec<_>>(), ["seconds", "limit"]);
+//! ```
+
+use crate::builtins::{Arg, Builtin, BuiltinRegistry, Callable, Invoker};
+use crate::lifecycle::StandardAction;
+use crate::limits::{ExecutionLimits, Limited};
+use crate::policy::{self, Retry};
+use crate::{
+    events, functions, strict, ArithmeticOp, ComparisonOp, Runtime, RuntimeError, RuntimeResult,
+};
+use futures::future::{self, BoxFuture};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde_json::Value as JsonValue;
+use sigmos_core::ast::*;
+use sigmos_core::types::ValueType;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Compiles specs into execution plans
+///
+/// Calls are resolved against a [`BuiltinRegistry`] and, for plugin calls,
+/// the plugin names bound to the spec's extensions.
+pub struct Engine<'a> {
+    builtins: &'a BuiltinRegistry,
+    extensions: HashMap<String, String>,
+    limits: ExecutionLimits,
+    strict: bool,
+}
+
+impl<'a> Engine<'a> {
+    /// Create an engine that resolves free function calls in `builtins`
+    pub fn new(builtins: &'a BuiltinRegistry) -> Self {
+        Self {
+            builtins,
+            extensions: HashMap::new(),
+            limits: ExecutionLimits::default(),
+            strict: false,
+        }
+    }
+
+    /// Resolve plugin calls on the local names in `extensions` to the plugins
+    /// they are bound to; other objects are taken as plugin names
+    pub fn with_extensions(mut self, extensions: HashMap<String, String>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Reject expressions that nest deeper than `limits` allow
+    pub fn with_limits(mut self, limits: ExecutionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reject names that no field, parameter or lambda defines, instead of
+    /// compiling them to `${name}` placeholders
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Compile `spec` into a plan
+    ///
+    /// Fails when computed fields depend on each other, an expression nests
+    /// deeper than the engine's limits allow, or a strict engine finds an
+    /// unresolved name. The spec is otherwise compiled
+    /// as written: calls to unknown functions compile to operations that fail
+    /// when they are evaluated, as they would when walking the AST.
+    pub fn compile(&self, spec: &Spec) -> RuntimeResult<ExecutionPlan> {
+        let mut names = Vec::new();
+        let mut slots = HashMap::new();
+        let fields = spec.inputs.iter().map(|field| &field.name);
+        for name in fields.chain(spec.computed.iter().map(|field| &field.name)) {
+            if !slots.contains_key(name) {
+                slots.insert(name.clone(), names.len());
+                names.push(name.clone());
+            }
+        }
+
+        let mut compiler = Compiler {
+            engine: self,
+            functions: spec
+                .functions
+                .iter()
+                .enumerate()
+                .map(|(index, function)| (function.name.clone(), index))
+                .collect(),
+            lambdas: Vec::new(),
+            depth: 0,
+            location: String::new(),
+            policy: None,
+            error: None,
+        };
+
+        let functions = spec
+            .functions
+            .iter()
+            .map(|function| {
+                compiler.location = format!("functions.{}", function.name);
+                let parameters = function
+                    .parameters
+                    .iter()
+                    .enumerate()
+                    .map(|(slot, parameter)| (parameter.name.clone(), slot))
+                    .collect();
+                CompiledFunction {
+                    definition: function.clone(),
+                    body: compiler.compile(&function.body, &parameters),
+                }
+            })
+            .collect();
+
+        let computed = dependency_order(spec)?
+            .into_iter()
+            .map(|field| {
+                compiler.location = format!("computed.{}", field.name);
+                compiler.policy = field.policy.clone();
+                let op = compiler.compile(&field.expression, &slots);
+                let op = compiler.recover(op, &slots);
+                compiler.policy = None;
+                Step {
+                    name: field.name.clone(),
+                    slot: slots[&field.name],
+                    immediate: !awaits(&op),
+                    op,
+                    reads: field
+                        .referenced_identifiers()
+                        .into_iter()
+                        .filter(|name| *name != field.name)
+                        .filter_map(|name| slots.get(name).copied())
+                        .collect(),
+                }
+            })
+            .collect();
 
-/// Execution engine placeholder
-pub struct Engine;
+        let constraints = spec
+            .constraints
+            .iter()
+            .enumerate()
+            .map(|(index, constraint)| {
+                compiler.location = format!("constraints[{index}]");
+                compiler.compile(&constraint.expression, &slots)
+            })
+            .collect();
 
-impl Engine {
-    /// Create new engine
-    pub fn new() -> Self {
-        Self
+        let lifecycle = spec
+            .lifecycle
+            .iter()
+            .enumerate()
+            .map(|(index, lifecycle)| {
+                compiler.location = format!("lifecycle[{index}]");
+                compiler.policy = lifecycle.policy.clone();
+                let action = compiler.compile_action(&lifecycle.action, &slots);
+                compiler.policy = None;
+                (lifecycle.phase.clone(), action)
+            })
+            .collect();
+
+        // Handlers see their parameter in the slot after the fields
+        let events = spec
+            .events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| {
+                compiler.location = format!("events[{index}]");
+                let mut scope = slots.clone();
+                scope.insert(event.parameter.clone(), names.len());
+                compiler.policy = event.policy.clone();
+                let action = compiler.compile_action(&event.action, &scope);
+                compiler.policy = None;
+                (events::event_name(&event.event_type).to_string(), action)
+            })
+            .collect();
+
+        if let Some(error) = compiler.error {
+            return Err(error);
+        }
+        Ok(ExecutionPlan {
+            names,
+            slots,
+            computed,
+            constraints,
+            lifecycle,
+            events,
+            functions,
+            lambdas: compiler.lambdas,
+            types: spec.types.clone(),
+        })
     }
 }
 
-impl Default for Engine {
-    fn default() -> Self {
-        Self::new()
+/// A spec compiled by an [`Engine`]
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionPlan {
+    /// Field names by slot
+    names: Vec<String>,
+    /// Slots by field name
+    slots: HashMap<String, usize>,
+    /// Computed fields in dependency order
+    computed: Vec<Step>,
+    /// Constraints in declaration order
+    constraints: Vec<Op>,
+    /// Lifecycle actions and their phases, in declaration order
+    lifecycle: Vec<(LifecyclePhase, CompiledAction)>,
+    /// Event handlers and the names of their events, in declaration order
+    events: Vec<(String, CompiledAction)>,
+    /// Spec functions, whose frames hold their parameters
+    functions: Vec<CompiledFunction>,
+    /// Lambdas, whose frames hold their captured values then their parameters
+    lambdas: Vec<CompiledLambda>,
+    /// Type definitions that arguments and results are coerced to
+    types: Vec<TypeDef>,
+}
+
+impl ExecutionPlan {
+    /// Slot holding the input or computed field `name`
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+
+    /// Names of the computed fields in the order they are evaluated
+    pub fn computed_order(&self) -> impl Iterator<Item = &str> {
+        self.computed.iter().map(|step| step.name.as_str())
+    }
+
+    pub(crate) fn steps(&self) -> &[Step] {
+        &self.computed
+    }
+
+    /// Computed fields that transitively depend on the field in `slot`, in
+    /// dependency order
+    pub(crate) fn dependents(&self, slot: usize) -> Vec<&Step> {
+        let mut changed = HashSet::from([slot]);
+        let mut affected = Vec::new();
+        for step in &self.computed {
+            if !changed.contains(&step.slot) && step.reads.iter().any(|read| changed.contains(read))
+            {
+                changed.insert(step.slot);
+                affected.push(step);
+            }
+        }
+        affected
+    }
+
+    pub(crate) fn constraint(&self, index: usize) -> Option<&Op> {
+        self.constraints.get(index)
+    }
+
+    pub(crate) fn lifecycle(&self) -> &[(LifecyclePhase, CompiledAction)] {
+        &self.lifecycle
+    }
+
+    pub(crate) fn events(&self) -> &[(String, CompiledAction)] {
+        &self.events
+    }
+
+    /// Slot of an event handler's parameter
+    pub(crate) fn parameter_slot(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Frame holding the fields found in `sources`, later sources taking
+    /// precedence
+    pub(crate) fn frame<'v>(
+        &self,
+        sources: impl IntoIterator<Item = &'v HashMap<String, JsonValue>>,
+    ) -> Frame {
+        let mut frame = Frame {
+            values: vec![None; self.names.len() + 1],
+            depth: 0,
+        };
+        for source in sources {
+            for (slot, name) in self.names.iter().enumerate() {
+                if let Some(value) = source.get(name) {
+                    frame.values[slot] = Some(value.clone());
+                }
+            }
+        }
+        frame
+    }
+
+    /// The fields bound in `frame`, by name
+    pub(crate) fn bindings(&self, frame: &Frame) -> HashMap<String, JsonValue> {
+        self.names
+            .iter()
+            .zip(&frame.values)
+            .filter_map(|(name, value)| Some((name.clone(), value.clone()?)))
+            .collect()
+    }
+}
+
+/// Values of the variables an operation can read, by slot
+#[derive(Debug, Clone)]
+pub(crate) struct Frame {
+    values: Vec<Option<JsonValue>>,
+    /// Spec function calls in progress where the frame is evaluated
+    depth: usize,
+}
+
+impl Frame {
+    pub(crate) fn get(&self, slot: usize) -> Option<&JsonValue> {
+        self.values.get(slot).and_then(Option::as_ref)
+    }
+
+    pub(crate) fn set(&mut self, slot: usize, value: JsonValue) {
+        self.values[slot] = Some(value);
+    }
+}
+
+/// A computed field compiled to the operation that produces its value
+#[derive(Debug, Clone)]
+pub(crate) struct Step {
+    pub(crate) name: String,
+    pub(crate) slot: usize,
+    pub(crate) op: Op,
+    /// Whether `op` can be evaluated without awaiting
+    immediate: bool,
+    /// Slots of the fields its expression reads
+    reads: Vec<usize>,
+}
+
+/// An event or lifecycle action with its expressions compiled
+#[derive(Debug, Clone)]
+pub(crate) enum CompiledAction {
+    Standard {
+        action: StandardAction,
+        arguments: Vec<Op>,
+    },
+    Call(Op),
+    Assign {
+        target: String,
+        slot: Option<usize>,
+        value: Op,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct CompiledFunction {
+    definition: FunctionDef,
+    body: Op,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledLambda {
+    parameters: Vec<String>,
+    body: Op,
+}
+
+/// A compiled expression
+#[derive(Debug, Clone)]
+pub(crate) enum Op {
+    Const(JsonValue),
+    /// A variable; reads of unbound variables give a `${name}` placeholder,
+    /// or fail on a strict runtime
+    Slot {
+        slot: usize,
+        name: String,
+    },
+    /// `ref("name")`, which fails if the variable is unbound
+    Ref {
+        slot: usize,
+        name: String,
+    },
+    Arithmetic(ArithmeticOp, Box<Op>, Box<Op>),
+    Equal {
+        negated: bool,
+        left: Box<Op>,
+        right: Box<Op>,
+    },
+    Compare(ComparisonOp, Box<Op>, Box<Op>),
+    And(Box<Op>, Box<Op>),
+    Or(Box<Op>, Box<Op>),
+    Not(Box<Op>),
+    Coalesce(Box<Op>, Box<Op>),
+    Conditional {
+        condition: Box<Op>,
+        if_true: Box<Op>,
+        if_false: Box<Op>,
+    },
+    Index(Box<Op>, Box<Op>),
+    Property {
+        object: Box<Op>,
+        property: String,
+        optional: bool,
+    },
+    Template(Vec<Part>),
+    Array(Vec<Op>),
+    Object(Vec<(String, Op)>),
+    Function {
+        index: usize,
+        arguments: Vec<Op>,
+    },
+    Builtin {
+        name: String,
+        builtin: Builtin,
+        arguments: Vec<PlanArg>,
+    },
+    Plugin {
+        plugin: String,
+        method: String,
+        arguments: Vec<(String, Op)>,
+        retry: Retry,
+    },
+    /// `op`, or `fallback` when a plugin call in `op` fails for good
+    Recover {
+        op: Box<Op>,
+        fallback: Box<Op>,
+    },
+    /// An expression that can only fail, such as a call to an unknown function
+    Fail(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Part {
+    Text(String),
+    /// A variable, or a path of properties from one such as `error.message`
+    Slot {
+        slot: usize,
+        path: String,
+    },
+}
+
+/// An argument of a builtin call
+#[derive(Debug, Clone)]
+pub(crate) enum PlanArg {
+    Value(Op),
+    /// A builtin passed by name
+    Builtin(String),
+    /// A lambda or spec function, with the slots of the values it captures
+    Routine {
+        routine: Routine,
+        captures: Vec<usize>,
+    },
+}
+
+/// A lambda or spec function of a plan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Routine {
+    Function(usize),
+    Lambda(usize),
+}
+
+/// A lambda or spec function of an [`ExecutionPlan`] passed to a higher-order
+/// builtin, with the values it captured where it was passed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    routine: Routine,
+    captured: Vec<Option<JsonValue>>,
+    depth: usize,
+}
+
+struct Compiler<'e> {
+    engine: &'e Engine<'e>,
+    /// Indices of the spec's functions by name
+    functions: HashMap<String, usize>,
+    lambdas: Vec<CompiledLambda>,
+    /// Levels of the expression being compiled above the current one
+    depth: usize,
+    /// Where in the spec the expression being compiled is, such as
+    /// `computed.total`
+    location: String,
+    /// Policy of the computed field or action being compiled
+    policy: Option<CallPolicy>,
+    /// The first reason the spec cannot be compiled
+    error: Option<RuntimeError>,
+}
+
+impl Compiler<'_> {
+    /// Compile `expr`, whose variables are found in the slots of `scope`
+    fn compile(&mut self, expr: &Expression, scope: &HashMap<String, usize>) -> Op {
+        // Stop before deep expressions can overflow the stack
+        if self.depth >= self.engine.limits.max_depth {
+            self.fail(self.engine.limits.depth_exceeded());
+            return Op::Const(JsonValue::Null);
+        }
+        self.depth += 1;
+        let op = self.compile_expression(expr, scope);
+        self.depth -= 1;
+        op
+    }
+
+    /// `op`, falling back to the value of the current policy's fallback
+    fn recover(&mut self, op: Op, scope: &HashMap<String, usize>) -> Op {
+        let Some(fallback) = self.policy.as_ref().and_then(|p| p.fallback.clone()) else {
+            return op;
+        };
+        // The fallback's own plugin calls are made once
+        let policy = self.policy.take();
+        let fallback = self.compile(&fallback, scope);
+        self.policy = policy;
+        Op::Recover {
+            op: Box::new(op),
+            fallback: Box::new(fallback),
+        }
+    }
+
+    /// Keep the first error found in the spec
+    fn fail(&mut self, error: RuntimeError) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Fail on `name`, which is not in `scope`, if the engine is strict
+    fn unresolved(&mut self, name: &str, scope: &HashMap<String, usize>) {
+        if !self.engine.strict {
+            return;
+        }
+        let mut error = strict::unresolved(name, scope.keys().map(String::as_str));
+        if let RuntimeError::UnresolvedName { location, .. } = &mut error {
+            location.clone_from(&self.location);
+        }
+        self.fail(error);
+    }
+
+    fn compile_expression(&mut self, expr: &Expression, scope: &HashMap<String, usize>) -> Op {
+        let mut binary = |left: &Expression, right: &Expression| {
+            (
+                Box::new(self.compile(left, scope)),
+                Box::new(self.compile(right, scope)),
+            )
+        };
+        let op = match expr {
+            Expression::StringLiteral(s) => Op::Const(JsonValue::String(s.clone())),
+            Expression::Number(n) => match serde_json::Number::from_f64(*n) {
+                Some(number) => Op::Const(JsonValue::Number(number)),
+                None => Op::Fail(format!("Invalid number: {n}")),
+            },
+            Expression::Boolean(b) => Op::Const(JsonValue::Bool(*b)),
+            Expression::Null => Op::Const(JsonValue::Null),
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(&slot) => Op::Slot {
+                    slot,
+                    name: name.clone(),
+                },
+                None => {
+                    self.unresolved(name, scope);
+                    Op::Const(placeholder(name))
+                }
+            },
+            Expression::FunctionCall {
+                object,
+                method,
+                arguments,
+            } => self.compile_call(object, method, arguments, scope),
+            Expression::StringTemplate { parts } => Op::Template(
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        TemplatePart::Text(text) => Part::Text(text.clone()),
+                        TemplatePart::Variable(path) => {
+                            let name = path.split('.').next().unwrap_or(path);
+                            match scope.get(name) {
+                                Some(&slot) => Part::Slot {
+                                    slot,
+                                    path: path.clone(),
+                                },
+                                None => {
+                                    self.unresolved(name, scope);
+                                    Part::Text(format!("${{{path}}}"))
+                                }
+                            }
+                        }
+                    })
+                    .collect(),
+            ),
+            Expression::Add(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Arithmetic(ArithmeticOp::Add, left, right)
+            }
+            Expression::Subtract(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Arithmetic(ArithmeticOp::Subtract, left, right)
+            }
+            Expression::Multiply(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Arithmetic(ArithmeticOp::Multiply, left, right)
+            }
+            Expression::Divide(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Arithmetic(ArithmeticOp::Divide, left, right)
+            }
+            Expression::Modulo(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Arithmetic(ArithmeticOp::Modulo, left, right)
+            }
+            Expression::Equal(left, right) | Expression::NotEqual(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Equal {
+                    negated: matches!(expr, Expression::NotEqual(..)),
+                    left,
+                    right,
+                }
+            }
+            Expression::LessThan(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Compare(ComparisonOp::LessThan, left, right)
+            }
+            Expression::LessThanOrEqual(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Compare(ComparisonOp::LessThanOrEqual, left, right)
+            }
+            Expression::GreaterThan(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Compare(ComparisonOp::GreaterThan, left, right)
+            }
+            Expression::GreaterThanOrEqual(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Compare(ComparisonOp::GreaterThanOrEqual, left, right)
+            }
+            Expression::And(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::And(left, right)
+            }
+            Expression::Or(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Or(left, right)
+            }
+            Expression::Coalesce(left, right) => {
+                let (left, right) = binary(left, right);
+                Op::Coalesce(left, right)
+            }
+            Expression::ArrayAccess(array, index) => {
+                let (array, index) = binary(array, index);
+                Op::Index(array, index)
+            }
+            Expression::Not(operand) => Op::Not(Box::new(self.compile(operand, scope))),
+            Expression::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => Op::Conditional {
+                condition: Box::new(self.compile(condition, scope)),
+                if_true: Box::new(self.compile(if_true, scope)),
+                if_false: Box::new(self.compile(if_false, scope)),
+            },
+            Expression::PropertyAccess(object, property)
+            | Expression::OptionalPropertyAccess(object, property) => Op::Property {
+                object: Box::new(self.compile(object, scope)),
+                property: property.clone(),
+                optional: matches!(expr, Expression::OptionalPropertyAccess(..)),
+            },
+            Expression::Array(items) => {
+                Op::Array(items.iter().map(|item| self.compile(item, scope)).collect())
+            }
+            Expression::Object(entries) => Op::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.compile(value, scope)))
+                    .collect(),
+            ),
+            Expression::Lambda { .. } => Op::Fail(
+                "Lambdas can only be passed to functions that take one, such as map or filter"
+                    .to_string(),
+            ),
+        };
+        fold(op)
+    }
+
+    /// Resolve a call to `ref`, a spec function, a builtin or a plugin, in
+    /// the order the runtime looks them up
+    fn compile_call(
+        &mut self,
+        object: &str,
+        method: &str,
+        arguments: &[Argument],
+        scope: &HashMap<String, usize>,
+    ) -> Op {
+        match (object, method) {
+            ("", "ref") => match arguments.first().map(|a| &a.value) {
+                Some(Expression::StringLiteral(name)) => match scope.get(name) {
+                    Some(&slot) => Op::Ref {
+                        slot,
+                        name: name.clone(),
+                    },
+                    None => Op::Fail(format!("ref(\"{name}\") names an unknown field")),
+                },
+                _ => Op::Fail("ref() requires a field name as a string literal".to_string()),
+            },
+            ("", name) if self.functions.contains_key(name) => Op::Function {
+                index: self.functions[name],
+                arguments: arguments
+                    .iter()
+                    .map(|argument| self.compile(&argument.value, scope))
+                    .collect(),
+            },
+            ("", name) if self.engine.builtins.contains(name) => {
+                let builtin = self.engine.builtins.get(name).cloned().unwrap();
+                let arguments = arguments
+                    .iter()
+                    .enumerate()
+                    .map(|(index, argument)| {
+                        let takes_function = builtin
+                            .signature()
+                            .parameter(index)
+                            .is_some_and(|parameter| parameter.value_type == ValueType::Function);
+                        self.compile_argument(name, &argument.value, takes_function, scope)
+                    })
+                    .collect();
+                Op::Builtin {
+                    name: name.to_string(),
+                    builtin,
+                    arguments,
+                }
+            }
+            (object, method) if !object.is_empty() => Op::Plugin {
+                plugin: self
+                    .engine
+                    .extensions
+                    .get(object)
+                    .map_or(object, String::as_str)
+                    .to_string(),
+                method: method.to_string(),
+                arguments: arguments
+                    .iter()
+                    .enumerate()
+                    .map(|(index, argument)| {
+                        let name = if argument.name.is_empty() {
+                            format!("arg_{index}")
+                        } else {
+                            argument.name.clone()
+                        };
+                        (name, self.compile(&argument.value, scope))
+                    })
+                    .collect(),
+                retry: self.policy.as_ref().map(Retry::from).unwrap_or_default(),
+            },
+            _ => Op::Fail(format!("Unknown function: {method}")),
+        }
+    }
+
+    /// Compile an argument of the builtin `name`, passing lambdas and the
+    /// names of functions as functions where it takes one
+    fn compile_argument(
+        &mut self,
+        name: &str,
+        argument: &Expression,
+        takes_function: bool,
+        scope: &HashMap<String, usize>,
+    ) -> PlanArg {
+        match argument {
+            Expression::Identifier(function) if takes_function && !scope.contains_key(function) => {
+                if self.engine.builtins.contains(function) {
+                    PlanArg::Builtin(function.clone())
+                } else if let Some(&index) = self.functions.get(function) {
+                    PlanArg::Routine {
+                        routine: Routine::Function(index),
+                        captures: Vec::new(),
+                    }
+                } else {
+                    PlanArg::Value(Op::Fail(format!(
+                        "{name}(): '{function}' is not a function"
+                    )))
+                }
+            }
+            lambda @ Expression::Lambda { parameters, body } if takes_function => {
+                // The lambda's frame holds the values it reads from `scope`, then its parameters
+                let mut captured: Vec<(&str, usize)> = Vec::new();
+                for identifier in lambda.referenced_identifiers() {
+                    if let Some(&slot) = scope.get(identifier) {
+                        if !captured.iter().any(|(name, _)| *name == identifier) {
+                            captured.push((identifier, slot));
+                        }
+                    }
+                }
+                let mut inner: HashMap<String, usize> = captured
+                    .iter()
+                    .enumerate()
+                    .map(|(slot, (name, _))| (name.to_string(), slot))
+                    .collect();
+                for (index, parameter) in parameters.iter().enumerate() {
+                    inner.insert(parameter.clone(), captured.len() + index);
+                }
+                let body = self.compile(body, &inner);
+                self.lambdas.push(CompiledLambda {
+                    parameters: parameters.clone(),
+                    body,
+                });
+                PlanArg::Routine {
+                    routine: Routine::Lambda(self.lambdas.len() - 1),
+                    captures: captured.into_iter().map(|(_, slot)| slot).collect(),
+                }
+            }
+            value => PlanArg::Value(self.compile(value, scope)),
+        }
+    }
+
+    fn compile_action(
+        &mut self,
+        action: &Action,
+        scope: &HashMap<String, usize>,
+    ) -> CompiledAction {
+        match action {
+            Action::FunctionCall {
+                object,
+                method,
+                arguments,
+            } => match StandardAction::from_name(method).filter(|_| object.is_empty()) {
+                Some(standard) => CompiledAction::Standard {
+                    action: standard,
+                    arguments: arguments
+                        .iter()
+                        .map(|argument| {
+                            let op = self.compile(&argument.value, scope);
+                            self.recover(op, scope)
+                        })
+                        .collect(),
+                },
+                None => {
+                    let op = self.compile_call(object, method, arguments, scope);
+                    CompiledAction::Call(self.recover(op, scope))
+                }
+            },
+            Action::Identifier(name) => match StandardAction::from_name(name) {
+                Some(standard) => CompiledAction::Standard {
+                    action: standard,
+                    arguments: Vec::new(),
+                },
+                // Other identifiers are calls to the `builtin` plugin
+                None => {
+                    let op = self.compile_call("builtin", name, &[], scope);
+                    CompiledAction::Call(self.recover(op, scope))
+                }
+            },
+            Action::Assign { target, value } => {
+                let value = self.compile(value, scope);
+                CompiledAction::Assign {
+                    target: target.clone(),
+                    slot: scope.get(target).copied(),
+                    value: self.recover(value, scope),
+                }
+            }
+        }
+    }
+}
+
+/// Replace `op` with its value when it only involves constants and
+/// evaluating it cannot fail
+fn fold(op: Op) -> Op {
+    let constant = match &op {
+        Op::Arithmetic(operator, left, right) => match (&**left, &**right) {
+            (Op::Const(l), Op::Const(r)) => {
+                Runtime::perform_arithmetic_operation(l, r, *operator).ok()
+            }
+            _ => None,
+        },
+        Op::Equal {
+            negated,
+            left,
+            right,
+        } => match (&**left, &**right) {
+            (Op::Const(l), Op::Const(r)) => {
+                Some(JsonValue::Bool(Runtime::values_equal(l, r) != *negated))
+            }
+            _ => None,
+        },
+        Op::Compare(operator, left, right) => match (&**left, &**right) {
+            (Op::Const(l), Op::Const(r)) => Runtime::perform_comparison(l, r, *operator).ok(),
+            _ => None,
+        },
+        Op::And(left, right) => match (&**left, &**right) {
+            (Op::Const(l), _) if !Runtime::is_truthy(l) => Some(JsonValue::Bool(false)),
+            (Op::Const(_), Op::Const(r)) => Some(JsonValue::Bool(Runtime::is_truthy(r))),
+            _ => None,
+        },
+        Op::Or(left, right) => match (&**left, &**right) {
+            (Op::Const(l), _) if Runtime::is_truthy(l) => Some(JsonValue::Bool(true)),
+            (Op::Const(_), Op::Const(r)) => Some(JsonValue::Bool(Runtime::is_truthy(r))),
+            _ => None,
+        },
+        Op::Not(operand) => match &**operand {
+            Op::Const(value) => Some(JsonValue::Bool(!Runtime::is_truthy(value))),
+            _ => None,
+        },
+        Op::Index(array, index) => match (&**array, &**index) {
+            (Op::Const(a), Op::Const(i)) => Runtime::perform_array_access(a, i).ok(),
+            _ => None,
+        },
+        Op::Property {
+            object,
+            property,
+            optional,
+        } => match &**object {
+            Op::Const(JsonValue::Null) if *optional => Some(JsonValue::Null),
+            Op::Const(value) => Runtime::perform_property_access(value, property).ok(),
+            _ => None,
+        },
+        Op::Template(parts) => parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => Some(text.as_str()),
+                Part::Slot { .. } => None,
+            })
+            .collect::<Option<String>>()
+            .map(JsonValue::String),
+        Op::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Op::Const(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(JsonValue::Array),
+        Op::Object(entries) => entries
+            .iter()
+            .map(|(key, value)| match value {
+                Op::Const(value) => Some((key.clone(), value.clone())),
+                _ => None,
+            })
+            .collect::<Option<serde_json::Map<_, _>>>()
+            .map(JsonValue::Object),
+        _ => None,
+    };
+    if let Some(value) = constant {
+        return Op::Const(value);
+    }
+
+    // Branches on constants reduce to the branch taken
+    match op {
+        Op::Coalesce(left, right) => match *left {
+            Op::Const(JsonValue::Null) => *right,
+            left @ Op::Const(_) => left,
+            left => Op::Coalesce(Box::new(left), right),
+        },
+        Op::Conditional {
+            condition,
+            if_true,
+            if_false,
+        } => match *condition {
+            Op::Const(value) if Runtime::is_truthy(&value) => *if_true,
+            Op::Const(_) => *if_false,
+            condition => Op::Conditional {
+                condition: Box::new(condition),
+                if_true,
+                if_false,
+            },
+        },
+        op => op,
+    }
+}
+
+/// Whether evaluating `op` calls a plugin, a spec function or a builtin that
+/// takes functions, which must be awaited
+fn awaits(op: &Op) -> bool {
+    match op {
+        Op::Const(_) | Op::Slot { .. } | Op::Ref { .. } | Op::Template(_) | Op::Fail(_) => false,
+        Op::Plugin { .. } | Op::Function { .. } => true,
+        Op::Builtin {
+            builtin, arguments, ..
+        } => {
+            builtin.is_higher_order()
+                || arguments.iter().any(|argument| match argument {
+                    PlanArg::Value(op) => awaits(op),
+                    PlanArg::Builtin(_) | PlanArg::Routine { .. } => true,
+                })
+        }
+        Op::Arithmetic(_, left, right)
+        | Op::Compare(_, left, right)
+        | Op::Equal { left, right, .. }
+        | Op::And(left, right)
+        | Op::Or(left, right)
+        | Op::Coalesce(left, right)
+        | Op::Index(left, right)
+        | Op::Recover {
+            op: left,
+            fallback: right,
+        } => awaits(left) || awaits(right),
+        Op::Not(operand)
+        | Op::Property {
+            object: operand, ..
+        } => awaits(operand),
+        Op::Conditional {
+            condition,
+            if_true,
+            if_false,
+        } => awaits(condition) || awaits(if_true) || awaits(if_false),
+        Op::Array(items) => items.iter().any(awaits),
+        Op::Object(entries) => entries.iter().any(|(_, value)| awaits(value)),
+    }
+}
+
+/// What an unbound variable evaluates to
+fn placeholder(name: &str) -> JsonValue {
+    JsonValue::String(format!("${{{name}}}"))
+}
+
+/// Value of the variable in `slot`; if it is unbound, a placeholder or,
+/// when `strict`, an error
+fn read(frame: &Frame, slot: usize, name: &str, strict: bool) -> RuntimeResult<JsonValue> {
+    match frame.get(slot) {
+        Some(value) => Ok(value.clone()),
+        None if strict => Err(strict::unresolved(name, [])),
+        None => Ok(placeholder(name)),
+    }
+}
+
+/// Value of `ref("name")`, which must be bound
+fn reference(frame: &Frame, slot: usize, name: &str) -> RuntimeResult<JsonValue> {
+    frame
+        .get(slot)
+        .cloned()
+        .ok_or_else(|| RuntimeError::Evaluation(format!("ref(\"{name}\") names an unknown field")))
+}
+
+/// `parts` with the values of their variables filled in
+fn render(parts: &[Part], frame: &Frame, strict: bool) -> RuntimeResult<JsonValue> {
+    let mut result = String::new();
+    for part in parts {
+        match part {
+            Part::Text(text) => result.push_str(text),
+            Part::Slot { slot, path } => match frame.get(*slot) {
+                Some(value) => result.push_str(&Runtime::template_text(value, path, strict)?),
+                None if strict => {
+                    let name = path.split('.').next().unwrap_or(path);
+                    return Err(strict::unresolved(name, []));
+                }
+                None => result.push_str(&format!("${{{path}}}")),
+            },
+        }
+    }
+    Ok(JsonValue::String(result))
+}
+
+/// Keep the failure of the step at `index` if no earlier step has failed
+fn record_failure(failed: &mut Option<(usize, RuntimeError)>, index: usize, error: RuntimeError) {
+    if failed.as_ref().is_none_or(|(earlier, _)| index < *earlier) {
+        *failed = Some((index, error));
+    }
+}
+
+/// `spec.computed` ordered so each field comes after the fields it reads
+///
+/// A field that reads its own name sees the input of that name, if any, so
+/// only dependencies between different fields are ordered.
+fn dependency_order(spec: &Spec) -> RuntimeResult<Vec<&ComputedField>> {
+    let names: HashSet<&str> = spec.computed.iter().map(|f| f.name.as_str()).collect();
+    let mut placed: HashSet<&str> = HashSet::new();
+    let mut ordered = Vec::with_capacity(spec.computed.len());
+    let mut pending: Vec<&ComputedField> = spec.computed.iter().collect();
+    while !pending.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = pending.into_iter().partition(|field| {
+            field
+                .referenced_identifiers()
+                .iter()
+                .all(|id| !names.contains(id) || placed.contains(id) || *id == field.name)
+        });
+        if ready.is_empty() {
+            let cycle: Vec<&str> = waiting.iter().map(|field| field.name.as_str()).collect();
+            return Err(RuntimeError::Execution(format!(
+                "Computed fields depend on each other: {}",
+                cycle.join(", ")
+            )));
+        }
+        placed.extend(ready.iter().map(|field| field.name.as_str()));
+        ordered.extend(ready);
+        pending = waiting;
+    }
+    Ok(ordered)
+}
+
+/// Calls builtins, lambdas and spec functions passed to higher-order builtins
+/// from a plan
+struct PlanInvoker<'a> {
+    runtime: &'a Runtime,
+    plan: &'a ExecutionPlan,
+}
+
+impl Invoker for PlanInvoker<'_> {
+    fn invoke<'b>(
+        &'b self,
+        function: &'b Callable,
+        args: Vec<JsonValue>,
+    ) -> BoxFuture<'b, RuntimeResult<JsonValue>> {
+        match function {
+            Callable::Compiled(closure) => Box::pin(self.runtime.call_routine(
+                self.plan,
+                closure.routine,
+                closure.captured.clone(),
+                args,
+                closure.depth,
+            )),
+            Callable::Builtin(name) => Box::pin(async move {
+                let builtin =
+                    self.runtime.builtins.get(name).ok_or_else(|| {
+                        RuntimeError::Evaluation(format!("Unknown function: {name}"))
+                    })?;
+                builtin
+                    .call(name, args.into_iter().map(Arg::Value).collect(), self)
+                    .await
+            }),
+            Callable::Lambda { .. } => self.runtime.invoke(function, args),
+        }
+    }
+}
+
+impl Runtime {
+    /// Evaluate the computed fields of `plan` from the values in `inputs`
+    ///
+    /// Unlike an execution, this reads and changes none of the runtime's
+    /// state; plugin calls still go through its plugin host. Builtins passed
+    /// by name to higher-order builtins are looked up in the runtime's
+    /// registry.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_runtime::engine::Engine;
+    /// use sigmos_runtime::Runtime;
+    /// use std::collections::HashMap;
+    ///
+    /// # tokio_test::block_on(async {
+    /// let spec = SigmosParser::parse_spec(r#"
+    /// spec "Cart" v1.0 {
+    ///     inputs:
+    ///         prices: list
+    ///     computed:
+    ///         total: -> reduce(prices, (sum, p) => sum + p, 0)
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let runtime = Runtime::new();
+    /// let plan = Engine::new(runtime.builtins()).compile(&spec).unwrap();
+    /// let inputs = HashMap::from([("prices".to_string(), json!([1.5, 2.5]))]);
+    /// let computed = runtime.evaluate_plan(&plan, &inputs).await.unwrap();
+    /// assert_eq!(computed["total"], json!(4.0));
+    /// # });
+    /// ```
+    pub async fn evaluate_plan(
+        &self,
+        plan: &ExecutionPlan,
+        inputs: &HashMap<String, JsonValue>,
+    ) -> RuntimeResult<HashMap<String, JsonValue>> {
+        let mut frame = plan.frame([inputs]);
+        let steps: Vec<&Step> = plan.steps().iter().collect();
+        Ok(self
+            .run_steps(plan, &steps, &mut frame)
+            .await?
+            .into_iter()
+            .collect())
+    }
+
+    /// Evaluate `steps`, storing each value in `frame` for the steps after it
+    ///
+    /// A step starts once the steps before it that it reads have values, so
+    /// steps that do not read each other, such as independent plugin calls,
+    /// run concurrently, up to `max_concurrency` at once. Values come back in
+    /// the order of `steps`. When steps fail, the error is the one evaluating
+    /// them in order would report: steps after a failed one are not started,
+    /// and the earliest failure wins.
+    pub(crate) async fn run_steps(
+        &self,
+        plan: &ExecutionPlan,
+        steps: &[&Step],
+        frame: &mut Frame,
+    ) -> RuntimeResult<Vec<(String, JsonValue)>> {
+        // Indices of the earlier steps each step reads
+        let waits: Vec<Vec<usize>> = steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                (0..index)
+                    .filter(|&earlier| step.reads.contains(&steps[earlier].slot))
+                    .collect()
+            })
+            .collect();
+        let mut values: Vec<Option<JsonValue>> = vec![None; steps.len()];
+        let mut started = vec![false; steps.len()];
+        let mut failed: Option<(usize, RuntimeError)> = None;
+        let mut running = FuturesUnordered::new();
+        // Copy of `frame` shared by the steps started since it last changed
+        let mut snapshot: Option<Arc<Frame>> = None;
+
+        loop {
+            for index in 0..steps.len() {
+                if failed.as_ref().is_some_and(|(failure, _)| index > *failure) {
+                    break;
+                }
+                let ready = waits[index]
+                    .iter()
+                    .all(|&earlier| values[earlier].is_some());
+                if started[index] || !ready {
+                    continue;
+                }
+                let step = steps[index];
+                if step.immediate {
+                    started[index] = true;
+                    match self.evaluate_now(&step.op, frame) {
+                        Ok(value) => {
+                            frame.set(step.slot, value.clone());
+                            values[index] = Some(value);
+                            snapshot = None;
+                        }
+                        Err(error) => record_failure(&mut failed, index, error),
+                    }
+                } else if running.len() < self.limits.max_concurrency.max(1) {
+                    started[index] = true;
+                    let snapshot =
+                        Arc::clone(snapshot.get_or_insert_with(|| Arc::new(frame.clone())));
+                    running.push(async move {
+                        (index, self.evaluate_op(plan, &step.op, &snapshot).await)
+                    });
+                }
+            }
+
+            // Steps already running finish, so an earlier failure can win
+            let Some((index, result)) = running.next().await else {
+                break;
+            };
+            match result {
+                Ok(value) => {
+                    frame.set(steps[index].slot, value.clone());
+                    values[index] = Some(value);
+                    snapshot = None;
+                }
+                Err(error) => record_failure(&mut failed, index, error),
+            }
+        }
+
+        if let Some((index, error)) = failed {
+            return Err(self.locate(error, || format!("computed.{}", steps[index].name)));
+        }
+        Ok(steps
+            .iter()
+            .zip(values)
+            .filter_map(|(step, value)| Some((step.name.clone(), value?)))
+            .collect())
+    }
+
+    /// Evaluate an operation of `plan` against `frame`
+    ///
+    /// Plugin calls inside the operation are awaited.
+    pub(crate) fn evaluate_op<'a>(
+        &'a self,
+        plan: &'a ExecutionPlan,
+        op: &'a Op,
+        frame: &'a Frame,
+    ) -> Limited<'a> {
+        if let Err(error) = self.step() {
+            return self.limits.limit(Box::pin(future::ready(Err(error))));
+        }
+        let evaluate = move |op: &'a Op| self.evaluate_op(plan, op, frame);
+        // Each kind of operation gets its own future, so nested operations
+        // take little stack
+        let future: BoxFuture<'a, RuntimeResult<JsonValue>> =
+            match op {
+                Op::Const(value) => Box::pin(future::ready(Ok(value.clone()))),
+                Op::Slot { slot, name } => {
+                    Box::pin(future::ready(read(frame, *slot, name, self.strict)))
+                }
+                Op::Ref { slot, name } => Box::pin(future::ready(reference(frame, *slot, name))),
+                Op::Template(parts) => Box::pin(future::ready(render(parts, frame, self.strict))),
+                Op::Fail(message) => Box::pin(future::ready(Err(RuntimeError::Evaluation(
+                    message.clone(),
+                )))),
+                Op::Arithmetic(operator, left, right) => Box::pin(async move {
+                    let left = evaluate(left).await?;
+                    let right = evaluate(right).await?;
+                    Self::perform_arithmetic_operation(&left, &right, *operator)
+                }),
+                Op::Equal {
+                    negated,
+                    left,
+                    right,
+                } => Box::pin(async move {
+                    let left = evaluate(left).await?;
+                    let right = evaluate(right).await?;
+                    Ok(JsonValue::Bool(
+                        Self::values_equal(&left, &right) != *negated,
+                    ))
+                }),
+                Op::Compare(operator, left, right) => Box::pin(async move {
+                    let left = evaluate(left).await?;
+                    let right = evaluate(right).await?;
+                    Self::perform_comparison(&left, &right, *operator)
+                }),
+                Op::And(left, right) => Box::pin(async move {
+                    Ok(JsonValue::Bool(
+                        Self::is_truthy(&evaluate(left).await?)
+                            && Self::is_truthy(&evaluate(right).await?),
+                    ))
+                }),
+                Op::Or(left, right) => Box::pin(async move {
+                    Ok(JsonValue::Bool(
+                        Self::is_truthy(&evaluate(left).await?)
+                            || Self::is_truthy(&evaluate(right).await?),
+                    ))
+                }),
+                Op::Not(operand) => Box::pin(async move {
+                    Ok(JsonValue::Bool(!Self::is_truthy(&evaluate(operand).await?)))
+                }),
+                Op::Coalesce(left, right) => Box::pin(async move {
+                    match evaluate(left).await? {
+                        JsonValue::Null => evaluate(right).await,
+                        value => Ok(value),
+                    }
+                }),
+                Op::Conditional {
+                    condition,
+                    if_true,
+                    if_false,
+                } => Box::pin(async move {
+                    if Self::is_truthy(&evaluate(condition).await?) {
+                        evaluate(if_true).await
+                    } else {
+                        evaluate(if_false).await
+                    }
+                }),
+                Op::Index(array, index) => Box::pin(async move {
+                    let array = evaluate(array).await?;
+                    let index = evaluate(index).await?;
+                    Self::perform_array_access(&array, &index)
+                }),
+                Op::Property {
+                    object,
+                    property,
+                    optional,
+                } => Box::pin(async move {
+                    match evaluate(object).await? {
+                        JsonValue::Null if *optional => Ok(JsonValue::Null),
+                        object => Self::perform_property_access(&object, property),
+                    }
+                }),
+                Op::Array(items) => Box::pin(async move {
+                    let mut values = Vec::with_capacity(items.len());
+                    for item in items {
+                        values.push(evaluate(item).await?);
+                    }
+                    Ok(JsonValue::Array(values))
+                }),
+                Op::Object(entries) => Box::pin(async move {
+                    let mut object = serde_json::Map::with_capacity(entries.len());
+                    for (key, value) in entries {
+                        object.insert(key.clone(), evaluate(value).await?);
+                    }
+                    Ok(JsonValue::Object(object))
+                }),
+                Op::Function { index, arguments } => Box::pin(async move {
+                    let mut args = Vec::with_capacity(arguments.len());
+                    for argument in arguments {
+                        args.push(evaluate(argument).await?);
+                    }
+                    self.call_routine(
+                        plan,
+                        Routine::Function(*index),
+                        Vec::new(),
+                        args,
+                        frame.depth,
+                    )
+                    .await
+                }),
+                Op::Builtin {
+                    name,
+                    builtin,
+                    arguments,
+                } => Box::pin(async move {
+                    let mut args = Vec::with_capacity(arguments.len());
+                    for argument in arguments {
+                        args.push(match argument {
+                            PlanArg::Value(op) => Arg::Value(evaluate(op).await?),
+                            PlanArg::Builtin(function) => {
+                                Arg::Function(Callable::Builtin(function.clone()))
+                            }
+                            PlanArg::Routine { routine, captures } => {
+                                Arg::Function(Callable::Compiled(Closure {
+                                    routine: *routine,
+                                    captured: captures
+                                        .iter()
+                                        .map(|slot| frame.get(*slot).cloned())
+                                        .collect(),
+                                    depth: frame.depth,
+                                }))
+                            }
+                        });
+                    }
+                    let invoker = PlanInvoker {
+                        runtime: self,
+                        plan,
+                    };
+                    builtin.call(name, args, &invoker).await
+                }),
+                Op::Plugin {
+                    plugin,
+                    method,
+                    arguments,
+                    retry,
+                } => Box::pin(async move {
+                    if !self.plugins.has_plugin(plugin) {
+                        return Err(RuntimeError::Evaluation(format!(
+                            "Plugin '{plugin}' not found"
+                        )));
+                    }
+                    let mut args = HashMap::with_capacity(arguments.len());
+                    for (name, argument) in arguments {
+                        args.insert(name.clone(), evaluate(argument).await?);
+                    }
+                    self.call_plugin(plugin, method, args, *retry).await
+                }),
+                Op::Recover { op, fallback } => Box::pin(async move {
+                    match evaluate(op).await {
+                        Err(error) if policy::recoverable(&error) => evaluate(fallback).await,
+                        result => result,
+                    }
+                }),
+            };
+        self.limits.limit(future)
+    }
+
+    /// Evaluate an operation that does not await anything against `frame`
+    ///
+    /// Skips the future [`Runtime::evaluate_op`] allocates for every operation.
+    fn evaluate_now(&self, op: &Op, frame: &Frame) -> RuntimeResult<JsonValue> {
+        let _level = self.limits.enter()?;
+        self.step()?;
+        let evaluate = |op| self.evaluate_now(op, frame);
+        let value = match op {
+            Op::Const(value) => Ok(value.clone()),
+            Op::Slot { slot, name } => read(frame, *slot, name, self.strict),
+            Op::Ref { slot, name } => reference(frame, *slot, name),
+            Op::Arithmetic(operator, left, right) => {
+                Self::perform_arithmetic_operation(&evaluate(left)?, &evaluate(right)?, *operator)
+            }
+            Op::Equal {
+                negated,
+                left,
+                right,
+            } => Ok(JsonValue::Bool(
+                Self::values_equal(&evaluate(left)?, &evaluate(right)?) != *negated,
+            )),
+            Op::Compare(operator, left, right) => {
+                Self::perform_comparison(&evaluate(left)?, &evaluate(right)?, *operator)
+            }
+            Op::And(left, right) => Ok(JsonValue::Bool(
+                Self::is_truthy(&evaluate(left)?) && Self::is_truthy(&evaluate(right)?),
+            )),
+            Op::Or(left, right) => Ok(JsonValue::Bool(
+                Self::is_truthy(&evaluate(left)?) || Self::is_truthy(&evaluate(right)?),
+            )),
+            Op::Not(operand) => Ok(JsonValue::Bool(!Self::is_truthy(&evaluate(operand)?))),
+            Op::Coalesce(left, right) => match evaluate(left)? {
+                JsonValue::Null => evaluate(right),
+                value => Ok(value),
+            },
+            Op::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                if Self::is_truthy(&evaluate(condition)?) {
+                    evaluate(if_true)
+                } else {
+                    evaluate(if_false)
+                }
+            }
+            Op::Index(array, index) => {
+                Self::perform_array_access(&evaluate(array)?, &evaluate(index)?)
+            }
+            Op::Property {
+                object,
+                property,
+                optional,
+            } => match evaluate(object)? {
+                JsonValue::Null if *optional => Ok(JsonValue::Null),
+                object => Self::perform_property_access(&object, property),
+            },
+            Op::Template(parts) => render(parts, frame, self.strict),
+            Op::Array(items) => items
+                .iter()
+                .map(evaluate)
+                .collect::<RuntimeResult<Vec<_>>>()
+                .map(JsonValue::Array),
+            Op::Object(entries) => entries
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), evaluate(value)?)))
+                .collect::<RuntimeResult<serde_json::Map<_, _>>>()
+                .map(JsonValue::Object),
+            Op::Builtin {
+                name,
+                builtin,
+                arguments,
+            } => {
+                let mut args = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    let PlanArg::Value(op) = argument else {
+                        return Err(RuntimeError::Evaluation(format!(
+                            "{name}() takes functions, so calls to it must be awaited"
+                        )));
+                    };
+                    args.push(Arg::Value(evaluate(op)?));
+                }
+                builtin.call_now(name, args)
+            }
+            Op::Recover { op, fallback } => match evaluate(op) {
+                Err(error) if policy::recoverable(&error) => evaluate(fallback),
+                result => result,
+            },
+            Op::Function { .. } | Op::Plugin { .. } => Err(RuntimeError::Evaluation(
+                "Calls to plugins and spec functions must be awaited".to_string(),
+            )),
+            Op::Fail(message) => Err(RuntimeError::Evaluation(message.clone())),
+        }?;
+        self.limits.check_size(&value)?;
+        Ok(value)
+    }
+
+    /// Call a lambda or spec function of `plan` from `depth` nested calls
+    async fn call_routine(
+        &self,
+        plan: &ExecutionPlan,
+        routine: Routine,
+        captured: Vec<Option<JsonValue>>,
+        args: Vec<JsonValue>,
+        depth: usize,
+    ) -> RuntimeResult<JsonValue> {
+        match routine {
+            Routine::Function(index) => {
+                let function = &plan.functions[index];
+                self.limits
+                    .check_call_depth(&function.definition.name, depth)?;
+                let values = functions::coerce_arguments(&function.definition, args, &plan.types)?;
+                let frame = Frame {
+                    values: values.into_iter().map(Some).collect(),
+                    depth: depth + 1,
+                };
+                let result = self.evaluate_op(plan, &function.body, &frame).await?;
+                functions::check_result(&function.definition, result, &plan.types)
+            }
+            Routine::Lambda(index) => {
+                let lambda = &plan.lambdas[index];
+                if lambda.parameters.len() != args.len() {
+                    return Err(RuntimeError::Evaluation(format!(
+                        "Lambda ({}) takes {} argument(s), got {}",
+                        lambda.parameters.join(", "),
+                        lambda.parameters.len(),
+                        args.len()
+                    )));
+                }
+                let mut values = captured;
+                values.extend(args.into_iter().map(Some));
+                self.evaluate_op(plan, &lambda.body, &Frame { values, depth })
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestPlugin;
+    use serde_json::json;
+    use sigmos_core::parser::SigmosParser;
+
+    fn compile(source: &str) -> RuntimeResult<ExecutionPlan> {
+        let spec = SigmosParser::parse_spec(source).unwrap();
+        Engine::new(&BuiltinRegistry::standard())
+            .with_extensions(HashMap::from([("ai".to_string(), "mcp".to_string())]))
+            .compile(&spec)
+    }
+
+    #[test]
+    fn test_plans_resolve_and_fold() {
+        let plan = compile(
+            r#"
+spec "Plan" v1.0 {
+    inputs:
+        name: string
+    computed:
+        shout: -> upper(name)
+        day: -> 24 * 60 * 60
+        label: -> true ? "fixed" : name
+        reply: -> ai.complete(name)
+        missing: -> nowhere(name)
+}
+"#,
+        )
+        .unwrap();
+
+        let op = |name: &str| {
+            &plan
+                .steps()
+                .iter()
+                .find(|step| step.name == name)
+                .unwrap()
+                .op
+        };
+        assert!(matches!(op("shout"), Op::Builtin { name, .. } if name == "upper"));
+        assert!(matches!(op("day"), Op::Const(value) if value == &json!(86400.0)));
+        assert!(matches!(op("label"), Op::Const(value) if value == &json!("fixed")));
+        assert!(matches!(op("reply"), Op::Plugin { plugin, .. } if plugin == "mcp"));
+        assert!(matches!(op("missing"), Op::Fail(message) if message.contains("nowhere")));
+    }
+
+    #[test]
+    fn test_computed_fields_are_ordered_and_cycles_rejected() {
+        let plan = compile(
+            r#"
+spec "Order" v1.0 {
+    inputs:
+        quantity: int
+        currency: string
+    computed:
+        total: -> subtotal + shipping
+        subtotal: -> quantity * 10
+        shipping: -> subtotal > 100 ? 0 : 5
+        label: -> currency
+}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.computed_order().collect::<Vec<_>>(),
+            ["subtotal", "label", "shipping", "total"]
+        );
+
+        let dependents = |name: &str| -> Vec<&str> {
+            plan.dependents(plan.slot(name).unwrap())
+                .iter()
+                .map(|step| step.name.as_str())
+                .collect()
+        };
+        assert_eq!(dependents("quantity"), ["subtotal", "shipping", "total"]);
+        assert_eq!(dependents("currency"), ["label"]);
+
+        let error = compile(
+            r#"
+spec "Loop" v1.0 {
+    computed:
+        a: -> b + 1
+        b: -> a + 1
+        c: -> 1
+}
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Execution failed: Computed fields depend on each other: a, b"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plans_evaluate_like_the_ast() {
+        let source = r#"
+spec "Same" v1.0 {
+    inputs:
+        items: list
+        limit: float
+    functions:
+        double(x: float) -> float = x * 2
+    computed:
+        large: -> filter(items, i => i > limit)
+        doubled: -> map(large, double)
+        total: -> reduce(doubled, (sum, i) => sum + double(i), 0)
+        summary: -> "{{limit}}: {{missing}}"
+}
+"#;
+        let spec = SigmosParser::parse_spec(source).unwrap();
+        let runtime = Runtime::new();
+        let plan = Engine::new(runtime.builtins()).compile(&spec).unwrap();
+        let mut values = HashMap::from([
+            ("items".to_string(), json!([1.0, 5.0, 10.0])),
+            ("limit".to_string(), json!(2.0)),
+        ]);
+
+        let computed = runtime.evaluate_plan(&plan, &values).await.unwrap();
+        assert_eq!(computed["doubled"], json!([10.0, 20.0]));
+        assert_eq!(computed["total"], json!(60.0));
+        assert_eq!(computed["summary"], json!("2.0: ${missing}"));
+
+        // The AST walk, with spec functions, gives the same values
+        let mut runtime = Runtime::new();
+        runtime.functions =
+            Arc::new(functions::collect_functions(&spec, runtime.builtins()).unwrap());
+        for field in &spec.computed {
+            let value = runtime
+                .evaluate_expression_with_context(&field.expression, &values)
+                .await
+                .unwrap();
+            assert_eq!(value, computed[&field.name], "{}", field.name);
+            values.insert(field.name.clone(), value);
+        }
+    }
+
+    /// Counts how many of its calls are in flight at once
+    #[derive(Debug, Default)]
+    struct Probe {
+        in_flight: std::sync::atomic::AtomicUsize,
+        most: std::sync::atomic::AtomicUsize,
+    }
+
+    /// Plugin whose `wait` answers with its argument after 20ms and whose
+    /// other methods fail, `fail` after 1ms
+    fn probe_plugin(probe: &Arc<Probe>) -> TestPlugin {
+        use std::sync::atomic::Ordering::SeqCst;
+        let probe = Arc::clone(probe);
+        TestPlugin::awaiting("probe", move |method, args| {
+            let probe = Arc::clone(&probe);
+            async move {
+                let in_flight = probe.in_flight.fetch_add(1, SeqCst) + 1;
+                probe.most.fetch_max(in_flight, SeqCst);
+                let millis = if method == "fail" { 1 } else { 20 };
+                tokio::time::sleep(std::time::Duration::from_millis(millis)).await;
+                probe.in_flight.fetch_sub(1, SeqCst);
+                match method.as_str() {
+                    "wait" => Ok(args["arg_0"].clone()),
+                    _ => Err(RuntimeError::Plugin(format!("{} failed", args["arg_0"]))),
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_independent_steps_run_concurrently() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Fanout" v1.0 {
+    computed:
+        joined: -> a + b + c
+        a: -> probe.wait("a")
+        b: -> probe.wait("b")
+        c: -> probe.wait("c")
+        late: -> probe.wait(joined)
+}
+"#,
+        )
+        .unwrap();
+        for (max_concurrency, most) in [(1, 1), (2, 2), (8, 3)] {
+            let probe = Arc::new(Probe::default());
+            let mut runtime = Runtime::new().with_limits(ExecutionLimits {
+                max_concurrency,
+                ..ExecutionLimits::default()
+            });
+            runtime
+                .register_async_plugin(Box::new(probe_plugin(&probe)))
+                .unwrap();
+            let plan = Engine::new(runtime.builtins()).compile(&spec).unwrap();
+            let computed = runtime.evaluate_plan(&plan, &HashMap::new()).await.unwrap();
+            assert_eq!(computed["late"], json!("abc"));
+            assert_eq!(probe.most.load(std::sync::atomic::Ordering::SeqCst), most);
+        }
+
+        // The slow failure comes first, so evaluating in order reports it
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Failures" v1.0 {
+    computed:
+        first: -> probe.slow("first")
+        second: -> probe.fail("second")
+}
+"#,
+        )
+        .unwrap();
+        let mut runtime = Runtime::new();
+        runtime
+            .register_async_plugin(Box::new(probe_plugin(&Arc::default())))
+            .unwrap();
+        let plan = Engine::new(runtime.builtins()).compile(&spec).unwrap();
+        let error = runtime
+            .evaluate_plan(&plan, &HashMap::new())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("first"), "{error}");
     }
 }
@@ -1 +1,119 @@
-//! Events module
+//! Event dispatch
+//!
+//! Handlers declared in a spec's `events:` section run when their event fires:
+//! `on_create` after the first successful execution, `on_change` when an input
+//! or computed field changes value, `on_error(error)` when a phase fails, and
+//! any other name when it is emitted with [`Runtime::emit`](crate::Runtime::emit).
+//! While a handler's action runs, its parameter is bound to the event payload.
+//!
+//! `on_change` handlers come in two forms. When the parameter names a field,
+//! as in `on_change(region)`, the handler runs only when that field changes and
+//! receives its new value. Any other parameter, as in `on_change(field)`, runs
+//! for every change and receives the changed field's name. Emitting
+//! `on_change` directly carries no field, so it reaches only the second form,
+//! with the emitted payload.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use serde_json::json;
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::Runtime;
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Deployer" v1.0 {
+//!     inputs:
+//!         region: string { default: "eu" }
+//!     events:
+//!         on_deploy(target): last_target = target
+//!         on_change(region): last_region = region
+//!         on_change(field): last_changed = field
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new();
+//! runtime.execute(&spec).await.unwrap();
+//! let inputs = serde_json::from_value(json!({ "region": "us" })).unwrap();
+//! runtime.execute_with_inputs(&spec, inputs).await.unwrap();
+//! runtime.emit("on_deploy", json!("staging")).await.unwrap();
+//!
+//! let report = runtime.snapshot().await;
+//! assert_eq!(report.inputs["last_target"], "staging");
+//! assert_eq!(report.inputs["last_region"], "us");
+//! assert_eq!(report.inputs["last_changed"], "region");
+//! # });
+//! ```
+
+use crate::RuntimeResult;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sigmos_core::ast::EventType;
+
+/// Fired after the first successful execution, with all field values as payload
+pub const ON_CREATE: &str = "on_create";
+/// Fired when a field's value changes, with the field's new value as payload
+/// and its name as [`Event::field`]
+pub const ON_CHANGE: &str = "on_change";
+/// Fired when a phase fails, with the error message as payload
+pub const ON_ERROR: &str = "on_error";
+
+/// Nested dispatches allowed before an event loop is assumed
+pub const MAX_EVENT_DEPTH: usize = 16;
+
+/// An event delivered to handlers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    pub name: String,
+    pub payload: JsonValue,
+    /// Field whose change fired an `on_change` event; `None` when the event
+    /// was emitted directly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+impl Event {
+    pub fn new(name: impl Into<String>, payload: JsonValue) -> Self {
+        Self {
+            name: name.into(),
+            payload,
+            field: None,
+        }
+    }
+
+    /// `on_change` event for `field`, which now holds `value`
+    pub fn changed(field: impl Into<String>, value: JsonValue) -> Self {
+        Self {
+            field: Some(field.into()),
+            ..Self::new(ON_CHANGE, value)
+        }
+    }
+}
+
+/// Handler registered from Rust with [`Runtime::on`](crate::Runtime::on)
+pub type EventHandler = Box<dyn Fn(&Event) -> RuntimeResult<()> + Send + Sync>;
+
+/// Name under which events of `event_type` are dispatched
+pub fn event_name(event_type: &EventType) -> &str {
+    match event_type {
+        EventType::OnCreate => ON_CREATE,
+        EventType::OnChange => ON_CHANGE,
+        EventType::OnError => ON_ERROR,
+        EventType::Custom(name) => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_names() {
+        assert_eq!(event_name(&EventType::OnCreate), "on_create");
+        assert_eq!(event_name(&EventType::OnError), "on_error");
+        assert_eq!(
+            event_name(&EventType::Custom("on_deploy".to_string())),
+            "on_deploy"
+        );
+    }
+}
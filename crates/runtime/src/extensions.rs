@@ -0,0 +1,176 @@
+//! Extension resolution
+//!
+//! Each entry of a spec's `extensions` block binds a local name to a plugin:
+//! `ai: import("sigmos.std.net.mcp@1.0")` makes `ai.complete(...)` call the
+//! plugin named `mcp`, the last segment of the module path. The version after
+//! `@` is a semver requirement (`1.0` means `^1.0`; ranges such as
+//! `>=1.2, <2` work too) checked against the version the plugin host reports.
+//!
+//! Extensions are resolved at the start of every execution, so a missing or
+//! incompatible plugin fails the run before any stage does.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::{Runtime, RuntimeError};
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Assistant" v1.0 {
+//!     extensions {
+//!         ai: import("sigmos.std.net.mcp@1.0")
+//!     }
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new();
+//! let error = tokio_test::block_on(runtime.execute(&spec)).unwrap_err();
+//! assert!(matches!(error, RuntimeError::Extension(_)));
+//! ```
+
+use crate::plugins::PluginHost;
+use crate::{RuntimeError, RuntimeResult};
+use semver::{Version, VersionReq};
+use sigmos_core::ast::{ExtensionDef, Spec};
+use std::collections::HashMap;
+
+/// Plugin names keyed by the local names the spec's extensions bind
+pub(crate) fn resolve_extensions(
+    spec: &Spec,
+    host: &dyn PluginHost,
+) -> RuntimeResult<HashMap<String, String>> {
+    spec.extensions
+        .iter()
+        .map(|extension| resolve(extension, host).map(|plugin| (extension.name.clone(), plugin)))
+        .collect()
+}
+
+/// Check that the plugin `extension` imports is registered and compatible
+fn resolve(extension: &ExtensionDef, host: &dyn PluginHost) -> RuntimeResult<String> {
+    let plugin = extension.plugin_name();
+    if !host.has_plugin(plugin) {
+        return Err(RuntimeError::Extension(format!(
+            "'{}' imports {}, but no plugin named '{plugin}' is registered",
+            extension.name,
+            extension.module()
+        )));
+    }
+
+    let Some(requirement) = extension.version_requirement() else {
+        return Ok(plugin.to_string());
+    };
+    let requirement = VersionReq::parse(requirement).map_err(|e| {
+        RuntimeError::Extension(format!(
+            "'{}': '{requirement}' is not a valid version requirement: {e}",
+            extension.name
+        ))
+    })?;
+
+    let Some(version) = host.plugin_version(plugin) else {
+        return Err(RuntimeError::Extension(format!(
+            "'{}' requires {plugin} {requirement}, but the plugin does not declare a version",
+            extension.name
+        )));
+    };
+    let version = Version::parse(&version).map_err(|e| {
+        RuntimeError::Extension(format!(
+            "plugin '{plugin}' has an invalid version '{version}': {e}"
+        ))
+    })?;
+
+    if requirement.matches(&version) {
+        Ok(plugin.to_string())
+    } else {
+        Err(RuntimeError::Extension(format!(
+            "'{}' requires {plugin} {requirement}, but version {version} is registered",
+            extension.name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::{AsyncPlugin, CancellationToken};
+    use crate::testing::TestPlugin;
+    use futures::future::BoxFuture;
+    use serde_json::Value as JsonValue;
+
+    /// Host with one plugin, `mcp`, at version 1.4.2
+    #[derive(Debug)]
+    struct VersionedHost;
+
+    impl PluginHost for VersionedHost {
+        fn register(&mut self, _plugin: Box<dyn AsyncPlugin>) -> RuntimeResult<()> {
+            Ok(())
+        }
+
+        fn initialize(&mut self) -> RuntimeResult<()> {
+            Ok(())
+        }
+
+        fn has_plugin(&self, name: &str) -> bool {
+            name == "mcp"
+        }
+
+        fn plugin_names(&self) -> Vec<String> {
+            vec!["mcp".to_string()]
+        }
+
+        fn plugin_version(&self, _name: &str) -> Option<String> {
+            Some("1.4.2".to_string())
+        }
+
+        fn call<'a>(
+            &'a self,
+            _name: &'a str,
+            _method: &'a str,
+            _args: &'a HashMap<String, JsonValue>,
+            _cancel: &'a CancellationToken,
+        ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+            Box::pin(async { Ok(JsonValue::Null) })
+        }
+    }
+
+    #[test]
+    fn test_version_requirements() {
+        let check = |import_spec: &str| {
+            let extension = ExtensionDef {
+                name: "ai".to_string(),
+                import_spec: import_spec.to_string(),
+            };
+            resolve(&extension, &VersionedHost)
+        };
+
+        assert_eq!(check("sigmos.std.net.mcp@1.0").unwrap(), "mcp");
+        assert!(check("sigmos.std.net.mcp@>=1.2, <2").is_ok());
+        assert!(check("mcp").is_ok());
+
+        let error = check("sigmos.std.net.mcp@2.1").unwrap_err().to_string();
+        assert!(
+            error.contains("requires mcp ^2.1, but version 1.4.2"),
+            "{error}"
+        );
+        assert!(check("sigmos.std.net.mcp@one").is_err());
+        assert!(check("sigmos.std.net.rest@1.0")
+            .unwrap_err()
+            .to_string()
+            .contains("no plugin named 'rest'"));
+    }
+
+    #[test]
+    fn test_registered_plugins_report_their_version() {
+        let mut host = crate::plugins::PluginMap::default();
+        host.register(Box::new(
+            TestPlugin::new("mcp", |_, _| Ok(JsonValue::Null)).with_version("1.2.0"),
+        ))
+        .unwrap();
+        assert_eq!(host.plugin_version("mcp").as_deref(), Some("1.2.0"));
+
+        let extension = ExtensionDef {
+            name: "ai".to_string(),
+            import_spec: "sigmos.std.net.mcp@1.0".to_string(),
+        };
+        assert_eq!(resolve(&extension, &host).unwrap(), "mcp");
+    }
+}
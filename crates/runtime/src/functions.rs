@@ -0,0 +1,252 @@
+//! Functions declared in a spec's `functions:` section
+//!
+//! Spec functions are pure: their bodies see only their parameters, may call
+//! builtins and other spec functions, and cannot call plugins. Arguments and
+//! results are coerced to the declared types. Recursion is allowed up to
+//! [`ExecutionLimits::max_call_depth`](crate::limits::ExecutionLimits::max_call_depth)
+//! nested calls, [`MAX_CALL_DEPTH`] by default.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::Runtime;
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Risk" v1.0 {
+//!     inputs:
+//!         exposure: float
+//!     functions:
+//!         score(x: float, w: float) -> float = x * w
+//!         factorial(n: float) -> float = n <= 1 ? 1 : n * factorial(n - 1)
+//!     computed:
+//!         risk: -> score(exposure, 0.5)
+//!         orderings: -> factorial(4)
+//! }
+//! "#).unwrap();
+//!
+//! # tokio_test::block_on(async {
+//! let mut runtime = Runtime::new();
+//! let inputs = serde_json::from_value(serde_json::json!({ "exposure": 10 })).unwrap();
+//! runtime.execute_with_inputs(&spec, inputs).await.unwrap();
+//!
+//! let report = runtime.snapshot().await;
+//! assert_eq!(report.computed["risk"], 5.0);
+//! assert_eq!(report.computed["orderings"], 24.0);
+//! # });
+//! ```
+
+use crate::builtins::{BuiltinRegistry, Callable};
+use crate::inputs::coerce_input;
+use crate::{RuntimeError, RuntimeResult};
+use serde_json::Value as JsonValue;
+use sigmos_core::ast::{Argument, Expression, FunctionDef, Spec, TypeDef};
+use std::collections::HashMap;
+
+/// Most spec function calls that may be in progress at once, by default
+pub const MAX_CALL_DEPTH: usize = 32;
+
+/// Scope entry holding the number of spec function calls in progress; no
+/// identifier can start with `#`
+pub(crate) const CALL_DEPTH: &str = "#depth";
+
+/// The spec's functions by name
+pub(crate) fn collect_functions(
+    spec: &Spec,
+    builtins: &BuiltinRegistry,
+) -> RuntimeResult<HashMap<String, FunctionDef>> {
+    let mut functions = HashMap::new();
+    for function in &spec.functions {
+        let error = |message: &str| {
+            RuntimeError::Execution(format!("Function '{}': {message}", function.name))
+        };
+        if function.name == "ref" || builtins.contains(&function.name) {
+            return Err(error("a builtin has the same name"));
+        }
+        if !function.body.called_objects().is_empty() {
+            return Err(error("functions are pure and cannot call plugins"));
+        }
+        if functions
+            .insert(function.name.clone(), function.clone())
+            .is_some()
+        {
+            return Err(error("declared more than once"));
+        }
+    }
+    Ok(functions)
+}
+
+/// Number of spec function calls in progress where `scope` is evaluated
+pub(crate) fn call_depth(scope: &HashMap<String, JsonValue>) -> usize {
+    scope
+        .get(CALL_DEPTH)
+        .and_then(JsonValue::as_u64)
+        .map_or(0, |depth| depth as usize)
+}
+
+/// Scope for the body of `function`: its parameters bound to `args`
+pub(crate) fn bind_arguments(
+    function: &FunctionDef,
+    args: Vec<JsonValue>,
+    types: &[TypeDef],
+    depth: usize,
+) -> RuntimeResult<HashMap<String, JsonValue>> {
+    let values = coerce_arguments(function, args, types)?;
+    let mut scope = HashMap::from([(CALL_DEPTH.to_string(), JsonValue::from(depth + 1))]);
+    scope.extend(
+        function
+            .parameters
+            .iter()
+            .map(|parameter| parameter.name.clone())
+            .zip(values),
+    );
+    Ok(scope)
+}
+
+/// `args` coerced to the parameter types of `function`
+pub(crate) fn coerce_arguments(
+    function: &FunctionDef,
+    args: Vec<JsonValue>,
+    types: &[TypeDef],
+) -> RuntimeResult<Vec<JsonValue>> {
+    let name = &function.name;
+    if args.len() != function.parameters.len() {
+        return Err(RuntimeError::Evaluation(format!(
+            "{name}() takes {} argument(s), got {}",
+            function.parameters.len(),
+            args.len()
+        )));
+    }
+
+    function
+        .parameters
+        .iter()
+        .zip(args)
+        .map(|(parameter, value)| {
+            coerce_input(value, &parameter.type_expr, types).map_err(|e| {
+                RuntimeError::Evaluation(format!("{name}(): argument '{}': {e}", parameter.name))
+            })
+        })
+        .collect()
+}
+
+/// Coerce the result of `function` to its return type
+pub(crate) fn check_result(
+    function: &FunctionDef,
+    value: JsonValue,
+    types: &[TypeDef],
+) -> RuntimeResult<JsonValue> {
+    coerce_input(value, &function.return_type, types).map_err(|e| {
+        RuntimeError::Evaluation(format!("{}() returned the wrong type: {e}", function.name))
+    })
+}
+
+/// A spec function passed by name to a higher-order builtin, as a lambda that
+/// calls it at the current depth
+pub(crate) fn as_callable(function: &FunctionDef, depth: usize) -> Callable {
+    let arguments = function
+        .parameters
+        .iter()
+        .map(|parameter| Argument {
+            name: String::new(),
+            value: Expression::Identifier(parameter.name.clone()),
+        })
+        .collect();
+    Callable::Lambda {
+        parameters: function.parameters.iter().map(|p| p.name.clone()).collect(),
+        body: Expression::FunctionCall {
+            object: String::new(),
+            method: function.name.clone(),
+            arguments,
+        },
+        captured: HashMap::from([(CALL_DEPTH.to_string(), JsonValue::from(depth))]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::spec_computing;
+    use serde_json::json;
+    use sigmos_core::parser::SigmosParser;
+
+    #[tokio::test]
+    async fn test_recursion_is_limited() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Loops" v1.0 {
+    inputs:
+        n: float
+    functions:
+        forever(x: float) -> float = forever(x + 1)
+        countdown(x: float) -> float = x <= 0 ? 0 : countdown(x - 1)
+        twice(x: float) -> float = x * 2
+        doubled_all(items: list) -> list = map(items, twice)
+    computed:
+        looped: -> forever(n)
+}
+"#,
+        )
+        .unwrap();
+
+        let mut runtime = crate::Runtime::new();
+        let inputs = serde_json::from_value(json!({ "n": 0 })).unwrap();
+        let error = runtime
+            .execute_with_inputs(&spec, inputs)
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("forever() exceeded the limit of 32 nested calls"),
+            "{error}"
+        );
+
+        let scope = HashMap::new();
+        let call = |source: &str| {
+            spec_computing("value", source).computed[0]
+                .expression
+                .clone()
+        };
+        let countdown = call("countdown(10)");
+        assert_eq!(
+            runtime
+                .evaluate_expression_with_context(&countdown, &scope)
+                .await
+                .unwrap(),
+            json!(0.0)
+        );
+        // Spec functions can be passed by name to higher-order builtins
+        let doubled = call("doubled_all(split(\"1,2\", \",\"))");
+        assert_eq!(
+            runtime
+                .evaluate_expression_with_context(&doubled, &scope)
+                .await
+                .unwrap(),
+            json!([2.0, 4.0])
+        );
+        let wrong = call("twice(\"many\")");
+        assert!(runtime
+            .evaluate_expression_with_context(&wrong, &scope)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_functions_cannot_shadow_builtins_or_call_plugins() {
+        let spec = |function: &str| {
+            SigmosParser::parse_spec(&format!("spec \"F\" v1.0 {{ functions: {function} }}"))
+                .unwrap()
+        };
+        let builtins = BuiltinRegistry::standard();
+        assert!(collect_functions(&spec("len(x: string) -> int = 1"), &builtins).is_err());
+        assert!(collect_functions(
+            &spec("ask(x: string) -> string = llm.complete(x)"),
+            &builtins
+        )
+        .is_err());
+        assert!(
+            collect_functions(&spec("shout(x: string) -> string = upper(x)"), &builtins).is_ok()
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! Hosting many instances of one spec
+//!
+//! A [`Runtime`] runs one execution context, so serving a spec to many users
+//! with runtimes of their own would initialize their plugins, resolve their
+//! extensions and compile the spec once per user. A [`SpecHost`] does that
+//! once and shares the result, with the plugins, between isolated instances
+//! it keeps by ID. Each instance holds only its own inputs, computed values,
+//! logs and event depth, and the host refuses new instances once it holds
+//! [`max_instances`](SpecHost::with_max_instances) of them.
+//!
+//! Instances do not inherit the configuring runtime's event handlers from
+//! [`Runtime::on`], its cancellation token or its state store.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use serde_json::json;
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::host::SpecHost;
+//! use sigmos_runtime::Runtime;
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Agent" v1.0 {
+//!     inputs:
+//!         user: string
+//!     computed:
+//!         greeting: -> "Hello {{user}}"
+//! }
+//! "#).unwrap();
+//!
+//! let host = SpecHost::new(spec, Runtime::new()).unwrap();
+//! for user in ["ada", "grace"] {
+//!     let mut inputs = serde_json::Map::new();
+//!     inputs.insert("user".to_string(), json!(user));
+//!     host.create(user, inputs).await.unwrap();
+//! }
+//! host.update("ada", "user", json!("Ada")).await.unwrap();
+//!
+//! assert_eq!(host.get("ada").await.unwrap().computed["greeting"], "Hello Ada");
+//! assert_eq!(host.get("grace").await.unwrap().computed["greeting"], "Hello grace");
+//! # });
+//! ```
+
+use crate::limits::{exceeded, Limit};
+use crate::plugins::CancellationToken;
+use crate::{ExecutionReport, Runtime, RuntimeError, RuntimeResult};
+use serde_json::Value as JsonValue;
+use sigmos_core::ast::Spec;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::RwLock;
+
+/// Most instances a host holds unless configured otherwise
+pub const DEFAULT_MAX_INSTANCES: usize = 10_000;
+
+/// Runs isolated instances of one spec that share its compiled plan and
+/// plugins
+pub struct SpecHost {
+    /// The spec the instances run
+    spec: Arc<Spec>,
+    /// Runtime the instances are created from, with the spec prepared
+    template: Runtime,
+    /// Instances by ID
+    instances: Mutex<HashMap<String, Instance>>,
+    /// Most instances held at once
+    max_instances: usize,
+}
+
+/// An instance's runtime and the token that cancels its plugin calls
+///
+/// The token is kept outside the lock so an instance can be cancelled while
+/// a call holds its runtime.
+#[derive(Clone)]
+struct Instance {
+    runtime: Arc<RwLock<Runtime>>,
+    cancellation: CancellationToken,
+}
+
+impl SpecHost {
+    /// Host instances of `spec` configured like `runtime`
+    ///
+    /// The runtime's plugins are initialized and the spec is compiled here,
+    /// once, with the runtime's builtins, limits, strictness and source map.
+    pub fn new(spec: Spec, mut runtime: Runtime) -> RuntimeResult<Self> {
+        runtime.prepare(&spec)?;
+        let spec = Arc::new(spec);
+        runtime.spec = Some(Arc::clone(&spec));
+        runtime.shared = true;
+        Ok(Self {
+            spec,
+            template: runtime,
+            instances: Mutex::default(),
+            max_instances: DEFAULT_MAX_INSTANCES,
+        })
+    }
+
+    /// Hold at most `max` instances at once
+    pub fn with_max_instances(mut self, max: usize) -> Self {
+        self.max_instances = max;
+        self
+    }
+
+    /// Execute a new instance `id` with `inputs`
+    ///
+    /// Fails if `id` is taken or the host is full. An instance whose
+    /// execution fails is not kept.
+    pub async fn create(
+        &self,
+        id: impl Into<String>,
+        inputs: serde_json::Map<String, JsonValue>,
+    ) -> RuntimeResult<()> {
+        let id = id.into();
+        let runtime = self.template.instance();
+        let instance = Instance {
+            cancellation: runtime.cancellation(),
+            runtime: Arc::new(RwLock::new(runtime)),
+        };
+        // Locked before it is visible, so no one sees it before it has run
+        let mut runtime = Arc::clone(&instance.runtime)
+            .try_write_owned()
+            .map_err(|_| RuntimeError::Execution(format!("Instance '{id}' is in use")))?;
+        {
+            let mut instances = self.lock();
+            if instances.contains_key(&id) {
+                return Err(RuntimeError::Execution(format!(
+                    "Instance '{id}' already exists"
+                )));
+            }
+            if instances.len() >= self.max_instances {
+                return Err(exceeded(
+                    Limit::Instances,
+                    format!(
+                        "the host already holds its limit of {} instances",
+                        self.max_instances
+                    ),
+                ));
+            }
+            instances.insert(id.clone(), instance);
+        }
+
+        let result = runtime.execute_with_inputs(&self.spec, inputs).await;
+        if result.is_err() {
+            self.lock().remove(&id);
+        }
+        result
+    }
+
+    /// Report of instance `id`, if there is one
+    pub async fn get(&self, id: &str) -> Option<ExecutionReport> {
+        let instance = self.instance(id).ok()?;
+        let runtime = instance.runtime.read().await;
+        Some(runtime.snapshot().await)
+    }
+
+    /// Change input `name` of instance `id`, as [`Runtime::set_input`] does
+    pub async fn update(&self, id: &str, name: &str, value: JsonValue) -> RuntimeResult<()> {
+        let instance = self.instance(id)?;
+        let runtime = instance.runtime.write().await;
+        runtime.set_input(name, value).await
+    }
+
+    /// Deliver the event `name` to the handlers of instance `id`
+    pub async fn emit(&self, id: &str, name: &str, payload: JsonValue) -> RuntimeResult<()> {
+        let instance = self.instance(id)?;
+        let runtime = instance.runtime.write().await;
+        runtime.emit(name, payload).await
+    }
+
+    /// Remove instance `id`, cancelling its plugin calls in flight
+    ///
+    /// Does not wait for calls holding the instance, so a hung plugin call
+    /// cannot hold up the delete. Returns whether there was such an instance.
+    pub fn delete(&self, id: &str) -> bool {
+        let Some(instance) = self.lock().remove(id) else {
+            return false;
+        };
+        instance.cancellation.cancel();
+        true
+    }
+
+    /// Number of instances held
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether the host holds no instances
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// IDs of the instances held, sorted
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.lock().keys().cloned().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The spec the instances run
+    pub fn spec(&self) -> &Arc<Spec> {
+        &self.spec
+    }
+
+    fn instance(&self, id: &str) -> RuntimeResult<Instance> {
+        self.lock()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| RuntimeError::Execution(format!("No instance '{id}'")))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Instance>> {
+        self.instances
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Runtime {
+    /// A fresh runtime sharing this one's prepared spec, plan and plugins
+    fn instance(&self) -> Runtime {
+        Runtime {
+            plugins: Arc::clone(&self.plugins),
+            extensions: Arc::clone(&self.extensions),
+            spec: self.spec.clone(),
+            source_map: self.source_map.clone(),
+            builtins: Arc::clone(&self.builtins),
+            functions: Arc::clone(&self.functions),
+            plan: Arc::clone(&self.plan),
+            shared: true,
+            limits: self.limits,
+            strict: self.strict,
+            ..Runtime::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestPlugin;
+    use serde_json::json;
+    use sigmos_core::parser::SigmosParser;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Plugin counting its initializations and calls, answering with its
+    /// argument; `hang` never answers
+    fn echo(initialized: &Arc<AtomicU32>, calls: &Arc<AtomicU32>) -> TestPlugin {
+        let (initialized, calls) = (Arc::clone(initialized), Arc::clone(calls));
+        TestPlugin::awaiting("echo", move |method, args| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match method.as_str() {
+                    "hang" => std::future::pending().await,
+                    _ => Ok(args["arg_0"].clone()),
+                }
+            }
+        })
+        .on_initialize(move || {
+            initialized.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    fn inputs(user: &str) -> serde_json::Map<String, JsonValue> {
+        serde_json::Map::from_iter([("user".to_string(), json!(user))])
+    }
+
+    #[tokio::test]
+    async fn test_instances_are_isolated_and_share_plugins() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Agent" v1.0 {
+    inputs:
+        user: string
+        visits: int { default: 0 }
+    computed:
+        reply: -> echo.say("Hi {{user}}")
+    events:
+        on_visit(count): visits = count
+}
+"#,
+        )
+        .unwrap();
+        let (initialized, calls) = (Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(0)));
+        let mut runtime = Runtime::new();
+        runtime
+            .register_async_plugin(Box::new(echo(&initialized, &calls)))
+            .unwrap();
+        let host = SpecHost::new(spec, runtime).unwrap();
+
+        for user in ["ada", "grace", "alan"] {
+            host.create(user, inputs(user)).await.unwrap();
+        }
+        host.emit("grace", "on_visit", json!(2)).await.unwrap();
+        host.update("alan", "user", json!("Alan")).await.unwrap();
+
+        assert_eq!(host.ids(), ["ada", "alan", "grace"]);
+        let ada = host.get("ada").await.unwrap();
+        assert_eq!(ada.computed["reply"], "Hi ada");
+        assert_eq!(ada.inputs["visits"], json!(0));
+        assert_eq!(host.get("grace").await.unwrap().inputs["visits"], json!(2));
+        assert_eq!(host.get("alan").await.unwrap().computed["reply"], "Hi Alan");
+        assert_eq!(initialized.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        let error = host.create("ada", inputs("ada")).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Execution failed: Instance 'ada' already exists"
+        );
+        // A failed execution leaves no instance behind
+        assert!(host.create("nobody", serde_json::Map::new()).await.is_err());
+        assert!(host.get("nobody").await.is_none());
+
+        assert!(host.delete("ada"));
+        assert!(!host.delete("ada"));
+        assert!(host.get("ada").await.is_none());
+        assert!(host.update("ada", "user", json!("Ada")).await.is_err());
+        assert_eq!(host.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_host_holds_at_most_max_instances() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Agent" v1.0 {
+    inputs:
+        user: string
+}
+"#,
+        )
+        .unwrap();
+        let host = SpecHost::new(spec, Runtime::new())
+            .unwrap()
+            .with_max_instances(2);
+        host.create("a", inputs("a")).await.unwrap();
+        host.create("b", inputs("b")).await.unwrap();
+        let error = host.create("c", inputs("c")).await.unwrap_err();
+        assert!(
+            matches!(
+                error,
+                RuntimeError::LimitExceeded {
+                    limit: Limit::Instances,
+                    ..
+                }
+            ),
+            "{error:?}"
+        );
+
+        host.delete("a");
+        host.create("c", inputs("c")).await.unwrap();
+        assert_eq!(host.ids(), ["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_cancels_calls_holding_the_instance() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Agent" v1.0 {
+    inputs:
+        user: string
+    events:
+        on_wait(reason): echo.hang(reason)
+}
+"#,
+        )
+        .unwrap();
+        let calls = Arc::new(AtomicU32::new(0));
+        let mut runtime = Runtime::new();
+        runtime
+            .register_async_plugin(Box::new(echo(&Arc::default(), &calls)))
+            .unwrap();
+        let host = Arc::new(SpecHost::new(spec, runtime).unwrap());
+        host.create("ada", inputs("ada")).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let host = Arc::clone(&host);
+            async move { host.emit("ada", "on_wait", json!("forever")).await }
+        });
+        while calls.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // The hung call holds the instance's lock while it is deleted
+        assert!(host.delete("ada"));
+        let error = waiting.await.unwrap().unwrap_err();
+        assert!(
+            error.to_string().contains("Cancelled: echo.hang"),
+            "{error}"
+        );
+        assert!(host.is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+//! Input values supplied at run time
+//!
+//! Values arrive as JSON, and from the command line usually as strings, so
+//! they are coerced to each field's declared type before refinements apply.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use serde_json::json;
+//! use sigmos_core::ast::{PrimitiveType, TypeExpr};
+//! use sigmos_runtime::inputs::coerce_input;
+//!
+//! let int = TypeExpr::Primitive(PrimitiveType::Int);
+//! assert_eq!(coerce_input(json!("42"), &int, &[]).unwrap(), json!(42));
+//! assert!(coerce_input(json!("forty-two"), &int, &[]).is_err());
+//! ```
+
+use serde_json::Value as JsonValue;
+use sigmos_core::ast::{PrimitiveType, TypeDef, TypeExpr};
+
+/// Coerce a supplied value to `type_expr`, resolving user types against `types`
+///
+/// Strings are parsed into numbers, booleans, arrays and maps when the
+/// declared type asks for one; anything else must already have the right shape.
+pub fn coerce_input(
+    value: JsonValue,
+    type_expr: &TypeExpr,
+    types: &[TypeDef],
+) -> Result<JsonValue, String> {
+    let type_expr = resolve_alias(type_expr, types)?;
+
+    match (type_expr, value) {
+        (TypeExpr::Nullable(_) | TypeExpr::Primitive(PrimitiveType::Null), JsonValue::Null) => {
+            Ok(JsonValue::Null)
+        }
+        (TypeExpr::Nullable(inner), value) => coerce_input(value, inner, types),
+        (_, JsonValue::Null) => Err(format!("null is not allowed for {type_expr}")),
+
+        (TypeExpr::Primitive(PrimitiveType::String), value @ JsonValue::String(_)) => Ok(value),
+
+        (TypeExpr::Primitive(PrimitiveType::Int), JsonValue::Number(n)) => {
+            if n.is_i64() || n.is_u64() {
+                Ok(JsonValue::Number(n))
+            } else {
+                match n.as_f64() {
+                    Some(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                        Ok(JsonValue::from(f as i64))
+                    }
+                    _ => Err(format!("expected an int, found {n}")),
+                }
+            }
+        }
+        (TypeExpr::Primitive(PrimitiveType::Int), JsonValue::String(s)) => s
+            .trim()
+            .parse::<i64>()
+            .map(JsonValue::from)
+            .map_err(|_| format!("expected an int, found \"{s}\"")),
+
+        (TypeExpr::Primitive(PrimitiveType::Float), value @ JsonValue::Number(_)) => Ok(value),
+        (TypeExpr::Primitive(PrimitiveType::Float), JsonValue::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(JsonValue::Number)
+            .ok_or_else(|| format!("expected a float, found \"{s}\"")),
+
+        (TypeExpr::Primitive(PrimitiveType::Bool), value @ JsonValue::Bool(_)) => Ok(value),
+        (TypeExpr::Primitive(PrimitiveType::Bool), JsonValue::String(s)) => match s.trim() {
+            "true" => Ok(JsonValue::Bool(true)),
+            "false" => Ok(JsonValue::Bool(false)),
+            _ => Err(format!("expected a bool, found \"{s}\"")),
+        },
+
+        (TypeExpr::Generic { name, args }, value) if name == "Array" || name == "Map" => {
+            // Collections given as strings are parsed as JSON
+            let value = match value {
+                JsonValue::String(s) => serde_json::from_str(&s)
+                    .map_err(|_| format!("expected {type_expr}, found \"{s}\""))?,
+                other => other,
+            };
+            match (name.as_str(), value) {
+                ("Array", JsonValue::Array(items)) => match args.first() {
+                    Some(element_type) => items
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, item)| {
+                            coerce_input(item, element_type, types)
+                                .map_err(|e| format!("element {i}: {e}"))
+                        })
+                        .collect::<Result<_, _>>()
+                        .map(JsonValue::Array),
+                    None => Ok(JsonValue::Array(items)),
+                },
+                ("Map", JsonValue::Object(entries)) => match args.last() {
+                    Some(value_type) => entries
+                        .into_iter()
+                        .map(|(key, item)| {
+                            coerce_input(item, value_type, types)
+                                .map(|item| (key.clone(), item))
+                                .map_err(|e| format!("entry '{key}': {e}"))
+                        })
+                        .collect::<Result<_, _>>()
+                        .map(JsonValue::Object),
+                    None => Ok(JsonValue::Object(entries)),
+                },
+                (_, other) => Err(format!("expected {type_expr}, found {other}")),
+            }
+        }
+
+        // Other generic and unresolved user types are accepted as given
+        (TypeExpr::Generic { .. } | TypeExpr::Reference(_), value) => Ok(value),
+
+        (TypeExpr::Primitive(primitive), value) => {
+            let expected = match primitive {
+                PrimitiveType::String => "a string",
+                PrimitiveType::Int => "an int",
+                PrimitiveType::Float => "a float",
+                PrimitiveType::Bool => "a bool",
+                PrimitiveType::Null => "null",
+            };
+            Err(format!("expected {expected}, found {value}"))
+        }
+    }
+}
+
+/// Follow `type Name = ...` aliases to the underlying type
+fn resolve_alias<'a>(
+    type_expr: &'a TypeExpr,
+    types: &'a [TypeDef],
+) -> Result<&'a TypeExpr, String> {
+    let mut resolved = type_expr;
+    // An alias chain longer than the number of types must loop
+    for _ in 0..=types.len() {
+        match resolved {
+            TypeExpr::Reference(name) => match types.iter().find(|def| &def.name == name) {
+                Some(def) => resolved = &def.type_expr,
+                None => return Ok(resolved),
+            },
+            _ => return Ok(resolved),
+        }
+    }
+    Err(format!("type {type_expr} is defined in terms of itself"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_collections_and_aliases_are_coerced() {
+        let ints = TypeExpr::Generic {
+            name: "Array".to_string(),
+            args: vec![TypeExpr::Primitive(PrimitiveType::Int)],
+        };
+        let types = vec![TypeDef {
+            name: "Quantities".to_string(),
+            type_expr: ints.clone(),
+        }];
+        let quantities = TypeExpr::Reference("Quantities".to_string());
+
+        assert_eq!(
+            coerce_input(json!("[1, 2.0, \"3\"]"), &quantities, &types).unwrap(),
+            json!([1, 2, 3])
+        );
+        assert_eq!(
+            coerce_input(json!([1, "x"]), &ints, &types).unwrap_err(),
+            "element 1: expected an int, found \"x\""
+        );
+        assert!(coerce_input(JsonValue::Null, &ints, &types).is_err());
+        assert_eq!(
+            coerce_input(JsonValue::Null, &TypeExpr::Nullable(Box::new(ints)), &types).unwrap(),
+            JsonValue::Null
+        );
+    }
+
+    #[test]
+    fn test_self_referential_alias_is_rejected() {
+        let types = vec![TypeDef {
+            name: "Loop".to_string(),
+            type_expr: TypeExpr::Reference("Loop".to_string()),
+        }];
+        let error =
+            coerce_input(json!(1), &TypeExpr::Reference("Loop".to_string()), &types).unwrap_err();
+        assert!(error.contains("defined in terms of itself"));
+    }
+}
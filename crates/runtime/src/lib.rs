@@ -6,6 +6,7 @@
 //! - Specification execution engine
 //! - Event handling and lifecycle management
 //! - Plugin system integration
+//! - A standard library of builtin functions
 //! - Async orchestration
 //!
 //! # Examples
@@ -21,17 +22,48 @@
 //! # });
 //! ```
 
+use builtins::{Arg, BuiltinRegistry, Callable, Invoker};
+pub use constraints::ConstraintViolation;
+use engine::{CompiledAction, Engine, ExecutionPlan, Frame};
+use events::{Event, EventHandler, MAX_EVENT_DEPTH, ON_CHANGE, ON_CREATE, ON_ERROR};
+use futures::future::{self, BoxFuture};
+use futures::Stream;
+use lifecycle::{Stage, StandardAction};
+use limits::{ExecutionLimits, Limit, Limited};
+use plugins::{AsyncPlugin, Blocking, CancellationToken, PluginHost, PluginMap};
+use policy::Retry;
+pub use report::ExecutionReport;
+use report::{PhaseTiming, PluginCall, Timings};
 use serde_json::Value as JsonValue;
 use sigmos_core::ast::*;
-use std::collections::HashMap;
-use std::sync::Arc;
+use sigmos_core::taint::{TaintAnalyzer, SECRET_MASK};
+use sigmos_core::types::{PatternCache, TypeChecker, ValueType};
+use state::{Checkpoint, StateStore};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use thiserror::Error;
 use tokio::sync::RwLock;
 
+pub mod builtins;
+pub mod constraints;
 pub mod engine;
 pub mod events;
+pub mod extensions;
+pub mod functions;
+pub mod host;
+pub mod inputs;
 pub mod lifecycle;
+pub mod limits;
 pub mod plugins;
+pub mod policy;
+pub mod reactive;
+pub mod report;
+pub mod state;
+pub mod strict;
+#[cfg(test)]
+mod testing;
 
 /// Runtime errors
 #[derive(Error, Debug)]
@@ -40,12 +72,65 @@ pub enum RuntimeError {
     Execution(String),
     #[error("Plugin error: {0}")]
     Plugin(String),
+    /// A plugin failure that may not recur, such as a dropped connection
+    #[error("Transient plugin error: {0}")]
+    Transient(String),
     #[error("Expression evaluation error: {0}")]
     Evaluation(String),
     #[error("Event handling error: {0}")]
     Event(String),
     #[error("Lifecycle error: {0}")]
     Lifecycle(String),
+    #[error(
+        "Cannot assign to readonly field '{field}' in {location}{}",
+        span.map(|span| format!(" (at {span})")).unwrap_or_default()
+    )]
+    ReadonlyAssignment {
+        field: String,
+        location: String,
+        span: Option<Span>,
+    },
+    #[error("Invalid input '{field}': {message}")]
+    InvalidInput { field: String, message: String },
+    #[error("Missing required inputs: {}", .0.join(", "))]
+    MissingInputs(Vec<String>),
+    #[error("Constraints violated: {}", constraints::summarize(.0))]
+    ConstraintsViolated(Vec<ConstraintViolation>),
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+    #[error("Extension error: {0}")]
+    Extension(String),
+    #[error("State store error: {0}")]
+    State(String),
+    #[error("Limit exceeded: {message}")]
+    LimitExceeded { limit: Limit, message: String },
+    #[error(
+        "Unresolved name '{name}'{}{}{}",
+        if location.is_empty() { String::new() } else { format!(" in {location}") },
+        span.map(|span| format!(" (at {span})")).unwrap_or_default(),
+        strict::did_you_mean(suggestions)
+    )]
+    UnresolvedName {
+        name: String,
+        location: String,
+        span: Option<Span>,
+        suggestions: Vec<String>,
+    },
+}
+
+impl RuntimeError {
+    /// Whether the plugin call that failed with this error may succeed if it
+    /// is made again
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            RuntimeError::Transient(_)
+                | RuntimeError::LimitExceeded {
+                    limit: Limit::PluginTimeout,
+                    ..
+                }
+        )
+    }
 }
 
 /// Result type for runtime operations
@@ -88,15 +173,53 @@ enum ComparisonOp {
 pub struct Runtime {
     /// Execution context
     context: Arc<RwLock<ExecutionContext>>,
-    /// Registered plugins
-    plugins: HashMap<String, Box<dyn Plugin + Send + Sync>>,
-    /// Event handlers
-    #[allow(dead_code)]
+    /// Plugins that specs can call
+    plugins: Arc<dyn PluginHost>,
+    /// Plugin names bound to the local names of the spec's extensions
+    extensions: Arc<HashMap<String, String>>,
+    /// Cancels plugin calls in flight
+    cancellation: CancellationToken,
+    /// Event handlers registered from Rust
     event_handlers: HashMap<String, Vec<EventHandler>>,
+    /// Specification of the last execution, whose event handlers stay active
+    spec: Option<Arc<Spec>>,
+    /// Whether an execution has succeeded and `on_create` has fired
+    created: bool,
+    /// Depth of nested event dispatches
+    event_depth: AtomicUsize,
+    /// Subscribers from [`Runtime::watch`]
+    watchers: reactive::Watchers,
+    /// Source spans attached to errors
+    source_map: SourceMap,
+    /// Plugin calls made during the current execution
+    plugin_calls: Mutex<Vec<PluginCall>>,
+    /// Functions that specs can call without a plugin prefix
+    builtins: Arc<BuiltinRegistry>,
+    /// Functions declared by the spec being executed
+    functions: Arc<HashMap<String, FunctionDef>>,
+    /// The spec being executed, compiled
+    plan: Arc<ExecutionPlan>,
+    /// Whether a [`SpecHost`](host::SpecHost) prepared the spec, plan and
+    /// plugins this runtime shares with its other instances
+    shared: bool,
+    /// Resource limits enforced during evaluation
+    limits: ExecutionLimits,
+    /// Operations evaluated against `limits.max_steps`
+    steps: AtomicU64,
+    /// Whether unresolved names are errors rather than placeholders
+    strict: bool,
+    /// Where checkpoints of executions are saved
+    state_store: Option<Arc<dyn StateStore>>,
+    /// Events delivered to handlers during the current execution
+    events: AtomicU64,
+    /// Regexes of the `pattern` refinements inputs are checked against
+    patterns: PatternCache,
 }
 
 /// Execution context for runtime
-#[derive(Debug, Default)]
+///
+/// The `Debug` output masks secret inputs and values derived from them.
+#[derive(Default)]
 pub struct ExecutionContext {
     /// Variable bindings
     variables: HashMap<String, serde_json::Value>,
@@ -104,6 +227,124 @@ pub struct ExecutionContext {
     computed_cache: HashMap<String, serde_json::Value>,
     /// Execution state
     state: ExecutionState,
+    /// Names holding secret or secret-derived values
+    secrets: HashSet<String>,
+    /// Name and version of the spec being executed
+    spec: (String, String),
+    /// Constraints that failed in the last execution
+    violations: Vec<ConstraintViolation>,
+    /// Phase timings of the last execution
+    timings: Timings,
+    /// Stage the last execution reached
+    stage: Option<Stage>,
+    /// Last stage of the execution that finished
+    completed: Option<Stage>,
+    /// Messages written by `log` actions
+    logs: Vec<String>,
+    /// Non-secret inputs the execution was given
+    given: BTreeMap<String, JsonValue>,
+    /// Fingerprint of the spec and inputs the execution was given
+    fingerprint: String,
+}
+
+impl ExecutionContext {
+    /// Copy of `values` with secret entries replaced by the mask
+    fn masked(&self, values: &HashMap<String, JsonValue>) -> BTreeMap<String, JsonValue> {
+        values
+            .iter()
+            .map(|(name, value)| {
+                let value = if self.secrets.contains(name) {
+                    JsonValue::String(SECRET_MASK.to_string())
+                } else {
+                    value.clone()
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Replace any secret value appearing in `message` with the mask
+    fn redact(&self, message: &str) -> String {
+        let mut redacted = message.to_string();
+        for name in &self.secrets {
+            let value = self
+                .variables
+                .get(name)
+                .or_else(|| self.computed_cache.get(name));
+            let needle = match value {
+                Some(JsonValue::String(s)) if !s.is_empty() => s.clone(),
+                Some(JsonValue::Array(_)) | Some(JsonValue::Object(_)) => {
+                    value.map(JsonValue::to_string).unwrap_or_default()
+                }
+                _ => continue,
+            };
+            redacted = redacted.replace(&needle, SECRET_MASK);
+        }
+        redacted
+    }
+
+    /// Copy of `value` with secret values inside strings replaced by the mask
+    fn redact_value(&self, value: &JsonValue) -> JsonValue {
+        match value {
+            JsonValue::String(s) => JsonValue::String(self.redact(s)),
+            JsonValue::Array(items) => {
+                JsonValue::Array(items.iter().map(|item| self.redact_value(item)).collect())
+            }
+            JsonValue::Object(entries) => JsonValue::Object(
+                entries
+                    .iter()
+                    .map(|(key, item)| (key.clone(), self.redact_value(item)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Redact secret values from an error message
+    fn redact_error(&self, error: RuntimeError) -> RuntimeError {
+        match error {
+            RuntimeError::Execution(msg) => RuntimeError::Execution(self.redact(&msg)),
+            RuntimeError::Plugin(msg) => RuntimeError::Plugin(self.redact(&msg)),
+            RuntimeError::Transient(msg) => RuntimeError::Transient(self.redact(&msg)),
+            RuntimeError::Evaluation(msg) => RuntimeError::Evaluation(self.redact(&msg)),
+            RuntimeError::Event(msg) => RuntimeError::Event(self.redact(&msg)),
+            RuntimeError::Lifecycle(msg) => RuntimeError::Lifecycle(self.redact(&msg)),
+            RuntimeError::Cancelled(msg) => RuntimeError::Cancelled(self.redact(&msg)),
+            RuntimeError::Extension(msg) => RuntimeError::Extension(self.redact(&msg)),
+            RuntimeError::State(msg) => RuntimeError::State(self.redact(&msg)),
+            error @ (RuntimeError::ReadonlyAssignment { .. }
+            | RuntimeError::MissingInputs(_)
+            | RuntimeError::UnresolvedName { .. }) => error,
+            RuntimeError::InvalidInput { field, message } => RuntimeError::InvalidInput {
+                message: self.redact(&message),
+                field,
+            },
+            RuntimeError::LimitExceeded { limit, message } => RuntimeError::LimitExceeded {
+                limit,
+                message: self.redact(&message),
+            },
+            RuntimeError::ConstraintsViolated(violations) => RuntimeError::ConstraintsViolated(
+                violations
+                    .into_iter()
+                    .map(|violation| ConstraintViolation {
+                        source: self.redact(&violation.source),
+                        error: violation.error.map(|error| self.redact(&error)),
+                        ..violation
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl std::fmt::Debug for ExecutionContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExecutionContext")
+            .field("variables", &self.masked(&self.variables))
+            .field("computed_cache", &self.masked(&self.computed_cache))
+            .field("state", &self.state)
+            .finish()
+    }
 }
 
 /// Execution state
@@ -116,13 +357,31 @@ pub enum ExecutionState {
     Failed(String),
 }
 
-/// Plugin trait for extending runtime functionality
+impl ExecutionState {
+    /// Lowercase name of the state without the failure message
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExecutionState::Idle => "idle",
+            ExecutionState::Running => "running",
+            ExecutionState::Completed => "completed",
+            ExecutionState::Failed(_) => "failed",
+        }
+    }
+}
+
+/// Synchronous plugin trait for extending runtime functionality
 ///
-/// Note: Async methods are not object-safe, so we use a simpler synchronous interface
+/// The runtime awaits plugins through [`AsyncPlugin`]; synchronous plugins are
+/// adapted with [`plugins::Blocking`] when registered.
 pub trait Plugin: std::fmt::Debug {
     /// Plugin name
     fn name(&self) -> &str;
 
+    /// Semver version checked against `import("...@x.y")` requirements
+    fn version(&self) -> Option<&str> {
+        None
+    }
+
     /// Initialize the plugin
     fn initialize(&mut self) -> RuntimeResult<()>;
 
@@ -134,9 +393,6 @@ pub trait Plugin: std::fmt::Debug {
     ) -> RuntimeResult<serde_json::Value>;
 }
 
-/// Event handler function type
-type EventHandler = Box<dyn Fn(&ExecutionContext) -> RuntimeResult<()> + Send + Sync>;
-
 impl Runtime {
     /// Create a new runtime instance
     ///
@@ -150,11 +406,114 @@ impl Runtime {
     pub fn new() -> Self {
         Self {
             context: Arc::new(RwLock::new(ExecutionContext::default())),
-            plugins: HashMap::new(),
+            plugins: Arc::new(PluginMap::default()),
+            extensions: Arc::default(),
+            cancellation: CancellationToken::new(),
             event_handlers: HashMap::new(),
+            spec: None,
+            created: false,
+            event_depth: AtomicUsize::new(0),
+            watchers: reactive::Watchers::default(),
+            source_map: SourceMap::default(),
+            plugin_calls: Mutex::new(Vec::new()),
+            builtins: Arc::new(BuiltinRegistry::standard()),
+            functions: Arc::default(),
+            plan: Arc::default(),
+            shared: false,
+            limits: ExecutionLimits::default(),
+            steps: AtomicU64::new(0),
+            strict: false,
+            state_store: None,
+            events: AtomicU64::new(0),
+            patterns: PatternCache::new(),
         }
     }
 
+    /// Attach source spans from the parser to the errors this runtime reports
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_runtime::{Runtime, RuntimeError};
+    ///
+    /// let (spec, source_map) = SigmosParser::parse_spec_with_source_map(r#"
+    /// spec "Agent" v1.0 {
+    ///     inputs:
+    ///         owner: string { readonly: true, default: "ops" }
+    ///     lifecycle:
+    ///         before: owner = "someone else"
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let mut runtime = Runtime::new().with_source_map(source_map);
+    /// let error = tokio_test::block_on(runtime.execute(&spec)).unwrap_err();
+    /// assert!(matches!(error, RuntimeError::ReadonlyAssignment { span: Some(_), .. }));
+    /// ```
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = source_map;
+        self
+    }
+
+    /// Look plugins up in `host` instead of the runtime's own [`PluginMap`]
+    ///
+    /// Plugins registered earlier are discarded. The host is initialized before
+    /// each execution, and every plugin call made by the spec goes through it.
+    pub fn with_plugin_host(mut self, host: impl PluginHost + 'static) -> Self {
+        self.plugins = Arc::new(host);
+        self
+    }
+
+    /// Resolve free function calls such as `upper(name)` in `builtins`
+    /// instead of the standard library
+    pub fn with_builtins(mut self, builtins: BuiltinRegistry) -> Self {
+        self.builtins = Arc::new(builtins);
+        self
+    }
+
+    /// Functions that specs can call without a plugin prefix
+    pub fn builtins(&self) -> &BuiltinRegistry {
+        &self.builtins
+    }
+
+    /// Enforce `limits` instead of the default [`ExecutionLimits`]
+    pub fn with_limits(mut self, limits: ExecutionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Resource limits enforced during evaluation
+    pub fn limits(&self) -> &ExecutionLimits {
+        &self.limits
+    }
+
+    /// Fail with [`RuntimeError::UnresolvedName`] where a name has no value,
+    /// instead of evaluating it to a `${name}` placeholder
+    ///
+    /// See [`strict`] for what is checked and when.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Whether unresolved names are errors rather than placeholders
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Save a checkpoint to `store` after each stage of every execution, so
+    /// that [`Runtime::resume`] can continue it
+    pub fn with_state_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    /// Cancel plugin calls when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
     /// Execute a SIGMOS specification
     ///
     /// # Arguments
@@ -180,43 +539,713 @@ impl Runtime {
     ///     lifecycle: vec![],
     ///     extensions: vec![],
     ///     types: vec![],
+    ///     functions: vec![],
     /// };
     ///
     /// runtime.execute(&spec).await.unwrap();
     /// # });
     /// ```
     pub async fn execute(&mut self, spec: &Spec) -> RuntimeResult<()> {
-        // Set execution state to running
-        {
+        self.execute_with_inputs(spec, serde_json::Map::new()).await
+    }
+
+    /// Execute a specification with input values supplied by the caller
+    ///
+    /// Each value is coerced to its field's declared type and checked against
+    /// the field's refinements. Inputs that are not supplied fall back to their
+    /// `default` or `generate` modifiers, or to null when optional; any that
+    /// remain unset are reported together as [`RuntimeError::MissingInputs`].
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The specification to execute
+    /// * `inputs` - Input values keyed by field name
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_runtime::{Runtime, RuntimeError};
+    ///
+    /// # tokio_test::block_on(async {
+    /// let spec = SigmosParser::parse_spec(r#"
+    /// spec "Greeter" v1.0 {
+    ///     inputs:
+    ///         name: string
+    ///         times: int
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let mut inputs = serde_json::Map::new();
+    /// inputs.insert("name".to_string(), json!("Ada"));
+    /// inputs.insert("times".to_string(), json!("3"));
+    ///
+    /// let mut runtime = Runtime::new();
+    /// runtime.execute_with_inputs(&spec, inputs).await.unwrap();
+    ///
+    /// let error = runtime.execute(&spec).await.unwrap_err();
+    /// assert!(matches!(error, RuntimeError::MissingInputs(names) if names.len() == 2));
+    /// # });
+    /// ```
+    pub async fn execute_with_inputs(
+        &mut self,
+        spec: &Spec,
+        inputs: serde_json::Map<String, JsonValue>,
+    ) -> RuntimeResult<()> {
+        self.run(spec, inputs, None).await
+    }
+
+    /// Execute `spec`, continuing from `checkpoint` if there is one
+    async fn run(
+        &mut self,
+        spec: &Spec,
+        inputs: serde_json::Map<String, JsonValue>,
+        checkpoint: Option<Checkpoint>,
+    ) -> RuntimeResult<()> {
+        if !self.shared {
+            self.spec = Some(Arc::new(spec.clone()));
+        }
+        self.reset_steps();
+
+        // Start from a clean context, recording which values must be masked
+        let previous = {
             let mut context = self.context.write().await;
-            context.state = ExecutionState::Running;
+            let previous = (
+                std::mem::take(&mut context.variables),
+                std::mem::take(&mut context.computed_cache),
+            );
+            *context = ExecutionContext {
+                state: ExecutionState::Running,
+                secrets: TaintAnalyzer::tainted_fields(spec),
+                spec: (spec.name.clone(), spec.version.to_string()),
+                // A resumed run was given the inputs its checkpoint was
+                given: checkpoint.as_ref().map_or_else(
+                    || state::given_inputs(spec, &inputs),
+                    |checkpoint| checkpoint.given.clone(),
+                ),
+                fingerprint: checkpoint.as_ref().map_or_else(
+                    || state::fingerprint(spec, &inputs),
+                    |checkpoint| checkpoint.fingerprint.clone(),
+                ),
+                ..ExecutionContext::default()
+            };
+            if let Some(checkpoint) = &checkpoint {
+                context.variables = checkpoint.inputs.clone().into_iter().collect();
+                context.computed_cache = checkpoint.computed.clone().into_iter().collect();
+                context.completed = checkpoint.completed;
+                context.logs.clone_from(&checkpoint.logs);
+            }
+            previous
+        };
+        self.plugin_calls_mut().clear();
+        self.events.store(
+            checkpoint.map_or(0, |checkpoint| checkpoint.events),
+            Ordering::Relaxed,
+        );
+
+        let started = Instant::now();
+        let mut phases = Vec::new();
+        let prepared = if self.shared {
+            Ok(())
+        } else {
+            self.prepare(spec)
+        };
+        let mut result = match prepared {
+            Ok(()) => self.execute_phases(spec, &inputs, &mut phases).await,
+            Err(error) => Err(error),
+        };
+
+        // The first success creates the instance; later ones report changed inputs
+        if result.is_ok() {
+            result = if self.created {
+                self.fire_changes(spec, &previous).await
+            } else {
+                self.created = true;
+                let payload = self.field_values().await;
+                self.dispatch(Event::new(ON_CREATE, payload)).await
+            };
+        }
+        if let Err(error) = &result {
+            self.report_error(error).await;
         }
 
-        // Execute lifecycle before phase
-        self.execute_lifecycle_before(spec).await?;
+        // `finally` runs on every path; its failure only surfaces if nothing failed before
+        let finally = self
+            .run_stage(spec, Stage::Finally, &inputs, &mut phases)
+            .await;
+        if let (Ok(()), Err(error)) = (&result, finally) {
+            self.context.write().await.stage = Some(Stage::Finally);
+            self.report_error(&error).await;
+            result = Err(error);
+        }
+
+        // Set execution state to completed or failed, never exposing secret values
+        let result = {
+            let mut context = self.context.write().await;
+            context.timings = Timings {
+                total_ms: report::millis(started.elapsed()),
+                phases,
+            };
+            match result {
+                Ok(()) => {
+                    context.state = ExecutionState::Completed;
+                    Ok(())
+                }
+                Err(error) => {
+                    let error = context.redact_error(error);
+                    if let RuntimeError::ConstraintsViolated(violations) = &error {
+                        context.violations = violations.clone();
+                    }
+                    context.state = ExecutionState::Failed(error.to_string());
+                    Err(error)
+                }
+            }
+        };
+        let saved = self.save_checkpoint().await;
+        result.and(saved)
+    }
+
+    /// Initialize the plugins, then resolve and compile `spec`
+    fn prepare(&mut self, spec: &Spec) -> RuntimeResult<()> {
+        let prepared = self.initialize_plugins().and_then(|()| {
+            self.extensions =
+                Arc::new(extensions::resolve_extensions(spec, self.plugins.as_ref())?);
+            self.functions = Arc::new(functions::collect_functions(spec, &self.builtins)?);
+            Ok(())
+        });
+        // Compiled even when preparation failed, so `finally` and `on_error` still run
+        let compiled = Engine::new(&self.builtins)
+            .with_extensions(self.extensions.as_ref().clone())
+            .with_limits(self.limits)
+            .with_strict(self.strict)
+            .compile(spec);
+        match compiled {
+            Ok(plan) => {
+                self.plan = Arc::new(plan);
+                prepared
+            }
+            Err(error) => {
+                self.plan = Arc::default();
+                prepared.and(Err(self.locate(error, String::new)))
+            }
+        }
+    }
+
+    /// Initialize plugins not yet initialized, unless another runtime shares them
+    fn initialize_plugins(&mut self) -> RuntimeResult<()> {
+        match Arc::get_mut(&mut self.plugins) {
+            Some(plugins) => plugins.initialize(),
+            None => Ok(()),
+        }
+    }
+
+    /// Deliver a failure to the `on_error` handlers
+    async fn report_error(&self, error: &RuntimeError) {
+        let message = self.context.read().await.redact(&error.to_string());
+        // A failing error handler must not replace the original error
+        let _ = self
+            .dispatch(Event::new(ON_ERROR, JsonValue::String(message)))
+            .await;
+    }
+
+    /// Report of the last execution, with secret values masked
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_runtime::Runtime;
+    ///
+    /// # tokio_test::block_on(async {
+    /// let report = Runtime::new().snapshot().await;
+    /// assert_eq!(report.state, "idle");
+    /// assert!(report.inputs.is_empty());
+    /// # });
+    /// ```
+    pub async fn snapshot(&self) -> ExecutionReport {
+        let context = self.context.read().await;
+        let plugin_calls = self
+            .plugin_calls_mut()
+            .iter()
+            .map(|call| PluginCall {
+                arguments: call
+                    .arguments
+                    .iter()
+                    .map(|(name, value)| (name.clone(), context.redact_value(value)))
+                    .collect(),
+                error: call.error.as_deref().map(|error| context.redact(error)),
+                ..call.clone()
+            })
+            .collect();
+
+        ExecutionReport {
+            spec: context.spec.0.clone(),
+            version: context.spec.1.clone(),
+            state: context.state.name().to_string(),
+            error: match &context.state {
+                ExecutionState::Failed(error) => Some(error.clone()),
+                _ => None,
+            },
+            inputs: context.masked(&context.variables),
+            computed: context.masked(&context.computed_cache),
+            violations: context.violations.clone(),
+            plugin_calls,
+            stage: context.stage,
+            logs: context
+                .logs
+                .iter()
+                .map(|message| context.redact(message))
+                .collect(),
+            timings: context.timings.clone(),
+        }
+    }
+
+    /// Update one input of the executed spec and recompute what depends on it
+    ///
+    /// The value is coerced and checked against the field's refinements, the
+    /// computed fields that read it (directly or transitively) are recomputed,
+    /// and constraints that read any changed field are re-checked. Nothing is
+    /// changed if any step fails. On success, watchers of the changed fields
+    /// receive their new values and `on_change` fires for each of them.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use sigmos_core::parser::SigmosParser;
+    /// use sigmos_runtime::{Runtime, RuntimeError};
+    ///
+    /// # tokio_test::block_on(async {
+    /// let spec = SigmosParser::parse_spec(r#"
+    /// spec "Agent" v1.0 {
+    ///     inputs:
+    ///         max_tokens: int { default: 1000 }
+    ///     computed:
+    ///         budget: -> max_tokens * 2
+    ///     constraints:
+    ///         ensure budget <= 8000
+    /// }
+    /// "#).unwrap();
+    ///
+    /// let mut runtime = Runtime::new();
+    /// runtime.execute(&spec).await.unwrap();
+    ///
+    /// runtime.set_input("max_tokens", json!(2000)).await.unwrap();
+    /// assert_eq!(runtime.snapshot().await.computed["budget"], json!(4000.0));
+    ///
+    /// let error = runtime.set_input("max_tokens", json!(5000)).await.unwrap_err();
+    /// assert!(matches!(error, RuntimeError::ConstraintsViolated(_)));
+    /// assert_eq!(runtime.snapshot().await.inputs["max_tokens"], json!(2000));
+    /// # });
+    /// ```
+    pub async fn set_input(&self, name: &str, value: JsonValue) -> RuntimeResult<()> {
+        self.reset_steps();
+        match self.apply_input(name, value).await {
+            Ok(()) => Ok(()),
+            Err(error) => Err(self.context.read().await.redact_error(error)),
+        }
+    }
 
-        // Process input fields
-        self.process_inputs(spec).await?;
+    async fn apply_input(&self, name: &str, value: JsonValue) -> RuntimeResult<()> {
+        let spec = self.spec.as_ref().ok_or_else(|| {
+            RuntimeError::Execution("No specification has been executed".to_string())
+        })?;
+        let field = spec
+            .inputs
+            .iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| RuntimeError::InvalidInput {
+                field: name.to_string(),
+                message: "not declared in the spec's inputs".to_string(),
+            })?;
+        if field.modifiers.contains(&Modifier::Readonly) {
+            return Err(RuntimeError::ReadonlyAssignment {
+                field: name.to_string(),
+                location: "set_input".to_string(),
+                span: None,
+            });
+        }
 
-        // Compute derived fields
-        self.compute_fields(spec).await?;
+        let (mut frame, secrets) = {
+            let context = self.context.read().await;
+            let frame = self
+                .plan
+                .frame([&context.variables, &context.computed_cache]);
+            (frame, context.secrets.clone())
+        };
 
-        // Execute lifecycle after phase
-        self.execute_lifecycle_after(spec).await?;
+        let secret = secrets.contains(name);
+        let value =
+            inputs::coerce_input(value.clone(), &TypeChecker::input_type(field), &spec.types)
+                .and_then(|coerced| {
+                    TypeChecker::check_refinements(field, &coerced, &self.patterns)
+                        .map(|()| coerced)
+                })
+                .map_err(|message| RuntimeError::InvalidInput {
+                    field: name.to_string(),
+                    // The new value is not in the context yet, so mask it here
+                    message: match (&value, secret) {
+                        (JsonValue::String(raw), true) if !raw.is_empty() => {
+                            message.replace(raw, SECRET_MASK)
+                        }
+                        _ => message,
+                    },
+                })?;
 
-        // Set execution state to completed
+        let slot = self.plan.slot(name).ok_or_else(|| {
+            RuntimeError::Execution(format!("Input '{name}' is not in the compiled spec"))
+        })?;
+        if frame.get(slot) == Some(&value) {
+            return Ok(());
+        }
+        frame.set(slot, value.clone());
+
+        // Recompute on a scratch frame so a failure leaves the context untouched
+        let affected = self.plan.dependents(slot);
+        let recomputed = self.run_steps(&self.plan, &affected, &mut frame).await?;
+
+        let changed: HashSet<&str> = std::iter::once(name)
+            .chain(affected.iter().map(|step| step.name.as_str()))
+            .collect();
+        self.constraint_violations(spec, &frame, &secrets, |constraint| {
+            constraint
+                .expression
+                .referenced_identifiers()
+                .iter()
+                .any(|identifier| changed.contains(identifier))
+        })
+        .await?;
+
+        let mut notifications = vec![(name.to_string(), value.clone())];
         {
             let mut context = self.context.write().await;
-            context.state = ExecutionState::Completed;
+            context.variables.insert(name.to_string(), value);
+            for (computed_name, computed_value) in recomputed {
+                let previous = context
+                    .computed_cache
+                    .insert(computed_name.clone(), computed_value.clone());
+                if previous.as_ref() != Some(&computed_value) {
+                    notifications.push((computed_name, computed_value));
+                }
+            }
         }
 
+        for (field, value) in &notifications {
+            let value = if secrets.contains(field) {
+                JsonValue::String(SECRET_MASK.to_string())
+            } else {
+                value.clone()
+            };
+            self.watchers.notify(field, &value);
+        }
+
+        // `on_change` handlers see the real values, like the rest of the spec
+
+        for (field, value) in notifications {
+            self.dispatch(Event::changed(field, value)).await?;
+        }
         Ok(())
     }
 
-    /// Register a plugin
-    pub fn register_plugin(&mut self, plugin: Box<dyn Plugin + Send + Sync>) {
-        let name = plugin.name().to_string();
-        self.plugins.insert(name, plugin);
+    /// Subscribe to new values of an input or computed field
+    ///
+    /// The stream yields each value assigned by [`Runtime::set_input`] or by
+    /// an event or lifecycle action, with secret values masked, and ends when
+    /// the runtime is dropped.
+    pub fn watch(&self, field: &str) -> impl Stream<Item = JsonValue> + Send + Unpin {
+        self.watchers.subscribe(field)
+    }
+
+    /// Register a Rust handler for the event `name`
+    ///
+    /// Rust handlers run before the spec's own handlers for the same event.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use sigmos_runtime::Runtime;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// # tokio_test::block_on(async {
+    /// let seen = Arc::new(Mutex::new(Vec::new()));
+    /// let mut runtime = Runtime::new();
+    /// let log = seen.clone();
+    /// runtime.on("on_deploy", move |event| {
+    ///     log.lock().unwrap().push(event.payload.clone());
+    ///     Ok(())
+    /// });
+    ///
+    /// runtime.emit("on_deploy", json!("staging")).await.unwrap();
+    /// assert_eq!(*seen.lock().unwrap(), vec![json!("staging")]);
+    /// # });
+    /// ```
+    pub fn on<F>(&mut self, name: impl Into<String>, handler: F)
+    where
+        F: Fn(&Event) -> RuntimeResult<()> + Send + Sync + 'static,
+    {
+        self.event_handlers
+            .entry(name.into())
+            .or_default()
+            .push(Box::new(handler));
+    }
+
+    /// Fire the event `name`, binding `payload` to each handler's parameter
+    ///
+    /// Handlers come from the last executed spec and from [`Runtime::on`].
+    pub async fn emit(&self, name: &str, payload: JsonValue) -> RuntimeResult<()> {
+        self.reset_steps();
+        match self.dispatch(Event::new(name, payload)).await {
+            Ok(()) => Ok(()),
+            Err(error) => Err(self.context.read().await.redact_error(error)),
+        }
+    }
+
+    /// Run the Rust and spec handlers for `event`, in registration order
+    fn dispatch(&self, event: Event) -> BoxFuture<'_, RuntimeResult<()>> {
+        Box::pin(async move {
+            if self.event_depth.fetch_add(1, Ordering::SeqCst) >= MAX_EVENT_DEPTH {
+                self.event_depth.fetch_sub(1, Ordering::SeqCst);
+                return Err(RuntimeError::Event(format!(
+                    "Event '{}' exceeded the nesting limit of {MAX_EVENT_DEPTH}; do its handlers trigger each other?",
+                    event.name
+                )));
+            }
+            self.events.fetch_add(1, Ordering::Relaxed);
+            let result = self.run_handlers(&event).await;
+            self.event_depth.fetch_sub(1, Ordering::SeqCst);
+            result
+        })
+    }
+
+    async fn run_handlers(&self, event: &Event) -> RuntimeResult<()> {
+        for handler in self.event_handlers.get(&event.name).into_iter().flatten() {
+            handler(event)?;
+        }
+
+        let Some(spec) = &self.spec else {
+            return Ok(());
+        };
+        for (index, (name, action)) in self.plan.events().iter().enumerate() {
+            if *name != event.name {
+                continue;
+            }
+            // `on_change` handlers naming a field watch only that field and
+            // receive its new value; any other parameter receives the name of
+            // whichever field changed, or the payload of an unfiltered `emit`
+            let payload = if event.name == ON_CHANGE {
+                let parameter = &spec.events[index].parameter;
+                let watches_field = spec.inputs.iter().any(|field| field.name == *parameter)
+                    || spec.computed.iter().any(|field| field.name == *parameter);
+                match (&event.field, watches_field) {
+                    (Some(field), true) if field == parameter => event.payload.clone(),
+                    (Some(field), false) => JsonValue::String(field.clone()),
+                    (None, false) => event.payload.clone(),
+                    _ => continue,
+                }
+            } else {
+                event.payload.clone()
+            };
+            let mut frame = {
+                let context = self.context.read().await;
+                self.plan
+                    .frame([&context.variables, &context.computed_cache])
+            };
+            frame.set(self.plan.parameter_slot(), payload);
+            self.run_action(action, &mut frame).await.map_err(|error| {
+                match self.locate(error, || format!("events[{index}]")) {
+                    error @ (RuntimeError::Event(_) | RuntimeError::UnresolvedName { .. }) => error,
+                    other => {
+                        RuntimeError::Event(format!("Handler for '{}' failed: {other}", event.name))
+                    }
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Fire `on_change` for each input and computed field whose value differs
+    /// from the previous run
+    async fn fire_changes(
+        &self,
+        spec: &Spec,
+        previous: &(HashMap<String, JsonValue>, HashMap<String, JsonValue>),
+    ) -> RuntimeResult<()> {
+        let changed: Vec<(String, JsonValue)> = {
+            let context = self.context.read().await;
+            let inputs = spec
+                .inputs
+                .iter()
+                .map(|field| (&field.name, &context.variables, &previous.0));
+            let computed = spec
+                .computed
+                .iter()
+                .map(|field| (&field.name, &context.computed_cache, &previous.1));
+            inputs
+                .chain(computed)
+                .filter_map(|(name, current, previous)| {
+                    let value = current.get(name)?;
+                    (previous.get(name) != Some(value)).then(|| (name.clone(), value.clone()))
+                })
+                .collect()
+        };
+        for (field, value) in changed {
+            self.dispatch(Event::changed(field, value)).await?;
+        }
+        Ok(())
+    }
+
+    /// Variables and computed values, computed values taking precedence
+    async fn field_values_map(&self) -> HashMap<String, JsonValue> {
+        let context = self.context.read().await;
+        let mut values = context.variables.clone();
+        values.extend(context.computed_cache.clone());
+        values
+    }
+
+    /// All field values as one object, the payload of `on_create`
+    async fn field_values(&self) -> JsonValue {
+        JsonValue::Object(self.field_values_map().await.into_iter().collect())
+    }
+
+    /// Plugin call log, recovering from a poisoned lock
+    fn plugin_calls_mut(&self) -> std::sync::MutexGuard<'_, Vec<PluginCall>> {
+        self.plugin_calls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Run the stages from `before` to `after`, stopping at the first failure
+    async fn execute_phases(
+        &self,
+        spec: &Spec,
+        inputs: &serde_json::Map<String, JsonValue>,
+        phases: &mut Vec<PhaseTiming>,
+    ) -> RuntimeResult<()> {
+        // Reject writes to readonly fields before anything runs
+        self.check_readonly_assignments(spec)?;
+
+        let mut stage = Some(Stage::Before);
+        while let Some(current) = stage {
+            self.run_stage(spec, current, inputs, phases).await?;
+            self.save_checkpoint().await?;
+            stage = current.next();
+        }
+        Ok(())
+    }
+
+    /// Run one stage, recording that it was entered and how long it took
+    async fn run_stage(
+        &self,
+        spec: &Spec,
+        stage: Stage,
+        inputs: &serde_json::Map<String, JsonValue>,
+        phases: &mut Vec<PhaseTiming>,
+    ) -> RuntimeResult<()> {
+        // `finally` keeps the stage that failed, if any, on record
+        if stage != Stage::Finally {
+            let mut context = self.context.write().await;
+            context.stage = Some(stage);
+            // A resumed execution does not repeat actions that finished
+            if matches!(stage, Stage::Before | Stage::After) && context.completed >= Some(stage) {
+                return Ok(());
+            }
+        }
+        let started = Instant::now();
+
+        let run = async {
+            match stage {
+                Stage::Inputs => {
+                    self.process_inputs(spec, inputs).await?;
+                    self.check_constraints(spec, ConstraintType::Assert).await
+                }
+                Stage::Computed => self.compute_fields().await,
+                Stage::Constraints => self.check_constraints(spec, ConstraintType::Ensure).await,
+                Stage::Before | Stage::After | Stage::Finally => self.run_lifecycle(stage).await,
+            }
+        };
+        let operation = format!("the {} phase", stage.name());
+        limits::deadline(
+            self.limits.phase_timeout,
+            Limit::PhaseTimeout,
+            &operation,
+            run,
+        )
+        .await?;
+
+        phases.push(PhaseTiming::new(stage.name(), started.elapsed()));
+        if stage != Stage::Finally {
+            let mut context = self.context.write().await;
+            context.completed = context.completed.max(Some(stage));
+        }
+        Ok(())
+    }
+
+    /// Run the lifecycle actions of `stage`
+    async fn run_lifecycle(&self, stage: Stage) -> RuntimeResult<()> {
+        let mut frame = None;
+        for (index, (phase, action)) in self.plan.lifecycle().iter().enumerate() {
+            if stage.phase().as_ref() != Some(phase) {
+                continue;
+            }
+            // Lifecycle actions see the inputs; assignments, and the
+            // handlers they trigger, change what the next action sees
+            let frame = match &mut frame {
+                Some(frame) => frame,
+                None => {
+                    let context = self.context.read().await;
+                    frame.insert(self.plan.frame([&context.variables]))
+                }
+            };
+            self.run_action(action, frame)
+                .await
+                .map_err(|error| self.locate(error, || format!("lifecycle[{index}]")))?;
+            if matches!(action, CompiledAction::Assign { .. }) {
+                *frame = self.plan.frame([&self.context.read().await.variables]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Register a synchronous plugin with the plugin host
+    ///
+    /// Its calls run through the [`Blocking`] adapter so they do not stall the
+    /// async executor.
+    pub fn register_plugin(&mut self, plugin: Box<dyn Plugin + Send + Sync>) -> RuntimeResult<()> {
+        self.register_async_plugin(Box::new(Blocking::from(plugin)))
+    }
+
+    /// Register a plugin whose calls are awaited with the plugin host
+    ///
+    /// Fails while a [`SpecHost`](host::SpecHost) shares the runtime's plugins.
+    pub fn register_async_plugin(&mut self, plugin: Box<dyn AsyncPlugin>) -> RuntimeResult<()> {
+        Arc::get_mut(&mut self.plugins)
+            .ok_or_else(|| {
+                RuntimeError::Plugin(
+                    "Plugins cannot be registered while other runtimes share them".to_string(),
+                )
+            })?
+            .register(plugin)
+    }
+
+    /// Token that cancels this runtime's plugin calls
+    ///
+    /// Cancelling it fails the calls in flight, and every later call, with
+    /// [`RuntimeError::Cancelled`]. Install a fresh token with
+    /// [`Runtime::with_cancellation`] to make calls again.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_runtime::Runtime;
+    ///
+    /// let runtime = Runtime::new();
+    /// let token = runtime.cancellation();
+    /// token.cancel();
+    /// assert!(runtime.cancellation().is_cancelled());
+    /// ```
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancellation.clone()
     }
 
     /// Evaluate an expression in the current context
@@ -229,157 +1258,312 @@ impl Runtime {
     ///
     /// let runtime = Runtime::new();
     /// let expr = Expression::StringLiteral("Hello World".to_string());
-    /// let result = runtime.evaluate_expression(&expr).unwrap();
+    /// let result = tokio_test::block_on(runtime.evaluate_expression(&expr)).unwrap();
     /// ```
-    pub fn evaluate_expression(&self, expr: &Expression) -> RuntimeResult<JsonValue> {
+    pub async fn evaluate_expression(&self, expr: &Expression) -> RuntimeResult<JsonValue> {
         self.evaluate_expression_with_context(expr, &HashMap::new())
+            .await
     }
 
     /// Evaluate an expression with additional context variables
-    pub fn evaluate_expression_with_context(
-        &self,
-        expr: &Expression,
-        context: &HashMap<String, JsonValue>,
-    ) -> RuntimeResult<JsonValue> {
-        match expr {
-            Expression::StringLiteral(s) => Ok(JsonValue::String(s.clone())),
-            Expression::Number(n) => Ok(JsonValue::Number(
-                serde_json::Number::from_f64(*n)
-                    .ok_or_else(|| RuntimeError::Evaluation(format!("Invalid number: {n}")))?,
-            )),
-            Expression::Boolean(b) => Ok(JsonValue::Bool(*b)),
+    ///
+    /// Plugin calls inside the expression are awaited. Fails with
+    /// [`RuntimeError::LimitExceeded`] when evaluation reaches one of the
+    /// runtime's [`ExecutionLimits`], and on a strict runtime with
+    /// [`RuntimeError::UnresolvedName`] when a name is not in `context`.
+    pub fn evaluate_expression_with_context<'a>(
+        &'a self,
+        expr: &'a Expression,
+        context: &'a HashMap<String, JsonValue>,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        self.reset_steps();
+        Box::pin(self.evaluate_in(expr, context))
+    }
 
-            Expression::Identifier(name) => {
+    /// Evaluate an expression one level below the expression evaluating it
+    fn evaluate_in<'a>(
+        &'a self,
+        expr: &'a Expression,
+        context: &'a HashMap<String, JsonValue>,
+    ) -> Limited<'a> {
+        if let Err(error) = self.step() {
+            return self.limits.limit(Box::pin(future::ready(Err(error))));
+        }
+        // Each kind of expression gets its own future, so nested expressions
+        // take little stack
+        let future: BoxFuture<'a, RuntimeResult<JsonValue>> = match expr {
+            Expression::StringLiteral(s) => {
+                Box::pin(async move { Ok(JsonValue::String(s.clone())) })
+            }
+            Expression::Number(n) => Box::pin(async move {
+                Ok(JsonValue::Number(
+                    serde_json::Number::from_f64(*n)
+                        .ok_or_else(|| RuntimeError::Evaluation(format!("Invalid number: {n}")))?,
+                ))
+            }),
+            Expression::Boolean(b) => Box::pin(async move { Ok(JsonValue::Bool(*b)) }),
+            Expression::Null => Box::pin(async move { Ok(JsonValue::Null) }),
+
+            Expression::Identifier(name) => Box::pin(async move {
                 // Look up variable in context, then in runtime context
                 if let Some(value) = context.get(name) {
                     Ok(value.clone())
+                } else if self.strict {
+                    Err(strict::unresolved(name, Self::names_in(context)))
                 } else {
                     // Try to get from runtime context (async context would require different approach)
                     // For now, return a descriptive placeholder
                     Ok(JsonValue::String(format!("${{{name}}}")))
                 }
-            }
+            }),
 
             Expression::FunctionCall {
                 object,
                 method,
                 arguments,
-            } => self.evaluate_function_call(object, method, arguments, context),
+            } => Box::pin(async move {
+                self.evaluate_function_call(object, method, arguments, context)
+                    .await
+            }),
 
-            Expression::StringTemplate { parts } => self.evaluate_string_template(parts, context),
+            Expression::StringTemplate { parts } => {
+                Box::pin(async move { self.evaluate_string_template(parts, context) })
+            }
 
             // Arithmetic operators
-            Expression::Add(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Add)
-            }
-            Expression::Subtract(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Subtract)
-            }
-            Expression::Multiply(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Multiply)
-            }
-            Expression::Divide(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Divide)
-            }
-            Expression::Modulo(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Modulo)
-            }
+            Expression::Add(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Add)
+            }),
+            Expression::Subtract(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Subtract)
+            }),
+            Expression::Multiply(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Multiply)
+            }),
+            Expression::Divide(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Divide)
+            }),
+            Expression::Modulo(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_arithmetic_operation(&left_val, &right_val, ArithmeticOp::Modulo)
+            }),
 
             // Comparison operators
-            Expression::Equal(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                Ok(JsonValue::Bool(self.values_equal(&left_val, &right_val)))
-            }
-            Expression::NotEqual(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                Ok(JsonValue::Bool(!self.values_equal(&left_val, &right_val)))
-            }
-            Expression::LessThan(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_comparison(&left_val, &right_val, ComparisonOp::LessThan)
-            }
-            Expression::LessThanOrEqual(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_comparison(&left_val, &right_val, ComparisonOp::LessThanOrEqual)
-            }
-            Expression::GreaterThan(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_comparison(&left_val, &right_val, ComparisonOp::GreaterThan)
-            }
-            Expression::GreaterThanOrEqual(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                let right_val = self.evaluate_expression_with_context(right, context)?;
-                self.perform_comparison(&left_val, &right_val, ComparisonOp::GreaterThanOrEqual)
-            }
+            Expression::Equal(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Ok(JsonValue::Bool(Self::values_equal(&left_val, &right_val)))
+            }),
+            Expression::NotEqual(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Ok(JsonValue::Bool(!Self::values_equal(&left_val, &right_val)))
+            }),
+            Expression::LessThan(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_comparison(&left_val, &right_val, ComparisonOp::LessThan)
+            }),
+            Expression::LessThanOrEqual(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_comparison(&left_val, &right_val, ComparisonOp::LessThanOrEqual)
+            }),
+            Expression::GreaterThan(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_comparison(&left_val, &right_val, ComparisonOp::GreaterThan)
+            }),
+            Expression::GreaterThanOrEqual(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                let right_val = self.evaluate_in(right, context).await?;
+                Self::perform_comparison(&left_val, &right_val, ComparisonOp::GreaterThanOrEqual)
+            }),
 
             // Logical operators
-            Expression::And(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                if !self.is_truthy(&left_val) {
+            Expression::And(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                if !Self::is_truthy(&left_val) {
                     Ok(JsonValue::Bool(false))
                 } else {
-                    let right_val = self.evaluate_expression_with_context(right, context)?;
-                    Ok(JsonValue::Bool(self.is_truthy(&right_val)))
+                    let right_val = self.evaluate_in(right, context).await?;
+                    Ok(JsonValue::Bool(Self::is_truthy(&right_val)))
                 }
-            }
-            Expression::Or(left, right) => {
-                let left_val = self.evaluate_expression_with_context(left, context)?;
-                if self.is_truthy(&left_val) {
+            }),
+            Expression::Or(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                if Self::is_truthy(&left_val) {
                     Ok(JsonValue::Bool(true))
                 } else {
-                    let right_val = self.evaluate_expression_with_context(right, context)?;
-                    Ok(JsonValue::Bool(self.is_truthy(&right_val)))
+                    let right_val = self.evaluate_in(right, context).await?;
+                    Ok(JsonValue::Bool(Self::is_truthy(&right_val)))
                 }
-            }
-            Expression::Not(operand) => {
-                let val = self.evaluate_expression_with_context(operand, context)?;
-                Ok(JsonValue::Bool(!self.is_truthy(&val)))
-            }
+            }),
+            Expression::Not(operand) => Box::pin(async move {
+                let val = self.evaluate_in(operand, context).await?;
+                Ok(JsonValue::Bool(!Self::is_truthy(&val)))
+            }),
+
+            // Null-safety operators
+            Expression::Coalesce(left, right) => Box::pin(async move {
+                let left_val = self.evaluate_in(left, context).await?;
+                if left_val.is_null() {
+                    self.evaluate_in(right, context).await
+                } else {
+                    Ok(left_val)
+                }
+            }),
 
             // Conditional expression
             Expression::Conditional {
                 condition,
                 if_true,
                 if_false,
-            } => {
-                let condition_val = self.evaluate_expression_with_context(condition, context)?;
-                if self.is_truthy(&condition_val) {
-                    self.evaluate_expression_with_context(if_true, context)
+            } => Box::pin(async move {
+                let condition_val = self.evaluate_in(condition, context).await?;
+                if Self::is_truthy(&condition_val) {
+                    self.evaluate_in(if_true, context).await
                 } else {
-                    self.evaluate_expression_with_context(if_false, context)
+                    self.evaluate_in(if_false, context).await
                 }
-            }
+            }),
 
             // Array and object access
-            Expression::ArrayAccess(array_expr, index_expr) => {
-                let array_val = self.evaluate_expression_with_context(array_expr, context)?;
-                let index_val = self.evaluate_expression_with_context(index_expr, context)?;
-                self.perform_array_access(&array_val, &index_val)
-            }
-            Expression::PropertyAccess(object_expr, property) => {
-                let object_val = self.evaluate_expression_with_context(object_expr, context)?;
-                self.perform_property_access(&object_val, property)
+            Expression::ArrayAccess(array_expr, index_expr) => Box::pin(async move {
+                let array_val = self.evaluate_in(array_expr, context).await?;
+                let index_val = self.evaluate_in(index_expr, context).await?;
+                Self::perform_array_access(&array_val, &index_val)
+            }),
+            Expression::PropertyAccess(object_expr, property) => Box::pin(async move {
+                let object_val = self.evaluate_in(object_expr, context).await?;
+                Self::perform_property_access(&object_val, property)
+            }),
+            Expression::OptionalPropertyAccess(object_expr, property) => Box::pin(async move {
+                let object_val = self.evaluate_in(object_expr, context).await?;
+                if object_val.is_null() {
+                    Ok(JsonValue::Null)
+                } else {
+                    Self::perform_property_access(&object_val, property)
+                }
+            }),
+
+            // Collection literals
+            Expression::Array(items) => Box::pin(async move {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.evaluate_in(item, context).await?);
+                }
+                Ok(JsonValue::Array(values))
+            }),
+            Expression::Object(entries) => Box::pin(async move {
+                let mut object = serde_json::Map::with_capacity(entries.len());
+                for (key, value) in entries {
+                    object.insert(key.clone(), self.evaluate_in(value, context).await?);
+                }
+                Ok(JsonValue::Object(object))
+            }),
+
+            Expression::Lambda { .. } => Box::pin(async move {
+                Err(RuntimeError::Evaluation(
+                    "Lambdas can only be passed to functions that take one, such as map or filter"
+                        .to_string(),
+                ))
+            }),
+        };
+        self.limits.limit(future)
+    }
+
+    /// Call the builtin `name`, passing lambdas and identifiers that name
+    /// builtins as functions where its signature takes one
+    async fn call_builtin(
+        &self,
+        name: &str,
+        arguments: &[Argument],
+        context: &HashMap<String, JsonValue>,
+    ) -> RuntimeResult<JsonValue> {
+        let Some(builtin) = self.builtins.get(name) else {
+            return Err(RuntimeError::Evaluation(format!(
+                "Unknown function: {name}"
+            )));
+        };
+        let mut args = Vec::with_capacity(arguments.len());
+        for (index, argument) in arguments.iter().enumerate() {
+            let takes_function = builtin
+                .signature()
+                .parameter(index)
+                .is_some_and(|parameter| parameter.value_type == ValueType::Function);
+            match &argument.value {
+                Expression::Identifier(function)
+                    if takes_function
+                        && !context.contains_key(function)
+                        && self.builtins.contains(function) =>
+                {
+                    args.push(Arg::Function(Callable::Builtin(function.clone())));
+                }
+                Expression::Identifier(function)
+                    if takes_function && !context.contains_key(function) =>
+                {
+                    let Some(function) = self.functions.get(function) else {
+                        return Err(RuntimeError::Evaluation(format!(
+                            "{name}(): '{function}' is not a function"
+                        )));
+                    };
+                    let depth = functions::call_depth(context);
+                    args.push(Arg::Function(functions::as_callable(function, depth)));
+                }
+                lambda @ Expression::Lambda { parameters, body } if takes_function => {
+                    // Calls made by the lambda count towards the depth where it is written
+                    let captured = lambda
+                        .referenced_identifiers()
+                        .into_iter()
+                        .chain([functions::CALL_DEPTH])
+                        .filter_map(|name| Some((name.to_string(), context.get(name)?.clone())))
+                        .collect();
+                    args.push(Arg::Function(Callable::Lambda {
+                        parameters: parameters.clone(),
+                        body: (**body).clone(),
+                        captured,
+                    }));
+                }
+                value => args.push(Arg::Value(self.evaluate_in(value, context).await?)),
             }
         }
+        builtin.call(name, args, self).await
+    }
+
+    /// Call the spec function `name` from `depth` nested calls
+    async fn call_function(
+        &self,
+        name: &str,
+        args: Vec<JsonValue>,
+        depth: usize,
+    ) -> RuntimeResult<JsonValue> {
+        let Some(function) = self.functions.get(name) else {
+            return Err(RuntimeError::Evaluation(format!(
+                "Unknown function: {name}"
+            )));
+        };
+        let types = self
+            .spec
+            .as_ref()
+            .map_or(&[][..], |spec| spec.types.as_slice());
+        self.limits.check_call_depth(name, depth)?;
+        let scope = functions::bind_arguments(function, args, types, depth)?;
+        let result = self.evaluate_in(&function.body, &scope).await?;
+        functions::check_result(function, result, types)
     }
 
     /// Evaluate a function call
-    fn evaluate_function_call(
+    async fn evaluate_function_call(
         &self,
         object: &str,
         method: &str,
@@ -388,103 +1572,32 @@ impl Runtime {
     ) -> RuntimeResult<JsonValue> {
         // Built-in functions
         match (object, method) {
-            ("", "len") => {
-                if arguments.len() != 1 {
-                    return Err(RuntimeError::Evaluation(
-                        "len() requires exactly one argument".to_string(),
-                    ));
-                }
-                let arg_value =
-                    self.evaluate_expression_with_context(&arguments[0].value, context)?;
-                match arg_value {
-                    JsonValue::String(s) => {
-                        Ok(JsonValue::Number(serde_json::Number::from(s.len())))
-                    }
-                    JsonValue::Array(arr) => {
-                        Ok(JsonValue::Number(serde_json::Number::from(arr.len())))
-                    }
-                    JsonValue::Object(obj) => {
-                        Ok(JsonValue::Number(serde_json::Number::from(obj.len())))
-                    }
-                    _ => Err(RuntimeError::Evaluation(
-                        "len() can only be applied to strings, arrays, or objects".to_string(),
-                    )),
+            ("", "ref") => match arguments.first().map(|a| &a.value) {
+                Some(Expression::StringLiteral(name)) => {
+                    context.get(name).cloned().ok_or_else(|| {
+                        RuntimeError::Evaluation(format!("ref(\"{name}\") names an unknown field"))
+                    })
                 }
-            }
-            ("", "upper") => {
-                if arguments.len() != 1 {
-                    return Err(RuntimeError::Evaluation(
-                        "upper() requires exactly one argument".to_string(),
-                    ));
-                }
-                let arg_value =
-                    self.evaluate_expression_with_context(&arguments[0].value, context)?;
-                match arg_value {
-                    JsonValue::String(s) => Ok(JsonValue::String(s.to_uppercase())),
-                    _ => Err(RuntimeError::Evaluation(
-                        "upper() can only be applied to strings".to_string(),
-                    )),
-                }
-            }
-            ("", "lower") => {
-                if arguments.len() != 1 {
-                    return Err(RuntimeError::Evaluation(
-                        "lower() requires exactly one argument".to_string(),
-                    ));
-                }
-                let arg_value =
-                    self.evaluate_expression_with_context(&arguments[0].value, context)?;
-                match arg_value {
-                    JsonValue::String(s) => Ok(JsonValue::String(s.to_lowercase())),
-                    _ => Err(RuntimeError::Evaluation(
-                        "lower() can only be applied to strings".to_string(),
-                    )),
-                }
-            }
-            ("", "trim") => {
-                if arguments.len() != 1 {
-                    return Err(RuntimeError::Evaluation(
-                        "trim() requires exactly one argument".to_string(),
-                    ));
-                }
-                let arg_value =
-                    self.evaluate_expression_with_context(&arguments[0].value, context)?;
-                match arg_value {
-                    JsonValue::String(s) => Ok(JsonValue::String(s.trim().to_string())),
-                    _ => Err(RuntimeError::Evaluation(
-                        "trim() can only be applied to strings".to_string(),
-                    )),
+                _ => Err(RuntimeError::Evaluation(
+                    "ref() requires a field name as a string literal".to_string(),
+                )),
+            },
+            ("", name) if self.functions.contains_key(name) => {
+                let mut args = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    args.push(self.evaluate_in(&argument.value, context).await?);
                 }
+                self.call_function(name, args, functions::call_depth(context))
+                    .await
             }
-            ("", "abs") => {
-                if arguments.len() != 1 {
-                    return Err(RuntimeError::Evaluation(
-                        "abs() requires exactly one argument".to_string(),
-                    ));
-                }
-                let arg_value =
-                    self.evaluate_expression_with_context(&arguments[0].value, context)?;
-                match arg_value {
-                    JsonValue::Number(n) => {
-                        let f = n.as_f64().ok_or_else(|| {
-                            RuntimeError::Evaluation("Invalid number for abs()".to_string())
-                        })?;
-                        Ok(JsonValue::Number(
-                            serde_json::Number::from_f64(f.abs()).ok_or_else(|| {
-                                RuntimeError::Evaluation(
-                                    "Result of abs() is not a valid number".to_string(),
-                                )
-                            })?,
-                        ))
-                    }
-                    _ => Err(RuntimeError::Evaluation(
-                        "abs() can only be applied to numbers".to_string(),
-                    )),
-                }
+            ("", name) if self.builtins.contains(name) => {
+                self.call_builtin(name, arguments, context).await
             }
             // Plugin method calls
-            (plugin_name, method_name) if !plugin_name.is_empty() => {
-                if let Some(plugin) = self.plugins.get(plugin_name) {
+            (object, method_name) if !object.is_empty() => {
+                // Extensions bind local names to plugins
+                let plugin_name = self.extensions.get(object).map_or(object, String::as_str);
+                if self.plugins.has_plugin(plugin_name) {
                     // Convert arguments to HashMap
                     let mut args = HashMap::new();
                     for (i, arg) in arguments.iter().enumerate() {
@@ -493,12 +1606,11 @@ impl Runtime {
                         } else {
                             arg.name.clone()
                         };
-                        let arg_value =
-                            self.evaluate_expression_with_context(&arg.value, context)?;
+                        let arg_value = self.evaluate_in(&arg.value, context).await?;
                         args.insert(arg_name, arg_value);
                     }
-
-                    plugin.execute(method_name, &args)
+                    self.call_plugin(plugin_name, method_name, args, Retry::default())
+                        .await
                 } else {
                     Err(RuntimeError::Evaluation(format!(
                         "Plugin '{plugin_name}' not found"
@@ -519,6 +1631,57 @@ impl Runtime {
         }
     }
 
+    /// Call `plugin.method`, cancellably, and record the call in the log
+    async fn call_plugin(
+        &self,
+        plugin: &str,
+        method: &str,
+        args: HashMap<String, JsonValue>,
+        retry: Retry,
+    ) -> RuntimeResult<JsonValue> {
+        let started = Instant::now();
+        let operation = format!("{plugin}.{method}");
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            let result = limits::deadline(
+                retry.timeout(self.limits.plugin_timeout),
+                Limit::PluginTimeout,
+                &operation,
+                self.cancellation.run(
+                    &operation,
+                    self.plugins.call(plugin, method, &args, &self.cancellation),
+                ),
+            )
+            .await;
+            match result {
+                Err(error) if error.is_retryable() && attempts <= retry.retries => {
+                    let wait = retry.delay(attempts - 1);
+                    let waited = self
+                        .cancellation
+                        .run(&operation, async {
+                            tokio::time::sleep(wait).await;
+                            Ok(())
+                        })
+                        .await;
+                    if let Err(error) = waited {
+                        break Err(error);
+                    }
+                }
+                result => break result,
+            }
+        };
+        self.plugin_calls_mut().push(PluginCall {
+            plugin: plugin.to_string(),
+            method: method.to_string(),
+            arguments: args.into_iter().collect(),
+            duration_ms: report::millis(started.elapsed()),
+            attempts,
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+        result
+    }
+
     /// Evaluate a string template
     fn evaluate_string_template(
         &self,
@@ -532,18 +1695,15 @@ impl Runtime {
                 TemplatePart::Text(text) => {
                     result.push_str(text);
                 }
-                TemplatePart::Variable(var_name) => {
+                TemplatePart::Variable(path) => {
+                    let var_name = path.split('.').next().unwrap_or(path);
                     if let Some(value) = context.get(var_name) {
-                        match value {
-                            JsonValue::String(s) => result.push_str(s),
-                            JsonValue::Number(n) => result.push_str(&n.to_string()),
-                            JsonValue::Bool(b) => result.push_str(&b.to_string()),
-                            JsonValue::Null => result.push_str("null"),
-                            _ => result.push_str(&value.to_string()),
-                        }
+                        result.push_str(&Self::template_text(value, path, self.strict)?);
+                    } else if self.strict {
+                        return Err(strict::unresolved(var_name, Self::names_in(context)));
                     } else {
                         // Variable not found, include placeholder
-                        result.push_str(&format!("${{{var_name}}}"));
+                        result.push_str(&format!("${{{path}}}"));
                     }
                 }
             }
@@ -552,9 +1712,35 @@ impl Runtime {
         Ok(JsonValue::String(result))
     }
 
+    /// Text of the template variable `path`, whose first name has `value`
+    ///
+    /// A path through something other than objects gives a placeholder, or
+    /// fails when `strict`.
+    fn template_text(value: &JsonValue, path: &str, strict: bool) -> RuntimeResult<String> {
+        let mut value = value.clone();
+        for property in path.split('.').skip(1) {
+            match Self::perform_property_access(&value, property) {
+                Ok(found) => value = found,
+                Err(error) if strict => return Err(error),
+                Err(_) => return Ok(format!("${{{path}}}")),
+            }
+        }
+        Ok(match value {
+            JsonValue::String(s) => s,
+            value => value.to_string(),
+        })
+    }
+
+    /// Names of the variables in `context` that expressions can refer to
+    fn names_in(context: &HashMap<String, JsonValue>) -> impl Iterator<Item = &str> {
+        context
+            .keys()
+            .map(String::as_str)
+            .filter(|name| *name != functions::CALL_DEPTH)
+    }
+
     /// Perform arithmetic operations
     fn perform_arithmetic_operation(
-        &self,
         left: &JsonValue,
         right: &JsonValue,
         op: ArithmeticOp,
@@ -602,6 +1788,10 @@ impl Runtime {
             (JsonValue::String(l), JsonValue::String(r)) if matches!(op, ArithmeticOp::Add) => {
                 Ok(JsonValue::String(format!("{l}{r}")))
             }
+            (JsonValue::Null, _) | (_, JsonValue::Null) => Err(RuntimeError::Evaluation(
+                "Cannot perform arithmetic on a null value; use `??` to supply a default"
+                    .to_string(),
+            )),
             _ => Err(RuntimeError::Evaluation(format!(
                 "Cannot perform arithmetic operation on {left:?} and {right:?}"
             ))),
@@ -610,7 +1800,6 @@ impl Runtime {
 
     /// Perform comparison operations
     fn perform_comparison(
-        &self,
         left: &JsonValue,
         right: &JsonValue,
         op: ComparisonOp,
@@ -650,8 +1839,7 @@ impl Runtime {
     }
 
     /// Check if two values are equal
-    #[allow(clippy::only_used_in_recursion)]
-    fn values_equal(&self, left: &JsonValue, right: &JsonValue) -> bool {
+    fn values_equal(left: &JsonValue, right: &JsonValue) -> bool {
         match (left, right) {
             (JsonValue::Null, JsonValue::Null) => true,
             (JsonValue::Bool(l), JsonValue::Bool(r)) => l == r,
@@ -664,19 +1852,22 @@ impl Runtime {
             }
             (JsonValue::String(l), JsonValue::String(r)) => l == r,
             (JsonValue::Array(l), JsonValue::Array(r)) => {
-                l.len() == r.len() && l.iter().zip(r.iter()).all(|(a, b)| self.values_equal(a, b))
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|(a, b)| Self::values_equal(a, b))
             }
             (JsonValue::Object(l), JsonValue::Object(r)) => {
                 l.len() == r.len()
                     && l.iter()
-                        .all(|(k, v)| r.get(k).is_some_and(|rv| self.values_equal(v, rv)))
+                        .all(|(k, v)| r.get(k).is_some_and(|rv| Self::values_equal(v, rv)))
             }
             _ => false,
         }
     }
 
     /// Check if a value is truthy
-    fn is_truthy(&self, value: &JsonValue) -> bool {
+    fn is_truthy(value: &JsonValue) -> bool {
         match value {
             JsonValue::Null => false,
             JsonValue::Bool(b) => *b,
@@ -688,11 +1879,7 @@ impl Runtime {
     }
 
     /// Perform array access
-    fn perform_array_access(
-        &self,
-        array: &JsonValue,
-        index: &JsonValue,
-    ) -> RuntimeResult<JsonValue> {
+    fn perform_array_access(array: &JsonValue, index: &JsonValue) -> RuntimeResult<JsonValue> {
         match (array, index) {
             (JsonValue::Array(arr), JsonValue::Number(n)) => {
                 let idx = n.as_u64().ok_or_else(|| {
@@ -715,11 +1902,7 @@ impl Runtime {
     }
 
     /// Perform property access
-    fn perform_property_access(
-        &self,
-        object: &JsonValue,
-        property: &str,
-    ) -> RuntimeResult<JsonValue> {
+    fn perform_property_access(object: &JsonValue, property: &str) -> RuntimeResult<JsonValue> {
         match object {
             JsonValue::Object(obj) => Ok(obj.get(property).cloned().unwrap_or(JsonValue::Null)),
             _ => Err(RuntimeError::Evaluation(
@@ -728,138 +1911,395 @@ impl Runtime {
         }
     }
 
-    /// Process input fields
-    async fn process_inputs(&self, spec: &Spec) -> RuntimeResult<()> {
-        let mut context = self.context.write().await;
+    /// Process input fields, preferring supplied values over modifiers
+    async fn process_inputs(
+        &self,
+        spec: &Spec,
+        inputs: &serde_json::Map<String, JsonValue>,
+    ) -> RuntimeResult<()> {
+        if let Some(name) = inputs
+            .keys()
+            .find(|name| !spec.inputs.iter().any(|field| &field.name == *name))
+        {
+            return Err(RuntimeError::InvalidInput {
+                field: name.clone(),
+                message: "not declared in the spec's inputs".to_string(),
+            });
+        }
+
+        let mut context = self.context.write().await;
+        let mut missing = Vec::new();
+
+        for field in &spec.inputs {
+            let (field_value, generated) = match inputs.get(&field.name) {
+                Some(value) => {
+                    // Stored first so a secret that fails coercion is masked in the error
+                    context.variables.insert(field.name.clone(), value.clone());
+                    let value = inputs::coerce_input(
+                        value.clone(),
+                        &TypeChecker::input_type(field),
+                        &spec.types,
+                    )
+                    .map_err(|message| RuntimeError::InvalidInput {
+                        field: field.name.clone(),
+                        message,
+                    })?;
+                    (value, false)
+                }
+                None => match self.initial_value(field).await? {
+                    Some(value) if field.modifiers.contains(&Modifier::Generate) => (value, true),
+                    // Defaults are evaluated as floats; coercion restores declared ints
+                    Some(value) => {
+                        context.variables.insert(field.name.clone(), value.clone());
+                        let value = inputs::coerce_input(
+                            value,
+                            &TypeChecker::input_type(field),
+                            &spec.types,
+                        )
+                        .map_err(|message| RuntimeError::InvalidInput {
+                            field: field.name.clone(),
+                            message: format!("invalid default: {message}"),
+                        })?;
+                        (value, false)
+                    }
+                    None => {
+                        missing.push(field.name.clone());
+                        continue;
+                    }
+                },
+            };
+
+            // Generated placeholders are exempt; supplied values must satisfy refinements.
+            // The value is stored first so a failing secret is masked in the error.
+            let check = if generated {
+                Ok(())
+            } else {
+                TypeChecker::check_refinements(field, &field_value, &self.patterns)
+            };
+            context.variables.insert(field.name.clone(), field_value);
+            check.map_err(|message| RuntimeError::InvalidInput {
+                field: field.name.clone(),
+                message,
+            })?;
+        }
+
+        if !missing.is_empty() {
+            return Err(RuntimeError::MissingInputs(missing));
+        }
+
+        Ok(())
+    }
+
+    /// Value of an input that was not supplied, or `None` if it is required
+    async fn initial_value(&self, field: &FieldDef) -> RuntimeResult<Option<JsonValue>> {
+        let mut field_value = None;
 
-        for field in &spec.inputs {
-            let mut field_value = serde_json::Value::Null;
-
-            // Process field modifiers
-            for modifier in &field.modifiers {
-                match modifier {
-                    Modifier::Default(expr) => {
-                        // Evaluate default expression
-                        field_value = self.evaluate_expression(expr)?;
-                    }
-                    Modifier::Optional => {
-                        // Optional fields can remain null
-                    }
-                    Modifier::Secret => {
-                        // Mark as secret (affects logging/serialization)
-                        // For now, just process normally
-                    }
-                    Modifier::Generate => {
-                        // Generate a value based on type
-                        field_value = self.generate_value_for_type(&field.type_expr)?;
-                    }
-                    _ => {
-                        // Other modifiers don't affect initial value
-                    }
+        for modifier in &field.modifiers {
+            match modifier {
+                Modifier::Default(expr) => {
+                    // Evaluate default expression
+                    field_value = Some(self.evaluate_in(expr, &HashMap::new()).await?);
+                }
+                Modifier::Generate => {
+                    // Generate a value based on type
+                    field_value = Some(self.generate_value_for_type(&field.type_expr)?);
+                }
+                _ => {
+                    // Other modifiers don't affect initial value
                 }
             }
+        }
 
-            context.variables.insert(field.name.clone(), field_value);
+        // Optional fields and nullable types can remain null
+        if field_value.is_none()
+            && (field.modifiers.contains(&Modifier::Optional) || field.type_expr.is_nullable())
+        {
+            field_value = Some(JsonValue::Null);
         }
 
+        Ok(field_value)
+    }
+
+    /// Compute derived fields that do not have a value yet
+    ///
+    /// Fields only have one when a resumed execution restored it.
+    async fn compute_fields(&self) -> RuntimeResult<()> {
+        let (mut frame, steps) = {
+            let context = self.context.read().await;
+            let frame = self
+                .plan
+                .frame([&context.variables, &context.computed_cache]);
+            let steps: Vec<_> = self
+                .plan
+                .steps()
+                .iter()
+                .filter(|step| !context.computed_cache.contains_key(&step.name))
+                .collect();
+            (frame, steps)
+        };
+
+        // Later fields build on earlier ones through the frame
+        let computed_values = self.run_steps(&self.plan, &steps, &mut frame).await?;
+
+        // Update the computed cache
+        let mut context = self.context.write().await;
+        context.computed_cache.extend(computed_values);
+
         Ok(())
     }
 
-    /// Compute derived fields
-    async fn compute_fields(&self, spec: &Spec) -> RuntimeResult<()> {
-        let context_read = self.context.read().await;
-        let variable_context = context_read.variables.clone();
-        drop(context_read);
+    /// Evaluate every constraint of one kind, reporting all that fail
+    async fn check_constraints(
+        &self,
+        spec: &Spec,
+        constraint_type: ConstraintType,
+    ) -> RuntimeResult<()> {
+        let context = self.context.read().await;
+        let frame = self
+            .plan
+            .frame([&context.variables, &context.computed_cache]);
+
+        self.constraint_violations(spec, &frame, &context.secrets, |constraint| {
+            constraint.constraint_type == constraint_type
+        })
+        .await
+    }
 
-        let mut computed_values = HashMap::new();
+    /// Evaluate the constraints selected by `include` against `frame`
+    async fn constraint_violations(
+        &self,
+        spec: &Spec,
+        frame: &Frame,
+        secrets: &HashSet<String>,
+        include: impl Fn(&ConstraintDef) -> bool,
+    ) -> RuntimeResult<()> {
+        let mut violations = Vec::new();
+        // Operands of failed constraints are evaluated from the AST, by name
+        let mut bindings = None;
+        for (index, constraint) in spec.constraints.iter().enumerate() {
+            if !include(constraint) {
+                continue;
+            }
+            let Some(op) = self.plan.constraint(index) else {
+                continue;
+            };
+
+            let error = match self.evaluate_op(&self.plan, op, frame).await {
+                Ok(JsonValue::Bool(true)) => continue,
+                Ok(JsonValue::Bool(false)) => None,
+                Ok(other) => Some(format!("evaluated to {other}, not a boolean")),
+                Err(error) => Some(error.to_string()),
+            };
+
+            let values = bindings.get_or_insert_with(|| self.plan.bindings(frame));
+            let mut operands = BTreeMap::new();
+            for operand in constraints::operands(&constraint.expression) {
+                let secret = operand
+                    .referenced_identifiers()
+                    .iter()
+                    .any(|name| secrets.contains(*name));
+                let value = if secret {
+                    JsonValue::String(SECRET_MASK.to_string())
+                } else {
+                    match self.evaluate_in(operand, values).await {
+                        Ok(value) => Self::normalize_number(value),
+                        Err(_) => continue,
+                    }
+                };
+                operands.insert(operand.to_string(), value);
+            }
 
-        for computed in &spec.computed {
-            // Evaluate the computed expression with current variable context
-            let computed_value =
-                self.evaluate_expression_with_context(&computed.expression, &variable_context)?;
+            violations.push(ConstraintViolation {
+                index,
+                constraint_type: constraint.constraint_type.clone(),
+                source: format!("{} {}", constraint.constraint_type, constraint.expression),
+                span: self.source_map.get(&format!("constraints[{index}]")),
+                operands,
+                error,
+            });
+        }
 
-            computed_values.insert(computed.name.clone(), computed_value);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(RuntimeError::ConstraintsViolated(violations))
         }
+    }
 
-        // Update the computed cache
-        let mut context = self.context.write().await;
-        for (name, value) in computed_values {
-            context.computed_cache.insert(name, value);
+    /// Report whole floats as integers, as they were written in the spec
+    fn normalize_number(value: JsonValue) -> JsonValue {
+        match value.as_f64() {
+            Some(n) if value.is_f64() && n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                JsonValue::from(n as i64)
+            }
+            _ => value,
         }
+    }
 
+    /// Execute an event or lifecycle action against the values in `frame`
+    async fn run_action(&self, action: &CompiledAction, frame: &mut Frame) -> RuntimeResult<()> {
+        match action {
+            CompiledAction::Standard { action, arguments } => {
+                self.run_standard_action(*action, arguments, frame).await?
+            }
+            CompiledAction::Call(op) => {
+                self.evaluate_op(&self.plan, op, frame).await?;
+            }
+            CompiledAction::Assign {
+                target,
+                slot,
+                value,
+            } => {
+                let value = self.evaluate_op(&self.plan, value, frame).await?;
+                let is_input = self
+                    .spec
+                    .as_ref()
+                    .is_some_and(|spec| spec.inputs.iter().any(|field| &field.name == target));
+
+                // Inputs are updated as by `set_input`, so the value is checked
+                // and its dependents, watchers and `on_change` handlers follow
+                let value = if is_input {
+                    self.apply_input(target, value).await?;
+                    self.context.read().await.variables[target].clone()
+                } else {
+                    self.context
+                        .write()
+                        .await
+                        .variables
+                        .insert(target.clone(), value.clone());
+                    value
+                };
+                if let Some(slot) = slot {
+                    frame.set(*slot, value);
+                }
+            }
+        }
         Ok(())
     }
 
-    /// Execute lifecycle before phase
-    async fn execute_lifecycle_before(&self, spec: &Spec) -> RuntimeResult<()> {
-        for lifecycle in &spec.lifecycle {
-            if matches!(lifecycle.phase, LifecyclePhase::Before) {
-                // Execute the lifecycle action
-                match &lifecycle.action {
-                    Action::FunctionCall {
-                        object,
-                        method,
-                        arguments,
-                    } => {
-                        let context_read = self.context.read().await;
-                        let variable_context = context_read.variables.clone();
-                        drop(context_read);
-
-                        self.evaluate_function_call(object, method, arguments, &variable_context)?;
-                    }
-                    Action::Identifier(name) => {
-                        // Execute identifier as a function call with no arguments
-                        let context_read = self.context.read().await;
-                        let variable_context = context_read.variables.clone();
-                        drop(context_read);
-
-                        // For now, treat identifier as a simple function call
-                        let empty_args = vec![];
-                        self.evaluate_function_call(
-                            "builtin",
-                            name,
-                            &empty_args,
-                            &variable_context,
+    /// Run `validate`, `cleanup` or `log`
+    async fn run_standard_action(
+        &self,
+        action: StandardAction,
+        arguments: &[engine::Op],
+        frame: &Frame,
+    ) -> RuntimeResult<()> {
+        let spec = self.spec.as_ref().ok_or_else(|| {
+            RuntimeError::Lifecycle("No specification has been executed".to_string())
+        })?;
+
+        match action {
+            StandardAction::Log => {
+                let mut parts = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    parts.push(match self.evaluate_op(&self.plan, argument, frame).await? {
+                        JsonValue::String(s) => s,
+                        other => other.to_string(),
+                    });
+                }
+                self.context.write().await.logs.push(parts.join(" "));
+            }
+            StandardAction::Cleanup => {
+                let declared: HashSet<&str> = spec
+                    .inputs
+                    .iter()
+                    .map(|field| field.name.as_str())
+                    .chain(spec.computed.iter().map(|field| field.name.as_str()))
+                    .collect();
+                self.context
+                    .write()
+                    .await
+                    .variables
+                    .retain(|name, _| declared.contains(name.as_str()));
+            }
+            StandardAction::Validate => {
+                TypeChecker::new()
+                    .with_functions(self.builtins.signatures())
+                    .validate_spec(spec)
+                    .map_err(|error| {
+                        RuntimeError::Lifecycle(format!("Validation failed: {error}"))
+                    })?;
+
+                let context = self.context.read().await;
+                for field in &spec.inputs {
+                    if let Some(value) = context.variables.get(&field.name) {
+                        TypeChecker::check_refinements(field, value, &self.patterns).map_err(
+                            |message| RuntimeError::InvalidInput {
+                                field: field.name.clone(),
+                                message,
+                            },
                         )?;
                     }
                 }
+
+                // Only constraints whose fields are all bound can be judged yet
+                let frame = self
+                    .plan
+                    .frame([&context.variables, &context.computed_cache]);
+                self.constraint_violations(spec, &frame, &context.secrets, |constraint| {
+                    constraint
+                        .expression
+                        .referenced_identifiers()
+                        .iter()
+                        .all(|name| {
+                            self.plan
+                                .slot(name)
+                                .is_some_and(|slot| frame.get(slot).is_some())
+                        })
+                })
+                .await?;
             }
         }
         Ok(())
     }
 
-    /// Execute lifecycle after phase
-    async fn execute_lifecycle_after(&self, spec: &Spec) -> RuntimeResult<()> {
-        for lifecycle in &spec.lifecycle {
-            if matches!(lifecycle.phase, LifecyclePhase::After) {
-                // Execute the lifecycle action
-                match &lifecycle.action {
-                    Action::FunctionCall {
-                        object,
-                        method,
-                        arguments,
-                    } => {
-                        let context_read = self.context.read().await;
-                        let variable_context = context_read.variables.clone();
-                        drop(context_read);
-
-                        self.evaluate_function_call(object, method, arguments, &variable_context)?;
-                    }
-                    Action::Identifier(name) => {
-                        // Execute identifier as a function call with no arguments
-                        let context_read = self.context.read().await;
-                        let variable_context = context_read.variables.clone();
-                        drop(context_read);
-
-                        // For now, treat identifier as a simple function call
-                        let empty_args = vec![];
-                        self.evaluate_function_call(
-                            "builtin",
-                            name,
-                            &empty_args,
-                            &variable_context,
-                        )?;
-                    }
+    /// Reject event and lifecycle actions that assign to readonly inputs, and
+    /// `before` actions that assign to any input
+    fn check_readonly_assignments(&self, spec: &Spec) -> RuntimeResult<()> {
+        let readonly: HashSet<&str> = spec
+            .inputs
+            .iter()
+            .filter(|field| field.modifiers.contains(&Modifier::Readonly))
+            .map(|field| field.name.as_str())
+            .collect();
+
+        let events = spec
+            .events
+            .iter()
+            .enumerate()
+            .map(|(i, event)| (format!("events[{i}]"), &event.action));
+        let lifecycle = spec
+            .lifecycle
+            .iter()
+            .enumerate()
+            .map(|(i, lifecycle)| (format!("lifecycle[{i}]"), &lifecycle.action));
+
+        for (location, action) in events.chain(lifecycle) {
+            if let Action::Assign { target, .. } = action {
+                if readonly.contains(target.as_str()) {
+                    return Err(RuntimeError::ReadonlyAssignment {
+                        field: target.clone(),
+                        span: self.source_map.get(&location),
+                        location,
+                    });
+                }
+            }
+        }
+
+        // `before` runs ahead of the inputs phase, which would overwrite it
+        for (index, lifecycle) in spec.lifecycle.iter().enumerate() {
+            if let (LifecyclePhase::Before, Action::Assign { target, .. }) =
+                (&lifecycle.phase, &lifecycle.action)
+            {
+                if spec.inputs.iter().any(|field| &field.name == target) {
+                    let location = format!("lifecycle[{index}]");
+                    return Err(RuntimeError::Lifecycle(format!(
+                        "Cannot assign to input '{target}' in {location}{}: `before` runs before inputs are bound",
+                        self.source_map
+                            .get(&location)
+                            .map(|span| format!(" (at {span})"))
+                            .unwrap_or_default()
+                    )));
                 }
             }
         }
@@ -889,10 +2329,53 @@ impl Runtime {
                 // For user-defined types, generate null for now
                 Ok(JsonValue::Null)
             }
+            TypeExpr::Nullable(inner) => self.generate_value_for_type(inner),
         }
     }
 }
 
+/// Calls the builtins and lambdas passed to higher-order builtins
+impl Invoker for Runtime {
+    fn invoke<'a>(
+        &'a self,
+        function: &'a Callable,
+        args: Vec<JsonValue>,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        Box::pin(async move {
+            match function {
+                Callable::Builtin(name) => {
+                    let builtin = self.builtins.get(name).ok_or_else(|| {
+                        RuntimeError::Evaluation(format!("Unknown function: {name}"))
+                    })?;
+                    builtin
+                        .call(name, args.into_iter().map(Arg::Value).collect(), self)
+                        .await
+                }
+                Callable::Lambda {
+                    parameters,
+                    body,
+                    captured,
+                } => {
+                    if parameters.len() != args.len() {
+                        return Err(RuntimeError::Evaluation(format!(
+                            "Lambda ({}) takes {} argument(s), got {}",
+                            parameters.join(", "),
+                            parameters.len(),
+                            args.len()
+                        )));
+                    }
+                    let mut scope = captured.clone();
+                    scope.extend(parameters.iter().cloned().zip(args));
+                    self.evaluate_in(body, &scope).await
+                }
+                Callable::Compiled(_) => Err(RuntimeError::Evaluation(
+                    "Compiled functions can only be called while their plan runs".to_string(),
+                )),
+            }
+        })
+    }
+}
+
 impl Default for Runtime {
     fn default() -> Self {
         Self::new()
@@ -902,12 +2385,15 @@ impl Default for Runtime {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TestPlugin;
+    use serde_json::json;
     use sigmos_core::ast::{Spec, Version};
+    use sigmos_core::parser::SigmosParser;
 
     #[tokio::test]
     async fn test_runtime_creation() {
         let runtime = Runtime::new();
-        assert!(runtime.plugins.is_empty());
+        assert!(runtime.plugins.plugin_names().is_empty());
     }
 
     #[tokio::test]
@@ -928,13 +2414,116 @@ mod tests {
             lifecycle: vec![],
             extensions: vec![],
             types: vec![],
+            functions: vec![],
+        };
+
+        runtime.execute(&spec).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_masked_in_dumps_and_errors() {
+        let mut runtime = Runtime::new();
+        let spec = Spec {
+            name: "Agent".to_string(),
+            version: Version {
+                major: 1,
+                minor: 0,
+                patch: None,
+            },
+            description: None,
+            inputs: vec![FieldDef {
+                name: "api_key".to_string(),
+                type_expr: TypeExpr::Primitive(PrimitiveType::String),
+                modifiers: vec![
+                    Modifier::Secret,
+                    Modifier::Default(Expression::StringLiteral("sk-live-123".to_string())),
+                ],
+            }],
+            computed: vec![ComputedField {
+                name: "broken".to_string(),
+                expression: Expression::Multiply(
+                    Box::new(Expression::Identifier("api_key".to_string())),
+                    Box::new(Expression::Number(2.0)),
+                ),
+                policy: None,
+            }],
+            events: vec![],
+            constraints: vec![],
+            lifecycle: vec![],
+            extensions: vec![],
+            types: vec![],
+            functions: vec![],
         };
 
+        let error = runtime.execute(&spec).await.unwrap_err();
+        assert!(!error.to_string().contains("sk-live-123"));
+        assert!(error.to_string().contains(SECRET_MASK));
+
+        let dump = format!("{:?}", runtime.context.read().await);
+        assert!(!dump.contains("sk-live-123"));
+        assert!(dump.contains("api_key"));
+    }
+
+    #[tokio::test]
+    async fn test_readonly_fields_reject_assignment() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            inputs:
+                owner: string { readonly: true, default: "ops" }
+                status: string { default: "idle" }
+            events:
+                on_error(failure): owner = "intruder"
+            lifecycle:
+                after: status = "running"
+        }
+        "#;
+        let (spec, source_map) = SigmosParser::parse_spec_with_source_map(input).unwrap();
+
+        let mut runtime = Runtime::new().with_source_map(source_map);
+        match runtime.execute(&spec).await.unwrap_err() {
+            RuntimeError::ReadonlyAssignment {
+                field,
+                location,
+                span,
+            } => {
+                assert_eq!(field, "owner");
+                assert_eq!(location, "events[0]");
+                assert_eq!(span.map(|span| span.line), Some(7));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+
+        // Assignments to writable fields are applied
+        let mut spec = spec;
+        spec.events.clear();
+        let mut runtime = Runtime::new();
         runtime.execute(&spec).await.unwrap();
+        let context = runtime.context.read().await;
+        assert_eq!(context.variables["status"], json!("running"));
+    }
+
+    #[tokio::test]
+    async fn test_refinements_are_enforced_on_inputs() {
+        let input = r#"
+        spec "Trading" v1.0 {
+            inputs:
+                quantity: int { min: 1, default: 0 }
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+
+        let mut runtime = Runtime::new();
+        match runtime.execute(&spec).await.unwrap_err() {
+            RuntimeError::InvalidInput { field, message } => {
+                assert_eq!(field, "quantity");
+                assert!(message.contains("less than the minimum 1"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
     }
 
-    #[test]
-    fn test_enhanced_arithmetic_expressions() {
+    #[tokio::test]
+    async fn test_enhanced_arithmetic_expressions() {
         let runtime = Runtime::new();
 
         // Test addition
@@ -942,7 +2531,7 @@ mod tests {
             Box::new(Expression::Number(5.0)),
             Box::new(Expression::Number(3.0)),
         );
-        let result = runtime.evaluate_expression(&add_expr).unwrap();
+        let result = runtime.evaluate_expression(&add_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from_f64(8.0).unwrap())
@@ -953,7 +2542,7 @@ mod tests {
             Box::new(Expression::StringLiteral("Hello ".to_string())),
             Box::new(Expression::StringLiteral("World".to_string())),
         );
-        let result = runtime.evaluate_expression(&concat_expr).unwrap();
+        let result = runtime.evaluate_expression(&concat_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::String("Hello World".to_string()));
 
         // Test subtraction
@@ -961,7 +2550,7 @@ mod tests {
             Box::new(Expression::Number(10.0)),
             Box::new(Expression::Number(4.0)),
         );
-        let result = runtime.evaluate_expression(&sub_expr).unwrap();
+        let result = runtime.evaluate_expression(&sub_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from_f64(6.0).unwrap())
@@ -972,7 +2561,7 @@ mod tests {
             Box::new(Expression::Number(3.0)),
             Box::new(Expression::Number(4.0)),
         );
-        let result = runtime.evaluate_expression(&mul_expr).unwrap();
+        let result = runtime.evaluate_expression(&mul_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from_f64(12.0).unwrap())
@@ -983,7 +2572,7 @@ mod tests {
             Box::new(Expression::Number(15.0)),
             Box::new(Expression::Number(3.0)),
         );
-        let result = runtime.evaluate_expression(&div_expr).unwrap();
+        let result = runtime.evaluate_expression(&div_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from_f64(5.0).unwrap())
@@ -994,15 +2583,15 @@ mod tests {
             Box::new(Expression::Number(17.0)),
             Box::new(Expression::Number(5.0)),
         );
-        let result = runtime.evaluate_expression(&mod_expr).unwrap();
+        let result = runtime.evaluate_expression(&mod_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from_f64(2.0).unwrap())
         );
     }
 
-    #[test]
-    fn test_enhanced_comparison_expressions() {
+    #[tokio::test]
+    async fn test_enhanced_comparison_expressions() {
         let runtime = Runtime::new();
 
         // Test less than
@@ -1010,7 +2599,7 @@ mod tests {
             Box::new(Expression::Number(3.0)),
             Box::new(Expression::Number(5.0)),
         );
-        let result = runtime.evaluate_expression(&lt_expr).unwrap();
+        let result = runtime.evaluate_expression(&lt_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(true));
 
         // Test greater than
@@ -1018,7 +2607,7 @@ mod tests {
             Box::new(Expression::Number(7.0)),
             Box::new(Expression::Number(4.0)),
         );
-        let result = runtime.evaluate_expression(&gt_expr).unwrap();
+        let result = runtime.evaluate_expression(&gt_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(true));
 
         // Test equal
@@ -1026,7 +2615,7 @@ mod tests {
             Box::new(Expression::StringLiteral("test".to_string())),
             Box::new(Expression::StringLiteral("test".to_string())),
         );
-        let result = runtime.evaluate_expression(&eq_expr).unwrap();
+        let result = runtime.evaluate_expression(&eq_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(true));
 
         // Test not equal
@@ -1034,12 +2623,12 @@ mod tests {
             Box::new(Expression::Number(5.0)),
             Box::new(Expression::Number(3.0)),
         );
-        let result = runtime.evaluate_expression(&ne_expr).unwrap();
+        let result = runtime.evaluate_expression(&ne_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(true));
     }
 
-    #[test]
-    fn test_enhanced_logical_expressions() {
+    #[tokio::test]
+    async fn test_enhanced_logical_expressions() {
         let runtime = Runtime::new();
 
         // Test AND - both true
@@ -1047,7 +2636,7 @@ mod tests {
             Box::new(Expression::Boolean(true)),
             Box::new(Expression::Boolean(true)),
         );
-        let result = runtime.evaluate_expression(&and_expr).unwrap();
+        let result = runtime.evaluate_expression(&and_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(true));
 
         // Test AND - one false
@@ -1055,7 +2644,7 @@ mod tests {
             Box::new(Expression::Boolean(true)),
             Box::new(Expression::Boolean(false)),
         );
-        let result = runtime.evaluate_expression(&and_false_expr).unwrap();
+        let result = runtime.evaluate_expression(&and_false_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(false));
 
         // Test OR - one true
@@ -1063,17 +2652,17 @@ mod tests {
             Box::new(Expression::Boolean(false)),
             Box::new(Expression::Boolean(true)),
         );
-        let result = runtime.evaluate_expression(&or_expr).unwrap();
+        let result = runtime.evaluate_expression(&or_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(true));
 
         // Test NOT
         let not_expr = Expression::Not(Box::new(Expression::Boolean(false)));
-        let result = runtime.evaluate_expression(&not_expr).unwrap();
+        let result = runtime.evaluate_expression(&not_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::Bool(true));
     }
 
-    #[test]
-    fn test_enhanced_conditional_expressions() {
+    #[tokio::test]
+    async fn test_enhanced_conditional_expressions() {
         let runtime = Runtime::new();
 
         // Test conditional - true condition
@@ -1082,7 +2671,7 @@ mod tests {
             if_true: Box::new(Expression::StringLiteral("yes".to_string())),
             if_false: Box::new(Expression::StringLiteral("no".to_string())),
         };
-        let result = runtime.evaluate_expression(&cond_true_expr).unwrap();
+        let result = runtime.evaluate_expression(&cond_true_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::String("yes".to_string()));
 
         // Test conditional - false condition
@@ -1091,15 +2680,15 @@ mod tests {
             if_true: Box::new(Expression::Number(1.0)),
             if_false: Box::new(Expression::Number(2.0)),
         };
-        let result = runtime.evaluate_expression(&cond_false_expr).unwrap();
+        let result = runtime.evaluate_expression(&cond_false_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from_f64(2.0).unwrap())
         );
     }
 
-    #[test]
-    fn test_enhanced_builtin_functions() {
+    #[tokio::test]
+    async fn test_enhanced_builtin_functions() {
         let runtime = Runtime::new();
 
         // Test len() function
@@ -1111,7 +2700,7 @@ mod tests {
                 value: Expression::StringLiteral("hello".to_string()),
             }],
         };
-        let result = runtime.evaluate_expression(&len_expr).unwrap();
+        let result = runtime.evaluate_expression(&len_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from(5))
@@ -1126,7 +2715,7 @@ mod tests {
                 value: Expression::StringLiteral("hello".to_string()),
             }],
         };
-        let result = runtime.evaluate_expression(&upper_expr).unwrap();
+        let result = runtime.evaluate_expression(&upper_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::String("HELLO".to_string()));
 
         // Test lower() function
@@ -1138,7 +2727,7 @@ mod tests {
                 value: Expression::StringLiteral("WORLD".to_string()),
             }],
         };
-        let result = runtime.evaluate_expression(&lower_expr).unwrap();
+        let result = runtime.evaluate_expression(&lower_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::String("world".to_string()));
 
         // Test trim() function
@@ -1150,7 +2739,7 @@ mod tests {
                 value: Expression::StringLiteral("  test  ".to_string()),
             }],
         };
-        let result = runtime.evaluate_expression(&trim_expr).unwrap();
+        let result = runtime.evaluate_expression(&trim_expr).await.unwrap();
         assert_eq!(result, serde_json::Value::String("test".to_string()));
 
         // Test abs() function
@@ -1162,15 +2751,15 @@ mod tests {
                 value: Expression::Number(-5.5),
             }],
         };
-        let result = runtime.evaluate_expression(&abs_expr).unwrap();
+        let result = runtime.evaluate_expression(&abs_expr).await.unwrap();
         assert_eq!(
             result,
             serde_json::Value::Number(serde_json::Number::from_f64(5.5).unwrap())
         );
     }
 
-    #[test]
-    fn test_enhanced_string_templates() {
+    #[tokio::test]
+    async fn test_enhanced_string_templates() {
         let runtime = Runtime::new();
         let mut context = std::collections::HashMap::new();
         context.insert(
@@ -1194,6 +2783,7 @@ mod tests {
         };
         let result = runtime
             .evaluate_expression_with_context(&template_expr, &context)
+            .await
             .unwrap();
         assert_eq!(
             result,
@@ -1210,6 +2800,7 @@ mod tests {
         };
         let result = runtime
             .evaluate_expression_with_context(&template_missing_expr, &context)
+            .await
             .unwrap();
         assert_eq!(
             result,
@@ -1217,8 +2808,8 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_enhanced_variable_resolution() {
+    #[tokio::test]
+    async fn test_enhanced_variable_resolution() {
         let runtime = Runtime::new();
         let mut context = std::collections::HashMap::new();
         context.insert(
@@ -1234,6 +2825,7 @@ mod tests {
         let var_expr = Expression::Identifier("x".to_string());
         let result = runtime
             .evaluate_expression_with_context(&var_expr, &context)
+            .await
             .unwrap();
         assert_eq!(
             result,
@@ -1244,6 +2836,7 @@ mod tests {
         let str_var_expr = Expression::Identifier("greeting".to_string());
         let result = runtime
             .evaluate_expression_with_context(&str_var_expr, &context)
+            .await
             .unwrap();
         assert_eq!(result, serde_json::Value::String("Hello".to_string()));
 
@@ -1251,12 +2844,13 @@ mod tests {
         let unknown_expr = Expression::Identifier("unknown".to_string());
         let result = runtime
             .evaluate_expression_with_context(&unknown_expr, &context)
+            .await
             .unwrap();
         assert_eq!(result, serde_json::Value::String("${unknown}".to_string()));
     }
 
-    #[test]
-    fn test_enhanced_complex_expressions() {
+    #[tokio::test]
+    async fn test_enhanced_complex_expressions() {
         let runtime = Runtime::new();
         let mut context = std::collections::HashMap::new();
         context.insert(
@@ -1278,6 +2872,7 @@ mod tests {
         );
         let result = runtime
             .evaluate_expression_with_context(&complex_expr, &context)
+            .await
             .unwrap();
         assert_eq!(
             result,
@@ -1295,12 +2890,64 @@ mod tests {
         };
         let result = runtime
             .evaluate_expression_with_context(&complex_cond, &context)
+            .await
             .unwrap();
         assert_eq!(result, serde_json::Value::String("greater".to_string()));
     }
 
-    #[test]
-    fn test_enhanced_error_handling() {
+    #[tokio::test]
+    async fn test_null_safety_operators() {
+        let runtime = Runtime::new();
+        let mut context = std::collections::HashMap::new();
+        context.insert("discount".to_string(), serde_json::Value::Null);
+        context.insert("user".to_string(), serde_json::json!({ "name": "Ada" }));
+
+        // `??` falls back only when the left side is null
+        let coalesce = Expression::Coalesce(
+            Box::new(Expression::Identifier("discount".to_string())),
+            Box::new(Expression::Number(0.0)),
+        );
+        let result = runtime
+            .evaluate_expression_with_context(&coalesce, &context)
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!(0.0));
+
+        // `?.` short-circuits on null and reads properties otherwise
+        let missing = Expression::OptionalPropertyAccess(
+            Box::new(Expression::Identifier("discount".to_string())),
+            "amount".to_string(),
+        );
+        let result = runtime
+            .evaluate_expression_with_context(&missing, &context)
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::Value::Null);
+
+        let present = Expression::OptionalPropertyAccess(
+            Box::new(Expression::Identifier("user".to_string())),
+            "name".to_string(),
+        );
+        let result = runtime
+            .evaluate_expression_with_context(&present, &context)
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!("Ada"));
+
+        // Arithmetic on an unguarded null is an error
+        let unguarded = Expression::Add(
+            Box::new(Expression::Identifier("discount".to_string())),
+            Box::new(Expression::Number(1.0)),
+        );
+        let err = runtime
+            .evaluate_expression_with_context(&unguarded, &context)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("null"));
+    }
+
+    #[tokio::test]
+    async fn test_enhanced_error_handling() {
         let runtime = Runtime::new();
 
         // Test division by zero
@@ -1308,7 +2955,7 @@ mod tests {
             Box::new(Expression::Number(10.0)),
             Box::new(Expression::Number(0.0)),
         );
-        let result = runtime.evaluate_expression(&div_zero_expr);
+        let result = runtime.evaluate_expression(&div_zero_expr).await;
         assert!(result.is_err());
 
         // Test modulo by zero
@@ -1316,7 +2963,7 @@ mod tests {
             Box::new(Expression::Number(10.0)),
             Box::new(Expression::Number(0.0)),
         );
-        let result = runtime.evaluate_expression(&mod_zero_expr);
+        let result = runtime.evaluate_expression(&mod_zero_expr).await;
         assert!(result.is_err());
 
         // Test invalid function
@@ -1325,7 +2972,7 @@ mod tests {
             method: "nonexistent".to_string(),
             arguments: vec![],
         };
-        let result = runtime.evaluate_expression(&invalid_func_expr);
+        let result = runtime.evaluate_expression(&invalid_func_expr).await;
         assert!(result.is_err());
 
         // Test len() with wrong argument type
@@ -1337,7 +2984,590 @@ mod tests {
                 value: Expression::Number(42.0),
             }],
         };
-        let result = runtime.evaluate_expression(&invalid_len_expr);
+        let result = runtime.evaluate_expression(&invalid_len_expr).await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_supplied_inputs_are_coerced_and_validated() {
+        let input = r#"
+        spec "Trading" v1.0 {
+            inputs:
+                symbol: string
+                quantity: int { min: 1 }
+                limit: float { optional: true }
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("symbol".to_string(), json!("ACME"));
+        inputs.insert("quantity".to_string(), json!("5"));
+
+        let mut runtime = Runtime::new();
+        runtime.execute_with_inputs(&spec, inputs).await.unwrap();
+        let context = runtime.context.read().await;
+        assert_eq!(context.variables.get("quantity"), Some(&json!(5)));
+        assert_eq!(context.variables.get("limit"), Some(&JsonValue::Null));
+        drop(context);
+
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("symbol".to_string(), json!("ACME"));
+        inputs.insert("quantity".to_string(), json!("lots"));
+        match runtime
+            .execute_with_inputs(&spec, inputs)
+            .await
+            .unwrap_err()
+        {
+            RuntimeError::InvalidInput { field, message } => {
+                assert_eq!(field, "quantity");
+                assert!(message.contains("expected an int"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("quantity".to_string(), json!(0));
+        inputs.insert("symbol".to_string(), json!("ACME"));
+        assert!(matches!(
+            runtime.execute_with_inputs(&spec, inputs).await,
+            Err(RuntimeError::InvalidInput { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_and_undeclared_inputs_are_reported() {
+        let input = r#"
+        spec "Trading" v1.0 {
+            inputs:
+                symbol: string
+                quantity: int
+                side: string { default: "buy" }
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+
+        let mut runtime = Runtime::new();
+        let error = runtime.execute(&spec).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Missing required inputs: symbol, quantity"
+        );
+
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("symbl".to_string(), json!("ACME"));
+        match runtime
+            .execute_with_inputs(&spec, inputs)
+            .await
+            .unwrap_err()
+        {
+            RuntimeError::InvalidInput { field, .. } => assert_eq!(field, "symbl"),
+            other => panic!("unexpected error: {other}"),
+        }
+
+        // A default that does not coerce to the declared type is not kept as-is
+        let spec = SigmosParser::parse_spec(
+            r#"
+            spec "Trading" v1.0 {
+                inputs:
+                    quantity: int { default: 5.5 }
+            }
+            "#,
+        )
+        .unwrap();
+        match runtime.execute(&spec).await.unwrap_err() {
+            RuntimeError::InvalidInput { field, message } => {
+                assert_eq!(field, "quantity");
+                assert_eq!(message, "invalid default: expected an int, found 5.5");
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_example_enforces_its_constraints() {
+        let spec =
+            SigmosParser::parse_spec(include_str!("../../../examples/agent.sigmos")).unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&calls);
+        let mut runtime = Runtime::new();
+        runtime
+            .register_async_plugin(Box::new(
+                TestPlugin::new("mcp", move |method, args| {
+                    recorded
+                        .lock()
+                        .unwrap()
+                        .push((method.to_string(), args["arg_1"].clone()));
+                    Ok(JsonValue::Null)
+                })
+                .with_version("1.0.0"),
+            ))
+            .unwrap();
+        let inputs = |tone: &str, max_tokens: i64| {
+            let mut inputs = serde_json::Map::new();
+            inputs.insert("name".to_string(), json!("Ada"));
+            inputs.insert("tone".to_string(), json!(tone));
+            inputs.insert("api_key".to_string(), json!("sk-test"));
+            inputs.insert("max_tokens".to_string(), json!(max_tokens));
+            inputs
+        };
+
+        match runtime
+            .execute_with_inputs(&spec, inputs("friendly", 5000))
+            .await
+            .unwrap_err()
+        {
+            RuntimeError::ConstraintsViolated(violations) => {
+                assert_eq!(violations.len(), 1);
+                assert_eq!(violations[0].source, "assert max_tokens <= 4000");
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+        assert!(matches!(
+            runtime
+                .execute_with_inputs(&spec, inputs("sarcastic", 100))
+                .await
+                .unwrap_err(),
+            RuntimeError::InvalidInput { field, .. } if field == "tone"
+        ));
+
+        runtime
+            .execute_with_inputs(&spec, inputs("friendly", 100))
+            .await
+            .unwrap();
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "call");
+        assert_eq!(calls[0].1["auth"], json!("sk-test"));
+        assert_eq!(calls[0].1["payload"]["config"]["max_tokens"], json!(100));
+    }
+
+    #[tokio::test]
+    async fn test_constraint_violations_are_collected() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            inputs:
+                name: string { default: "" }
+                max_tokens: int { default: 5000 }
+                api_key: string { secret: true, default: "sk-live-123" }
+            computed:
+                budget: -> max_tokens * 2
+            constraints:
+                assert name != ""
+                assert max_tokens <= 4000
+                assert api_key != "sk-live-123"
+                ensure budget < 100
+        }
+        "#;
+        let (spec, source_map) = SigmosParser::parse_spec_with_source_map(input).unwrap();
+
+        let mut runtime = Runtime::new().with_source_map(source_map);
+        let violations = match runtime.execute(&spec).await.unwrap_err() {
+            RuntimeError::ConstraintsViolated(violations) => violations,
+            other => panic!("unexpected error: {other}"),
+        };
+        // `ensure` is not reached while `assert` constraints fail
+        assert_eq!(violations.len(), 3);
+        assert_eq!(violations[0].operands["name"], json!(""));
+        assert_eq!(violations[1].source, "assert max_tokens <= 4000");
+        assert_eq!(violations[1].operands["max_tokens"], json!(5000));
+        assert_eq!(violations[1].span.unwrap().line, 11);
+        assert_eq!(violations[2].operands["api_key"], json!(SECRET_MASK));
+        assert!(!format!("{violations:?}").contains("sk-live-123"));
+
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("name".to_string(), json!("Ada"));
+        inputs.insert("max_tokens".to_string(), json!(100));
+        inputs.insert("api_key".to_string(), json!("sk-test"));
+        let violations = match runtime.execute_with_inputs(&spec, inputs).await {
+            Err(RuntimeError::ConstraintsViolated(violations)) => violations,
+            other => panic!("unexpected result: {other:?}"),
+        };
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].constraint_type, ConstraintType::Ensure);
+        assert_eq!(violations[0].operands["budget"], json!(200));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_reports_values_calls_and_timings() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            inputs:
+                name: string { default: "Ada" }
+                api_key: string { secret: true, default: "sk-live-123" }
+            computed:
+                greeting: -> "Hello, {{name}}"
+                sent: -> echo.send(token: api_key, to: name)
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+
+        let mut runtime = Runtime::new();
+        let echo = TestPlugin::new("echo", |method, args| match method {
+            "send" => Ok(json!(args.len())),
+            _ => Err(RuntimeError::Plugin(format!("Unknown method: {method}"))),
+        });
+        runtime.register_async_plugin(Box::new(echo)).unwrap();
+        runtime.execute(&spec).await.unwrap();
+
+        let report = runtime.snapshot().await;
+        assert_eq!(report.spec, "Agent");
+        assert_eq!(report.version, "1.0");
+        assert_eq!(report.state, "completed");
+        assert_eq!(report.inputs["api_key"], json!(SECRET_MASK));
+        assert_eq!(report.computed["greeting"], json!("Hello, Ada"));
+        assert_eq!(report.plugin_calls.len(), 1);
+        assert_eq!(report.plugin_calls[0].method, "send");
+        assert_eq!(
+            report.plugin_calls[0].arguments["token"],
+            json!(SECRET_MASK)
+        );
+        assert_eq!(report.plugin_calls[0].arguments["to"], json!("Ada"));
+        let phases: Vec<&str> = report
+            .timings
+            .phases
+            .iter()
+            .map(|timing| timing.phase.as_str())
+            .collect();
+        assert_eq!(
+            phases,
+            vec![
+                "before",
+                "inputs",
+                "computed",
+                "constraints",
+                "after",
+                "finally"
+            ]
+        );
+        assert!(!serde_json::to_string(&report)
+            .unwrap()
+            .contains("sk-live-123"));
+
+        let spec = SigmosParser::parse_spec(
+            r#"spec "Agent" v1.0 { inputs: name: string constraints: assert name != "" }"#,
+        )
+        .unwrap();
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("name".to_string(), json!(""));
+        runtime
+            .execute_with_inputs(&spec, inputs)
+            .await
+            .unwrap_err();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.state, "failed");
+        assert_eq!(report.violations.len(), 1);
+        assert!(report.plugin_calls.is_empty());
+        assert!(report.computed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plugin_calls_are_awaited_and_cancellable() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+            spec "Agent" v1.0 {
+                computed:
+                    reply: -> stall.wait()
+            }
+            "#,
+        )
+        .unwrap();
+
+        let token = CancellationToken::new();
+        let mut runtime = Runtime::new().with_cancellation(token.clone());
+        runtime
+            .register_async_plugin(Box::new(TestPlugin::awaiting("stall", |_, _| {
+                std::future::pending()
+            })))
+            .unwrap();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            token.cancel();
+        });
+        let error = runtime.execute(&spec).await.unwrap_err();
+        assert!(matches!(error, RuntimeError::Cancelled(ref op) if op == "stall.wait"));
+
+        let report = runtime.snapshot().await;
+        assert_eq!(report.plugin_calls.len(), 1);
+        assert_eq!(
+            report.plugin_calls[0].error.as_deref(),
+            Some("Cancelled: stall.wait")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_events_fire_with_bound_payloads() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            inputs:
+                name: string
+                retries: int { default: 0 }
+            events:
+                on_create(agent): created_for = agent.name
+                on_change(retries): changed_retries = retries
+                on_change(name): changed_name = name
+                on_error(error): failure = error
+                on_retry(count): retries = count
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let mut runtime = Runtime::new();
+
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("name".to_string(), json!("Ada"));
+        runtime
+            .execute_with_inputs(&spec, inputs.clone())
+            .await
+            .unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["created_for"], json!("Ada"));
+        assert!(!report.inputs.contains_key("changed_retries"));
+
+        // Custom events bind the payload; assigning an input fires its on_change
+        runtime.emit("on_retry", json!(3)).await.unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["retries"], json!(3));
+        assert_eq!(report.inputs["changed_retries"], json!(3));
+        assert!(!report.inputs.contains_key("changed_name"));
+
+        // Re-running with a different input fires its on_change, not on_create
+        inputs.insert("name".to_string(), json!("Grace"));
+        inputs.insert("retries".to_string(), json!(3));
+        runtime.execute_with_inputs(&spec, inputs).await.unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["changed_name"], json!("Grace"));
+        assert!(!report.inputs.contains_key("changed_retries"));
+        assert!(!report.inputs.contains_key("created_for"));
+
+        runtime.execute(&spec).await.unwrap_err();
+        let report = runtime.snapshot().await;
+        assert_eq!(
+            report.inputs["failure"],
+            json!("Missing required inputs: name")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_loops_are_cut_off() {
+        let input = r#"
+        spec "Loop" v1.0 {
+            inputs:
+                count: int { default: 0 }
+            events:
+                on_change(field): count = count + 1
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let mut runtime = Runtime::new();
+        runtime.execute(&spec).await.unwrap();
+
+        let error = runtime.emit("on_change", json!("count")).await.unwrap_err();
+        assert!(error.to_string().contains("nesting limit"));
+    }
+
+    #[tokio::test]
+    async fn test_on_change_handlers_without_a_field_see_every_change() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            inputs:
+                retries: int { default: 0 }
+            events:
+                on_change(retries): changed_retries = retries
+                on_change(field): last_changed = field
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let mut runtime = Runtime::new();
+        runtime.execute(&spec).await.unwrap();
+
+        // An emitted on_change names no field, so field handlers ignore it
+        runtime.emit("on_change", json!("manual")).await.unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["last_changed"], json!("manual"));
+        assert!(!report.inputs.contains_key("changed_retries"));
+
+        runtime.set_input("retries", json!(2)).await.unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["changed_retries"], json!(2));
+        assert_eq!(report.inputs["last_changed"], json!("retries"));
+    }
+
+    #[tokio::test]
+    async fn test_set_input_recomputes_dependents_only() {
+        use futures::{FutureExt, StreamExt};
+
+        let input = r#"
+        spec "Pricing" v1.0 {
+            inputs:
+                quantity: int { min: 1, default: 1 }
+                currency: string { default: "EUR" }
+            computed:
+                total: -> subtotal + 1
+                subtotal: -> quantity * 10
+                label: -> "{{currency}}"
+            events:
+                on_change(quantity): last_quantity = quantity
+                on_change(total): last_total = total
+                on_change(label): last_label = label
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let mut runtime = Runtime::new();
+        assert!(runtime.set_input("quantity", json!(2)).await.is_err());
+        runtime.execute(&spec).await.unwrap();
+
+        let mut totals = runtime.watch("total");
+        let mut labels = runtime.watch("label");
+        runtime.set_input("quantity", json!("4")).await.unwrap();
+        assert_eq!(totals.next().await, Some(json!(41.0)));
+
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["quantity"], json!(4));
+        assert_eq!(report.inputs["last_quantity"], json!(4));
+        assert_eq!(report.inputs["last_total"], json!(41.0));
+        assert!(!report.inputs.contains_key("last_label"));
+        assert_eq!(report.computed["label"], json!("EUR"));
+
+        // Failing refinements change nothing and notify no one
+        assert!(matches!(
+            runtime.set_input("quantity", json!(0)).await,
+            Err(RuntimeError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            runtime.set_input("price", json!(1)).await,
+            Err(RuntimeError::InvalidInput { .. })
+        ));
+        runtime.set_input("currency", json!("USD")).await.unwrap();
+        assert_eq!(labels.next().await, Some(json!("USD")));
+        assert_eq!(runtime.snapshot().await.computed["total"], json!(41.0));
+        assert!(totals.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_action_assignments_update_inputs_like_set_input() {
+        use futures::StreamExt;
+
+        let input = r#"
+        spec "Pricing" v1.0 {
+            inputs:
+                quantity: int { min: 1, default: 1 }
+            computed:
+                subtotal: -> quantity * 10
+            events:
+                on_restock(count): quantity = count
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let mut runtime = Runtime::new();
+        runtime.execute(&spec).await.unwrap();
+
+        // Assigned values are coerced, and dependents and watchers follow
+        let mut subtotals = runtime.watch("subtotal");
+        runtime.emit("on_restock", json!("3")).await.unwrap();
+        assert_eq!(subtotals.next().await, Some(json!(30.0)));
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["quantity"], json!(3));
+        assert_eq!(report.computed["subtotal"], json!(30.0));
+
+        // Values outside the refinements are rejected and change nothing
+        let error = runtime.emit("on_restock", json!(0)).await.unwrap_err();
+        assert!(error.to_string().contains("Invalid input 'quantity'"));
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["quantity"], json!(3));
+        assert_eq!(report.computed["subtotal"], json!(30.0));
+
+        // `before` would be overwritten by the inputs phase, so it may not assign
+        let spec = SigmosParser::parse_spec(
+            r#"
+            spec "Pricing" v1.0 {
+                inputs:
+                    quantity: int { default: 1 }
+                lifecycle:
+                    before: quantity = 5
+            }
+            "#,
+        )
+        .unwrap();
+        let error = runtime.execute(&spec).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Cannot assign to input 'quantity' in lifecycle[0]"));
+    }
+
+    #[tokio::test]
+    async fn test_finally_runs_and_errors_reach_on_error() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            inputs:
+                name: string
+                max_tokens: int { default: 1000 }
+            events:
+                on_error(error): failure = error
+            constraints:
+                ensure max_tokens <= 4000
+            lifecycle:
+                before: validate
+                after: log("agent {{name}} initialized")
+                finally: cleanup()
+                finally: log("done")
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let mut runtime = Runtime::new();
+
+        let mut inputs = serde_json::Map::new();
+        inputs.insert("name".to_string(), json!("Ada"));
+        runtime
+            .execute_with_inputs(&spec, inputs.clone())
+            .await
+            .unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.logs, vec!["agent Ada initialized", "done"]);
+        assert_eq!(report.stage, Some(Stage::After));
+
+        // A failing stage skips `after` but still runs `finally`, whose cleanup
+        // drops the temporary assigned by `on_error`
+        inputs.insert("max_tokens".to_string(), json!(9000));
+        runtime
+            .execute_with_inputs(&spec, inputs)
+            .await
+            .unwrap_err();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.logs, vec!["done"]);
+        assert_eq!(report.stage, Some(Stage::Constraints));
+        assert!(!report.inputs.contains_key("failure"));
+        assert_eq!(report.timings.phases.last().unwrap().phase, "finally");
+    }
+
+    #[tokio::test]
+    async fn test_validate_action_checks_bound_values() {
+        let input = r#"
+        spec "Agent" v1.0 {
+            inputs:
+                max_tokens: int { default: 1000 }
+            events:
+                on_error(error): failure = error
+            constraints:
+                ensure max_tokens <= 4000
+            lifecycle:
+                after: max_tokens = 5000
+                finally: validate
+        }
+        "#;
+        let spec = SigmosParser::parse_spec(input).unwrap();
+        let mut runtime = Runtime::new();
+
+        // The assignment is checked like `set_input`, so `validate` then
+        // finds the bound value unchanged
+        let error = runtime.execute(&spec).await.unwrap_err();
+        assert!(matches!(error, RuntimeError::ConstraintsViolated(_)));
+        let report = runtime.snapshot().await;
+        assert_eq!(report.stage, Some(Stage::After));
+        assert_eq!(report.inputs["max_tokens"], json!(1000));
+        assert!(report.inputs["failure"]
+            .as_str()
+            .unwrap()
+            .contains("max_tokens <= 4000"));
+    }
 }
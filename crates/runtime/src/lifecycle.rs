@@ -1 +1,120 @@
-//! Lifecycle module
+//! Execution lifecycle
+//!
+//! An execution moves through the stages `before` → `inputs` → `computed` →
+//! `constraints` → `after`, stopping at the first failure. The spec's `finally`
+//! actions run afterwards whether or not a stage failed, and a failure is
+//! reported to `on_error` handlers before they do.
+//!
+//! Lifecycle and event actions may use the standard actions `validate`,
+//! `cleanup` and `log(message)` without registering a plugin.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_runtime::lifecycle::Stage;
+//!
+//! let stages: Vec<&str> = std::iter::successors(Some(Stage::Before), |s| s.next())
+//!     .map(Stage::name)
+//!     .collect();
+//! assert_eq!(stages, vec!["before", "inputs", "computed", "constraints", "after"]);
+//! ```
+
+use serde::{Deserialize, Serialize};
+use sigmos_core::ast::LifecyclePhase;
+
+/// A stage of execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// The spec's `before` actions
+    Before,
+    /// Inputs are bound and `assert` constraints checked
+    Inputs,
+    /// Computed fields are evaluated
+    Computed,
+    /// `ensure` constraints are checked
+    Constraints,
+    /// The spec's `after` actions
+    After,
+    /// The spec's `finally` actions, run even when an earlier stage failed
+    Finally,
+}
+
+impl Stage {
+    /// The stage that follows this one on success; `None` once `after` is done
+    ///
+    /// `finally` is not part of the sequence since it runs on every path.
+    pub fn next(self) -> Option<Stage> {
+        match self {
+            Stage::Before => Some(Stage::Inputs),
+            Stage::Inputs => Some(Stage::Computed),
+            Stage::Computed => Some(Stage::Constraints),
+            Stage::Constraints => Some(Stage::After),
+            Stage::After | Stage::Finally => None,
+        }
+    }
+
+    /// Lowercase name, as used in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Before => "before",
+            Stage::Inputs => "inputs",
+            Stage::Computed => "computed",
+            Stage::Constraints => "constraints",
+            Stage::After => "after",
+            Stage::Finally => "finally",
+        }
+    }
+
+    /// Lifecycle phase whose actions run in this stage, if any
+    pub fn phase(self) -> Option<LifecyclePhase> {
+        match self {
+            Stage::Before => Some(LifecyclePhase::Before),
+            Stage::After => Some(LifecyclePhase::After),
+            Stage::Finally => Some(LifecyclePhase::Finally),
+            _ => None,
+        }
+    }
+}
+
+/// Actions the runtime provides without a plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StandardAction {
+    /// Type-check the spec and check refinements and constraints against the
+    /// values bound so far
+    Validate,
+    /// Drop variables assigned by actions that are not declared fields
+    Cleanup,
+    /// Append a message to the execution log
+    Log,
+}
+
+impl StandardAction {
+    /// The standard action called `name`, if there is one
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "validate" => Some(StandardAction::Validate),
+            "cleanup" => Some(StandardAction::Cleanup),
+            "log" => Some(StandardAction::Log),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_sequence_excludes_finally() {
+        assert_eq!(Stage::After.next(), None);
+        assert_eq!(Stage::Finally.next(), None);
+        assert_eq!(Stage::Finally.phase(), Some(LifecyclePhase::Finally));
+        assert_eq!(Stage::Computed.phase(), None);
+        assert_eq!(
+            StandardAction::from_name("cleanup"),
+            Some(StandardAction::Cleanup)
+        );
+        assert_eq!(StandardAction::from_name("deploy"), None);
+    }
+}
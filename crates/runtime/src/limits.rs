@@ -0,0 +1,327 @@
+//! Resource limits for evaluation
+//!
+//! Specs are untrusted input. An expression nested thousands of levels deep
+//! overflows the stack, a function that recurses twice per call runs for
+//! hours, and a plugin that never answers hangs its host. A runtime checks its
+//! [`ExecutionLimits`] as it evaluates and fails with
+//! [`RuntimeError::LimitExceeded`] when one is reached.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::limits::{ExecutionLimits, Limit};
+//! use sigmos_runtime::{Runtime, RuntimeError};
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Fibonacci" v1.0 {
+//!     functions:
+//!         fib(n: float) -> float = n < 2 ? n : fib(n - 1) + fib(n - 2)
+//!     computed:
+//!         answer: -> fib(30)
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new().with_limits(ExecutionLimits {
+//!     max_steps: 10_000,
+//!     ..ExecutionLimits::default()
+//! });
+//! let error = tokio_test::block_on(runtime.execute(&spec)).unwrap_err();
+//! assert!(matches!(error, RuntimeError::LimitExceeded { limit: Limit::Steps, .. }));
+//! ```
+
+use crate::functions::MAX_CALL_DEPTH;
+use crate::{Runtime, RuntimeError, RuntimeResult};
+use futures::future::BoxFuture;
+use serde_json::Value as JsonValue;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// The limits a runtime enforces
+///
+/// Timeouts need a Tokio runtime with its time driver enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionLimits {
+    /// Most levels evaluation may nest, counting the levels of the functions
+    /// it calls
+    pub max_depth: usize,
+    /// Most spec function calls that may be in progress at once
+    pub max_call_depth: usize,
+    /// Most operations an execution, input change, event or
+    /// [`Runtime::evaluate_expression`] call may evaluate
+    pub max_steps: u64,
+    /// Most bytes in a string, or items in a list or object, that an
+    /// operation may produce
+    pub max_size: usize,
+    /// Longest a lifecycle phase may run
+    pub phase_timeout: Option<Duration>,
+    /// Longest a plugin call may take
+    pub plugin_timeout: Option<Duration>,
+    /// Most computed fields that may be evaluated at once; 1 evaluates them
+    /// one at a time
+    pub max_concurrency: usize,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 256,
+            max_call_depth: MAX_CALL_DEPTH,
+            max_steps: 1_000_000,
+            max_size: 16 * 1024 * 1024,
+            phase_timeout: None,
+            plugin_timeout: None,
+            max_concurrency: 8,
+        }
+    }
+}
+
+/// Which of the [`ExecutionLimits`] was reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    CallDepth,
+    Steps,
+    Size,
+    PhaseTimeout,
+    PluginTimeout,
+    Instances,
+}
+
+impl ExecutionLimits {
+    /// The error for evaluation that nests deeper than `max_depth`
+    pub(crate) fn depth_exceeded(&self) -> RuntimeError {
+        exceeded(
+            Limit::Depth,
+            format!(
+                "evaluation nests deeper than {} levels; does an expression or recursion go too deep?",
+                self.max_depth
+            ),
+        )
+    }
+
+    /// Enter one more level of evaluation on the current thread
+    pub(crate) fn enter(&self) -> RuntimeResult<Level> {
+        let levels = LEVELS.with(Cell::get);
+        if levels >= self.max_depth {
+            return Err(self.depth_exceeded());
+        }
+        LEVELS.with(|cell| cell.set(levels + 1));
+        Ok(Level(()))
+    }
+
+    /// Evaluate `future` one level deeper than the evaluation polling it
+    pub(crate) fn limit<'a>(
+        &'a self,
+        future: BoxFuture<'a, RuntimeResult<JsonValue>>,
+    ) -> Limited<'a> {
+        Limited {
+            future,
+            limits: self,
+        }
+    }
+
+    /// Fail if calling `name` from `depth` nested calls goes past
+    /// `max_call_depth`
+    pub(crate) fn check_call_depth(&self, name: &str, depth: usize) -> RuntimeResult<()> {
+        if depth >= self.max_call_depth {
+            return Err(exceeded(
+                Limit::CallDepth,
+                format!(
+                    "{name}() exceeded the limit of {} nested calls; does its recursion stop?",
+                    self.max_call_depth
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fail if `value` is larger than `max_size`
+    pub(crate) fn check_size(&self, value: &JsonValue) -> RuntimeResult<()> {
+        let (size, unit, kind) = match value {
+            JsonValue::String(s) => (s.len(), "bytes", "string"),
+            JsonValue::Array(items) => (items.len(), "items", "list"),
+            JsonValue::Object(entries) => (entries.len(), "entries", "object"),
+            _ => return Ok(()),
+        };
+        if size > self.max_size {
+            return Err(exceeded(
+                Limit::Size,
+                format!(
+                    "a {kind} of {size} {unit} is larger than the limit of {}",
+                    self.max_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    /// Levels of evaluation in progress on this thread, which is how deep
+    /// evaluation has recursed into its stack
+    static LEVELS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// One level of evaluation in progress on the current thread, left when
+/// dropped
+pub(crate) struct Level(());
+
+impl Drop for Level {
+    fn drop(&mut self) {
+        LEVELS.with(|cell| cell.set(cell.get() - 1));
+    }
+}
+
+/// An evaluation future that enforces [`ExecutionLimits`] whenever it is
+/// polled
+///
+/// Levels are counted while futures are polled, not while they wait, so
+/// evaluations that run concurrently or move between threads do not add up.
+pub(crate) struct Limited<'a> {
+    future: BoxFuture<'a, RuntimeResult<JsonValue>>,
+    limits: &'a ExecutionLimits,
+}
+
+impl Future for Limited<'_> {
+    type Output = RuntimeResult<JsonValue>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _level = match self.limits.enter() {
+            Ok(level) => level,
+            Err(error) => return Poll::Ready(Err(error)),
+        };
+        match self.future.as_mut().poll(cx) {
+            Poll::Ready(Ok(value)) => Poll::Ready(self.limits.check_size(&value).map(|()| value)),
+            poll => poll,
+        }
+    }
+}
+
+/// Await `future`, failing with `limit` if it runs longer than `timeout`
+pub(crate) async fn deadline<T>(
+    timeout: Option<Duration>,
+    limit: Limit,
+    operation: &str,
+    future: impl Future<Output = RuntimeResult<T>>,
+) -> RuntimeResult<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+    tokio::time::timeout(timeout, future)
+        .await
+        .unwrap_or_else(|_| {
+            Err(exceeded(
+                limit,
+                format!("{operation} took longer than {timeout:?}"),
+            ))
+        })
+}
+
+pub(crate) fn exceeded(limit: Limit, message: String) -> RuntimeError {
+    RuntimeError::LimitExceeded { limit, message }
+}
+
+impl Runtime {
+    /// Count one evaluated operation against `max_steps`
+    pub(crate) fn step(&self) -> RuntimeResult<()> {
+        let max = self.limits.max_steps;
+        if self.steps.fetch_add(1, Ordering::Relaxed) >= max {
+            return Err(exceeded(
+                Limit::Steps,
+                format!("evaluation took more than {max} steps; does it loop?"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Give the next execution, input change or event a fresh step budget
+    pub(crate) fn reset_steps(&self) {
+        self.steps.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sigmos_core::ast::{Argument, Expression};
+
+    fn nested(levels: usize) -> Expression {
+        (1..levels).fold(Expression::Number(1.0), |expr, _| {
+            Expression::Add(Box::new(expr), Box::new(Expression::Number(1.0)))
+        })
+    }
+
+    #[tokio::test]
+    async fn test_limits_fail_instead_of_crashing() {
+        let runtime = Runtime::new().with_limits(ExecutionLimits {
+            max_depth: 50,
+            max_size: 8,
+            ..ExecutionLimits::default()
+        });
+        let limit = |result: RuntimeResult<JsonValue>| match result {
+            Err(RuntimeError::LimitExceeded { limit, .. }) => Some(limit),
+            _ => None,
+        };
+
+        assert_eq!(
+            runtime.evaluate_expression(&nested(50)).await.unwrap(),
+            json!(50.0)
+        );
+        assert_eq!(
+            limit(runtime.evaluate_expression(&nested(51)).await),
+            Some(Limit::Depth)
+        );
+        assert_eq!(
+            limit(Runtime::new().evaluate_expression(&nested(1_000)).await),
+            Some(Limit::Depth)
+        );
+
+        let long = Expression::Add(
+            Box::new(Expression::StringLiteral("12345".to_string())),
+            Box::new(Expression::StringLiteral("6789".to_string())),
+        );
+        assert_eq!(
+            limit(runtime.evaluate_expression(&long).await),
+            Some(Limit::Size)
+        );
+
+        let result = deadline(
+            Some(Duration::from_millis(10)),
+            Limit::PluginTimeout,
+            "slow.call",
+            std::future::pending::<RuntimeResult<()>>(),
+        )
+        .await;
+        assert!(
+            matches!(result, Err(RuntimeError::LimitExceeded { limit: Limit::PluginTimeout, ref message })
+                if message == "slow.call took longer than 10ms"),
+            "{result:?}"
+        );
+
+        let counting = Runtime::new().with_limits(ExecutionLimits {
+            max_steps: 10,
+            ..ExecutionLimits::default()
+        });
+        let call = Expression::FunctionCall {
+            object: String::new(),
+            method: "abs".to_string(),
+            arguments: vec![Argument {
+                name: String::new(),
+                value: nested(5),
+            }],
+        };
+        assert!(counting.evaluate_expression(&call).await.is_ok());
+        // Each evaluation gets its own budget
+        assert!(counting.evaluate_expression(&call).await.is_ok());
+        assert_eq!(
+            limit(counting.evaluate_expression(&nested(6)).await),
+            Some(Limit::Steps)
+        );
+    }
+}
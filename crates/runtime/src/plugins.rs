@@ -1 +1,387 @@
-//! Plugins module
+//! Plugin interfaces
+//!
+//! The runtime awaits plugin calls made from expressions and actions through
+//! [`AsyncPlugin`]. Plugins with a synchronous [`Plugin`] implementation are
+//! wrapped in [`Blocking`], which runs each call on Tokio's blocking thread
+//! pool so it cannot stall the executor.
+//!
+//! The runtime finds plugins through a [`PluginHost`]: its own [`PluginMap`] by
+//! default, or a richer registry installed with
+//! [`Runtime::with_plugin_host`](crate::Runtime::with_plugin_host).
+//!
+//! Every call receives the runtime's [`CancellationToken`]. Cancelling it drops
+//! the calls in flight and fails them with [`RuntimeError::Cancelled`];
+//! plugins may also check the token to stop work early.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use futures::future::BoxFuture;
+//! use serde_json::{json, Value as JsonValue};
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::plugins::{AsyncPlugin, CancellationToken};
+//! use sigmos_runtime::{Runtime, RuntimeResult};
+//! use std::collections::HashMap;
+//!
+//! #[derive(Debug)]
+//! struct Clock;
+//!
+//! impl AsyncPlugin for Clock {
+//!     fn name(&self) -> &str {
+//!         "clock"
+//!     }
+//!
+//!     fn initialize(&mut self) -> RuntimeResult<()> {
+//!         Ok(())
+//!     }
+//!
+//!     fn call<'a>(
+//!         &'a self,
+//!         _method: &'a str,
+//!         _args: &'a HashMap<String, JsonValue>,
+//!         _cancel: &'a CancellationToken,
+//!     ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+//!         Box::pin(async { Ok(json!("12:00")) })
+//!     }
+//! }
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Scheduler" v1.0 {
+//!     computed:
+//!         now: -> clock.now()
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new();
+//! runtime.register_async_plugin(Box::new(Clock)).unwrap();
+//! runtime.execute(&spec).await.unwrap();
+//! assert_eq!(runtime.snapshot().await.computed["now"], "12:00");
+//! # });
+//! ```
+
+use crate::{Plugin, RuntimeError, RuntimeResult};
+use futures::future::BoxFuture;
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Plugin whose methods are awaited by the runtime
+///
+/// Methods return boxed futures so the trait stays object-safe and plugins can
+/// be registered as `Box<dyn AsyncPlugin>`.
+pub trait AsyncPlugin: std::fmt::Debug + Send + Sync {
+    /// Plugin name, used as the object in `name.method(...)` calls
+    fn name(&self) -> &str;
+
+    /// Semver version checked against `import("...@x.y")` requirements
+    fn version(&self) -> Option<&str> {
+        None
+    }
+
+    /// Initialize the plugin
+    fn initialize(&mut self) -> RuntimeResult<()>;
+
+    /// Call a plugin method
+    ///
+    /// The runtime stops polling the returned future once `cancel` fires, so
+    /// plugins only need to watch it to release resources early.
+    fn call<'a>(
+        &'a self,
+        method: &'a str,
+        args: &'a HashMap<String, JsonValue>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>>;
+}
+
+/// Where a runtime looks up the plugins that specs call
+pub trait PluginHost: std::fmt::Debug + Send + Sync {
+    /// Add a plugin under its own name
+    fn register(&mut self, plugin: Box<dyn AsyncPlugin>) -> RuntimeResult<()>;
+
+    /// Initialize plugins not yet initialized; called before each execution
+    fn initialize(&mut self) -> RuntimeResult<()>;
+
+    /// Whether `name` resolves to a plugin
+    fn has_plugin(&self, name: &str) -> bool;
+
+    /// Names of the registered plugins, in registration order
+    fn plugin_names(&self) -> Vec<String>;
+
+    /// Semver version of the plugin `name` resolves to, if it declares one
+    fn plugin_version(&self, name: &str) -> Option<String>;
+
+    /// Call `method` on the plugin `name` resolves to
+    fn call<'a>(
+        &'a self,
+        name: &'a str,
+        method: &'a str,
+        args: &'a HashMap<String, JsonValue>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>>;
+}
+
+/// Plugins registered directly on a runtime
+///
+/// Registering a name again replaces the earlier plugin. Plugins are
+/// initialized in registration order.
+#[derive(Debug, Default)]
+pub struct PluginMap {
+    plugins: HashMap<String, Box<dyn AsyncPlugin>>,
+    order: Vec<String>,
+    initialized: HashSet<String>,
+}
+
+impl PluginHost for PluginMap {
+    fn register(&mut self, plugin: Box<dyn AsyncPlugin>) -> RuntimeResult<()> {
+        let name = plugin.name().to_string();
+        self.initialized.remove(&name);
+        if self.plugins.insert(name.clone(), plugin).is_none() {
+            self.order.push(name);
+        }
+        Ok(())
+    }
+
+    fn initialize(&mut self) -> RuntimeResult<()> {
+        for name in &self.order {
+            if self.initialized.contains(name) {
+                continue;
+            }
+            if let Some(plugin) = self.plugins.get_mut(name) {
+                plugin.initialize()?;
+                self.initialized.insert(name.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn has_plugin(&self, name: &str) -> bool {
+        self.plugins.contains_key(name)
+    }
+
+    fn plugin_names(&self) -> Vec<String> {
+        self.order.clone()
+    }
+
+    fn plugin_version(&self, name: &str) -> Option<String> {
+        self.plugins
+            .get(name)
+            .and_then(|plugin| plugin.version())
+            .map(str::to_string)
+    }
+
+    fn call<'a>(
+        &'a self,
+        name: &'a str,
+        method: &'a str,
+        args: &'a HashMap<String, JsonValue>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        match self.plugins.get(name) {
+            Some(plugin) => plugin.call(method, args, cancel),
+            None => Box::pin(async move {
+                Err(RuntimeError::Evaluation(format!(
+                    "Plugin '{name}' not found"
+                )))
+            }),
+        }
+    }
+}
+
+/// Shared flag that cancels plugin calls in flight
+///
+/// Clones share the same state. Once cancelled a token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<CancellationState>,
+}
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every call watching this token
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Await `future` unless the token is cancelled first
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sigmos_runtime::plugins::CancellationToken;
+    /// use sigmos_runtime::RuntimeError;
+    ///
+    /// # tokio_test::block_on(async {
+    /// let token = CancellationToken::new();
+    /// token.cancel();
+    ///
+    /// let result = token.run("slow.call", std::future::pending::<Result<(), _>>()).await;
+    /// assert!(matches!(result, Err(RuntimeError::Cancelled(_))));
+    /// # });
+    /// ```
+    pub async fn run<T, F>(&self, operation: &str, future: F) -> RuntimeResult<T>
+    where
+        F: Future<Output = RuntimeResult<T>>,
+    {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => Err(RuntimeError::Cancelled(operation.to_string())),
+            result = future => result,
+        }
+    }
+}
+
+/// Adapter that lets a synchronous [`Plugin`] be awaited
+///
+/// Calls run on Tokio's blocking thread pool when a Tokio runtime is current,
+/// and inline otherwise.
+#[derive(Debug)]
+pub struct Blocking<P: ?Sized> {
+    plugin: Arc<P>,
+}
+
+impl<P> Blocking<P> {
+    pub fn new(plugin: P) -> Self {
+        Self {
+            plugin: Arc::new(plugin),
+        }
+    }
+}
+
+impl<P> AsyncPlugin for Blocking<P>
+where
+    P: Plugin + Send + Sync + ?Sized + 'static,
+{
+    fn name(&self) -> &str {
+        self.plugin.name()
+    }
+
+    fn version(&self) -> Option<&str> {
+        self.plugin.version()
+    }
+
+    fn initialize(&mut self) -> RuntimeResult<()> {
+        let name = self.plugin.name().to_string();
+        Arc::get_mut(&mut self.plugin)
+            .ok_or_else(|| {
+                RuntimeError::Plugin(format!(
+                    "Plugin '{name}' cannot be initialized while a call is running"
+                ))
+            })?
+            .initialize()
+    }
+
+    fn call<'a>(
+        &'a self,
+        method: &'a str,
+        args: &'a HashMap<String, JsonValue>,
+        _cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        Box::pin(async move {
+            if tokio::runtime::Handle::try_current().is_err() {
+                return self.plugin.execute(method, args);
+            }
+            let plugin = Arc::clone(&self.plugin);
+            let (method, args) = (method.to_string(), args.clone());
+            tokio::task::spawn_blocking(move || plugin.execute(&method, &args))
+                .await
+                .map_err(|e| {
+                    RuntimeError::Plugin(format!("Plugin '{}' panicked: {e}", self.plugin.name()))
+                })?
+        })
+    }
+}
+
+impl<P> From<Box<P>> for Blocking<P>
+where
+    P: ?Sized,
+{
+    fn from(plugin: Box<P>) -> Self {
+        Self {
+            plugin: Arc::from(plugin),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Debug)]
+    struct Upper;
+
+    impl Plugin for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn initialize(&mut self) -> RuntimeResult<()> {
+            Ok(())
+        }
+
+        fn execute(
+            &self,
+            method: &str,
+            args: &HashMap<String, JsonValue>,
+        ) -> RuntimeResult<JsonValue> {
+            std::thread::sleep(Duration::from_millis(5));
+            let text = args.get("arg_0").and_then(JsonValue::as_str).unwrap_or("");
+            Ok(JsonValue::String(format!(
+                "{method}:{}",
+                text.to_uppercase()
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocking_adapter_and_cancellation() {
+        let plugin = Blocking::new(Upper);
+        let token = CancellationToken::new();
+        let args = HashMap::from([("arg_0".to_string(), JsonValue::from("hi"))]);
+
+        let result = plugin.call("shout", &args, &token).await.unwrap();
+        assert_eq!(result, "shout:HI");
+
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            canceller.cancel();
+        });
+        let result = token
+            .run(
+                "upper.wait",
+                futures::future::pending::<RuntimeResult<()>>(),
+            )
+            .await;
+        assert!(matches!(result, Err(RuntimeError::Cancelled(op)) if op == "upper.wait"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Retry, timeout and fallback policies for plugin calls
+//!
+//! Without a policy a failed plugin call fails the run. A computed field or
+//! action written with `{ retry: 3, backoff: exponential(200ms), timeout: 5s,
+//! fallback: <expr> }` instead makes each of its plugin calls again, after the
+//! backoff, while they fail with an error that
+//! [may not recur](RuntimeError::is_retryable), gives every attempt its own
+//! timeout, and uses the fallback's value when a call fails for good. Each
+//! call is reported once, with the number of attempts it took.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use futures::future::BoxFuture;
+//! use serde_json::Value as JsonValue;
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::plugins::{AsyncPlugin, CancellationToken};
+//! use sigmos_runtime::{Runtime, RuntimeError, RuntimeResult};
+//! use std::collections::HashMap;
+//!
+//! #[derive(Debug)]
+//! struct Offline;
+//!
+//! impl AsyncPlugin for Offline {
+//!     fn name(&self) -> &str {
+//!         "llm"
+//!     }
+//!
+//!     fn initialize(&mut self) -> RuntimeResult<()> {
+//!         Ok(())
+//!     }
+//!
+//!     fn call<'a>(
+//!         &'a self,
+//!         _method: &'a str,
+//!         _args: &'a HashMap<String, JsonValue>,
+//!         _cancel: &'a CancellationToken,
+//!     ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+//!         Box::pin(async { Err(RuntimeError::Transient("connection reset".to_string())) })
+//!     }
+//! }
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Assistant" v1.0 {
+//!     computed:
+//!         reply: -> llm.complete(prompt: "hi") { retry: 2, backoff: fixed(1ms), fallback: "offline" }
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new();
+//! runtime.register_async_plugin(Box::new(Offline)).unwrap();
+//! runtime.execute(&spec).await.unwrap();
+//!
+//! let report = runtime.snapshot().await;
+//! assert_eq!(report.computed["reply"], "offline");
+//! assert_eq!(report.plugin_calls[0].attempts, 3);
+//! # });
+//! ```
+
+use crate::limits::Limit;
+use crate::RuntimeError;
+use sigmos_core::ast::{Backoff, CallPolicy};
+use std::time::Duration;
+
+/// How a plugin call is retried and bounded
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Retry {
+    /// Attempts after the first
+    pub(crate) retries: u32,
+    backoff: Option<Backoff>,
+    timeout: Option<Duration>,
+}
+
+impl From<&CallPolicy> for Retry {
+    fn from(policy: &CallPolicy) -> Self {
+        Self {
+            retries: policy.retry,
+            backoff: policy.backoff,
+            timeout: policy.timeout,
+        }
+    }
+}
+
+impl Retry {
+    /// Longest one attempt may take, given the runtime's `plugin_timeout`
+    pub(crate) fn timeout(&self, limit: Option<Duration>) -> Option<Duration> {
+        match (self.timeout, limit) {
+            (Some(timeout), Some(limit)) => Some(timeout.min(limit)),
+            (timeout, limit) => timeout.or(limit),
+        }
+    }
+
+    /// Wait before the retry numbered `retry`, counting from 0
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .map_or(Duration::ZERO, |backoff| backoff.delay(retry))
+    }
+}
+
+/// Whether a fallback replaces a value whose evaluation failed with `error`:
+/// a plugin call failed, rather than the spec being wrong, a limit being
+/// reached or the run being cancelled
+pub(crate) fn recoverable(error: &RuntimeError) -> bool {
+    matches!(
+        error,
+        RuntimeError::Plugin(_)
+            | RuntimeError::Transient(_)
+            | RuntimeError::LimitExceeded {
+                limit: Limit::PluginTimeout,
+                ..
+            }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{spec_computing, TestPlugin};
+    use crate::{Runtime, RuntimeError, RuntimeResult};
+    use serde_json::json;
+    use sigmos_core::parser::SigmosParser;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Fails calls with the kind of error their argument names until it has
+    /// been called `failures` times
+    fn flaky(failures: u32, calls: &Arc<AtomicU32>) -> TestPlugin {
+        let calls = Arc::clone(calls);
+        TestPlugin::awaiting("flaky", move |method, args| {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                if call > failures {
+                    return Ok(json!(format!("{method} on call {call}")));
+                }
+                match args["arg_0"].as_str() {
+                    Some("transient") => Err(RuntimeError::Transient("reset".to_string())),
+                    Some("slow") => std::future::pending().await,
+                    _ => Err(RuntimeError::Plugin("bad request".to_string())),
+                }
+            }
+        })
+    }
+
+    async fn run(failures: u32, source: &str) -> (Runtime, RuntimeResult<()>) {
+        let mut runtime = Runtime::new();
+        runtime
+            .register_async_plugin(Box::new(flaky(failures, &Arc::default())))
+            .unwrap();
+        let result = runtime.execute(&spec_computing("reply", source)).await;
+        (runtime, result)
+    }
+
+    #[tokio::test]
+    async fn test_policies_retry_and_fall_back() {
+        // Transient failures are retried until an attempt succeeds
+        let (runtime, result) = run(
+            2,
+            r#"flaky.ask("transient") { retry: 3, backoff: exponential(1ms) }"#,
+        )
+        .await;
+        assert!(result.is_ok(), "{result:?}");
+        let report = runtime.snapshot().await;
+        assert_eq!(report.computed["reply"], "ask on call 3");
+        assert_eq!(report.plugin_calls.len(), 1);
+        assert_eq!(report.plugin_calls[0].attempts, 3);
+        assert_eq!(report.plugin_calls[0].error, None);
+
+        // Running out of retries fails the run, or uses the fallback
+        let (runtime, result) = run(5, r#"flaky.ask("transient") { retry: 1 }"#).await;
+        assert!(
+            matches!(result, Err(RuntimeError::Transient(_))),
+            "{result:?}"
+        );
+        assert_eq!(runtime.snapshot().await.plugin_calls[0].attempts, 2);
+        let (runtime, result) = run(
+            5,
+            r#"flaky.ask("transient") { retry: 1, fallback: "unavailable" }"#,
+        )
+        .await;
+        assert!(result.is_ok(), "{result:?}");
+        let report = runtime.snapshot().await;
+        assert_eq!(report.computed["reply"], "unavailable");
+        assert_eq!(
+            report.plugin_calls[0].error.as_deref(),
+            Some("Transient plugin error: reset")
+        );
+
+        // Errors that would recur are not retried
+        let (runtime, result) = run(
+            1,
+            r#"flaky.ask("invalid") { retry: 3, fallback: "unavailable" }"#,
+        )
+        .await;
+        assert!(result.is_ok(), "{result:?}");
+        let report = runtime.snapshot().await;
+        assert_eq!(report.computed["reply"], "unavailable");
+        assert_eq!(report.plugin_calls[0].attempts, 1);
+
+        // Each attempt gets the timeout
+        let (runtime, result) = run(1, r#"flaky.ask("slow") { retry: 1, timeout: 10ms }"#).await;
+        assert!(result.is_ok(), "{result:?}");
+        let report = runtime.snapshot().await;
+        assert_eq!(report.computed["reply"], "ask on call 2");
+        assert!(report.plugin_calls[0].duration_ms >= 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_policies_apply_to_actions() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Agent" v1.0 {
+    inputs:
+        answer: string { default: "none" }
+    lifecycle:
+        after: answer = flaky.ask("transient") { retry: 1, fallback: "unavailable" }
+}
+"#,
+        )
+        .unwrap();
+        let calls = Arc::new(AtomicU32::new(0));
+        let mut runtime = Runtime::new();
+        runtime
+            .register_async_plugin(Box::new(flaky(3, &calls)))
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), runtime.execute(&spec))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(runtime.snapshot().await.inputs["answer"], "unavailable");
+    }
+}
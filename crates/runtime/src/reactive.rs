@@ -0,0 +1,69 @@
+//! Incremental recomputation
+//!
+//! [`Runtime::set_input`](crate::Runtime::set_input) updates one input and
+//! recomputes only the computed fields that depend on it, directly or through
+//! other computed fields. [`Runtime::watch`](crate::Runtime::watch) subscribes
+//! to a field and yields each new value.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use futures::StreamExt;
+//! use serde_json::json;
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::Runtime;
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Pricing" v1.0 {
+//!     inputs:
+//!         quantity: int { default: 1 }
+//!     computed:
+//!         total: -> quantity * 10
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new();
+//! runtime.execute(&spec).await.unwrap();
+//!
+//! let mut totals = runtime.watch("total");
+//! runtime.set_input("quantity", json!(3)).await.unwrap();
+//! assert_eq!(totals.next().await, Some(json!(30.0)));
+//! # });
+//! ```
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Subscribers to field value changes
+#[derive(Default)]
+pub(crate) struct Watchers {
+    senders: Mutex<HashMap<String, Vec<UnboundedSender<JsonValue>>>>,
+}
+
+impl Watchers {
+    /// Stream of future values of `field`
+    pub(crate) fn subscribe(&self, field: &str) -> UnboundedReceiver<JsonValue> {
+        let (sender, receiver) = unbounded();
+        self.lock()
+            .entry(field.to_string())
+            .or_default()
+            .push(sender);
+        receiver
+    }
+
+    /// Send `value` to the subscribers of `field`, forgetting dropped streams
+    pub(crate) fn notify(&self, field: &str, value: &JsonValue) {
+        if let Some(senders) = self.lock().get_mut(field) {
+            senders.retain(|sender| sender.unbounded_send(value.clone()).is_ok());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<UnboundedSender<JsonValue>>>> {
+        self.senders
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
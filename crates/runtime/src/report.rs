@@ -0,0 +1,107 @@
+//! Execution reports
+//!
+//! [`Runtime::snapshot`](crate::Runtime::snapshot) captures what a run produced:
+//! bound inputs, computed values, the plugin calls made, the final state and
+//! how long each phase took. Secret values are masked before they get here.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::Runtime;
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Greeter" v1.0 {
+//!     inputs:
+//!         name: string { default: "Ada" }
+//!     computed:
+//!         greeting: -> "Hello, {{name}}"
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new();
+//! runtime.execute(&spec).await.unwrap();
+//!
+//! let report = runtime.snapshot().await;
+//! assert_eq!(report.state, "completed");
+//! assert_eq!(report.computed["greeting"], "Hello, Ada");
+//! # });
+//! ```
+
+use crate::lifecycle::Stage;
+use crate::ConstraintViolation;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Everything a run produced, safe to print or serialize
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecutionReport {
+    /// Name of the executed spec
+    pub spec: String,
+    /// Version of the executed spec
+    pub version: String,
+    /// `idle`, `running`, `completed` or `failed`
+    pub state: String,
+    /// Why the run failed, if it did
+    pub error: Option<String>,
+    /// Bound input values, including assignments made by actions
+    pub inputs: BTreeMap<String, JsonValue>,
+    /// Computed field values
+    pub computed: BTreeMap<String, JsonValue>,
+    /// Constraints that failed, in spec order
+    pub violations: Vec<ConstraintViolation>,
+    /// Plugin methods called, in call order
+    pub plugin_calls: Vec<PluginCall>,
+    /// Stage that failed, or `after` when every stage succeeded
+    pub stage: Option<Stage>,
+    /// Messages written by `log` actions
+    pub logs: Vec<String>,
+    pub timings: Timings,
+}
+
+/// One plugin method invocation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PluginCall {
+    pub plugin: String,
+    pub method: String,
+    /// Evaluated arguments; positional ones are named `arg_0`, `arg_1`, ...
+    pub arguments: BTreeMap<String, JsonValue>,
+    /// Time across every attempt, including waits between them
+    pub duration_ms: f64,
+    /// Times the method was called; more than 1 when failures were retried
+    pub attempts: u32,
+    /// The plugin's error message when the last attempt failed
+    pub error: Option<String>,
+}
+
+/// Wall-clock time spent executing
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Timings {
+    pub total_ms: f64,
+    /// Phases in the order they ran; phases after a failure are absent
+    pub phases: Vec<PhaseTiming>,
+}
+
+/// Time spent in one execution phase
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub duration_ms: f64,
+}
+
+impl PhaseTiming {
+    pub(crate) fn new(phase: &str, duration: Duration) -> Self {
+        Self {
+            phase: phase.to_string(),
+            duration_ms: millis(duration),
+        }
+    }
+}
+
+/// Duration in fractional milliseconds
+pub(crate) fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,537 @@
+//! Checkpoints for resuming runs
+//!
+//! A runtime given a [`StateStore`] saves a [`Checkpoint`] of its bound
+//! inputs, computed values, logs, state and event count after each stage, and
+//! once more when the run ends. [`Runtime::resume`] continues a run whose
+//! last checkpoint failed or was interrupted: `before` and `after` actions
+//! that finished are not run again, and computed fields that have a value are
+//! not evaluated again, so plugin calls already paid for are not repeated.
+//!
+//! A checkpoint records the non-secret inputs its run was given, and a
+//! fingerprint of them and the spec. [`Runtime::resume`] gives the run those
+//! inputs again; a run given other inputs, or an edited spec, starts afresh
+//! instead of resuming values computed from what it replaced.
+//!
+//! Secret inputs and the values derived from them are never saved. A resumed
+//! run takes them from the inputs it is given, or from their defaults, and
+//! computes the values derived from them again.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::lifecycle::Stage;
+//! use sigmos_runtime::state::{JsonFileStore, StateStore};
+//! use sigmos_runtime::Runtime;
+//! use std::sync::Arc;
+//!
+//! # tokio_test::block_on(async {
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Report" v1.0 {
+//!     inputs:
+//!         topic: string { default: "rust" }
+//!     computed:
+//!         title: -> "Notes on {{topic}}"
+//! }
+//! "#).unwrap();
+//!
+//! let dir = std::env::temp_dir().join(format!("sigmos-state-doc-{}", std::process::id()));
+//! let store = Arc::new(JsonFileStore::new(&dir).unwrap());
+//! let mut runtime = Runtime::new();
+//! runtime.resume(&spec, store.clone()).await.unwrap();
+//!
+//! let checkpoint = store.load("Report").unwrap().unwrap();
+//! assert_eq!(checkpoint.state, "completed");
+//! assert_eq!(checkpoint.completed, Some(Stage::After));
+//! assert_eq!(checkpoint.computed["title"], "Notes on rust");
+//! # std::fs::remove_dir_all(&dir).unwrap();
+//! # });
+//! ```
+
+use crate::lifecycle::Stage;
+use crate::{Runtime, RuntimeError, RuntimeResult};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use sigmos_core::ast::Spec;
+use sigmos_core::taint::TaintAnalyzer;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// What a run had done when it was saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Name of the spec the run executed
+    pub spec: String,
+    /// Version of the spec the run executed
+    pub version: String,
+    /// `running`, `completed` or `failed`
+    pub state: String,
+    /// Why the run failed, if it did
+    pub error: Option<String>,
+    /// Last stage that finished
+    pub completed: Option<Stage>,
+    /// Bound inputs and variables assigned by actions, without secrets
+    pub inputs: BTreeMap<String, JsonValue>,
+    /// Computed values, without those derived from secrets
+    pub computed: BTreeMap<String, JsonValue>,
+    /// Messages written by `log` actions
+    pub logs: Vec<String>,
+    /// Events delivered to handlers; a resumed run keeps counting from here
+    pub events: u64,
+    /// Non-secret inputs the run was given
+    #[serde(default)]
+    pub given: BTreeMap<String, JsonValue>,
+    /// [`fingerprint`] of the spec and inputs the run was given
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+/// SHA-256 digest, in hex, of `spec` and the `inputs` given to a run of it,
+/// leaving out secret inputs
+///
+/// A checkpoint is only resumed by a run with the same fingerprint.
+pub fn fingerprint(spec: &Spec, inputs: &serde_json::Map<String, JsonValue>) -> String {
+    let inputs = given_inputs(spec, inputs);
+    let mut hasher = Sha256::new();
+    // Both serialize infallibly: their maps have string keys
+    hasher.update(serde_json::to_vec(spec).unwrap_or_default());
+    hasher.update(serde_json::to_vec(&inputs).unwrap_or_default());
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The `inputs` given to a run of `spec` that a checkpoint may keep
+pub(crate) fn given_inputs(
+    spec: &Spec,
+    inputs: &serde_json::Map<String, JsonValue>,
+) -> BTreeMap<String, JsonValue> {
+    let secrets = TaintAnalyzer::tainted_fields(spec);
+    inputs
+        .iter()
+        .filter(|(name, _)| !secrets.contains(*name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Where checkpoints are kept, by key
+///
+/// Runtimes key checkpoints by spec name.
+pub trait StateStore: std::fmt::Debug + Send + Sync {
+    /// The checkpoint saved under `key`, if there is one
+    fn load(&self, key: &str) -> RuntimeResult<Option<Checkpoint>>;
+
+    /// Save `checkpoint` under `key`, replacing the one saved before
+    fn save(&self, key: &str, checkpoint: &Checkpoint) -> RuntimeResult<()>;
+}
+
+/// Checkpoints kept as JSON files in a directory, one per key
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    dir: PathBuf,
+}
+
+impl JsonFileStore {
+    /// Keep checkpoints in `dir`, creating it if needed
+    pub fn new(dir: impl AsRef<Path>) -> RuntimeResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir).map_err(|e| failed(&dir, e))?;
+        Ok(Self { dir })
+    }
+
+    /// File holding the checkpoint saved under `key`
+    fn path(&self, key: &str) -> PathBuf {
+        let name: String = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{name}.json"))
+    }
+}
+
+impl StateStore for JsonFileStore {
+    fn load(&self, key: &str) -> RuntimeResult<Option<Checkpoint>> {
+        let path = self.path(key);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(failed(&path, error)),
+        };
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| failed(&path, e))
+    }
+
+    fn save(&self, key: &str, checkpoint: &Checkpoint) -> RuntimeResult<()> {
+        let path = self.path(key);
+        let text = serde_json::to_string_pretty(checkpoint).map_err(|e| failed(&path, e))?;
+        // Written aside and renamed, so a crash never leaves half a checkpoint
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, text)
+            .and_then(|()| std::fs::rename(&partial, &path))
+            .map_err(|e| failed(&path, e))
+    }
+}
+
+/// Checkpoints kept in an embedded SQLite database
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStore {
+    /// Keep checkpoints in the database file at `path`, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> RuntimeResult<Self> {
+        let path = path.as_ref();
+        let connection = rusqlite::Connection::open(path).map_err(|e| failed(path, e))?;
+        Self::with_connection(connection)
+    }
+
+    /// Keep checkpoints in a database that lasts as long as the store
+    pub fn in_memory() -> RuntimeResult<Self> {
+        let connection = rusqlite::Connection::open_in_memory().map_err(sqlite)?;
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(connection: rusqlite::Connection) -> RuntimeResult<Self> {
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS checkpoints (key TEXT PRIMARY KEY, checkpoint TEXT NOT NULL)",
+                [],
+            )
+            .map_err(sqlite)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn load(&self, key: &str) -> RuntimeResult<Option<Checkpoint>> {
+        let text: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT checkpoint FROM checkpoints WHERE key = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sqlite)?;
+        text.map(|text| serde_json::from_str(&text).map_err(sqlite))
+            .transpose()
+    }
+
+    fn save(&self, key: &str, checkpoint: &Checkpoint) -> RuntimeResult<()> {
+        let text = serde_json::to_string(checkpoint).map_err(sqlite)?;
+        self.connection()
+            .execute(
+                "INSERT INTO checkpoints (key, checkpoint) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET checkpoint = excluded.checkpoint",
+                [key, text.as_str()],
+            )
+            .map_err(sqlite)?;
+        Ok(())
+    }
+}
+
+fn failed(path: &Path, error: impl std::fmt::Display) -> RuntimeError {
+    RuntimeError::State(format!("{}: {error}", path.display()))
+}
+
+fn sqlite(error: impl std::fmt::Display) -> RuntimeError {
+    RuntimeError::State(format!("SQLite: {error}"))
+}
+
+impl Runtime {
+    /// Continue the execution of `spec` saved in `store`, or start one
+    ///
+    /// A checkpoint that failed or was interrupted is resumed, with the
+    /// inputs its run was given, when it was saved by a run of the same spec;
+    /// without one, or when the last run completed, `spec` is executed afresh.
+    /// Either way the execution saves its checkpoints to `store`.
+    pub async fn resume(&mut self, spec: &Spec, store: Arc<dyn StateStore>) -> RuntimeResult<()> {
+        let inputs = store
+            .load(&spec.name)?
+            .map(|checkpoint| checkpoint.given.into_iter().collect())
+            .unwrap_or_default();
+        self.resume_with_inputs(spec, inputs, store).await
+    }
+
+    /// [`Runtime::resume`] with input values supplied by the caller
+    ///
+    /// The checkpoint is only resumed when its run was given the same
+    /// non-secret `inputs`. A resumed run keeps the values it bound for
+    /// inputs not given, such as generated ones.
+    pub async fn resume_with_inputs(
+        &mut self,
+        spec: &Spec,
+        mut inputs: serde_json::Map<String, JsonValue>,
+        store: Arc<dyn StateStore>,
+    ) -> RuntimeResult<()> {
+        let fingerprint = fingerprint(spec, &inputs);
+        let checkpoint = store.load(&spec.name)?.filter(|checkpoint| {
+            checkpoint.state != "completed" && checkpoint.fingerprint == fingerprint
+        });
+        self.state_store = Some(store);
+        let Some(checkpoint) = checkpoint else {
+            return self.execute_with_inputs(spec, inputs).await;
+        };
+
+        if checkpoint.completed >= Some(Stage::Inputs) {
+            for field in &spec.inputs {
+                if let Some(value) = checkpoint.inputs.get(&field.name) {
+                    inputs
+                        .entry(field.name.clone())
+                        .or_insert_with(|| value.clone());
+                }
+            }
+        }
+        self.run(spec, inputs, Some(checkpoint)).await
+    }
+
+    /// Save a checkpoint of the current run, if the runtime has a store
+    pub(crate) async fn save_checkpoint(&self) -> RuntimeResult<()> {
+        let Some(store) = &self.state_store else {
+            return Ok(());
+        };
+        let checkpoint = {
+            let context = self.context.read().await;
+            let kept = |values: &std::collections::HashMap<String, JsonValue>| {
+                values
+                    .iter()
+                    .filter(|(name, _)| !context.secrets.contains(*name))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect()
+            };
+            Checkpoint {
+                spec: context.spec.0.clone(),
+                version: context.spec.1.clone(),
+                state: context.state.name().to_string(),
+                error: match &context.state {
+                    crate::ExecutionState::Failed(error) => Some(error.clone()),
+                    _ => None,
+                },
+                completed: context.completed,
+                inputs: kept(&context.variables),
+                computed: kept(&context.computed_cache),
+                logs: context
+                    .logs
+                    .iter()
+                    .map(|message| context.redact(message))
+                    .collect(),
+                events: self.events.load(std::sync::atomic::Ordering::Relaxed),
+                given: context.given.clone(),
+                fingerprint: context.fingerprint.clone(),
+            }
+        };
+        store.save(&checkpoint.spec, &checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestPlugin;
+    use serde_json::json;
+    use sigmos_core::parser::SigmosParser;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Counts its calls, failing while `down` is set
+    #[derive(Debug, Default)]
+    struct Service {
+        calls: AtomicU32,
+        down: std::sync::atomic::AtomicBool,
+    }
+
+    /// Plugin answering with the method called, through `service`
+    fn service_plugin(service: &Arc<Service>) -> TestPlugin {
+        let service = Arc::clone(service);
+        TestPlugin::new("service", move |method, _args| {
+            service.calls.fetch_add(1, Ordering::SeqCst);
+            if service.down.load(Ordering::SeqCst) {
+                return Err(RuntimeError::Plugin(format!("{method} is down")));
+            }
+            Ok(json!(method))
+        })
+    }
+
+    #[test]
+    fn test_stores_round_trip_checkpoints() {
+        let checkpoint = Checkpoint {
+            spec: "Agent".to_string(),
+            version: "1.0".to_string(),
+            state: "running".to_string(),
+            error: None,
+            completed: Some(Stage::Inputs),
+            inputs: BTreeMap::from([("topic".to_string(), json!("rust"))]),
+            computed: BTreeMap::new(),
+            logs: vec!["started".to_string()],
+            events: 2,
+            given: BTreeMap::from([("topic".to_string(), json!("rust"))]),
+            fingerprint: "0a1b".to_string(),
+        };
+        let dir = std::env::temp_dir().join(format!("sigmos-state-test-{}", std::process::id()));
+        let stores: Vec<Box<dyn StateStore>> = vec![
+            Box::new(JsonFileStore::new(&dir).unwrap()),
+            Box::new(SqliteStore::in_memory().unwrap()),
+        ];
+        for store in stores {
+            assert_eq!(store.load("Agent/1").unwrap(), None);
+            store.save("Agent/1", &checkpoint).unwrap();
+            let done = Checkpoint {
+                state: "completed".to_string(),
+                ..checkpoint.clone()
+            };
+            store.save("Agent/1", &done).unwrap();
+            assert_eq!(store.load("Agent/1").unwrap(), Some(done));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_continues_a_failed_run() {
+        let spec = SigmosParser::parse_spec(
+            r#"
+spec "Pipeline" v1.0 {
+    inputs:
+        topic: string
+        api_key: string { secret: true, default: "sk-test" }
+    computed:
+        research: -> service.research(topic: topic)
+        signed: -> api_key + "-signed"
+        publish: -> service.publish(text: research)
+    lifecycle:
+        before: log("starting")
+}
+"#,
+        )
+        .unwrap();
+        let store = Arc::new(SqliteStore::in_memory().unwrap());
+        let service = Arc::new(Service::default());
+        let runtime = || {
+            let mut runtime = Runtime::new();
+            runtime
+                .register_async_plugin(Box::new(service_plugin(&service)))
+                .unwrap();
+            runtime
+        };
+        let inputs = serde_json::Map::from_iter([("topic".to_string(), json!("rust"))]);
+
+        // The service is down, so the run fails computing `research`
+        service.down.store(true, Ordering::SeqCst);
+        let error = runtime()
+            .resume_with_inputs(&spec, inputs.clone(), store.clone())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("is down"), "{error}");
+        let checkpoint = store.load("Pipeline").unwrap().unwrap();
+        assert_eq!(checkpoint.state, "failed");
+        assert_eq!(checkpoint.completed, Some(Stage::Inputs));
+        assert_eq!(checkpoint.inputs["topic"], "rust");
+        assert!(!checkpoint.inputs.contains_key("api_key"));
+        assert_eq!(checkpoint.logs, ["starting"]);
+        // `on_error` was delivered
+        assert_eq!(checkpoint.events, 1);
+
+        // Resuming gives the run its inputs again and does not run `before` again
+        service.down.store(false, Ordering::SeqCst);
+        let mut resumed = runtime();
+        resumed.resume(&spec, store.clone()).await.unwrap();
+        let report = resumed.snapshot().await;
+        assert_eq!(report.inputs["topic"], "rust");
+        assert_eq!(report.logs, ["starting"]);
+        assert_eq!(report.computed["publish"], "publish");
+        assert_eq!(report.computed["signed"], "***");
+        let checkpoint = store.load("Pipeline").unwrap().unwrap();
+        assert_eq!(checkpoint.state, "completed");
+        assert!(!checkpoint.computed.contains_key("signed"));
+
+        // A completed run starts over; interrupted ones skip computed values they have
+        let calls = service.calls.load(Ordering::SeqCst);
+        let interrupted = Checkpoint {
+            state: "running".to_string(),
+            completed: Some(Stage::Computed),
+            ..checkpoint
+        };
+        store.save("Pipeline", &interrupted).unwrap();
+        runtime()
+            .resume_with_inputs(&spec, inputs.clone(), store.clone())
+            .await
+            .unwrap();
+        assert_eq!(service.calls.load(Ordering::SeqCst), calls);
+        runtime()
+            .resume_with_inputs(&spec, inputs, store.clone())
+            .await
+            .unwrap();
+        assert_eq!(service.calls.load(Ordering::SeqCst), calls + 2);
+    }
+
+    #[tokio::test]
+    async fn test_changed_inputs_or_spec_start_afresh() {
+        let source = r#"
+spec "Budget" v1.0 {
+    inputs:
+        a: int
+        token: string { secret: true, default: "t1" }
+    computed:
+        b: -> a * 10
+    constraints:
+        ensure b > 100
+}
+"#;
+        let spec = SigmosParser::parse_spec(source).unwrap();
+        let store = Arc::new(SqliteStore::in_memory().unwrap());
+        let inputs = |a: i64, token: &str| {
+            serde_json::Map::from_iter([
+                ("a".to_string(), json!(a)),
+                ("token".to_string(), json!(token)),
+            ])
+        };
+        let result = Runtime::new()
+            .resume_with_inputs(&spec, inputs(1, "t1"), store.clone())
+            .await;
+        assert!(result.is_err());
+        let failed = store.load("Budget").unwrap().unwrap();
+        assert_eq!(failed.computed["b"], json!(10.0));
+
+        // Other secret inputs still resume the failed run
+        assert_eq!(fingerprint(&spec, &inputs(1, "t2")), failed.fingerprint);
+
+        // Other inputs are used, not replaced by the saved ones
+        let mut runtime = Runtime::new();
+        runtime
+            .resume_with_inputs(&spec, inputs(50, "t1"), store.clone())
+            .await
+            .unwrap();
+        let report = runtime.snapshot().await;
+        assert_eq!(report.inputs["a"], json!(50));
+        assert_eq!(report.computed["b"], json!(500.0));
+
+        // An edited spec does not reuse values computed by the old one
+        store.save("Budget", &failed).unwrap();
+        let edited = SigmosParser::parse_spec(&source.replace("a * 10", "a * 1000")).unwrap();
+        let mut runtime = Runtime::new();
+        runtime
+            .resume_with_inputs(&edited, inputs(1, "t1"), store.clone())
+            .await
+            .unwrap();
+        assert_eq!(runtime.snapshot().await.computed["b"], json!(1000.0));
+    }
+}
@@ -0,0 +1,233 @@
+//! Strict name resolution
+//!
+//! By default a name that nothing defines evaluates to a `${name}`
+//! placeholder, so a typo such as `{{nmae}}` ends up in the prompt sent to a
+//! model. A strict runtime fails with [`RuntimeError::UnresolvedName`]
+//! instead. Names no input, computed field, parameter or lambda defines are
+//! rejected when the spec is compiled, before anything runs; fields read
+//! before they have a value, such as an input read in `before`, fail when
+//! they are read. The error names the item the name was used in and the
+//! names in scope that it resembles.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sigmos_core::parser::SigmosParser;
+//! use sigmos_runtime::{Runtime, RuntimeError};
+//!
+//! let spec = SigmosParser::parse_spec(r#"
+//! spec "Greeter" v1.0 {
+//!     inputs:
+//!         name: string { default: "Ada" }
+//!     computed:
+//!         greeting: -> "Hello {{nmae}}"
+//! }
+//! "#).unwrap();
+//!
+//! let mut runtime = Runtime::new().with_strict(true);
+//! let error = tokio_test::block_on(runtime.execute(&spec)).unwrap_err();
+//! assert!(matches!(
+//!     error,
+//!     RuntimeError::UnresolvedName { ref location, ref suggestions, .. }
+//!         if location == "computed.greeting" && suggestions == &["name"]
+//! ));
+//! ```
+
+use crate::{Runtime, RuntimeError};
+
+/// Most names suggested for an unresolved one
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The error for `name`, which is not among the names in `scope`
+pub(crate) fn unresolved<'n>(name: &str, scope: impl IntoIterator<Item = &'n str>) -> RuntimeError {
+    RuntimeError::UnresolvedName {
+        name: name.to_string(),
+        location: String::new(),
+        span: None,
+        suggestions: suggestions(name, scope),
+    }
+}
+
+/// `; did you mean 'a' or 'b'?` for `suggestions`, or nothing without any
+pub(crate) fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|name| format!("'{name}'")).collect();
+    match quoted.split_last() {
+        None => String::new(),
+        Some((last, [])) => format!("; did you mean {last}?"),
+        Some((last, rest)) => format!("; did you mean {} or {last}?", rest.join(", ")),
+    }
+}
+
+/// The names in `scope` close enough to `name` to be what was meant, closest
+/// first
+fn suggestions<'n>(name: &str, scope: impl IntoIterator<Item = &'n str>) -> Vec<String> {
+    let allowed = (name.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, &str)> = scope
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= allowed)
+        .collect();
+    close.sort_unstable();
+    close.dedup();
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Edits that turn `a` into `b`, counting insertions, deletions,
+/// substitutions and swaps of adjacent characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // distances[i][j] is the distance between the first i of `a` and first j of `b`
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = substitution
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+impl Runtime {
+    /// Place an [`RuntimeError::UnresolvedName`] that does not yet say where
+    /// it happened at `location`, with its span from the source map
+    pub(crate) fn locate(
+        &self,
+        error: RuntimeError,
+        location: impl FnOnce() -> String,
+    ) -> RuntimeError {
+        match error {
+            RuntimeError::UnresolvedName {
+                name,
+                location: found,
+                span,
+                suggestions,
+            } => {
+                let location = if found.is_empty() { location() } else { found };
+                RuntimeError::UnresolvedName {
+                    span: span.or_else(|| self.source_map.get(&location)),
+                    name,
+                    location,
+                    suggestions,
+                }
+            }
+            error => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sigmos_core::ast::{Expression, TemplatePart};
+    use sigmos_core::parser::SigmosParser;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_suggestions_are_close_names() {
+        assert_eq!(edit_distance("nmae", "name"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(
+            suggestions("user_nme", ["user_name", "username", "url", "user_nme"]),
+            ["user_name", "username"]
+        );
+        assert!(suggestions("x", ["total", "items"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_strict_runtime_rejects_unresolved_names() {
+        let context = HashMap::from([("name".to_string(), json!("Ada"))]);
+        let template = Expression::StringTemplate {
+            parts: vec![
+                TemplatePart::Text("Hello ".to_string()),
+                TemplatePart::Variable("nam".to_string()),
+            ],
+        };
+        assert_eq!(
+            Runtime::new()
+                .evaluate_expression_with_context(&template, &context)
+                .await
+                .unwrap(),
+            json!("Hello ${nam}")
+        );
+        let error = Runtime::new()
+            .with_strict(true)
+            .evaluate_expression_with_context(&template, &context)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unresolved name 'nam'; did you mean 'name'?"
+        );
+
+        // Template variables can be paths into objects
+        let context = HashMap::from([("user".to_string(), json!({"name": "Ada"}))]);
+        let template = Expression::StringTemplate {
+            parts: vec![TemplatePart::Variable("user.name".to_string())],
+        };
+        assert_eq!(
+            Runtime::new()
+                .with_strict(true)
+                .evaluate_expression_with_context(&template, &context)
+                .await
+                .unwrap(),
+            json!("Ada")
+        );
+
+        let source = r#"
+spec "Agent" v1.0 {
+    inputs:
+        topic: string { default: "rust" }
+    lifecycle:
+        before: log("Researching {{topic}}")
+    events:
+        on_error(error): log("Failed: {{eror.message}}")
+}
+"#;
+        let (spec, source_map) = SigmosParser::parse_spec_with_source_map(source).unwrap();
+        // Leniently, `topic` is read before the inputs are processed
+        assert!(Runtime::new().execute(&spec).await.is_ok());
+
+        let mut runtime = Runtime::new().with_strict(true).with_source_map(source_map);
+        match runtime.execute(&spec).await.unwrap_err() {
+            RuntimeError::UnresolvedName {
+                name,
+                location,
+                span,
+                suggestions,
+            } => {
+                assert_eq!(name, "eror");
+                assert_eq!(location, "events[0]");
+                assert_eq!(span.map(|span| span.line), Some(8));
+                assert_eq!(suggestions, ["error"]);
+            }
+            other => panic!("expected an unresolved name, got {other:?}"),
+        }
+
+        let spec = SigmosParser::parse_spec(&source.replace("eror", "error")).unwrap();
+        let error = runtime.execute(&spec).await.unwrap_err();
+        assert!(
+            matches!(error, RuntimeError::UnresolvedName { ref name, ref location, .. }
+                if name == "topic" && location == "lifecycle[0]"),
+            "{error:?}"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Helpers shared by the runtime's tests
+
+use crate::plugins::{AsyncPlugin, CancellationToken};
+use crate::RuntimeResult;
+use futures::future::BoxFuture;
+use serde_json::Value as JsonValue;
+use sigmos_core::ast::Spec;
+use sigmos_core::parser::SigmosParser;
+use std::collections::HashMap;
+use std::future::Future;
+
+type Call = dyn Fn(String, HashMap<String, JsonValue>) -> BoxFuture<'static, RuntimeResult<JsonValue>>
+    + Send
+    + Sync;
+
+/// Plugin whose calls are answered by a closure
+pub(crate) struct TestPlugin {
+    name: &'static str,
+    version: Option<&'static str>,
+    on_initialize: Option<Box<dyn FnMut() + Send + Sync>>,
+    call: Box<Call>,
+}
+
+impl TestPlugin {
+    /// Plugin `name` answering each call with `answer(method, args)`
+    pub(crate) fn new(
+        name: &'static str,
+        answer: impl Fn(&str, &HashMap<String, JsonValue>) -> RuntimeResult<JsonValue>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self::awaiting(name, move |method, args| {
+            std::future::ready(answer(&method, &args))
+        })
+    }
+
+    /// Plugin `name` answering each call by awaiting `call(method, args)`
+    pub(crate) fn awaiting<F>(
+        name: &'static str,
+        call: impl Fn(String, HashMap<String, JsonValue>) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = RuntimeResult<JsonValue>> + Send + 'static,
+    {
+        Self {
+            name,
+            version: None,
+            on_initialize: None,
+            call: Box::new(move |method, args| Box::pin(call(method, args))),
+        }
+    }
+
+    /// Declare `version` to `import("...@x.y")` requirements
+    pub(crate) fn with_version(mut self, version: &'static str) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Run `hook` each time the plugin is initialized
+    pub(crate) fn on_initialize(mut self, hook: impl FnMut() + Send + Sync + 'static) -> Self {
+        self.on_initialize = Some(Box::new(hook));
+        self
+    }
+}
+
+impl std::fmt::Debug for TestPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestPlugin")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncPlugin for TestPlugin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn version(&self) -> Option<&str> {
+        self.version
+    }
+
+    fn initialize(&mut self) -> RuntimeResult<()> {
+        if let Some(hook) = &mut self.on_initialize {
+            hook();
+        }
+        Ok(())
+    }
+
+    fn call<'a>(
+        &'a self,
+        method: &'a str,
+        args: &'a HashMap<String, JsonValue>,
+        _cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, RuntimeResult<JsonValue>> {
+        (self.call)(method.to_string(), args.clone())
+    }
+}
+
+/// Spec "Test" with one computed field, `field`, set to `expression`
+pub(crate) fn spec_computing(field: &str, expression: &str) -> Spec {
+    SigmosParser::parse_spec(&format!(
+        "spec \"Test\" v1.0 {{\n    computed:\n        {field}: -> {expression}\n}}"
+    ))
+    .unwrap()
+}
@@ -0,0 +1,73 @@
+//! Execution plan benchmark
+//!
+//! Evaluates a chain of computed fields from a compiled [`ExecutionPlan`]
+//! and by walking their AST with variables bound by name, as the runtime did
+//! before plans, and checks that the plan is faster. Run it with
+//! `cargo test -p sigmos-runtime --release --test execution_plan -- --nocapture`
+//! to see the timings.
+//!
+//! [`ExecutionPlan`]: sigmos_runtime::engine::ExecutionPlan
+
+use serde_json::Value as JsonValue;
+use sigmos_core::parser::SigmosParser;
+use sigmos_runtime::engine::Engine;
+use sigmos_runtime::Runtime;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Average time of one run of `operation`, after a warm-up run
+fn average(iterations: u32, mut operation: impl FnMut()) -> Duration {
+    operation();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        operation();
+    }
+    start.elapsed() / iterations
+}
+
+#[test]
+fn test_execution_plan_is_faster_than_the_ast_walk() {
+    // A chain of fields, each reading the one before it, a builtin and a constant
+    let fields: String = (0..50)
+        .map(|i| {
+            let previous = if i == 0 {
+                "base".to_string()
+            } else {
+                format!("f{}", i - 1)
+            };
+            format!("        f{i}: -> {previous} * 2 + (60 * 60) - len(label)\n")
+        })
+        .collect();
+    let source = format!(
+        "spec \"Chain\" v1.0 {{\n    inputs:\n        base: float\n        label: string\n    computed:\n{fields}}}\n"
+    );
+    let spec = SigmosParser::parse_spec(&source).unwrap();
+    let runtime = Runtime::new();
+    let inputs: HashMap<String, JsonValue> = HashMap::from([
+        ("base".to_string(), JsonValue::from(1.0)),
+        ("label".to_string(), JsonValue::from("chain")),
+    ]);
+
+    let ast = average(1000, || {
+        let mut values = inputs.clone();
+        for field in &spec.computed {
+            let value = tokio_test::block_on(
+                runtime.evaluate_expression_with_context(&field.expression, &values),
+            )
+            .unwrap();
+            values.insert(field.name.clone(), value);
+        }
+    });
+
+    let plan = Engine::new(runtime.builtins()).compile(&spec).unwrap();
+    let compiled = average(1000, || {
+        tokio_test::block_on(runtime.evaluate_plan(&plan, &inputs)).unwrap();
+    });
+
+    let speedup = ast.as_secs_f64() / compiled.as_secs_f64();
+    println!("AST walk: {ast:?}, execution plan: {compiled:?}, speedup: {speedup:.2}x");
+    assert!(
+        speedup > 1.5,
+        "Execution plan not faster than the AST walk: {speedup:.2}x"
+    );
+}
@@ -7,6 +7,8 @@
 //! - YAML
 //! - TOML
 //!
+//! Literal `default:` values of `secret` inputs are masked in every format.
+//!
 //! # Examples
 //!
 //! ```rust
@@ -25,12 +27,14 @@
 //!     lifecycle: vec![],
 //!     extensions: vec![],
 //!     types: vec![],
+//!     functions: vec![],
 //! };
 //!
 //! let json = transpiler.to_json(&spec).unwrap();
 //! ```
 
-use sigmos_core::ast::Spec;
+use sigmos_core::ast::{Expression, Modifier, Spec};
+use sigmos_core::taint::SECRET_MASK;
 use thiserror::Error;
 
 /// Transpiler errors
@@ -97,13 +101,14 @@ impl Transpiler {
     ///     lifecycle: vec![],
     ///     extensions: vec![],
     ///     types: vec![],
+    ///     functions: vec![],
     /// };
     ///
     /// let json = transpiler.to_json(&spec).unwrap();
     /// assert!(json.contains("Test"));
     /// ```
     pub fn to_json(&self, spec: &Spec) -> TranspilerResult<String> {
-        let json = serde_json::to_string_pretty(spec)?;
+        let json = serde_json::to_string_pretty(&Self::redact_secrets(spec))?;
         Ok(json)
     }
 
@@ -131,13 +136,17 @@ impl Transpiler {
     ///     lifecycle: vec![],
     ///     extensions: vec![],
     ///     types: vec![],
+    ///     functions: vec![],
     /// };
     ///
     /// let yaml = transpiler.to_yaml(&spec).unwrap();
     /// assert!(yaml.contains("name: Test"));
     /// ```
     pub fn to_yaml(&self, spec: &Spec) -> TranspilerResult<String> {
-        serde_yaml::to_string(spec)
+        // serde_yaml cannot serialize nested enums (e.g. `Primitive(String)`),
+        // so go through JSON's externally tagged representation
+        let value = serde_json::to_value(Self::redact_secrets(spec))?;
+        serde_yaml::to_string(&value)
             .map_err(|e| TranspilerError::Yaml(format!("YAML serialization failed: {e}")))
     }
 
@@ -165,15 +174,34 @@ impl Transpiler {
     ///     lifecycle: vec![],
     ///     extensions: vec![],
     ///     types: vec![],
+    ///     functions: vec![],
     /// };
     ///
     /// let toml_str = transpiler.to_toml(&spec).unwrap();
     /// assert!(toml_str.contains("name = \"Test\""));
     /// ```
     pub fn to_toml(&self, spec: &Spec) -> TranspilerResult<String> {
-        toml::to_string(spec)
+        toml::to_string(&Self::redact_secrets(spec))
             .map_err(|e| TranspilerError::Toml(format!("TOML serialization failed: {e}")))
     }
+
+    /// Copy of `spec` with literal defaults of secret inputs masked
+    fn redact_secrets(spec: &Spec) -> Spec {
+        let mut spec = spec.clone();
+        for field in &mut spec.inputs {
+            if !field.modifiers.contains(&Modifier::Secret) {
+                continue;
+            }
+            for modifier in &mut field.modifiers {
+                if let Modifier::Default(expr) = modifier {
+                    if *expr != Expression::Null {
+                        *expr = Expression::StringLiteral(SECRET_MASK.to_string());
+                    }
+                }
+            }
+        }
+        spec
+    }
 }
 
 #[cfg(test)]
@@ -197,6 +225,7 @@ mod tests {
             lifecycle: vec![],
             extensions: vec![],
             types: vec![],
+            functions: vec![],
         }
     }
 
@@ -237,4 +266,49 @@ mod tests {
         assert!(toml.contains("major = 1"));
         assert!(toml.contains("minor = 0"));
     }
+
+    #[test]
+    fn test_refinements_are_carried_through() {
+        let transpiler = Transpiler::new();
+        let mut spec = create_test_spec();
+        spec.inputs.push(FieldDef {
+            name: "tier".to_string(),
+            type_expr: TypeExpr::Primitive(PrimitiveType::String),
+            modifiers: vec![
+                Modifier::Pattern("^[a-z]+$".to_string()),
+                Modifier::MaxLength(8),
+                Modifier::OneOf(vec![serde_json::json!("gold"), serde_json::json!("silver")]),
+            ],
+        });
+
+        let json = transpiler.to_json(&spec).unwrap();
+        assert!(json.contains("\"Pattern\": \"^[a-z]+$\""));
+        assert!(json.contains("\"MaxLength\": 8"));
+
+        let yaml = transpiler.to_yaml(&spec).unwrap();
+        assert!(yaml.contains("OneOf:"));
+        assert!(yaml.contains("- silver"));
+
+        let toml = transpiler.to_toml(&spec).unwrap();
+        assert!(toml.contains("MaxLength = 8"));
+    }
+
+    #[test]
+    fn test_secret_defaults_are_masked() {
+        let transpiler = Transpiler::new();
+        let mut spec = create_test_spec();
+        spec.inputs.push(FieldDef {
+            name: "api_key".to_string(),
+            type_expr: TypeExpr::Primitive(PrimitiveType::String),
+            modifiers: vec![
+                Modifier::Secret,
+                Modifier::Default(Expression::StringLiteral("sk-live-123".to_string())),
+            ],
+        });
+
+        let json = transpiler.to_json(&spec).unwrap();
+        assert!(!json.contains("sk-live-123"));
+        assert!(json.contains("api_key"));
+        assert!(json.contains(SECRET_MASK));
+    }
 }